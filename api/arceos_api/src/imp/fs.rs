@@ -85,3 +85,11 @@ pub fn ax_current_dir() -> AxResult<String> {
 pub fn ax_set_current_dir(path: &str) -> AxResult {
     axfs::api::set_current_dir(path)
 }
+
+pub fn ax_mount(fstype: &str, path: &str) -> AxResult {
+    axfs::mount(path, axfs::new_fs(fstype)?)
+}
+
+pub fn ax_umount(path: &str) -> AxResult {
+    axfs::umount(path)
+}
@@ -50,5 +50,12 @@ pub use self::stdio::*;
 pub use self::task::*;
 pub use self::time::*;
 
-pub use axhal::misc::terminate as ax_terminate;
 pub use axio::PollState as AxPollState;
+
+pub fn ax_terminate() -> ! {
+    #[cfg(feature = "fs")]
+    if let Err(e) = axfs::sync() {
+        axlog::warn!("failed to sync filesystems before shutdown: {:?}", e);
+    }
+    axhal::misc::terminate()
+}
@@ -24,9 +24,11 @@ pub fn ax_exit(_exit_code: i32) -> ! {
 }
 
 cfg_task! {
+    use core::sync::atomic::AtomicU32;
     use core::time::Duration;
 
     /// A handle to a task.
+    #[derive(Clone)]
     pub struct AxTaskHandle {
         inner: axtask::AxTaskRef,
         id: u64,
@@ -42,6 +44,12 @@ cfg_task! {
     /// A mask to specify the CPU affinity.
     pub use axtask::AxCpuMask;
 
+    /// A snapshot of the information of a task.
+    pub use axtask::TaskInfo as AxTaskInfo;
+
+    #[cfg(feature = "select_rq_if")]
+    pub use axtask::{SelectRunQueueIf, TaskInner as AxTaskInner};
+
     /// A handle to a wait queue.
     ///
     /// A wait queue is used to store sleeping tasks waiting for a certain event
@@ -55,6 +63,32 @@ cfg_task! {
         }
     }
 
+    /// A handle to a set of tasks, which can be joined in the order they exit.
+    pub struct AxJoinSetHandle(axtask::JoinSet);
+
+    impl AxJoinSetHandle {
+        /// Creates an empty join set.
+        pub fn new() -> Self {
+            Self(axtask::JoinSet::new())
+        }
+
+        /// Returns the number of tasks in the set that have not been joined.
+        pub fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        /// Whether all tasks in the set have been joined.
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+    }
+
+    impl Default for AxJoinSetHandle {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
     pub fn ax_current_task_id() -> u64 {
         axtask::current().id().as_u64()
     }
@@ -74,6 +108,48 @@ cfg_task! {
         task.inner.join()
     }
 
+    pub fn ax_cancel_task(task: &AxTaskHandle) {
+        task.inner.cancel();
+    }
+
+    pub fn ax_join_set_spawn<F>(
+        set: &mut AxJoinSetHandle,
+        f: F,
+        name: alloc::string::String,
+        stack_size: usize,
+    ) -> AxTaskHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let inner = set.0.spawn_raw(f, name, stack_size);
+        AxTaskHandle {
+            id: inner.id().as_u64(),
+            inner,
+        }
+    }
+
+    pub fn ax_join_set_join_next(set: &mut AxJoinSetHandle) -> Option<(AxTaskHandle, i32)> {
+        set.0.join_next().map(|(inner, exit_code)| {
+            let handle = AxTaskHandle {
+                id: inner.id().as_u64(),
+                inner,
+            };
+            (handle, exit_code)
+        })
+    }
+
+    pub fn ax_test_cancel() {
+        axtask::test_cancel();
+    }
+
+    pub fn ax_task_list() -> alloc::vec::Vec<AxTaskInfo> {
+        axtask::tasks().map(|t| t.info()).collect()
+    }
+
+    pub fn ax_task_info(id: u64) -> Option<AxTaskInfo> {
+        axtask::lookup(axtask::TaskId::from_u64(id)).map(|t| t.info())
+    }
+
     pub fn ax_set_current_priority(prio: isize) -> crate::AxResult {
         if axtask::set_priority(prio) {
             Ok(())
@@ -135,4 +211,50 @@ cfg_task! {
             }
         }
     }
+
+    pub fn ax_futex_wait(
+        futex: &AtomicU32,
+        expected: u32,
+        timeout: Option<Duration>,
+    ) -> crate::AxResult<bool> {
+        use axtask::futex::WaitResult;
+
+        #[cfg(feature = "irq")]
+        let res = match timeout {
+            Some(dur) => axtask::futex::wait_timeout(futex, expected, dur),
+            None => axtask::futex::wait(futex, expected),
+        };
+        #[cfg(not(feature = "irq"))]
+        let res = {
+            if timeout.is_some() {
+                axlog::warn!("ax_futex_wait: the `timeout` argument is ignored without the `irq` feature");
+            }
+            axtask::futex::wait(futex, expected)
+        };
+        match res {
+            WaitResult::Woken => Ok(false),
+            WaitResult::TimedOut => Ok(true),
+            WaitResult::Mismatched => Err(axerrno::AxError::WouldBlock),
+        }
+    }
+
+    pub fn ax_futex_wake(futex: &AtomicU32, count: u32) -> u32 {
+        axtask::futex::wake(futex, count as usize) as u32
+    }
+
+    pub fn ax_futex_requeue(
+        from: &AtomicU32,
+        wake_count: u32,
+        to: &AtomicU32,
+        requeue_count: u32,
+        expected: Option<u32>,
+    ) -> crate::AxResult<u32> {
+        let (wake_count, requeue_count) = (wake_count as usize, requeue_count as usize);
+        let num = match expected {
+            Some(val) => axtask::futex::cmp_requeue(from, wake_count, to, requeue_count, val)
+                .ok_or(axerrno::AxError::WouldBlock)?,
+            None => axtask::futex::requeue(from, wake_count, to, requeue_count),
+        };
+        Ok(num as u32)
+    }
 }
@@ -121,7 +121,13 @@ pub mod task {
         @cfg "multitask";
         pub type AxTaskHandle;
         pub type AxWaitQueueHandle;
+        pub type AxJoinSetHandle;
         pub type AxCpuMask;
+        pub type AxTaskInfo;
+        #[cfg(feature = "select_rq_if")]
+        pub type AxTaskInner;
+        #[cfg(feature = "select_rq_if")]
+        pub type SelectRunQueueIf;
     }
 
     define_api! {
@@ -154,6 +160,33 @@ pub mod task {
         /// Waits for the given task to exit, and returns its exit code (the
         /// argument of [`ax_exit`]).
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
+        /// Requests the cancellation of the given task.
+        ///
+        /// The task is woken up if it is blocked in a cancellable wait, and
+        /// exits when it reaches a cancellation point (see [`ax_test_cancel`]).
+        pub fn ax_cancel_task(task: &AxTaskHandle);
+        /// Spawns a new task into the given join set, with the given entry
+        /// point and other arguments.
+        pub fn ax_join_set_spawn(
+            set: &mut AxJoinSetHandle,
+            f: impl FnOnce() + Send + 'static,
+            name: alloc::string::String,
+            stack_size: usize
+        ) -> AxTaskHandle;
+        /// Waits for any task in the join set to exit, removes it from the
+        /// set, and returns it with its exit code.
+        ///
+        /// Returns `None` if the set is empty.
+        pub fn ax_join_set_join_next(set: &mut AxJoinSetHandle) -> Option<(AxTaskHandle, i32)>;
+        /// A cancellation point, exits the current task if it has been
+        /// cancelled.
+        pub fn ax_test_cancel();
+        /// Returns the information of all live tasks, in the order of task
+        /// IDs.
+        pub fn ax_task_list() -> alloc::vec::Vec<AxTaskInfo>;
+        /// Returns the information of the task with the given ID, or `None`
+        /// if there is no such live task.
+        pub fn ax_task_info(id: u64) -> Option<AxTaskInfo>;
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
         /// Sets the cpu affinity of the current task.
@@ -175,6 +208,38 @@ pub mod task {
         /// The maximum number of tasks to wake up is specified by `count`. If
         /// `count` is `u32::MAX`, it will wake up all tasks in the wait queue.
         pub fn ax_wait_queue_wake(wq: &AxWaitQueueHandle, count: u32);
+
+        /// Blocks the current task if the futex word still holds `expected`,
+        /// until other tasks wake it up by [`ax_futex_wake`] or
+        /// [`ax_futex_requeue`], or the given duration has elapsed (if
+        /// specified).
+        ///
+        /// Returns whether the wait has timed out, or
+        /// [`WouldBlock`](crate::AxError::WouldBlock) if the futex word does
+        /// not hold `expected`.
+        pub fn ax_futex_wait(
+            futex: &core::sync::atomic::AtomicU32,
+            expected: u32,
+            timeout: Option<core::time::Duration>,
+        ) -> crate::AxResult<bool>;
+        /// Wakes up at most `count` tasks waiting on the futex, and returns
+        /// the number of tasks woken up.
+        pub fn ax_futex_wake(futex: &core::sync::atomic::AtomicU32, count: u32) -> u32;
+        /// Wakes up at most `wake_count` tasks waiting on the futex `from`,
+        /// and moves at most `requeue_count` of the others to wait on the
+        /// futex `to`.
+        ///
+        /// If `expected` is specified, it fails with
+        /// [`WouldBlock`](crate::AxError::WouldBlock) if the futex word
+        /// `from` does not hold it. Returns the number of tasks woken up or
+        /// requeued.
+        pub fn ax_futex_requeue(
+            from: &core::sync::atomic::AtomicU32,
+            wake_count: u32,
+            to: &core::sync::atomic::AtomicU32,
+            requeue_count: u32,
+            expected: Option<u32>,
+        ) -> crate::AxResult<u32>;
     }
 }
 
@@ -260,6 +325,17 @@ pub mod fs {
         pub fn ax_current_dir() -> AxResult<alloc::string::String>;
         /// Changes the current working directory to the specified path.
         pub fn ax_set_current_dir(path: &str) -> AxResult;
+
+        /// Mounts a new filesystem of type `fstype` at `path`.
+        ///
+        /// Only the filesystems living in memory are supported, i.e., `ramfs`
+        /// (or `tmpfs`), `devfs` (or `devtmpfs`), `proc` and `sysfs`.
+        pub fn ax_mount(fstype: &str, path: &str) -> AxResult;
+        /// Unmounts the filesystem at `path`.
+        ///
+        /// It fails with [`ResourceBusy`](crate::AxError::ResourceBusy) if the
+        /// filesystem is still in use.
+        pub fn ax_umount(path: &str) -> AxResult;
     }
 }
 
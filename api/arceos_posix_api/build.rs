@@ -2,16 +2,22 @@ fn main() {
     use std::io::Write;
 
     fn gen_pthread_mutex(out_file: &str) -> std::io::Result<()> {
-        // TODO: generate size and initial content automatically.
-        let (mutex_size, mutex_init) = if cfg!(feature = "multitask") {
-            if cfg!(feature = "smp") {
-                (6, "{0, 0, 8, 0, 0, 0}") // core::mem::transmute::<_, [usize; 6]>(axsync::Mutex::new(()))
-            } else {
-                (5, "{0, 8, 0, 0, 0}") // core::mem::transmute::<_, [usize; 5]>(axsync::Mutex::new(()))
-            }
+        // The size is checked against `PthreadMutex` by a static assertion.
+        // The initializer is all zeros, which marks the mutex to be
+        // initialized on first use, so it does not depend on the layout of
+        // `axsync::Mutex`.
+        let mutex_size = if cfg!(feature = "multitask") {
+            if cfg!(feature = "smp") { 8 } else { 7 }
+        } else {
+            2
+        };
+        // One more word for the lock class of `lockdep`.
+        let mutex_size = if cfg!(feature = "lockdep") {
+            mutex_size + 1
         } else {
-            (1, "{0}")
+            mutex_size
         };
+        let mutex_init = "{0}";
 
         let mut output = Vec::new();
         writeln!(
@@ -52,6 +58,7 @@ typedef struct {{
             "iovec",
             "clockid_t",
             "rlimit",
+            "rusage",
             "aibuf",
         ];
         let allow_vars = [
@@ -66,6 +73,7 @@ typedef struct {{
             "EPOLL_CTL_.*",
             "EPOLL.*",
             "RLIMIT_.*",
+            "RUSAGE_.*",
             "EAI_.*",
             "MAXADDRS",
         ];
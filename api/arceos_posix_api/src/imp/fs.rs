@@ -1,8 +1,8 @@
 use alloc::sync::Arc;
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_ulong, c_void};
 
 use axerrno::{LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
+use axfs::fops::{FileAttr, FileIds, OpenOptions};
 use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 
@@ -42,21 +42,8 @@ impl FileLike for File {
     }
 
     fn stat(&self) -> LinuxResult<ctypes::stat> {
-        let metadata = self.inner.lock().get_attr()?;
-        let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
-        let st_mode = ((ty as u32) << 12) | perm;
-        Ok(ctypes::stat {
-            st_ino: 1,
-            st_nlink: 1,
-            st_mode,
-            st_uid: 1000,
-            st_gid: 1000,
-            st_size: metadata.size() as _,
-            st_blocks: metadata.blocks() as _,
-            st_blksize: 512,
-            ..Default::default()
-        })
+        let file = self.inner.lock();
+        Ok(attr_to_stat(&file.get_attr()?, file.get_ids()?))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
@@ -76,6 +63,23 @@ impl FileLike for File {
 }
 
 /// Convert open flags to [`OpenOptions`].
+fn attr_to_stat(attr: &FileAttr, ids: FileIds) -> ctypes::stat {
+    let ty = attr.file_type() as u8;
+    let perm = attr.perm().bits() as u32;
+    let st_mode = ((ty as u32) << 12) | perm;
+    ctypes::stat {
+        st_ino: ids.ino as _,
+        st_nlink: ids.nlink as _,
+        st_mode,
+        st_uid: 1000,
+        st_gid: 1000,
+        st_size: attr.size() as _,
+        st_blocks: attr.blocks() as _,
+        st_blksize: 512,
+        ..Default::default()
+    }
+}
+
 fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     let flags = flags as u32;
     let mut options = OpenOptions::new();
@@ -177,7 +181,8 @@ pub unsafe fn sys_lstat(path: *const c_char, buf: *mut ctypes::stat) -> ctypes::
         if buf.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        unsafe { *buf = Default::default() }; // TODO
+        let (attr, ids) = axfs::fops::symlink_attr(path?)?;
+        unsafe { *buf = attr_to_stat(&attr, ids) };
         Ok(0)
     })
 }
@@ -212,7 +217,105 @@ pub fn sys_rename(old: *const c_char, new: *const c_char) -> c_int {
         let old_path = char_ptr_to_str(old)?;
         let new_path = char_ptr_to_str(new)?;
         debug!("sys_rename <= old: {:?}, new: {:?}", old_path, new_path);
-        axfs::api::rename(old_path, new_path)?;
+        axfs::fops::rename(old_path, new_path)?;
+        Ok(0)
+    })
+}
+
+/// Create a symbolic link `linkpath` which contains the string `target`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_symlink(target: *const c_char, linkpath: *const c_char) -> c_int {
+    syscall_body!(sys_symlink, {
+        let target = char_ptr_to_str(target)?;
+        let linkpath = char_ptr_to_str(linkpath)?;
+        debug!("sys_symlink <= {:?} {:?}", target, linkpath);
+        axfs::fops::create_symlink(target, linkpath)?;
+        Ok(0)
+    })
+}
+
+/// Read the target of the symbolic link `path` into `buf`.
+///
+/// The target is truncated to `bufsiz` bytes and not null-terminated. Return
+/// the number of bytes placed in `buf`.
+pub fn sys_readlink(path: *const c_char, buf: *mut c_char, bufsiz: usize) -> ctypes::ssize_t {
+    let path = char_ptr_to_str(path);
+    debug!("sys_readlink <= {:?} {:#x} {}", path, buf as usize, bufsiz);
+    syscall_body!(sys_readlink, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let target = axfs::fops::read_link(path?)?;
+        let len = target.len().min(bufsiz);
+        let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len) };
+        dst.copy_from_slice(&target.as_bytes()[..len]);
+        Ok(len)
+    })
+}
+
+/// Create a new hard link `new` to the existing file `old`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_link(old: *const c_char, new: *const c_char) -> c_int {
+    syscall_body!(sys_link, {
+        let old_path = char_ptr_to_str(old)?;
+        let new_path = char_ptr_to_str(new)?;
+        debug!("sys_link <= old: {:?}, new: {:?}", old_path, new_path);
+        axfs::fops::link(old_path, new_path)?;
+        Ok(0)
+    })
+}
+
+/// Mount a new filesystem of type `fstype` at `target`.
+///
+/// Only the filesystems living in memory are supported, so `source`, `flags`
+/// and `data` are ignored.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_mount(
+    source: *const c_char,
+    target: *const c_char,
+    fstype: *const c_char,
+    flags: c_ulong,
+    _data: *const c_void,
+) -> c_int {
+    syscall_body!(sys_mount, {
+        let source = char_ptr_to_str(source);
+        let target = char_ptr_to_str(target)?;
+        let fstype = char_ptr_to_str(fstype)?;
+        debug!(
+            "sys_mount <= source: {:?}, target: {:?}, fstype: {:?}, flags: {:#x}",
+            source, target, fstype, flags
+        );
+        let fs = axfs::new_fs(fstype).map_err(|_| LinuxError::ENODEV)?;
+        axfs::mount(target, fs)?;
         Ok(0)
     })
 }
+
+/// Unmount the filesystem mounted at `target`.
+///
+/// No `flags` are supported yet, so the filesystem cannot be unmounted if it
+/// is busy.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_umount2(target: *const c_char, flags: c_int) -> c_int {
+    syscall_body!(sys_umount2, {
+        let target = char_ptr_to_str(target)?;
+        debug!("sys_umount2 <= target: {:?}, flags: {:#x}", target, flags);
+        if flags != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        axfs::umount(target)?;
+        Ok(0)
+    })
+}
+
+/// Write all cached filesystem changes back to the disk.
+pub fn sys_sync() {
+    debug!("sys_sync");
+    if let Err(e) = axfs::sync() {
+        warn!("sys_sync: {:?}", e);
+    }
+}
@@ -10,6 +10,9 @@ use crate::ctypes;
 
 pub mod mutex;
 
+/// The return value of a thread that is cancelled, i.e. `PTHREAD_CANCELED`.
+const PTHREAD_CANCELED: *mut c_void = -1isize as *mut c_void;
+
 lazy_static::lazy_static! {
     static ref TID_TO_PTHREAD: RwLock<BTreeMap<u64, ForceSendSync<ctypes::pthread_t>>> = {
         let mut map = BTreeMap::new();
@@ -78,6 +81,21 @@ impl Pthread {
         }
     }
 
+    /// Finds the task of a live thread, without dereferencing the handle
+    /// which may have been freed by `pthread_join`.
+    fn lookup(ptr: ctypes::pthread_t) -> LinuxResult<AxTaskRef> {
+        let tid = TID_TO_PTHREAD
+            .read()
+            .iter()
+            .find(|(_, p)| core::ptr::eq(p.0, ptr))
+            .map(|(&tid, _)| tid)
+            .ok_or(LinuxError::ESRCH)?;
+        match axtask::lookup(axtask::TaskId::from_u64(tid)) {
+            Some(task) if task.exit_code().is_none() => Ok(task),
+            _ => Err(LinuxError::ESRCH),
+        }
+    }
+
     fn current() -> Option<&'static Pthread> {
         unsafe { core::ptr::NonNull::new(Self::current_ptr()).map(|ptr| ptr.as_ref()) }
     }
@@ -135,6 +153,54 @@ pub fn sys_pthread_exit(retval: *mut c_void) -> ! {
     Pthread::exit_current(retval);
 }
 
+/// Requests the cancellation of the given thread.
+///
+/// The thread is woken up if it is blocked in a cancellable wait, and exits
+/// with `PTHREAD_CANCELED` when it reaches a cancellation point.
+///
+/// Only `nanosleep` (and the sleep functions based on it) and
+/// `pthread_testcancel` are cancellation points. Other blocking calls, e.g.,
+/// `pthread_join`, `pthread_mutex_lock` or socket I/O, are not, and a
+/// cancelled thread keeps blocking in them.
+pub unsafe fn sys_pthread_cancel(thread: ctypes::pthread_t) -> c_int {
+    debug!("sys_pthread_cancel <= {:#x}", thread as usize);
+    syscall_body!(sys_pthread_cancel, {
+        Pthread::lookup(thread)?.cancel();
+        Ok(0)
+    })
+}
+
+/// Sends a signal to the given thread.
+///
+/// Signals are not supported yet, only `sig == 0` is accepted to check
+/// whether the thread is still alive. It returns `EINVAL` for an invalid
+/// signal number, and `ENOSYS` for any other valid signal, without sending it.
+pub fn sys_pthread_kill(thread: ctypes::pthread_t, sig: c_int) -> c_int {
+    debug!("sys_pthread_kill <= {:#x} {}", thread as usize, sig);
+    syscall_body!(sys_pthread_kill, {
+        // Same as `_NSIG` in `signal.h`, valid signals are in `1.._NSIG`.
+        const NSIG: c_int = 65;
+        if !(0..NSIG).contains(&sig) {
+            return Err(LinuxError::EINVAL);
+        }
+        Pthread::lookup(thread)?;
+        if sig != 0 {
+            warn!("sys_pthread_kill: signal {} is not supported", sig);
+            return Err(LinuxError::ENOSYS);
+        }
+        Ok(0)
+    })
+}
+
+/// A cancellation point, exits the current thread with `PTHREAD_CANCELED` if
+/// it has been cancelled.
+pub fn sys_pthread_testcancel() {
+    if axtask::current().is_cancelled() {
+        debug!("sys_pthread_testcancel: thread is cancelled");
+        Pthread::exit_current(PTHREAD_CANCELED);
+    }
+}
+
 /// Waits for the given thread to exit, and stores the return value in `retval`.
 pub unsafe fn sys_pthread_join(thread: ctypes::pthread_t, retval: *mut *mut c_void) -> c_int {
     debug!("sys_pthread_join <= {:#x}", retval as usize);
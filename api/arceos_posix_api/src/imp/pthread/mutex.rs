@@ -3,29 +3,69 @@ use crate::{ctypes, utils::check_null_mut_ptr};
 use axerrno::LinuxResult;
 use axsync::Mutex;
 
+use core::cell::UnsafeCell;
 use core::ffi::c_int;
-use core::mem::{ManuallyDrop, size_of};
+use core::mem::{ManuallyDrop, MaybeUninit, size_of};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 static_assertions::const_assert_eq!(
     size_of::<ctypes::pthread_mutex_t>(),
     size_of::<PthreadMutex>()
 );
 
+// The states of a `PthreadMutex`. `PTHREAD_MUTEX_INITIALIZER` is all zeros,
+// i.e., `STATE_UNINIT`, as the initial value of `Mutex` can not be written in
+// C without depending on its layout.
+const STATE_UNINIT: usize = 0;
+const STATE_INITIALIZING: usize = 1;
+const STATE_READY: usize = 2;
+
 #[repr(C)]
-pub struct PthreadMutex(Mutex<()>);
+pub struct PthreadMutex {
+    state: AtomicUsize,
+    inner: UnsafeCell<MaybeUninit<Mutex<()>>>,
+}
 
 impl PthreadMutex {
     const fn new() -> Self {
-        Self(Mutex::new(()))
+        Self {
+            state: AtomicUsize::new(STATE_READY),
+            inner: UnsafeCell::new(MaybeUninit::new(Mutex::new(()))),
+        }
+    }
+
+    /// Returns the inner mutex, and initializes it first if it's created by
+    /// `PTHREAD_MUTEX_INITIALIZER`.
+    fn get(&self) -> &Mutex<()> {
+        if self.state.load(Ordering::Acquire) != STATE_READY {
+            match self.state.compare_exchange(
+                STATE_UNINIT,
+                STATE_INITIALIZING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    unsafe { (*self.inner.get()).write(Mutex::new(())) };
+                    self.state.store(STATE_READY, Ordering::Release);
+                }
+                Err(_) => {
+                    while self.state.load(Ordering::Acquire) != STATE_READY {
+                        core::hint::spin_loop();
+                    }
+                }
+            }
+        }
+        // SAFETY: the mutex is initialized in the ready state.
+        unsafe { (*self.inner.get()).assume_init_ref() }
     }
 
     fn lock(&self) -> LinuxResult {
-        let _guard = ManuallyDrop::new(self.0.lock());
+        let _guard = ManuallyDrop::new(self.get().lock());
         Ok(())
     }
 
     fn unlock(&self) -> LinuxResult {
-        unsafe { self.0.force_unlock() };
+        unsafe { self.get().force_unlock() };
         Ok(())
     }
 }
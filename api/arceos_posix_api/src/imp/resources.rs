@@ -1,6 +1,6 @@
 use crate::ctypes;
 use axerrno::LinuxError;
-use core::ffi::c_int;
+use core::ffi::{c_int, c_long};
 
 /// Get resource limitations
 ///
@@ -49,3 +49,46 @@ pub unsafe fn sys_setrlimit(resource: c_int, rlimits: *mut crate::ctypes::rlimit
         Ok(0)
     })
 }
+
+/// Get resource usage
+///
+/// Only the CPU time and the context switch counts are reported. The system
+/// time is always zero, as all tasks run in kernel mode.
+pub unsafe fn sys_getrusage(who: c_int, usage: *mut ctypes::rusage) -> c_int {
+    debug!("sys_getrusage <= {} {:#x}", who, usage as usize);
+    syscall_body!(sys_getrusage, {
+        if usage.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        const RUSAGE_SELF: c_int = ctypes::RUSAGE_SELF as _;
+        const RUSAGE_THREAD: c_int = ctypes::RUSAGE_THREAD as _;
+        const RUSAGE_CHILDREN: c_int = ctypes::RUSAGE_CHILDREN as _;
+
+        let mut ru = ctypes::rusage::default();
+        match who {
+            RUSAGE_SELF => {
+                ru.ru_utime = super::time::process_cpu_time().into();
+                #[cfg(feature = "multitask")]
+                {
+                    let usage = axtask::cpu_usage();
+                    ru.ru_nvcsw = usage.voluntary_switches as c_long;
+                    ru.ru_nivcsw = usage.involuntary_switches as c_long;
+                }
+            }
+            RUSAGE_THREAD => {
+                ru.ru_utime = super::time::thread_cpu_time().into();
+                #[cfg(feature = "multitask")]
+                {
+                    let stats = axtask::current().stats();
+                    ru.ru_nvcsw = stats.voluntary_switches as c_long;
+                    ru.ru_nivcsw = stats.involuntary_switches as c_long;
+                }
+            }
+            // There are no child processes.
+            RUSAGE_CHILDREN => {}
+            _ => return Err(LinuxError::EINVAL),
+        }
+        unsafe { *usage = ru };
+        Ok(0)
+    })
+}
@@ -3,7 +3,9 @@ use core::ffi::{c_int, c_long};
 use core::time::Duration;
 
 use crate::ctypes;
-use crate::ctypes::{CLOCK_MONOTONIC, CLOCK_REALTIME};
+use crate::ctypes::{
+    CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID,
+};
 
 impl From<ctypes::timespec> for Duration {
     fn from(ts: ctypes::timespec) -> Self {
@@ -35,6 +37,32 @@ impl From<Duration> for ctypes::timeval {
     }
 }
 
+/// Returns the CPU time consumed by the current task.
+pub(crate) fn thread_cpu_time() -> Duration {
+    #[cfg(feature = "multitask")]
+    {
+        axtask::current().stats().runtime
+    }
+    // The only task consumes all the CPU time.
+    #[cfg(not(feature = "multitask"))]
+    {
+        axhal::time::monotonic_time()
+    }
+}
+
+/// Returns the CPU time consumed by all tasks except the idle ones, including
+/// the exited ones.
+pub(crate) fn process_cpu_time() -> Duration {
+    #[cfg(feature = "multitask")]
+    {
+        axtask::cpu_usage().runtime
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        axhal::time::monotonic_time()
+    }
+}
+
 /// Get clock time since booting
 pub unsafe fn sys_clock_gettime(clk: ctypes::clockid_t, ts: *mut ctypes::timespec) -> c_int {
     syscall_body!(sys_clock_gettime, {
@@ -44,6 +72,8 @@ pub unsafe fn sys_clock_gettime(clk: ctypes::clockid_t, ts: *mut ctypes::timespe
         let now = match clk as u32 {
             CLOCK_REALTIME => axhal::time::wall_time().into(),
             CLOCK_MONOTONIC => axhal::time::monotonic_time().into(),
+            CLOCK_PROCESS_CPUTIME_ID => process_cpu_time().into(),
+            CLOCK_THREAD_CPUTIME_ID => thread_cpu_time().into(),
             _ => {
                 warn!("Called sys_clock_gettime for unsupported clock {}", clk);
                 return Err(LinuxError::EINVAL);
@@ -73,8 +103,11 @@ pub unsafe fn sys_nanosleep(req: *const ctypes::timespec, rem: *mut ctypes::time
 
         let now = axhal::time::monotonic_time();
 
+        // `nanosleep` is a cancellation point.
         #[cfg(feature = "multitask")]
-        axtask::sleep(dur);
+        if axtask::sleep_cancellable(dur).is_err() {
+            super::pthread::sys_pthread_testcancel();
+        }
         #[cfg(not(feature = "multitask"))]
         axhal::time::busy_wait(dur);
 
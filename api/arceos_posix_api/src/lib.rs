@@ -31,7 +31,7 @@ pub mod config {
 pub mod ctypes;
 
 pub use imp::io::{sys_read, sys_write, sys_writev};
-pub use imp::resources::{sys_getrlimit, sys_setrlimit};
+pub use imp::resources::{sys_getrlimit, sys_getrusage, sys_setrlimit};
 pub use imp::sys::sys_sysconf;
 pub use imp::task::{sys_exit, sys_getpid, sys_sched_yield};
 pub use imp::time::{sys_clock_gettime, sys_nanosleep};
@@ -39,7 +39,10 @@ pub use imp::time::{sys_clock_gettime, sys_nanosleep};
 #[cfg(feature = "fd")]
 pub use imp::fd_ops::{sys_close, sys_dup, sys_dup2, sys_fcntl};
 #[cfg(feature = "fs")]
-pub use imp::fs::{sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat};
+pub use imp::fs::{
+    sys_fstat, sys_getcwd, sys_link, sys_lseek, sys_lstat, sys_mount, sys_open, sys_readlink,
+    sys_rename, sys_stat, sys_symlink, sys_sync, sys_umount2,
+};
 #[cfg(feature = "select")]
 pub use imp::io_mpx::sys_select;
 #[cfg(feature = "epoll")]
@@ -57,4 +60,7 @@ pub use imp::pthread::mutex::{
     sys_pthread_mutex_init, sys_pthread_mutex_lock, sys_pthread_mutex_unlock,
 };
 #[cfg(feature = "multitask")]
-pub use imp::pthread::{sys_pthread_create, sys_pthread_exit, sys_pthread_join, sys_pthread_self};
+pub use imp::pthread::{
+    sys_pthread_cancel, sys_pthread_create, sys_pthread_exit, sys_pthread_join, sys_pthread_kill,
+    sys_pthread_self, sys_pthread_testcancel,
+};
@@ -26,6 +26,7 @@ const CMD_TABLE: &[(&str, CmdHandler)] = &[
     ("help", do_help),
     ("ls", do_ls),
     ("mkdir", do_mkdir),
+    ("ps", do_ps),
     ("pwd", do_pwd),
     ("rm", do_rm),
     ("uname", do_uname),
@@ -262,6 +263,39 @@ fn do_uname(_args: &str) {
     );
 }
 
+#[cfg(all(feature = "axstd", feature = "multitask"))]
+fn do_ps(_args: &str) {
+    use std::os::arceos::api::task::ax_task_list;
+
+    println!(
+        "{:>5} {:<12} {:>5} {:>3} {:>12} {:>8}  NAME",
+        "TID", "STATE", "PRIO", "CPU", "TIME", "CSW"
+    );
+    for task in ax_task_list() {
+        let state = match task.exit_code {
+            Some(code) => std::format!("Exited({})", code),
+            None => std::format!("{:?}", task.state),
+        };
+        let stats = &task.stats;
+        println!(
+            "{:>5} {:<12} {:>5} {:>3} {:>8}.{:03} {:>8}  {}",
+            task.id.as_u64(),
+            state,
+            task.priority,
+            stats.last_cpu,
+            stats.runtime.as_secs(),
+            stats.runtime.subsec_millis(),
+            stats.voluntary_switches + stats.involuntary_switches,
+            task.name,
+        );
+    }
+}
+
+#[cfg(not(all(feature = "axstd", feature = "multitask")))]
+fn do_ps(_args: &str) {
+    print_err!("ps", "requires the `multitask` feature");
+}
+
 fn do_help(_args: &str) {
     println!("Available commands:");
     for (name, _) in CMD_TABLE {
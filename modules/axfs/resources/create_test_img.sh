@@ -28,3 +28,51 @@ create_test_img() {
 
 create_test_img "$CUR_DIR/fat16.img" 2500 16
 create_test_img "$CUR_DIR/fat32.img" 34000 32
+
+create_ext_img() {
+	local name=$1
+	local blkcount=$2
+	local fsType=$3
+	local src=$(mktemp -d)
+	for i in $(seq 1 1000); do
+	  echo "Rust is cool!" >>"$src/long.txt"
+	done
+	echo "Rust is cool!" >>"$src/short.txt"
+	mkdir -p "$src/very/long/path"
+	echo "Rust is cool!" >>"$src/very/long/path/test.txt"
+	mkdir -p "$src/very-long-dir-name"
+	echo "Rust is cool!" >>"$src/very-long-dir-name/very-long-file-name.txt"
+	# mount points of a read-only filesystem must exist in advance
+	mkdir -p "$src/dev" "$src/tmp" "$src/proc" "$src/sys"
+
+	rm -f "$name"
+	mke2fs -q -t $fsType -b 1024 -L "Test!" -U 12345678-1234-1234-1234-123456789abc \
+		-d "$src" "$name" $blkcount
+	rm -rf "$src"
+}
+
+create_ext_img "$CUR_DIR/ext2.img" 2048 ext2
+create_ext_img "$CUR_DIR/ext4.img" 2048 ext4
+
+create_gpt_img() {
+	local name=$1
+	local src=$(mktemp -d)
+	mkdir -p "$src/root" "$src/data"
+	echo "root" >"$src/root/root.txt"
+	echo "data" >"$src/data/data.txt"
+	mke2fs -q -t ext2 -b 1024 -L rootfs -d "$src/root" "$src/root.img" 256
+	mke2fs -q -t ext2 -b 1024 -L datafs -d "$src/data" "$src/data.img" 256
+
+	rm -f "$name"
+	truncate -s 3M "$name"
+	sfdisk -q "$name" <<-END
+		label: gpt
+		start=1MiB, size=256KiB, name=root
+		start=2MiB, size=256KiB, name=data
+	END
+	dd if="$src/root.img" of="$name" bs=1M seek=1 conv=notrunc status=none
+	dd if="$src/data.img" of="$name" bs=1M seek=2 conv=notrunc status=none
+	rm -rf "$src"
+}
+
+create_gpt_img "$CUR_DIR/gpt.img"
@@ -16,7 +16,7 @@ pub struct File {
 }
 
 /// Metadata information about a file.
-pub struct Metadata(fops::FileAttr);
+pub struct Metadata(pub(super) fops::FileAttr, pub(super) fops::FileIds);
 
 /// Options and flags which can be used to configure how a file is opened.
 #[derive(Clone, Debug)]
@@ -72,7 +72,9 @@ impl OpenOptions {
 
     /// Opens a file at `path` with the options specified by `self`.
     pub fn open(&self, path: &str) -> Result<File> {
-        fops::File::open(path, &self.0).map(|inner| File { inner })
+        fops::File::open(path, &self.0)
+            .map(|inner| File { inner })
+            .map_err(Into::into)
     }
 }
 
@@ -96,6 +98,12 @@ impl Metadata {
         self.0.is_file()
     }
 
+    /// Returns `true` if this metadata is for a symbolic link, which is only
+    /// possible from [`symlink_metadata`](super::symlink_metadata).
+    pub const fn is_symlink(&self) -> bool {
+        self.0.file_type().is_symlink()
+    }
+
     /// Returns the size of the file, in bytes, this metadata is for.
     #[allow(clippy::len_without_is_empty)]
     pub const fn len(&self) -> u64 {
@@ -116,6 +124,21 @@ impl Metadata {
     pub const fn blocks(&self) -> u64 {
         self.0.blocks()
     }
+
+    /// Returns the inode number of the file.
+    pub const fn ino(&self) -> u64 {
+        self.1.ino
+    }
+
+    /// Returns the number of hard links to the file.
+    pub const fn nlink(&self) -> u64 {
+        self.1.nlink
+    }
+
+    /// Returns the underlying [`fops::FileAttr`].
+    pub const fn raw_metadata(&self) -> &fops::FileAttr {
+        &self.0
+    }
 }
 
 impl fmt::Debug for Metadata {
@@ -166,7 +189,7 @@ impl File {
 
     /// Queries metadata about the underlying file.
     pub fn metadata(&self) -> Result<Metadata> {
-        self.inner.get_attr().map(Metadata)
+        Ok(Metadata(self.inner.get_attr()?, self.inner.get_ids()?))
     }
 }
 
@@ -9,6 +9,8 @@ pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
 use alloc::{string::String, vec::Vec};
 use axio::{self as io, prelude::*};
 
+use crate::fops;
+
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
     ReadDir::new(path)
@@ -72,12 +74,35 @@ pub fn create_dir_all(path: &str) -> io::Result<()> {
 
 /// Removes an empty directory.
 pub fn remove_dir(path: &str) -> io::Result<()> {
-    crate::root::remove_dir(None, path)
+    crate::root::remove_dir(None, path).map_err(Into::into)
 }
 
 /// Removes a file from the filesystem.
 pub fn remove_file(path: &str) -> io::Result<()> {
-    crate::root::remove_file(None, path)
+    crate::root::remove_file(None, path).map_err(Into::into)
+}
+
+/// Queries the metadata about a file without following symbolic links.
+pub fn symlink_metadata(path: &str) -> io::Result<Metadata> {
+    let (attr, ids) = fops::symlink_attr(path)?;
+    Ok(Metadata(attr, ids))
+}
+
+/// Creates a new symbolic link `link` pointing to `original`.
+pub fn symlink(original: &str, link: &str) -> io::Result<()> {
+    fops::create_symlink(original, link).map_err(Into::into)
+}
+
+/// Reads a symbolic link, returning the path that it points to.
+pub fn read_link(path: &str) -> io::Result<String> {
+    fops::read_link(path).map_err(Into::into)
+}
+
+/// Creates a new hard link `link` to the file `original`.
+///
+/// This only works when both paths are in the same mounted fs.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    fops::link(original, link).map_err(Into::into)
 }
 
 /// Rename a file or directory to a new name.
@@ -85,5 +110,5 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 ///
 /// This only works then the new path is in the same mounted fs.
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
-    crate::root::rename(old, new)
+    fops::rename(old, new).map_err(Into::into)
 }
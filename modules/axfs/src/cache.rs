@@ -0,0 +1,335 @@
+//! An LRU cache of disk blocks, with read-ahead and optional write-back.
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use axdriver::prelude::*;
+
+use crate::dev::BLOCK_SIZE;
+
+/// Maximum number of blocks in the cache.
+const CACHE_CAPACITY: usize = 1024;
+/// Maximum number of blocks read ahead on sequential misses.
+const READ_AHEAD_BLOCKS: usize = 16;
+
+/// Statistics of a block cache.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    /// Number of block lookups served from the cache.
+    pub hits: u64,
+    /// Number of block lookups that had to read the device.
+    pub misses: u64,
+    /// Number of blocks read ahead of the lookups.
+    pub read_ahead: u64,
+    /// Number of dirty blocks written back to the device.
+    pub write_backs: u64,
+    /// Number of read requests issued to the device.
+    pub dev_reads: u64,
+    /// Number of write requests issued to the device.
+    pub dev_writes: u64,
+}
+
+impl core::ops::AddAssign for CacheStats {
+    fn add_assign(&mut self, other: Self) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.read_ahead += other.read_ahead;
+        self.write_backs += other.write_backs;
+        self.dev_reads += other.dev_reads;
+        self.dev_writes += other.dev_writes;
+    }
+}
+
+struct CachedBlock {
+    data: Vec<u8>,
+    dirty: bool,
+    /// The last time the block was accessed, the key in [`BlockCache::lru`].
+    stamp: u64,
+}
+
+/// A cache of the blocks of a block device.
+///
+/// The writes go to the device immediately by default, so that nothing is
+/// lost if the system stops without syncing. With the `write-back` feature,
+/// they only mark the blocks dirty, which are written to the device when
+/// evicted, or when [`BlockCache::sync`] is called.
+pub struct BlockCache {
+    dev: AxBlockDevice,
+    write_back: bool,
+    blocks: BTreeMap<u64, CachedBlock>,
+    /// Block IDs ordered by their last access time.
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+    /// The block after the last missed one, to detect sequential reads.
+    next_seq: u64,
+    stats: CacheStats,
+}
+
+impl BlockCache {
+    pub fn new(dev: AxBlockDevice) -> Self {
+        Self {
+            dev,
+            write_back: cfg!(feature = "write-back"),
+            blocks: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            next_seq: u64::MAX,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Reads the block `block_id` into `buf`.
+    pub fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        let data = self.get_block(block_id)?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Overwrites the whole block `block_id` with `buf`, without reading it
+    /// from the device.
+    pub fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        if !self.blocks.contains_key(&block_id) {
+            self.insert(block_id, buf.to_vec(), false)?;
+        } else {
+            self.touch(block_id).unwrap().data.copy_from_slice(buf);
+        }
+        self.mark_dirty(block_id)
+    }
+
+    /// Updates part of the block `block_id` with `buf`, starting at `offset`.
+    pub fn write_partial(&mut self, block_id: u64, offset: usize, buf: &[u8]) -> DevResult {
+        self.get_block(block_id)?;
+        let block = self.blocks.get_mut(&block_id).unwrap();
+        block.data[offset..offset + buf.len()].copy_from_slice(buf);
+        self.mark_dirty(block_id)
+    }
+
+    /// Marks the updated block `block_id` dirty, or writes it to the device
+    /// at once if the cache is not write-back.
+    fn mark_dirty(&mut self, block_id: u64) -> DevResult {
+        let block = self.blocks.get_mut(&block_id).unwrap();
+        if self.write_back {
+            block.dirty = true;
+            return Ok(());
+        }
+        self.dev.write_block(block_id, &block.data)?;
+        self.stats.dev_writes += 1;
+        Ok(())
+    }
+
+    /// Writes all dirty blocks back to the device, and flushes the device.
+    pub fn sync(&mut self) -> DevResult {
+        let dirty = self
+            .blocks
+            .iter()
+            .filter(|(_, b)| b.dirty)
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+
+        // Contiguous blocks are written in one request.
+        let mut buf = Vec::new();
+        let mut i = 0;
+        while i < dirty.len() {
+            let start = dirty[i];
+            let mut count = 0;
+            buf.clear();
+            while i + count < dirty.len() && dirty[i + count] == start + count as u64 {
+                buf.extend_from_slice(&self.blocks[&(start + count as u64)].data);
+                count += 1;
+            }
+            self.dev.write_block(start, &buf)?;
+            self.stats.dev_writes += 1;
+            self.stats.write_backs += count as u64;
+            for id in start..start + count as u64 {
+                self.blocks.get_mut(&id).unwrap().dirty = false;
+            }
+            i += count;
+        }
+        self.dev.flush()
+    }
+
+    /// Marks the block as the most recently used one, and returns it.
+    fn touch(&mut self, block_id: u64) -> Option<&mut CachedBlock> {
+        let block = self.blocks.get_mut(&block_id)?;
+        self.lru.remove(&block.stamp);
+        self.clock += 1;
+        block.stamp = self.clock;
+        self.lru.insert(self.clock, block_id);
+        Some(block)
+    }
+
+    fn get_block(&mut self, block_id: u64) -> DevResult<&[u8]> {
+        if self.blocks.contains_key(&block_id) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            self.fill(block_id)?;
+        }
+        Ok(&self.touch(block_id).unwrap().data)
+    }
+
+    /// Reads the missed block `block_id` from the device, and some following
+    /// blocks if the reads are sequential.
+    fn fill(&mut self, block_id: u64) -> DevResult {
+        let mut count = 1;
+        if block_id == self.next_seq {
+            let max = (READ_AHEAD_BLOCKS as u64).min(self.dev.num_blocks() - block_id);
+            // stop at the cached blocks, which may be newer than the device
+            while count < max && !self.blocks.contains_key(&(block_id + count)) {
+                count += 1;
+            }
+        }
+        let mut buf = vec![0; count as usize * BLOCK_SIZE];
+        self.dev.read_block(block_id, &mut buf)?;
+        self.stats.dev_reads += 1;
+        self.stats.read_ahead += count - 1;
+        self.next_seq = block_id + count;
+
+        // Insert the read-ahead blocks first, so that the requested one is
+        // the most recently used.
+        for (i, data) in buf.chunks(BLOCK_SIZE).enumerate().rev() {
+            self.insert(block_id + i as u64, data.to_vec(), false)?;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, block_id: u64, data: Vec<u8>, dirty: bool) -> DevResult {
+        while self.blocks.len() >= CACHE_CAPACITY {
+            self.evict()?;
+        }
+        self.clock += 1;
+        self.lru.insert(self.clock, block_id);
+        self.blocks.insert(block_id, CachedBlock {
+            data,
+            dirty,
+            stamp: self.clock,
+        });
+        Ok(())
+    }
+
+    /// Removes the least recently used block, writes it back if it is dirty.
+    fn evict(&mut self) -> DevResult {
+        let (&stamp, &block_id) = self.lru.first_key_value().unwrap();
+        let block = &self.blocks[&block_id];
+        if block.dirty {
+            self.dev.write_block(block_id, &block.data)?;
+            self.stats.dev_writes += 1;
+            self.stats.write_backs += 1;
+        }
+        self.lru.remove(&stamp);
+        self.blocks.remove(&block_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axdriver_block::ramdisk::RamDisk;
+
+    use super::*;
+
+    fn new_cache(write_back: bool) -> BlockCache {
+        let mut cache = BlockCache::new(RamDisk::new((CACHE_CAPACITY + 16) * BLOCK_SIZE));
+        cache.write_back = write_back;
+        cache
+    }
+
+    fn dev_block(cache: &mut BlockCache, block_id: u64) -> Vec<u8> {
+        let mut buf = vec![0; BLOCK_SIZE];
+        cache.dev.read_block(block_id, &mut buf).unwrap();
+        buf
+    }
+
+    /// Reads the blocks in the descending order, which is not read ahead.
+    fn read_blocks(cache: &mut BlockCache, blocks: core::ops::Range<u64>) {
+        let mut buf = vec![0; BLOCK_SIZE];
+        for id in blocks.rev() {
+            cache.read_block(id, &mut buf).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_lru_order() {
+        let mut cache = new_cache(false);
+        let cap = CACHE_CAPACITY as u64;
+        read_blocks(&mut cache, 0..cap);
+        assert_eq!(cache.stats().read_ahead, 0);
+        assert_eq!(cache.blocks.len(), CACHE_CAPACITY);
+
+        // block `cap - 1` was read first, but is used again
+        read_blocks(&mut cache, cap - 1..cap);
+        read_blocks(&mut cache, cap..cap + 2);
+        assert!(cache.blocks.contains_key(&(cap - 1)));
+        assert!(!cache.blocks.contains_key(&(cap - 2)));
+        assert!(!cache.blocks.contains_key(&(cap - 3)));
+        assert!(cache.blocks.contains_key(&(cap - 4)));
+        assert_eq!(cache.blocks.len(), CACHE_CAPACITY);
+        assert_eq!(cache.lru.len(), CACHE_CAPACITY);
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let mut cache = new_cache(false);
+        let mut buf = vec![0; BLOCK_SIZE];
+        cache.read_block(0, &mut buf).unwrap();
+        cache.read_block(1, &mut buf).unwrap();
+        assert_eq!(cache.stats().read_ahead, READ_AHEAD_BLOCKS as u64 - 1);
+        cache.read_block(2, &mut buf).unwrap();
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().dev_reads, 2);
+    }
+
+    #[test]
+    fn test_write_through() {
+        let mut cache = new_cache(false);
+        cache.write_block(1, &[1; BLOCK_SIZE]).unwrap();
+        cache.write_partial(2, 8, &[2; 8]).unwrap();
+        assert_eq!(dev_block(&mut cache, 1), [1; BLOCK_SIZE]);
+        assert_eq!(dev_block(&mut cache, 2)[8..16], [2; 8]);
+        assert!(cache.blocks.values().all(|b| !b.dirty));
+        assert_eq!(cache.stats().write_backs, 0);
+    }
+
+    #[test]
+    fn test_eviction_write_back() {
+        let mut cache = new_cache(true);
+        cache.write_block(0, &[1; BLOCK_SIZE]).unwrap();
+        assert_eq!(dev_block(&mut cache, 0), [0; BLOCK_SIZE]);
+
+        // block 0 is the least recently used one
+        let cap = CACHE_CAPACITY as u64;
+        read_blocks(&mut cache, 1..cap + 1);
+        assert!(!cache.blocks.contains_key(&0));
+        assert_eq!(dev_block(&mut cache, 0), [1; BLOCK_SIZE]);
+        assert_eq!(cache.stats().write_backs, 1);
+
+        // clean blocks are not written back
+        read_blocks(&mut cache, cap + 1..cap + 2);
+        assert_eq!(cache.stats().write_backs, 1);
+        assert_eq!(cache.stats().dev_writes, 1);
+    }
+
+    #[test]
+    fn test_dirty_flush() {
+        let mut cache = new_cache(true);
+        for id in [3, 4, 5, 9] {
+            cache.write_partial(id, 0, &[id as u8; 4]).unwrap();
+        }
+        assert_eq!(dev_block(&mut cache, 4)[..4], [0; 4]);
+        assert_eq!(cache.stats().dev_writes, 0);
+
+        cache.sync().unwrap();
+        for id in [3, 4, 5, 9] {
+            assert_eq!(dev_block(&mut cache, id)[..4], [id as u8; 4]);
+        }
+        assert!(cache.blocks.values().all(|b| !b.dirty));
+        // the contiguous blocks are written in one request
+        assert_eq!(cache.stats().dev_writes, 2);
+        assert_eq!(cache.stats().write_backs, 4);
+
+        cache.sync().unwrap();
+        assert_eq!(cache.stats().dev_writes, 2);
+    }
+}
@@ -1,12 +1,28 @@
+use alloc::sync::Arc;
 use axdriver::prelude::*;
+use axsync::Mutex;
 
-const BLOCK_SIZE: usize = 512;
+use crate::cache::{BlockCache, CacheStats};
+
+pub(crate) const BLOCK_SIZE: usize = 512;
 
 /// A disk device with a cursor.
+///
+/// All accesses go through an LRU block cache. With the `write-back` feature,
+/// the data written is not on the device until [`Disk::sync`] is called or the
+/// blocks are evicted. The clones of a disk have their own cursors but share
+/// the cache.
+///
+/// A disk can also be a partition of a device, which only covers a range of
+/// its blocks.
+#[derive(Clone)]
 pub struct Disk {
     block_id: u64,
     offset: usize,
-    dev: AxBlockDevice,
+    /// The first block of the device covered by the disk.
+    start: u64,
+    num_blocks: u64,
+    cache: Arc<Mutex<BlockCache>>,
 }
 
 impl Disk {
@@ -16,13 +32,27 @@ impl Disk {
         Self {
             block_id: 0,
             offset: 0,
-            dev,
+            start: 0,
+            num_blocks: dev.num_blocks(),
+            cache: Arc::new(Mutex::new(BlockCache::new(dev))),
+        }
+    }
+
+    /// Create a disk on the `num_blocks` blocks of this disk from `start`.
+    pub(crate) fn partition(&self, start: u64, num_blocks: u64) -> Self {
+        assert!(start + num_blocks <= self.num_blocks);
+        Self {
+            block_id: 0,
+            offset: 0,
+            start: self.start + start,
+            num_blocks,
+            cache: self.cache.clone(),
         }
     }
 
     /// Get the size of the disk.
     pub fn size(&self) -> u64 {
-        self.dev.num_blocks() * BLOCK_SIZE as u64
+        self.num_blocks * BLOCK_SIZE as u64
     }
 
     /// Get the position of the cursor.
@@ -37,11 +67,18 @@ impl Disk {
     }
 
     /// Read within one block, returns the number of bytes read.
+    ///
+    /// Returns 0 at the end of the disk.
     pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
+        if self.block_id >= self.num_blocks {
+            return Ok(0);
+        }
+        let dev_block = self.start + self.block_id;
         let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
-            self.dev
-                .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
+            self.cache
+                .lock()
+                .read_block(dev_block, &mut buf[0..BLOCK_SIZE])?;
             self.block_id += 1;
             BLOCK_SIZE
         } else {
@@ -50,7 +87,7 @@ impl Disk {
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.cache.lock().read_block(dev_block, &mut data)?;
             buf[..count].copy_from_slice(&data[start..start + count]);
 
             self.offset += count;
@@ -64,21 +101,28 @@ impl Disk {
     }
 
     /// Write within one block, returns the number of bytes written.
+    ///
+    /// Returns 0 at the end of the disk.
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
+        if self.block_id >= self.num_blocks {
+            return Ok(0);
+        }
+        let dev_block = self.start + self.block_id;
         let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
+            self.cache
+                .lock()
+                .write_block(dev_block, &buf[0..BLOCK_SIZE])?;
             self.block_id += 1;
             BLOCK_SIZE
         } else {
             // partial block
-            let mut data = [0u8; BLOCK_SIZE];
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
-            data[start..start + count].copy_from_slice(&buf[..count]);
-            self.dev.write_block(self.block_id, &data)?;
+            self.cache
+                .lock()
+                .write_partial(dev_block, start, &buf[..count])?;
 
             self.offset += count;
             if self.offset >= BLOCK_SIZE {
@@ -89,4 +133,18 @@ impl Disk {
         };
         Ok(write_size)
     }
+
+    /// Write all cached changes back to the device.
+    ///
+    /// The changes of other partitions on the same device are also written.
+    pub fn sync(&self) -> DevResult {
+        self.cache.lock().sync()
+    }
+
+    /// Get the statistics of the block cache.
+    ///
+    /// The cache is shared by all partitions on the same device.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().stats()
+    }
 }
@@ -1,11 +1,14 @@
 //! Low-level filesystem operations.
 
-use axerrno::{AxError, AxResult, ax_err, ax_err_type};
+use alloc::{string::String, sync::Arc};
+use axerrno::{AxError, AxResult, LinuxError, ax_err, ax_err_type};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use cap_access::{Cap, WithCap};
 use core::fmt;
 
+use crate::root::MountPoint;
+
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
 #[cfg(feature = "myfs")]
@@ -20,11 +23,101 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
+/// The error of an operation on paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    /// Too many levels of symbolic links (`ELOOP`), e.g., for a loop of links,
+    /// which [`AxError`] has no kind for.
+    SymlinkLoop,
+    /// Any other error.
+    Ax(AxError),
+}
+
+/// A specialized [`Result`] type for operations on paths.
+pub type PathResult<T = ()> = Result<T, PathError>;
+
+impl From<AxError> for PathError {
+    fn from(e: AxError) -> Self {
+        Self::Ax(e)
+    }
+}
+
+impl From<PathError> for AxError {
+    /// Converts a [`PathError`], where a symbolic link loop becomes
+    /// [`InvalidData`](AxError::InvalidData).
+    fn from(e: PathError) -> Self {
+        match e {
+            PathError::SymlinkLoop => AxError::InvalidData,
+            PathError::Ax(e) => e,
+        }
+    }
+}
+
+impl From<PathError> for LinuxError {
+    fn from(e: PathError) -> Self {
+        match e {
+            PathError::SymlinkLoop => LinuxError::ELOOP,
+            PathError::Ax(e) => e.into(),
+        }
+    }
+}
+
+/// The inode number and the number of hard links of a file, which are not in
+/// [`FileAttr`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileIds {
+    /// The inode number. It's the address of the node for the filesystems
+    /// without inode numbers, which is unique while the node is alive.
+    pub ino: u64,
+    /// The number of hard links, which is 1 for the filesystems without them.
+    pub nlink: u64,
+}
+
+impl FileIds {
+    pub(crate) fn of(node: &VfsNodeRef) -> Self {
+        let (ino, nlink) = crate::fs::node_ids(node);
+        Self { ino, nlink }
+    }
+}
+
+/// Gets the attributes, the inode number and the number of hard links of the
+/// file at `path`, without following the symbolic link at the last component.
+pub fn symlink_attr(path: &str) -> PathResult<(FileAttr, FileIds)> {
+    let node = crate::root::lookup_no_follow(None, path)?;
+    Ok((node.get_attr()?, FileIds::of(&node)))
+}
+
+/// Creates a symbolic link at `path`, which points to `target`.
+pub fn create_symlink(target: &str, path: &str) -> PathResult {
+    crate::root::create_symlink(None, target, path)
+}
+
+/// Reads the target of the symbolic link at `path`.
+pub fn read_link(path: &str) -> PathResult<String> {
+    crate::root::read_link(None, path)
+}
+
+/// Rename a file or directory to a new name.
+/// Delete the original file if `old` already exists.
+///
+/// This only works then the new path is in the same mounted fs.
+pub fn rename(old: &str, new: &str) -> PathResult {
+    crate::root::rename(old, new)
+}
+
+/// Creates a hard link `new` to the file `old`.
+///
+/// Both paths must be in the same mounted fs.
+pub fn link(old: &str, new: &str) -> PathResult {
+    crate::root::link(old, new)
+}
+
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
     is_append: bool,
     offset: u64,
+    _mount: Option<Arc<MountPoint>>,
 }
 
 /// An opened directory object, with open permissions and a cursor for
@@ -32,6 +125,7 @@ pub struct File {
 pub struct Directory {
     node: WithCap<VfsNodeRef>,
     entry_idx: usize,
+    mount: Option<Arc<MountPoint>>,
 }
 
 /// Options and flags which can be used to configure how a file is opened.
@@ -122,24 +216,31 @@ impl File {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
-    fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_at(
+        dir: Option<&VfsNodeRef>,
+        dir_mount: Option<Arc<MountPoint>>,
+        path: &str,
+        opts: &OpenOptions,
+    ) -> PathResult<Self> {
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
-            return ax_err!(InvalidInput);
+            return Err(ax_err_type!(InvalidInput).into());
         }
 
-        let node_option = crate::root::lookup(dir, path);
-        let node = if opts.create || opts.create_new {
+        let node_option = crate::root::lookup_mounted(dir, dir_mount.clone(), path);
+        let (node, mount) = if opts.create || opts.create_new {
             match node_option {
                 Ok(node) => {
                     // already exists
                     if opts.create_new {
-                        return ax_err!(AlreadyExists);
+                        return Err(ax_err_type!(AlreadyExists).into());
                     }
                     node
                 }
                 // not exists, create new
-                Err(VfsError::NotFound) => crate::root::create_file(dir, path)?,
+                Err(PathError::Ax(VfsError::NotFound)) => {
+                    crate::root::create_file_mounted(dir, dir_mount, path)?
+                }
                 Err(e) => return Err(e),
             }
         } else {
@@ -151,11 +252,11 @@ impl File {
         if attr.is_dir()
             && (opts.create || opts.create_new || opts.write || opts.append || opts.truncate)
         {
-            return ax_err!(IsADirectory);
+            return Err(ax_err_type!(IsADirectory).into());
         }
         let access_cap = opts.into();
         if !perm_to_cap(attr.perm()).contains(access_cap) {
-            return ax_err!(PermissionDenied);
+            return Err(ax_err_type!(PermissionDenied).into());
         }
 
         node.open()?;
@@ -166,13 +267,14 @@ impl File {
             node: WithCap::new(node, access_cap),
             is_append: opts.append,
             offset: 0,
+            _mount: mount,
         })
     }
 
     /// Opens a file at the path relative to the current directory. Returns a
     /// [`File`] object.
-    pub fn open(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_at(None, path, opts)
+    pub fn open(path: &str, opts: &OpenOptions) -> PathResult<Self> {
+        Self::_open_at(None, None, path, opts)
     }
 
     /// Truncates the file to the specified size.
@@ -252,6 +354,11 @@ impl File {
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.access_node(Cap::empty())?.get_attr()
     }
+
+    /// Gets the inode number and the number of hard links.
+    pub fn get_ids(&self) -> AxResult<FileIds> {
+        Ok(FileIds::of(self.access_node(Cap::empty())?))
+    }
 }
 
 impl Directory {
@@ -259,29 +366,35 @@ impl Directory {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
-    fn _open_dir_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_dir_at(
+        dir: Option<&VfsNodeRef>,
+        dir_mount: Option<Arc<MountPoint>>,
+        path: &str,
+        opts: &OpenOptions,
+    ) -> PathResult<Self> {
         debug!("open dir: {}", path);
         if !opts.read {
-            return ax_err!(InvalidInput);
+            return Err(ax_err_type!(InvalidInput).into());
         }
         if opts.create || opts.create_new || opts.write || opts.append || opts.truncate {
-            return ax_err!(InvalidInput);
+            return Err(ax_err_type!(InvalidInput).into());
         }
 
-        let node = crate::root::lookup(dir, path)?;
+        let (node, mount) = crate::root::lookup_mounted(dir, dir_mount, path)?;
         let attr = node.get_attr()?;
         if !attr.is_dir() {
-            return ax_err!(NotADirectory);
+            return Err(ax_err_type!(NotADirectory).into());
         }
         let access_cap = opts.into();
         if !perm_to_cap(attr.perm()).contains(access_cap) {
-            return ax_err!(PermissionDenied);
+            return Err(ax_err_type!(PermissionDenied).into());
         }
 
         node.open()?;
         Ok(Self {
             node: WithCap::new(node, access_cap),
             entry_idx: 0,
+            mount,
         })
     }
 
@@ -295,42 +408,54 @@ impl Directory {
 
     /// Opens a directory at the path relative to the current directory.
     /// Returns a [`Directory`] object.
-    pub fn open_dir(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(None, path, opts)
+    pub fn open_dir(path: &str, opts: &OpenOptions) -> PathResult<Self> {
+        Self::_open_dir_at(None, None, path, opts)
     }
 
     /// Opens a directory at the path relative to this directory. Returns a
     /// [`Directory`] object.
-    pub fn open_dir_at(&self, path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(self.access_at(path)?, path, opts)
+    pub fn open_dir_at(&self, path: &str, opts: &OpenOptions) -> PathResult<Self> {
+        Self::_open_dir_at(self.access_at(path)?, self.mount.clone(), path, opts)
     }
 
     /// Opens a file at the path relative to this directory. Returns a [`File`]
     /// object.
-    pub fn open_file_at(&self, path: &str, opts: &OpenOptions) -> AxResult<File> {
-        File::_open_at(self.access_at(path)?, path, opts)
+    pub fn open_file_at(&self, path: &str, opts: &OpenOptions) -> PathResult<File> {
+        File::_open_at(self.access_at(path)?, self.mount.clone(), path, opts)
     }
 
     /// Creates an empty file at the path relative to this directory.
-    pub fn create_file(&self, path: &str) -> AxResult<VfsNodeRef> {
+    pub fn create_file(&self, path: &str) -> PathResult<VfsNodeRef> {
         crate::root::create_file(self.access_at(path)?, path)
     }
 
     /// Creates an empty directory at the path relative to this directory.
-    pub fn create_dir(&self, path: &str) -> AxResult {
+    pub fn create_dir(&self, path: &str) -> PathResult {
         crate::root::create_dir(self.access_at(path)?, path)
     }
 
     /// Removes a file at the path relative to this directory.
-    pub fn remove_file(&self, path: &str) -> AxResult {
+    pub fn remove_file(&self, path: &str) -> PathResult {
         crate::root::remove_file(self.access_at(path)?, path)
     }
 
     /// Removes a directory at the path relative to this directory.
-    pub fn remove_dir(&self, path: &str) -> AxResult {
+    pub fn remove_dir(&self, path: &str) -> PathResult {
         crate::root::remove_dir(self.access_at(path)?, path)
     }
 
+    /// Creates a symbolic link at the path relative to this directory, which
+    /// points to `target`.
+    pub fn create_symlink(&self, target: &str, path: &str) -> PathResult {
+        crate::root::create_symlink(self.access_at(path)?, target, path)
+    }
+
+    /// Reads the target of the symbolic link at the path relative to this
+    /// directory.
+    pub fn read_link(&self, path: &str) -> PathResult<String> {
+        crate::root::read_link(self.access_at(path)?, path)
+    }
+
     /// Reads directory entries starts from the current position into the
     /// given buffer. Returns the number of entries read.
     ///
@@ -348,9 +473,16 @@ impl Directory {
     /// Delete the original file if `old` already exists.
     ///
     /// This only works then the new path is in the same mounted fs.
-    pub fn rename(&self, old: &str, new: &str) -> AxResult {
+    pub fn rename(&self, old: &str, new: &str) -> PathResult {
         crate::root::rename(old, new)
     }
+
+    /// Creates a hard link `new` to the file `old`.
+    ///
+    /// Both paths must be in the same mounted fs.
+    pub fn link(&self, old: &str, new: &str) -> PathResult {
+        crate::root::link(old, new)
+    }
 }
 
 impl Drop for File {
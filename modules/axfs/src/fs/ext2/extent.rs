@@ -0,0 +1,69 @@
+//! Read support for the extent trees of ext4.
+
+use alloc::vec;
+use axfs_vfs::{VfsError, VfsResult};
+
+use super::layout::{Inode, read_u16, read_u32};
+use super::volume::Volume;
+
+const EXTENT_MAGIC: u16 = 0xf30a;
+const HEADER_SIZE: usize = 12;
+const ENTRY_SIZE: usize = 12;
+/// Extents longer than this are uninitialized, which read as zeros.
+const MAX_INIT_LEN: u16 = 32768;
+/// The deepest extent tree that ext4 creates.
+const MAX_DEPTH: u16 = 5;
+
+impl Volume {
+    /// Looks up the logical block `lbn` in the extent tree of the inode.
+    pub(super) fn map_extent(&mut self, inode: &Inode, lbn: u64) -> VfsResult<Option<u64>> {
+        let lbn = lbn as u32;
+        let mut node = inode.block_area().to_vec();
+        let mut buf = vec![0; node.len().max(self.block_size())];
+        // The depth of the node to read next, which is unknown for the root.
+        // Each level must be one less than its parent, so that a corrupted
+        // tree cannot lead to a cycle.
+        let mut expected_depth = None;
+        loop {
+            if node.len() < HEADER_SIZE || read_u16(&node, 0) != EXTENT_MAGIC {
+                warn!("ext4: bad extent header");
+                return Err(VfsError::InvalidData);
+            }
+            let entries = read_u16(&node, 2) as usize;
+            let depth = read_u16(&node, 6);
+            if depth > MAX_DEPTH || expected_depth.is_some_and(|d| d != depth) {
+                warn!("ext4: bad extent tree depth {}", depth);
+                return Err(VfsError::InvalidData);
+            }
+            if HEADER_SIZE + entries * ENTRY_SIZE > node.len() {
+                return Err(VfsError::InvalidData);
+            }
+            // The entries are sorted by their first logical block, find the
+            // last one that starts at or before `lbn`.
+            let entry = (0..entries)
+                .map(|i| HEADER_SIZE + i * ENTRY_SIZE)
+                .take_while(|&off| read_u32(&node, off) <= lbn)
+                .last();
+            let Some(off) = entry else {
+                return Ok(None);
+            };
+
+            if depth == 0 {
+                let first = read_u32(&node, off);
+                let len = read_u16(&node, off + 4);
+                if len > MAX_INIT_LEN || lbn - first >= len as u32 {
+                    return Ok(None);
+                }
+                let start =
+                    (read_u16(&node, off + 6) as u64) << 32 | read_u32(&node, off + 8) as u64;
+                return Ok(Some(start + (lbn - first) as u64));
+            }
+
+            let leaf = (read_u16(&node, off + 8) as u64) << 32 | read_u32(&node, off + 4) as u64;
+            expected_depth = Some(depth - 1);
+            self.read_block(leaf, &mut buf)?;
+            node.clear();
+            node.extend_from_slice(&buf[..self.block_size()]);
+        }
+    }
+}
@@ -0,0 +1,478 @@
+//! On-disk structures of ext2, and of ext4 as far as they are read.
+//!
+//! All multi-byte fields are little-endian. The structures are kept as raw
+//! bytes, so the fields not known here are preserved when written back.
+
+use axfs_vfs::VfsNodeType;
+
+pub const SUPERBLOCK_OFFSET: u64 = 1024;
+pub const SUPERBLOCK_SIZE: usize = 1024;
+pub const EXT2_MAGIC: u16 = 0xef53;
+
+pub const ROOT_INO: u32 = 2;
+/// The first non-reserved inode for revision 0 filesystems.
+pub const GOOD_OLD_FIRST_INO: u32 = 11;
+pub const GOOD_OLD_INODE_SIZE: usize = 128;
+
+pub const COMPAT_HAS_JOURNAL: u32 = 0x4;
+pub const INCOMPAT_FILETYPE: u32 = 0x2;
+pub const INCOMPAT_RECOVER: u32 = 0x4;
+pub const INCOMPAT_EXTENTS: u32 = 0x40;
+pub const INCOMPAT_64BIT: u32 = 0x80;
+pub const INCOMPAT_FLEX_BG: u32 = 0x200;
+pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x2;
+
+pub const S_IFMT: u16 = 0o170000;
+pub const S_IFSOCK: u16 = 0o140000;
+pub const S_IFLNK: u16 = 0o120000;
+pub const S_IFREG: u16 = 0o100000;
+pub const S_IFBLK: u16 = 0o060000;
+pub const S_IFDIR: u16 = 0o040000;
+pub const S_IFCHR: u16 = 0o020000;
+pub const S_IFIFO: u16 = 0o010000;
+
+/// The directory is indexed by a hashed b-tree.
+pub const INDEX_FL: u32 = 0x1000;
+/// The file is mapped by extents instead of indirect blocks.
+pub const EXTENTS_FL: u32 = 0x80000;
+
+/// Number of block pointers in `i_block`.
+pub const N_BLOCKS: usize = 15;
+/// Number of direct block pointers in `i_block`.
+pub const N_DIRECT: usize = 12;
+
+pub fn read_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+pub fn read_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+pub fn write_u16(buf: &mut [u8], off: usize, val: u16) {
+    buf[off..off + 2].copy_from_slice(&val.to_le_bytes());
+}
+
+pub fn write_u32(buf: &mut [u8], off: usize, val: u32) {
+    buf[off..off + 4].copy_from_slice(&val.to_le_bytes());
+}
+
+pub struct SuperBlock {
+    pub raw: [u8; SUPERBLOCK_SIZE],
+}
+
+impl SuperBlock {
+    pub fn inodes_count(&self) -> u32 {
+        read_u32(&self.raw, 0)
+    }
+
+    pub fn blocks_count(&self) -> u64 {
+        let hi = if self.has_incompat(INCOMPAT_64BIT) {
+            read_u32(&self.raw, 0x150)
+        } else {
+            0
+        };
+        ((hi as u64) << 32) | read_u32(&self.raw, 0x4) as u64
+    }
+
+    pub fn free_blocks_count(&self) -> u32 {
+        read_u32(&self.raw, 0xc)
+    }
+
+    pub fn set_free_blocks_count(&mut self, count: u32) {
+        write_u32(&mut self.raw, 0xc, count);
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        read_u32(&self.raw, 0x10)
+    }
+
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        write_u32(&mut self.raw, 0x10, count);
+    }
+
+    pub fn first_data_block(&self) -> u32 {
+        read_u32(&self.raw, 0x14)
+    }
+
+    pub fn log_block_size(&self) -> u32 {
+        read_u32(&self.raw, 0x18)
+    }
+
+    pub fn blocks_per_group(&self) -> u32 {
+        read_u32(&self.raw, 0x20)
+    }
+
+    pub fn inodes_per_group(&self) -> u32 {
+        read_u32(&self.raw, 0x28)
+    }
+
+    /// The last time the filesystem was written, in seconds since the epoch.
+    pub fn write_time(&self) -> u32 {
+        read_u32(&self.raw, 0x30)
+    }
+
+    pub fn magic(&self) -> u16 {
+        read_u16(&self.raw, 0x38)
+    }
+
+    pub fn rev_level(&self) -> u32 {
+        read_u32(&self.raw, 0x4c)
+    }
+
+    pub fn first_ino(&self) -> u32 {
+        if self.rev_level() == 0 {
+            GOOD_OLD_FIRST_INO
+        } else {
+            read_u32(&self.raw, 0x54)
+        }
+    }
+
+    pub fn inode_size(&self) -> usize {
+        if self.rev_level() == 0 {
+            GOOD_OLD_INODE_SIZE
+        } else {
+            read_u16(&self.raw, 0x58) as usize
+        }
+    }
+
+    pub fn feature_compat(&self) -> u32 {
+        read_u32(&self.raw, 0x5c)
+    }
+
+    pub fn feature_incompat(&self) -> u32 {
+        read_u32(&self.raw, 0x60)
+    }
+
+    pub fn feature_ro_compat(&self) -> u32 {
+        read_u32(&self.raw, 0x64)
+    }
+
+    pub fn has_incompat(&self, feature: u32) -> bool {
+        self.feature_incompat() & feature != 0
+    }
+
+    /// Size of a group descriptor in bytes.
+    pub fn desc_size(&self) -> usize {
+        if self.has_incompat(INCOMPAT_64BIT) {
+            read_u16(&self.raw, 0xfe) as usize
+        } else {
+            32
+        }
+    }
+}
+
+pub struct GroupDesc {
+    pub raw: [u8; 64],
+    /// Whether the high halves of the fields are present.
+    wide: bool,
+}
+
+impl GroupDesc {
+    pub fn new(raw: &[u8]) -> Self {
+        let mut desc = Self {
+            raw: [0; 64],
+            wide: raw.len() >= 64,
+        };
+        let len = raw.len().min(64);
+        desc.raw[..len].copy_from_slice(&raw[..len]);
+        desc
+    }
+
+    fn lo_hi(&self, lo: usize, hi: usize) -> u64 {
+        let hi = if self.wide {
+            read_u32(&self.raw, hi)
+        } else {
+            0
+        };
+        ((hi as u64) << 32) | read_u32(&self.raw, lo) as u64
+    }
+
+    pub fn block_bitmap(&self) -> u64 {
+        self.lo_hi(0x0, 0x20)
+    }
+
+    pub fn inode_bitmap(&self) -> u64 {
+        self.lo_hi(0x4, 0x24)
+    }
+
+    pub fn inode_table(&self) -> u64 {
+        self.lo_hi(0x8, 0x28)
+    }
+
+    pub fn free_blocks_count(&self) -> u16 {
+        read_u16(&self.raw, 0xc)
+    }
+
+    pub fn set_free_blocks_count(&mut self, count: u16) {
+        write_u16(&mut self.raw, 0xc, count);
+    }
+
+    pub fn free_inodes_count(&self) -> u16 {
+        read_u16(&self.raw, 0xe)
+    }
+
+    pub fn set_free_inodes_count(&mut self, count: u16) {
+        write_u16(&mut self.raw, 0xe, count);
+    }
+
+    pub fn used_dirs_count(&self) -> u16 {
+        read_u16(&self.raw, 0x10)
+    }
+
+    pub fn set_used_dirs_count(&mut self, count: u16) {
+        write_u16(&mut self.raw, 0x10, count);
+    }
+}
+
+/// The first 128 bytes of an inode, which are the same in all revisions.
+#[derive(Clone)]
+pub struct Inode {
+    pub raw: [u8; GOOD_OLD_INODE_SIZE],
+}
+
+impl Inode {
+    pub const fn empty() -> Self {
+        Self {
+            raw: [0; GOOD_OLD_INODE_SIZE],
+        }
+    }
+
+    pub fn mode(&self) -> u16 {
+        read_u16(&self.raw, 0x0)
+    }
+
+    pub fn set_mode(&mut self, mode: u16) {
+        write_u16(&mut self.raw, 0x0, mode);
+    }
+
+    pub fn node_type(&self) -> VfsNodeType {
+        match self.mode() & S_IFMT {
+            S_IFSOCK => VfsNodeType::Socket,
+            S_IFLNK => VfsNodeType::SymLink,
+            S_IFBLK => VfsNodeType::BlockDevice,
+            S_IFDIR => VfsNodeType::Dir,
+            S_IFCHR => VfsNodeType::CharDevice,
+            S_IFIFO => VfsNodeType::Fifo,
+            _ => VfsNodeType::File,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode() & S_IFMT == S_IFDIR
+    }
+
+    pub fn size(&self) -> u64 {
+        ((read_u32(&self.raw, 0x6c) as u64) << 32) | read_u32(&self.raw, 0x4) as u64
+    }
+
+    pub fn set_size(&mut self, size: u64) {
+        write_u32(&mut self.raw, 0x4, size as u32);
+        write_u32(&mut self.raw, 0x6c, (size >> 32) as u32);
+    }
+
+    pub fn set_dtime(&mut self, dtime: u32) {
+        write_u32(&mut self.raw, 0x14, dtime);
+    }
+
+    pub fn links_count(&self) -> u16 {
+        read_u16(&self.raw, 0x1a)
+    }
+
+    pub fn set_links_count(&mut self, count: u16) {
+        write_u16(&mut self.raw, 0x1a, count);
+    }
+
+    /// Number of 512-byte sectors allocated to the inode.
+    pub fn sectors(&self) -> u64 {
+        ((read_u16(&self.raw, 0x74) as u64) << 32) | read_u32(&self.raw, 0x1c) as u64
+    }
+
+    pub fn set_sectors(&mut self, sectors: u64) {
+        write_u32(&mut self.raw, 0x1c, sectors as u32);
+        write_u16(&mut self.raw, 0x74, (sectors >> 32) as u16);
+    }
+
+    pub fn flags(&self) -> u32 {
+        read_u32(&self.raw, 0x20)
+    }
+
+    pub fn set_flags(&mut self, flags: u32) {
+        write_u32(&mut self.raw, 0x20, flags);
+    }
+
+    /// The raw `i_block` area, which holds block pointers, the extent tree
+    /// root, or the target of a fast symbolic link.
+    pub fn block_area(&self) -> &[u8] {
+        &self.raw[0x28..0x28 + N_BLOCKS * 4]
+    }
+
+    pub fn block_area_mut(&mut self) -> &mut [u8] {
+        &mut self.raw[0x28..0x28 + N_BLOCKS * 4]
+    }
+
+    pub fn block(&self, idx: usize) -> u32 {
+        read_u32(&self.raw, 0x28 + idx * 4)
+    }
+
+    pub fn set_block(&mut self, idx: usize, block: u32) {
+        write_u32(&mut self.raw, 0x28 + idx * 4, block);
+    }
+
+    pub fn file_acl(&self) -> u32 {
+        read_u32(&self.raw, 0x68)
+    }
+}
+
+pub const FT_UNKNOWN: u8 = 0;
+pub const FT_REG_FILE: u8 = 1;
+pub const FT_DIR: u8 = 2;
+pub const FT_CHRDEV: u8 = 3;
+pub const FT_BLKDEV: u8 = 4;
+pub const FT_FIFO: u8 = 5;
+pub const FT_SOCK: u8 = 6;
+pub const FT_SYMLINK: u8 = 7;
+
+/// Size of the fixed part of a directory entry.
+pub const DIRENT_HEADER_SIZE: usize = 8;
+
+/// The minimal record length of a directory entry with the given name length.
+pub const fn dirent_rec_len(name_len: usize) -> usize {
+    (DIRENT_HEADER_SIZE + name_len).next_multiple_of(4)
+}
+
+pub fn file_type_to_node_type(ft: u8) -> Option<VfsNodeType> {
+    Some(match ft {
+        FT_REG_FILE => VfsNodeType::File,
+        FT_DIR => VfsNodeType::Dir,
+        FT_CHRDEV => VfsNodeType::CharDevice,
+        FT_BLKDEV => VfsNodeType::BlockDevice,
+        FT_FIFO => VfsNodeType::Fifo,
+        FT_SOCK => VfsNodeType::Socket,
+        FT_SYMLINK => VfsNodeType::SymLink,
+        _ => return None,
+    })
+}
+
+pub fn node_type_to_file_type(ty: VfsNodeType) -> u8 {
+    match ty {
+        VfsNodeType::File => FT_REG_FILE,
+        VfsNodeType::Dir => FT_DIR,
+        VfsNodeType::CharDevice => FT_CHRDEV,
+        VfsNodeType::BlockDevice => FT_BLKDEV,
+        VfsNodeType::Fifo => FT_FIFO,
+        VfsNodeType::Socket => FT_SOCK,
+        VfsNodeType::SymLink => FT_SYMLINK,
+    }
+}
+
+/// A directory entry parsed from a directory block.
+pub struct DirEntry<'a> {
+    pub inode: u32,
+    pub rec_len: usize,
+    pub file_type: u8,
+    pub name: &'a [u8],
+}
+
+impl<'a> DirEntry<'a> {
+    /// Parses the entry at the start of `buf`, returns `None` if it is
+    /// corrupted.
+    pub fn parse(buf: &'a [u8], has_file_type: bool) -> Option<Self> {
+        if buf.len() < DIRENT_HEADER_SIZE {
+            return None;
+        }
+        let rec_len = read_u16(buf, 4) as usize;
+        let (name_len, file_type) = if has_file_type {
+            (buf[6] as usize, buf[7])
+        } else {
+            (read_u16(buf, 6) as usize, FT_UNKNOWN)
+        };
+        if rec_len < DIRENT_HEADER_SIZE || rec_len > buf.len() || rec_len % 4 != 0 {
+            return None;
+        }
+        let inode = read_u32(buf, 0);
+        let name = if inode == 0 {
+            &[][..] // unused entry, the name is meaningless
+        } else if DIRENT_HEADER_SIZE + name_len > rec_len {
+            return None;
+        } else {
+            &buf[DIRENT_HEADER_SIZE..DIRENT_HEADER_SIZE + name_len]
+        };
+        Some(Self {
+            inode,
+            rec_len,
+            file_type,
+            name,
+        })
+    }
+
+    /// The minimal record length of this entry.
+    pub fn used_len(&self) -> usize {
+        if self.inode == 0 {
+            0
+        } else {
+            dirent_rec_len(self.name.len())
+        }
+    }
+
+    /// Writes an entry at the start of `buf`.
+    pub fn write(
+        buf: &mut [u8],
+        inode: u32,
+        rec_len: usize,
+        name: &[u8],
+        file_type: u8,
+        has_file_type: bool,
+    ) {
+        write_u32(buf, 0, inode);
+        write_u16(buf, 4, rec_len as u16);
+        if has_file_type {
+            buf[6] = name.len() as u8;
+            buf[7] = file_type;
+        } else {
+            write_u16(buf, 6, name.len() as u16);
+        }
+        buf[DIRENT_HEADER_SIZE..DIRENT_HEADER_SIZE + name.len()].copy_from_slice(name);
+    }
+}
+
+/// An iterator over the entries in a directory block, with their offsets in
+/// the block.
+pub struct DirEntries<'a> {
+    buf: &'a [u8],
+    off: usize,
+    has_file_type: bool,
+}
+
+impl<'a> DirEntries<'a> {
+    pub fn new(buf: &'a [u8], has_file_type: bool) -> Self {
+        Self {
+            buf,
+            off: 0,
+            has_file_type,
+        }
+    }
+}
+
+impl<'a> Iterator for DirEntries<'a> {
+    /// The offset and the entry, or `None` if the entry is corrupted.
+    type Item = (usize, Option<DirEntry<'a>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.off >= self.buf.len() {
+            return None;
+        }
+        let off = self.off;
+        match DirEntry::parse(&self.buf[off..], self.has_file_type) {
+            Some(entry) => {
+                self.off += entry.rec_len;
+                Some((off, Some(entry)))
+            }
+            None => {
+                self.off = self.buf.len(); // stop at the corrupted entry
+                Some((off, None))
+            }
+        }
+    }
+}
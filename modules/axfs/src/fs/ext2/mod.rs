@@ -0,0 +1,173 @@
+//! A driver of the [ext2] filesystem, with read-only support of [ext4].
+//!
+//! Only the features of ext2 are supported for writing. Filesystems with ext3
+//! or ext4 features are mounted read-only, and extents are only understood
+//! if the `ext4` feature is enabled.
+//!
+//! [ext2]: https://en.wikipedia.org/wiki/Ext2
+//! [ext4]: https://en.wikipedia.org/wiki/Ext4
+
+mod layout;
+mod volume;
+
+#[cfg(feature = "ext4")]
+mod extent;
+
+use alloc::sync::Arc;
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult};
+use axsync::Mutex;
+use core::any::Any;
+
+use self::layout::{Inode, ROOT_INO};
+use self::volume::Volume;
+use super::LinkOps;
+use crate::dev::Disk;
+
+pub struct Ext2FileSystem {
+    vol: Arc<Mutex<Volume>>,
+}
+
+struct DirNode {
+    vol: Arc<Mutex<Volume>>,
+    ino: u32,
+}
+
+struct FileNode {
+    vol: Arc<Mutex<Volume>>,
+    ino: u32,
+}
+
+impl Ext2FileSystem {
+    pub fn new(disk: Disk) -> VfsResult<Self> {
+        Ok(Self {
+            vol: Arc::new(Mutex::new(Volume::new(disk)?)),
+        })
+    }
+}
+
+impl VfsOps for Ext2FileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        new_node(&self.vol, ROOT_INO, VfsNodeType::Dir)
+    }
+}
+
+impl LinkOps for Ext2FileSystem {
+    fn link(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("link at ext2: {} -> {}", dst_path, src_path);
+        self.vol.lock().link(ROOT_INO, src_path, dst_path)
+    }
+}
+
+/// Returns the inode number and the number of hard links of `node`, if it's
+/// a node of ext2.
+pub(crate) fn node_ids(node: &dyn Any) -> Option<(u64, u64)> {
+    let (vol, ino) = match node.downcast_ref::<DirNode>() {
+        Some(dir) => (&dir.vol, dir.ino),
+        None => {
+            let file = node.downcast_ref::<FileNode>()?;
+            (&file.vol, file.ino)
+        }
+    };
+    let inode = vol.lock().read_inode(ino).ok()?;
+    Some((ino as u64, inode.links_count() as u64))
+}
+
+fn new_node(vol: &Arc<Mutex<Volume>>, ino: u32, ty: VfsNodeType) -> VfsNodeRef {
+    let vol = vol.clone();
+    if ty == VfsNodeType::Dir {
+        Arc::new(DirNode { vol, ino })
+    } else {
+        Arc::new(FileNode { vol, ino })
+    }
+}
+
+fn node_attr(vol: &Volume, inode: &Inode) -> VfsNodeAttr {
+    let mut perm = inode.mode() & 0o777;
+    if vol.is_read_only() {
+        perm &= !0o222;
+    }
+    VfsNodeAttr::new(
+        VfsNodePerm::from_bits_truncate(perm),
+        inode.node_type(),
+        inode.size(),
+        inode.sectors(),
+    )
+}
+
+fn get_attr(vol: &Mutex<Volume>, ino: u32) -> VfsResult<VfsNodeAttr> {
+    let mut vol = vol.lock();
+    let inode = vol.read_inode(ino)?;
+    Ok(node_attr(&vol, &inode))
+}
+
+impl VfsNodeOps for DirNode {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        get_attr(&self.vol, self.ino)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        if self.ino == ROOT_INO {
+            return None;
+        }
+        let ino = self.vol.lock().resolve(self.ino, "..").ok()?;
+        Some(new_node(&self.vol, ino, VfsNodeType::Dir))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at ext2: {}", path);
+        let mut vol = self.vol.lock();
+        let ino = vol.resolve(self.ino, path)?;
+        if ino == self.ino {
+            drop(vol);
+            return Ok(self);
+        }
+        let ty = vol.read_inode(ino)?.node_type();
+        Ok(new_node(&self.vol, ino, ty))
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at ext2: {}", ty, path);
+        self.vol.lock().create(self.ino, path, ty)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at ext2: {}", path);
+        self.vol.lock().remove(self.ino, path)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        self.vol.lock().read_dir(self.ino, start_idx, dirents)
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at ext2: {} -> {}", src_path, dst_path);
+        self.vol.lock().rename(self.ino, src_path, dst_path)
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        get_attr(&self.vol, self.ino)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.vol.lock().read_at(self.ino, offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.vol.lock().write_at(self.ino, offset, buf)
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.vol.lock().truncate(self.ino, size)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.vol.lock().sync()
+    }
+}
@@ -0,0 +1,1024 @@
+//! The state of a mounted ext2 filesystem, and the operations on its inodes.
+
+use alloc::{vec, vec::Vec};
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeType, VfsResult};
+
+use super::layout::*;
+use crate::dev::Disk;
+
+/// Permissions of the files and directories created by us.
+const FILE_PERM: u16 = 0o644;
+const DIR_PERM: u16 = 0o755;
+const LINK_PERM: u16 = 0o777;
+/// Maximum number of hard links to an inode.
+const MAX_LINKS: u16 = 32000;
+/// The longest name of a directory entry.
+const MAX_NAME_LEN: usize = 255;
+
+pub struct Volume {
+    disk: Disk,
+    sb: SuperBlock,
+    groups: Vec<GroupDesc>,
+    block_size: usize,
+    read_only: bool,
+}
+
+impl Volume {
+    pub fn new(disk: Disk) -> VfsResult<Self> {
+        let mut vol = Self {
+            disk,
+            sb: SuperBlock {
+                raw: [0; SUPERBLOCK_SIZE],
+            },
+            groups: Vec::new(),
+            block_size: 0,
+            read_only: false,
+        };
+        let mut raw = [0; SUPERBLOCK_SIZE];
+        vol.read_bytes(SUPERBLOCK_OFFSET, &mut raw)?;
+        vol.sb.raw = raw;
+
+        let sb = &vol.sb;
+        if sb.magic() != EXT2_MAGIC {
+            warn!("ext2: bad magic number {:#x}", sb.magic());
+            return Err(VfsError::InvalidData);
+        }
+        if sb.log_block_size() > 6 {
+            warn!("ext2: bad block size 2^{}", sb.log_block_size() + 10);
+            return Err(VfsError::InvalidData);
+        }
+        let readable_incompat = if cfg!(feature = "ext4") {
+            INCOMPAT_FILETYPE
+                | INCOMPAT_RECOVER
+                | INCOMPAT_EXTENTS
+                | INCOMPAT_64BIT
+                | INCOMPAT_FLEX_BG
+        } else {
+            INCOMPAT_FILETYPE | INCOMPAT_RECOVER
+        };
+        let unknown = sb.feature_incompat() & !readable_incompat;
+        if unknown != 0 {
+            warn!("ext2: unsupported incompatible features {:#x}", unknown);
+            return Err(VfsError::Unsupported);
+        }
+        // Only the plain ext2 features are supported for writing. The journal
+        // of ext3 would not record our changes.
+        vol.read_only = sb.feature_compat() & COMPAT_HAS_JOURNAL != 0
+            || sb.feature_incompat() & !INCOMPAT_FILETYPE != 0
+            || sb.feature_ro_compat() & !(RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE) != 0;
+        if sb.has_incompat(INCOMPAT_RECOVER) {
+            warn!("ext2: the journal needs recovery, the data may be inconsistent");
+        }
+        if vol.read_only {
+            info!("ext2: mounted read-only for unsupported features");
+        }
+
+        vol.block_size = 1024 << sb.log_block_size();
+        let first_data_block = sb.first_data_block() as u64;
+        let num_groups =
+            (sb.blocks_count() - first_data_block).div_ceil(sb.blocks_per_group() as u64);
+        let desc_size = sb.desc_size();
+        let mut descs = vec![0; num_groups as usize * desc_size];
+        vol.read_bytes(vol.gdt_pos(), &mut descs)?;
+        vol.groups = descs.chunks(desc_size).map(GroupDesc::new).collect();
+        Ok(vol)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Writes the cached blocks back to the disk.
+    pub fn sync(&mut self) -> VfsResult {
+        self.disk.sync().map_err(|_| VfsError::Io)
+    }
+
+    fn check_writable(&self) -> VfsResult {
+        if self.read_only {
+            Err(VfsError::PermissionDenied)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn has_file_type(&self) -> bool {
+        self.sb.has_incompat(INCOMPAT_FILETYPE)
+    }
+
+    fn sectors_per_block(&self) -> u64 {
+        self.block_size as u64 / 512
+    }
+
+    fn ptrs_per_block(&self) -> u64 {
+        self.block_size as u64 / 4
+    }
+
+    fn new_block_buf(&self) -> Vec<u8> {
+        vec![0; self.block_size]
+    }
+
+    fn read_bytes(&mut self, pos: u64, mut buf: &mut [u8]) -> VfsResult {
+        self.disk.set_position(pos);
+        while !buf.is_empty() {
+            match self.disk.read_one(buf) {
+                Ok(0) => return Err(VfsError::UnexpectedEof),
+                Ok(n) => {
+                    let tmp = buf;
+                    buf = &mut tmp[n..];
+                }
+                Err(_) => return Err(VfsError::Io),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, pos: u64, mut buf: &[u8]) -> VfsResult {
+        self.disk.set_position(pos);
+        while !buf.is_empty() {
+            match self.disk.write_one(buf) {
+                Ok(0) => return Err(VfsError::WriteZero),
+                Ok(n) => buf = &buf[n..],
+                Err(_) => return Err(VfsError::Io),
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn read_block(&mut self, block: u64, buf: &mut [u8]) -> VfsResult {
+        let pos = block * self.block_size as u64;
+        self.read_bytes(pos, &mut buf[..self.block_size])
+    }
+
+    fn write_block(&mut self, block: u64, buf: &[u8]) -> VfsResult {
+        let pos = block * self.block_size as u64;
+        self.write_bytes(pos, &buf[..self.block_size])
+    }
+
+    /// Position of the group descriptor table, which follows the superblock.
+    fn gdt_pos(&self) -> u64 {
+        (self.sb.first_data_block() as u64 + 1) * self.block_size as u64
+    }
+
+    fn write_super(&mut self) -> VfsResult {
+        let raw = self.sb.raw;
+        self.write_bytes(SUPERBLOCK_OFFSET, &raw)
+    }
+
+    fn write_group_desc(&mut self, group: usize) -> VfsResult {
+        let desc_size = self.sb.desc_size();
+        let pos = self.gdt_pos() + (group * desc_size) as u64;
+        let raw = self.groups[group].raw;
+        self.write_bytes(pos, &raw[..desc_size])
+    }
+
+    fn group_of_inode(&self, ino: u32) -> usize {
+        ((ino - 1) / self.sb.inodes_per_group()) as usize
+    }
+
+    fn inode_pos(&self, ino: u32) -> VfsResult<u64> {
+        if ino == 0 || ino > self.sb.inodes_count() {
+            warn!("ext2: bad inode number {}", ino);
+            return Err(VfsError::InvalidData);
+        }
+        let index = (ino - 1) % self.sb.inodes_per_group();
+        let table = self.groups[self.group_of_inode(ino)].inode_table();
+        Ok(table * self.block_size as u64 + index as u64 * self.sb.inode_size() as u64)
+    }
+
+    pub fn read_inode(&mut self, ino: u32) -> VfsResult<Inode> {
+        let mut inode = Inode::empty();
+        self.read_bytes(self.inode_pos(ino)?, &mut inode.raw)?;
+        Ok(inode)
+    }
+
+    fn write_inode(&mut self, ino: u32, inode: &Inode) -> VfsResult {
+        self.write_bytes(self.inode_pos(ino)?, &inode.raw)
+    }
+
+    /// Allocates a zeroed block, preferably in the given group.
+    fn alloc_block(&mut self, goal_group: usize) -> VfsResult<u32> {
+        let first_data_block = self.sb.first_data_block() as u64;
+        let blocks_per_group = self.sb.blocks_per_group() as u64;
+        let mut bitmap = self.new_block_buf();
+        for i in 0..self.groups.len() {
+            let group = (goal_group + i) % self.groups.len();
+            if self.groups[group].free_blocks_count() == 0 {
+                continue;
+            }
+            let first = first_data_block + group as u64 * blocks_per_group;
+            let count = (self.sb.blocks_count() - first).min(blocks_per_group) as usize;
+            self.read_block(self.groups[group].block_bitmap(), &mut bitmap)?;
+            let Some(bit) = find_zero_bit(&bitmap, 0, count) else {
+                continue;
+            };
+            set_bit(&mut bitmap, bit, true);
+            self.write_block(self.groups[group].block_bitmap(), &bitmap)?;
+
+            let desc = &mut self.groups[group];
+            desc.set_free_blocks_count(desc.free_blocks_count() - 1);
+            self.sb
+                .set_free_blocks_count(self.sb.free_blocks_count() - 1);
+            self.write_group_desc(group)?;
+            self.write_super()?;
+
+            let block = first + bit as u64;
+            bitmap.fill(0);
+            self.write_block(block, &bitmap)?;
+            return Ok(block as u32);
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    fn free_block(&mut self, block: u32) -> VfsResult {
+        if block < self.sb.first_data_block() || block as u64 >= self.sb.blocks_count() {
+            warn!("ext2: freeing an invalid block {}", block);
+            return Err(VfsError::InvalidData);
+        }
+        let rel = (block - self.sb.first_data_block()) as u64;
+        let blocks_per_group = self.sb.blocks_per_group() as u64;
+        let group = (rel / blocks_per_group) as usize;
+        let mut bitmap = self.new_block_buf();
+        self.read_block(self.groups[group].block_bitmap(), &mut bitmap)?;
+        set_bit(&mut bitmap, (rel % blocks_per_group) as usize, false);
+        self.write_block(self.groups[group].block_bitmap(), &bitmap)?;
+
+        let desc = &mut self.groups[group];
+        desc.set_free_blocks_count(desc.free_blocks_count() + 1);
+        self.sb
+            .set_free_blocks_count(self.sb.free_blocks_count() + 1);
+        self.write_group_desc(group)?;
+        self.write_super()
+    }
+
+    /// Allocates a zeroed inode, preferably in the given group.
+    fn alloc_inode(&mut self, goal_group: usize, is_dir: bool) -> VfsResult<u32> {
+        let inodes_per_group = self.sb.inodes_per_group();
+        let mut bitmap = self.new_block_buf();
+        for i in 0..self.groups.len() {
+            let group = (goal_group + i) % self.groups.len();
+            if self.groups[group].free_inodes_count() == 0 {
+                continue;
+            }
+            // skip the reserved inodes
+            let start = if group == 0 {
+                self.sb.first_ino() as usize - 1
+            } else {
+                0
+            };
+            self.read_block(self.groups[group].inode_bitmap(), &mut bitmap)?;
+            let Some(bit) = find_zero_bit(&bitmap, start, inodes_per_group as usize) else {
+                continue;
+            };
+            set_bit(&mut bitmap, bit, true);
+            self.write_block(self.groups[group].inode_bitmap(), &bitmap)?;
+
+            let desc = &mut self.groups[group];
+            desc.set_free_inodes_count(desc.free_inodes_count() - 1);
+            if is_dir {
+                desc.set_used_dirs_count(desc.used_dirs_count() + 1);
+            }
+            self.sb
+                .set_free_inodes_count(self.sb.free_inodes_count() - 1);
+            self.write_group_desc(group)?;
+            self.write_super()?;
+
+            let ino = group as u32 * inodes_per_group + bit as u32 + 1;
+            let zeros = vec![0; self.sb.inode_size()];
+            self.write_bytes(self.inode_pos(ino)?, &zeros)?;
+            return Ok(ino);
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    fn free_inode(&mut self, ino: u32, is_dir: bool) -> VfsResult {
+        let group = self.group_of_inode(ino);
+        let bit = ((ino - 1) % self.sb.inodes_per_group()) as usize;
+        let mut bitmap = self.new_block_buf();
+        self.read_block(self.groups[group].inode_bitmap(), &mut bitmap)?;
+        set_bit(&mut bitmap, bit, false);
+        self.write_block(self.groups[group].inode_bitmap(), &bitmap)?;
+
+        let desc = &mut self.groups[group];
+        desc.set_free_inodes_count(desc.free_inodes_count() + 1);
+        if is_dir {
+            desc.set_used_dirs_count(desc.used_dirs_count() - 1);
+        }
+        self.sb
+            .set_free_inodes_count(self.sb.free_inodes_count() + 1);
+        self.write_group_desc(group)?;
+        self.write_super()
+    }
+
+    fn add_sectors(&self, inode: &mut Inode, blocks: i64) {
+        let sectors = inode.sectors() as i64 + blocks * self.sectors_per_block() as i64;
+        inode.set_sectors(sectors as u64);
+    }
+
+    /// Returns the block that the logical block `lbn` of the inode is mapped
+    /// to, or `None` if it is a hole.
+    ///
+    /// If `create` is true, the holes (including the indirect blocks) are
+    /// filled with zeroed blocks, and the caller should write the inode back.
+    fn map_block(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        lbn: u64,
+        create: bool,
+    ) -> VfsResult<Option<u64>> {
+        if inode.flags() & EXTENTS_FL != 0 {
+            #[cfg(feature = "ext4")]
+            if !create {
+                return self.map_extent(inode, lbn);
+            }
+            return Err(VfsError::Unsupported);
+        }
+
+        // The slot in `i_block`, and the indices in each level of the
+        // indirect blocks.
+        let ptrs = self.ptrs_per_block();
+        let mut path = [0; 3];
+        let (slot, depth) = if lbn < N_DIRECT as u64 {
+            (lbn as usize, 0)
+        } else if lbn - (N_DIRECT as u64) < ptrs {
+            path[0] = lbn - N_DIRECT as u64;
+            (N_DIRECT, 1)
+        } else if lbn - (N_DIRECT as u64) - ptrs < ptrs * ptrs {
+            let rel = lbn - N_DIRECT as u64 - ptrs;
+            path[..2].copy_from_slice(&[rel / ptrs, rel % ptrs]);
+            (N_DIRECT + 1, 2)
+        } else if lbn - (N_DIRECT as u64) - ptrs - ptrs * ptrs < ptrs * ptrs * ptrs {
+            let rel = lbn - N_DIRECT as u64 - ptrs - ptrs * ptrs;
+            path = [rel / (ptrs * ptrs), rel / ptrs % ptrs, rel % ptrs];
+            (N_DIRECT + 2, 3)
+        } else {
+            return Err(VfsError::InvalidInput); // too large
+        };
+
+        let goal_group = self.group_of_inode(ino);
+        let mut block = inode.block(slot) as u64;
+        if block == 0 {
+            if !create {
+                return Ok(None);
+            }
+            block = self.alloc_block(goal_group)? as u64;
+            inode.set_block(slot, block as u32);
+            self.add_sectors(inode, 1);
+        }
+        let mut buf = self.new_block_buf();
+        for &idx in &path[..depth] {
+            let idx = idx as usize * 4;
+            self.read_block(block, &mut buf)?;
+            let mut next = read_u32(&buf, idx) as u64;
+            if next == 0 {
+                if !create {
+                    return Ok(None);
+                }
+                next = self.alloc_block(goal_group)? as u64;
+                write_u32(&mut buf, idx, next as u32);
+                self.write_block(block, &buf)?;
+                self.add_sectors(inode, 1);
+            }
+            block = next;
+        }
+        Ok(Some(block))
+    }
+
+    /// Frees the data blocks of the inode from the logical block `keep`, and
+    /// the indirect blocks that become empty.
+    fn free_blocks_from(&mut self, inode: &mut Inode, keep: u64) -> VfsResult {
+        for slot in (keep.min(N_DIRECT as u64) as usize)..N_DIRECT {
+            let block = inode.block(slot);
+            if block != 0 {
+                self.free_block(block)?;
+                inode.set_block(slot, 0);
+                self.add_sectors(inode, -1);
+            }
+        }
+
+        let ptrs = self.ptrs_per_block();
+        let mut base = N_DIRECT as u64; // the first logical block in the slot
+        let mut span = ptrs; // number of logical blocks in the slot
+        for (slot, level) in [(N_DIRECT, 1), (N_DIRECT + 1, 2), (N_DIRECT + 2, 3)] {
+            let block = inode.block(slot);
+            if block != 0 && keep < base + span {
+                let start = keep.saturating_sub(base);
+                if self.free_tree(inode, block, level, start)? {
+                    inode.set_block(slot, 0);
+                }
+            }
+            base += span;
+            span *= ptrs;
+        }
+        Ok(())
+    }
+
+    /// Frees the blocks from the logical block `start` in the tree of the
+    /// indirect `block` at `level`, returns whether the whole tree is freed.
+    fn free_tree(
+        &mut self,
+        inode: &mut Inode,
+        block: u32,
+        level: u32,
+        start: u64,
+    ) -> VfsResult<bool> {
+        let ptrs = self.ptrs_per_block();
+        let span = ptrs.pow(level - 1); // number of logical blocks per entry
+        let first = (start / span) as usize;
+        let mut buf = self.new_block_buf();
+        self.read_block(block as u64, &mut buf)?;
+
+        let mut changed = false;
+        for i in first..ptrs as usize {
+            let child = read_u32(&buf, i * 4);
+            if child == 0 {
+                continue;
+            }
+            let freed = if level == 1 {
+                self.free_block(child)?;
+                self.add_sectors(inode, -1);
+                true
+            } else {
+                let sub_start = if i == first { start % span } else { 0 };
+                self.free_tree(inode, child, level - 1, sub_start)?
+            };
+            if freed {
+                write_u32(&mut buf, i * 4, 0);
+                changed = true;
+            }
+        }
+
+        if start == 0 {
+            self.free_block(block)?;
+            self.add_sectors(inode, -1);
+            Ok(true)
+        } else {
+            if changed {
+                self.write_block(block as u64, &buf)?;
+            }
+            Ok(false)
+        }
+    }
+
+    /// Whether the inode is a symbolic link with the target stored in the
+    /// inode itself.
+    fn is_fast_symlink(&self, inode: &Inode) -> bool {
+        let acl_sectors = if inode.file_acl() != 0 {
+            self.sectors_per_block()
+        } else {
+            0
+        };
+        inode.node_type() == VfsNodeType::SymLink
+            && inode.flags() & EXTENTS_FL == 0
+            && inode.sectors() == acl_sectors
+    }
+
+    pub fn read_at(&mut self, ino: u32, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut inode = self.read_inode(ino)?;
+        let size = inode.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = (size - offset).min(buf.len() as u64) as usize;
+        if self.is_fast_symlink(&inode) {
+            let start = offset as usize;
+            buf[..len].copy_from_slice(&inode.block_area()[start..start + len]);
+            return Ok(len);
+        }
+
+        let bs = self.block_size as u64;
+        let mut block_buf = self.new_block_buf();
+        let mut read = 0;
+        while read < len {
+            let pos = offset + read as u64;
+            let block_off = (pos % bs) as usize;
+            let n = (self.block_size - block_off).min(len - read);
+            let dst = &mut buf[read..read + n];
+            match self.map_block(ino, &mut inode, pos / bs, false)? {
+                Some(block) => {
+                    self.read_block(block, &mut block_buf)?;
+                    dst.copy_from_slice(&block_buf[block_off..block_off + n]);
+                }
+                None => dst.fill(0), // a hole
+            }
+            read += n;
+        }
+        Ok(len)
+    }
+
+    pub fn write_at(&mut self, ino: u32, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.check_writable()?;
+        let mut inode = self.read_inode(ino)?;
+        if self.is_fast_symlink(&inode) {
+            // A short target is kept in the inode, as long as there is room
+            // for the terminating zero.
+            let end = offset + buf.len() as u64;
+            if end < inode.block_area().len() as u64 {
+                inode.block_area_mut()[offset as usize..end as usize].copy_from_slice(buf);
+                inode.set_size(inode.size().max(end));
+                self.write_inode(ino, &inode)?;
+                return Ok(buf.len());
+            } else if inode.size() != 0 {
+                return Err(VfsError::Unsupported);
+            }
+        }
+        let bs = self.block_size as u64;
+        let mut block_buf = self.new_block_buf();
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < buf.len() {
+            let pos = offset + written as u64;
+            let block_off = (pos % bs) as usize;
+            let n = (self.block_size - block_off).min(buf.len() - written);
+            let block = match self.map_block(ino, &mut inode, pos / bs, true) {
+                Ok(block) => block.unwrap(),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            if n < self.block_size {
+                self.read_block(block, &mut block_buf)?;
+            }
+            block_buf[block_off..block_off + n].copy_from_slice(&buf[written..written + n]);
+            self.write_block(block, &block_buf)?;
+            written += n;
+        }
+
+        let end = offset + written as u64;
+        if end > inode.size() {
+            inode.set_size(end);
+        }
+        // write back the inode even on errors, for the allocated blocks
+        self.write_inode(ino, &inode)?;
+        match result {
+            Err(e) if written == 0 => Err(e),
+            _ => Ok(written),
+        }
+    }
+
+    pub fn truncate(&mut self, ino: u32, size: u64) -> VfsResult {
+        self.check_writable()?;
+        let mut inode = self.read_inode(ino)?;
+        if inode.flags() & EXTENTS_FL != 0 || inode.node_type() == VfsNodeType::SymLink {
+            return Err(VfsError::Unsupported);
+        }
+        if size < inode.size() {
+            let bs = self.block_size as u64;
+            self.free_blocks_from(&mut inode, size.div_ceil(bs))?;
+            // Zero the rest of the last block, in case the file is extended
+            // later.
+            let block_off = (size % bs) as usize;
+            if block_off != 0 {
+                if let Some(block) = self.map_block(ino, &mut inode, size / bs, false)? {
+                    let mut buf = self.new_block_buf();
+                    self.read_block(block, &mut buf)?;
+                    buf[block_off..].fill(0);
+                    self.write_block(block, &buf)?;
+                }
+            }
+        }
+        inode.set_size(size);
+        self.write_inode(ino, &inode)
+    }
+
+    /// Reads the logical block `lbn` of the directory, returns the block
+    /// number, or `None` if it is a hole.
+    fn read_dir_block(
+        &mut self,
+        dir_ino: u32,
+        dir: &mut Inode,
+        lbn: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<Option<u64>> {
+        let block = self.map_block(dir_ino, dir, lbn, false)?;
+        if let Some(block) = block {
+            self.read_block(block, buf)?;
+        }
+        Ok(block)
+    }
+
+    fn num_blocks(&self, inode: &Inode) -> u64 {
+        inode.size().div_ceil(self.block_size as u64)
+    }
+
+    /// Finds the entry named `name` in the directory, returns its inode number
+    /// and file type.
+    pub fn lookup_entry(&mut self, dir_ino: u32, name: &str) -> VfsResult<Option<(u32, u8)>> {
+        let mut dir = self.read_inode(dir_ino)?;
+        if !dir.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        let mut buf = self.new_block_buf();
+        for lbn in 0..self.num_blocks(&dir) {
+            if self
+                .read_dir_block(dir_ino, &mut dir, lbn, &mut buf)?
+                .is_none()
+            {
+                continue;
+            }
+            for (_, entry) in DirEntries::new(&buf, self.has_file_type()) {
+                let entry = entry.ok_or(VfsError::InvalidData)?;
+                if entry.inode != 0 && entry.name == name.as_bytes() {
+                    return Ok(Some((entry.inode, entry.file_type)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the entries of the directory from the `start_idx`-th one.
+    pub fn read_dir(
+        &mut self,
+        dir_ino: u32,
+        start_idx: usize,
+        dirents: &mut [VfsDirEntry],
+    ) -> VfsResult<usize> {
+        let mut dir = self.read_inode(dir_ino)?;
+        let mut buf = self.new_block_buf();
+        let mut idx = 0;
+        let mut count = 0;
+        for lbn in 0..self.num_blocks(&dir) {
+            if count == dirents.len() {
+                break;
+            }
+            if self
+                .read_dir_block(dir_ino, &mut dir, lbn, &mut buf)?
+                .is_none()
+            {
+                continue;
+            }
+            for (_, entry) in DirEntries::new(&buf, self.has_file_type()) {
+                let entry = entry.ok_or(VfsError::InvalidData)?;
+                if entry.inode == 0 {
+                    continue;
+                }
+                if idx >= start_idx && count < dirents.len() {
+                    let Ok(name) = core::str::from_utf8(entry.name) else {
+                        warn!("ext2: skip a non-UTF-8 name in inode {}", dir_ino);
+                        continue;
+                    };
+                    let ty = match file_type_to_node_type(entry.file_type) {
+                        Some(ty) => ty,
+                        None => self.read_inode(entry.inode)?.node_type(),
+                    };
+                    dirents[count] = VfsDirEntry::new(name, ty);
+                    count += 1;
+                }
+                idx += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn is_dir_empty(&mut self, dir_ino: u32) -> VfsResult<bool> {
+        let mut dir = self.read_inode(dir_ino)?;
+        let mut buf = self.new_block_buf();
+        for lbn in 0..self.num_blocks(&dir) {
+            if self
+                .read_dir_block(dir_ino, &mut dir, lbn, &mut buf)?
+                .is_none()
+            {
+                continue;
+            }
+            for (_, entry) in DirEntries::new(&buf, self.has_file_type()) {
+                let entry = entry.ok_or(VfsError::InvalidData)?;
+                if entry.inode != 0 && entry.name != b"." && entry.name != b".." {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Writes the inode of a directory back after its entries are changed.
+    fn dir_entries_changed(&mut self, dir_ino: u32, dir: &mut Inode) -> VfsResult {
+        // The hashed index is not maintained, so stop using it.
+        dir.set_flags(dir.flags() & !INDEX_FL);
+        self.write_inode(dir_ino, dir)
+    }
+
+    fn add_entry(&mut self, dir_ino: u32, name: &str, ino: u32, file_type: u8) -> VfsResult {
+        if name.len() > MAX_NAME_LEN {
+            return Err(VfsError::InvalidInput);
+        }
+        let has_file_type = self.has_file_type();
+        let need = dirent_rec_len(name.len());
+        let mut dir = self.read_inode(dir_ino)?;
+        let mut buf = self.new_block_buf();
+        let num_blocks = self.num_blocks(&dir);
+        for lbn in 0..num_blocks {
+            let Some(block) = self.read_dir_block(dir_ino, &mut dir, lbn, &mut buf)? else {
+                continue;
+            };
+            let mut slot = None;
+            for (off, entry) in DirEntries::new(&buf, has_file_type) {
+                let entry = entry.ok_or(VfsError::InvalidData)?;
+                if entry.rec_len - entry.used_len() >= need {
+                    slot = Some((off, entry.rec_len, entry.used_len()));
+                    break;
+                }
+            }
+            if let Some((off, rec_len, used)) = slot {
+                // Split the unused space at the end of the entry.
+                if used != 0 {
+                    write_u16(&mut buf, off + 4, used as u16);
+                }
+                let new_entry = &mut buf[off + used..];
+                DirEntry::write(
+                    new_entry,
+                    ino,
+                    rec_len - used,
+                    name.as_bytes(),
+                    file_type,
+                    has_file_type,
+                );
+                self.write_block(block, &buf)?;
+                return self.dir_entries_changed(dir_ino, &mut dir);
+            }
+        }
+
+        // No space in the existing blocks, append a new one.
+        let block = self
+            .map_block(dir_ino, &mut dir, num_blocks, true)?
+            .unwrap();
+        buf.fill(0);
+        let rec_len = self.block_size;
+        DirEntry::write(
+            &mut buf,
+            ino,
+            rec_len,
+            name.as_bytes(),
+            file_type,
+            has_file_type,
+        );
+        self.write_block(block, &buf)?;
+        dir.set_size((num_blocks + 1) * self.block_size as u64);
+        self.dir_entries_changed(dir_ino, &mut dir)
+    }
+
+    /// Removes the entry named `name` from the directory, returns its inode
+    /// number.
+    fn remove_entry(&mut self, dir_ino: u32, name: &str) -> VfsResult<u32> {
+        let mut dir = self.read_inode(dir_ino)?;
+        let mut buf = self.new_block_buf();
+        for lbn in 0..self.num_blocks(&dir) {
+            let Some(block) = self.read_dir_block(dir_ino, &mut dir, lbn, &mut buf)? else {
+                continue;
+            };
+            let mut prev = None;
+            let mut found = None;
+            for (off, entry) in DirEntries::new(&buf, self.has_file_type()) {
+                let entry = entry.ok_or(VfsError::InvalidData)?;
+                if entry.inode != 0 && entry.name == name.as_bytes() {
+                    found = Some((off, entry.rec_len, entry.inode));
+                    break;
+                }
+                prev = Some((off, entry.rec_len));
+            }
+            let Some((off, rec_len, ino)) = found else {
+                continue;
+            };
+            match prev {
+                // merge into the previous entry
+                Some((prev_off, prev_len)) => {
+                    write_u16(&mut buf, prev_off + 4, (prev_len + rec_len) as u16)
+                }
+                // the first entry in the block, just mark it unused
+                None => write_u32(&mut buf, off, 0),
+            }
+            self.write_block(block, &buf)?;
+            self.dir_entries_changed(dir_ino, &mut dir)?;
+            return Ok(ino);
+        }
+        Err(VfsError::NotFound)
+    }
+
+    /// Points the entry named `name` in the directory to another inode.
+    fn set_entry(&mut self, dir_ino: u32, name: &str, ino: u32) -> VfsResult {
+        let mut dir = self.read_inode(dir_ino)?;
+        let mut buf = self.new_block_buf();
+        for lbn in 0..self.num_blocks(&dir) {
+            let Some(block) = self.read_dir_block(dir_ino, &mut dir, lbn, &mut buf)? else {
+                continue;
+            };
+            let found = DirEntries::new(&buf, self.has_file_type()).find_map(|(off, entry)| {
+                entry
+                    .filter(|e| e.inode != 0 && e.name == name.as_bytes())
+                    .map(|_| off)
+            });
+            if let Some(off) = found {
+                write_u32(&mut buf, off, ino);
+                return self.write_block(block, &buf);
+            }
+        }
+        Err(VfsError::NotFound)
+    }
+
+    fn add_links(&mut self, ino: u32, delta: i32) -> VfsResult<Inode> {
+        let mut inode = self.read_inode(ino)?;
+        inode.set_links_count((inode.links_count() as i32 + delta) as u16);
+        self.write_inode(ino, &inode)?;
+        Ok(inode)
+    }
+
+    /// Resolves `path` relative to the directory `dir_ino`, returns the inode
+    /// number.
+    pub fn resolve(&mut self, dir_ino: u32, path: &str) -> VfsResult<u32> {
+        let mut ino = dir_ino;
+        for name in path.split('/') {
+            if name.is_empty() || name == "." {
+                continue;
+            }
+            ino = match self.lookup_entry(ino, name)? {
+                Some((ino, _)) => ino,
+                None => return Err(VfsError::NotFound),
+            };
+        }
+        Ok(ino)
+    }
+
+    /// Resolves the parent directory of `path` relative to the directory
+    /// `dir_ino`, returns its inode number and the last component of `path`.
+    fn resolve_parent<'a>(&mut self, dir_ino: u32, path: &'a str) -> VfsResult<(u32, &'a str)> {
+        let path = path.trim_matches('/');
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let parent = self.resolve(dir_ino, parent)?;
+        if !self.read_inode(parent)?.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        Ok((parent, name))
+    }
+
+    pub fn create(&mut self, dir_ino: u32, path: &str, ty: VfsNodeType) -> VfsResult {
+        let (parent, name) = self.resolve_parent(dir_ino, path)?;
+        if self.lookup_entry(parent, name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        self.check_writable()?;
+
+        let mode = match ty {
+            VfsNodeType::File => S_IFREG | FILE_PERM,
+            VfsNodeType::SymLink => S_IFLNK | LINK_PERM,
+            VfsNodeType::Dir => S_IFDIR | DIR_PERM,
+            _ => return Err(VfsError::Unsupported),
+        };
+        let is_dir = ty == VfsNodeType::Dir;
+        let ino = self.alloc_inode(self.group_of_inode(parent), is_dir)?;
+        let mut inode = Inode::empty();
+        inode.set_mode(mode);
+        if is_dir {
+            inode.set_links_count(2);
+            if let Err(e) = self.init_dir(ino, &mut inode, parent) {
+                self.free_inode(ino, true)?;
+                return Err(e);
+            }
+        } else {
+            inode.set_links_count(1);
+            self.write_inode(ino, &inode)?;
+        }
+
+        if let Err(e) = self.add_entry(parent, name, ino, node_type_to_file_type(ty)) {
+            self.free_blocks_from(&mut inode, 0)?;
+            self.free_inode(ino, is_dir)?;
+            return Err(e);
+        }
+        if is_dir {
+            self.add_links(parent, 1)?; // the ".." entry
+        }
+        Ok(())
+    }
+
+    /// Writes the `.` and `..` entries of a new directory.
+    fn init_dir(&mut self, ino: u32, inode: &mut Inode, parent: u32) -> VfsResult {
+        let block = self.map_block(ino, inode, 0, true)?.unwrap();
+        let has_file_type = self.has_file_type();
+        let mut buf = self.new_block_buf();
+        let dot_len = dirent_rec_len(1);
+        DirEntry::write(&mut buf, ino, dot_len, b".", FT_DIR, has_file_type);
+        let rest = self.block_size - dot_len;
+        DirEntry::write(
+            &mut buf[dot_len..],
+            parent,
+            rest,
+            b"..",
+            FT_DIR,
+            has_file_type,
+        );
+        self.write_block(block, &buf)?;
+        inode.set_size(self.block_size as u64);
+        self.write_inode(ino, inode)
+    }
+
+    pub fn remove(&mut self, dir_ino: u32, path: &str) -> VfsResult {
+        let (parent, name) = self.resolve_parent(dir_ino, path)?;
+        let Some((ino, _)) = self.lookup_entry(parent, name)? else {
+            return Err(VfsError::NotFound);
+        };
+        self.check_writable()?;
+
+        let mut inode = self.read_inode(ino)?;
+        if inode.is_dir() {
+            if !self.is_dir_empty(ino)? {
+                return Err(VfsError::DirectoryNotEmpty);
+            }
+            self.remove_entry(parent, name)?;
+            self.add_links(parent, -1)?; // the ".." entry
+            inode.set_links_count(0);
+        } else {
+            self.remove_entry(parent, name)?;
+            inode.set_links_count(inode.links_count().saturating_sub(1));
+        }
+
+        if inode.links_count() == 0 {
+            // There is no orphan list, so the inode is freed even if it is
+            // still opened.
+            if inode.flags() & EXTENTS_FL == 0 && !self.is_fast_symlink(&inode) {
+                self.free_blocks_from(&mut inode, 0)?;
+            }
+            inode.set_size(0);
+            // Without a clock, use the last write time, which is at least
+            // a valid timestamp rather than an orphan list link.
+            inode.set_dtime(self.sb.write_time().max(1));
+            self.write_inode(ino, &inode)?;
+            self.free_inode(ino, inode.is_dir())
+        } else {
+            self.write_inode(ino, &inode)
+        }
+    }
+
+    /// Creates a hard link `dst_path` to the file at `src_path`.
+    pub fn link(&mut self, dir_ino: u32, src_path: &str, dst_path: &str) -> VfsResult {
+        let (src_parent, src_name) = self.resolve_parent(dir_ino, src_path)?;
+        let (dst_parent, dst_name) = self.resolve_parent(dir_ino, dst_path)?;
+        let Some((ino, file_type)) = self.lookup_entry(src_parent, src_name)? else {
+            return Err(VfsError::NotFound);
+        };
+        if self.lookup_entry(dst_parent, dst_name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        self.check_writable()?;
+
+        let inode = self.read_inode(ino)?;
+        if inode.is_dir() {
+            return Err(VfsError::PermissionDenied);
+        } else if inode.links_count() >= MAX_LINKS {
+            return Err(VfsError::StorageFull);
+        }
+        self.add_entry(dst_parent, dst_name, ino, file_type)?;
+        self.add_links(ino, 1)?;
+        Ok(())
+    }
+
+    pub fn rename(&mut self, dir_ino: u32, src_path: &str, dst_path: &str) -> VfsResult {
+        let (src_parent, src_name) = self.resolve_parent(dir_ino, src_path)?;
+        let (dst_parent, dst_name) = self.resolve_parent(dir_ino, dst_path)?;
+        let Some((ino, file_type)) = self.lookup_entry(src_parent, src_name)? else {
+            return Err(VfsError::NotFound);
+        };
+        if self.lookup_entry(dst_parent, dst_name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        self.check_writable()?;
+
+        let is_dir = self.read_inode(ino)?.is_dir();
+        if is_dir && src_parent != dst_parent {
+            // A directory cannot be moved into itself.
+            let mut cur = dst_parent;
+            while cur != ROOT_INO {
+                if cur == ino {
+                    return Err(VfsError::InvalidInput);
+                }
+                cur = self.resolve(cur, "..")?;
+            }
+        }
+
+        self.add_entry(dst_parent, dst_name, ino, file_type)?;
+        self.remove_entry(src_parent, src_name)?;
+        if is_dir && src_parent != dst_parent {
+            self.set_entry(ino, "..", dst_parent)?;
+            self.add_links(src_parent, -1)?;
+            self.add_links(dst_parent, 1)?;
+        }
+        Ok(())
+    }
+}
+
+fn find_zero_bit(bitmap: &[u8], start: usize, end: usize) -> Option<usize> {
+    (start..end).find(|&i| bitmap[i / 8] & (1 << (i % 8)) == 0)
+}
+
+fn set_bit(bitmap: &mut [u8], bit: usize, value: bool) {
+    if value {
+        bitmap[bit / 8] |= 1 << (bit % 8);
+    } else {
+        bitmap[bit / 8] &= !(1 << (bit % 8));
+    }
+}
@@ -1,5 +1,4 @@
 use alloc::sync::Arc;
-use core::cell::UnsafeCell;
 
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
@@ -10,59 +9,73 @@ use crate::dev::Disk;
 
 const BLOCK_SIZE: usize = 512;
 
+type FsInner = fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>;
+
 pub struct FatFileSystem {
-    inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
-    root_dir: UnsafeCell<Option<VfsNodeRef>>,
+    root_dir: Arc<DirWrapper>,
 }
 
-pub struct FileWrapper<'a>(Mutex<File<'a, Disk, NullTimeProvider, LossyOemCpConverter>>);
-pub struct DirWrapper<'a>(Dir<'a, Disk, NullTimeProvider, LossyOemCpConverter>);
-
-unsafe impl Sync for FatFileSystem {}
-unsafe impl Send for FatFileSystem {}
-unsafe impl Send for FileWrapper<'_> {}
-unsafe impl Sync for FileWrapper<'_> {}
-unsafe impl Send for DirWrapper<'_> {}
-unsafe impl Sync for DirWrapper<'_> {}
+// The files and directories borrow the filesystem, which is kept alive by the
+// `Arc` held next to them. The fields are dropped in order, so the borrow
+// ends before the `Arc` is released.
+pub struct FileWrapper(
+    Mutex<File<'static, Disk, NullTimeProvider, LossyOemCpConverter>>,
+    Arc<FsInner>,
+);
+pub struct DirWrapper(
+    Dir<'static, Disk, NullTimeProvider, LossyOemCpConverter>,
+    Arc<FsInner>,
+);
+
+unsafe impl Send for FileWrapper {}
+unsafe impl Sync for FileWrapper {}
+unsafe impl Send for DirWrapper {}
+unsafe impl Sync for DirWrapper {}
 
 impl FatFileSystem {
     #[cfg(feature = "use-ramdisk")]
     pub fn new(mut disk: Disk) -> Self {
         let opts = fatfs::FormatVolumeOptions::new();
         fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
-            inner,
-            root_dir: UnsafeCell::new(None),
-        }
+        Self::open(disk).expect("failed to initialize FAT filesystem")
     }
 
     #[cfg(not(feature = "use-ramdisk"))]
     pub fn new(disk: Disk) -> Self {
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
-            inner,
-            root_dir: UnsafeCell::new(None),
-        }
+        Self::open(disk).expect("failed to initialize FAT filesystem")
     }
 
-    pub fn init(&'static self) {
-        // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
+    /// Opens the existing FAT filesystem on the disk, which is never
+    /// formatted.
+    pub fn open(disk: Disk) -> VfsResult<Self> {
+        let inner =
+            Arc::new(fatfs::FileSystem::new(disk, fatfs::FsOptions::new()).map_err(as_vfs_err)?);
+        // SAFETY: the filesystem does not move in the `Arc`, and every node
+        // borrowing it holds a clone of the `Arc`.
+        let fs: &'static FsInner = unsafe { &*Arc::as_ptr(&inner) };
+        Ok(Self {
+            root_dir: Arc::new(DirWrapper(fs.root_dir(), inner)),
+        })
     }
+}
 
-    fn new_file(file: File<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<FileWrapper> {
-        Arc::new(FileWrapper(Mutex::new(file)))
+impl DirWrapper {
+    fn new_file(
+        &self,
+        file: File<'static, Disk, NullTimeProvider, LossyOemCpConverter>,
+    ) -> Arc<FileWrapper> {
+        Arc::new(FileWrapper(Mutex::new(file), self.1.clone()))
     }
 
-    fn new_dir(dir: Dir<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<DirWrapper> {
-        Arc::new(DirWrapper(dir))
+    fn new_dir(
+        &self,
+        dir: Dir<'static, Disk, NullTimeProvider, LossyOemCpConverter>,
+    ) -> Arc<DirWrapper> {
+        Arc::new(DirWrapper(dir, self.1.clone()))
     }
 }
 
-impl VfsNodeOps for FileWrapper<'static> {
+impl VfsNodeOps for FileWrapper {
     axfs_vfs::impl_vfs_non_dir_default! {}
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
@@ -90,9 +103,13 @@ impl VfsNodeOps for FileWrapper<'static> {
         file.seek(SeekFrom::Start(size)).map_err(as_vfs_err)?; // TODO: more efficient
         file.truncate().map_err(as_vfs_err)
     }
+
+    fn fsync(&self) -> VfsResult {
+        self.0.lock().flush().map_err(as_vfs_err)
+    }
 }
 
-impl VfsNodeOps for DirWrapper<'static> {
+impl VfsNodeOps for DirWrapper {
     axfs_vfs::impl_vfs_dir_default! {}
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
@@ -108,7 +125,7 @@ impl VfsNodeOps for DirWrapper<'static> {
     fn parent(&self) -> Option<VfsNodeRef> {
         self.0
             .open_dir("..")
-            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir)))
+            .map_or(None, |dir| Some(self.new_dir(dir)))
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
@@ -123,9 +140,9 @@ impl VfsNodeOps for DirWrapper<'static> {
 
         // TODO: use `fatfs::Dir::find_entry`, but it's not public.
         if let Ok(file) = self.0.open_file(path) {
-            Ok(FatFileSystem::new_file(file))
+            Ok(self.new_file(file))
         } else if let Ok(dir) = self.0.open_dir(path) {
-            Ok(FatFileSystem::new_dir(dir))
+            Ok(self.new_dir(dir))
         } else {
             Err(VfsError::NotFound)
         }
@@ -200,8 +217,7 @@ impl VfsNodeOps for DirWrapper<'static> {
 
 impl VfsOps for FatFileSystem {
     fn root_dir(&self) -> VfsNodeRef {
-        let root_dir = unsafe { (*self.root_dir.get()).as_ref().unwrap() };
-        root_dir.clone()
+        self.root_dir.clone()
     }
 }
 
@@ -243,7 +259,7 @@ impl Write for Disk {
         Ok(write_len)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        self.sync().map_err(|_| ())
     }
 }
 
@@ -1,13 +1,70 @@
-cfg_if::cfg_if! {
-    if #[cfg(feature = "myfs")] {
-        pub mod myfs;
-    } else if #[cfg(feature = "fatfs")] {
-        pub mod fatfs;
-    }
-}
+#[cfg(feature = "myfs")]
+pub mod myfs;
+
+#[cfg(feature = "ext2")]
+pub mod ext2;
+
+#[cfg(feature = "fatfs")]
+pub mod fatfs;
 
 #[cfg(feature = "devfs")]
 pub use axfs_devfs as devfs;
 
-#[cfg(feature = "ramfs")]
-pub use axfs_ramfs as ramfs;
+#[cfg(any(feature = "ramfs", feature = "procfs", feature = "sysfs"))]
+pub mod ramfs;
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use axsync::Mutex;
+
+/// Hard links of a filesystem, which are not in [`VfsOps`].
+pub(crate) trait LinkOps: Send + Sync {
+    /// Creates a hard link `dst_path` to the file at `src_path`, both relative
+    /// to the root directory.
+    fn link(&self, src_path: &str, dst_path: &str) -> VfsResult;
+}
+
+/// The filesystems supporting hard links, by the addresses of them.
+///
+/// The weak references keep the addresses from being reused by other
+/// filesystems.
+static LINK_FS: Mutex<Vec<(usize, Weak<dyn LinkOps>)>> = Mutex::new(Vec::new());
+
+/// Registers the hard link support of `fs`, to be found by [`link_ops`] after
+/// it is turned into `Arc<dyn VfsOps>`.
+pub(crate) fn register_link_ops<T: VfsOps + LinkOps + 'static>(fs: &Arc<T>) {
+    let mut list = LINK_FS.lock();
+    list.retain(|(_, fs)| fs.strong_count() > 0);
+    let weak: Weak<dyn LinkOps> = Arc::downgrade(fs) as _;
+    list.push((Arc::as_ptr(fs) as *const () as usize, weak));
+}
+
+/// Returns the hard link operations of `fs`, if it supports them.
+pub(crate) fn link_ops(fs: &Arc<dyn VfsOps>) -> Option<Arc<dyn LinkOps>> {
+    let addr = Arc::as_ptr(fs) as *const () as usize;
+    LINK_FS
+        .lock()
+        .iter()
+        .find(|(a, _)| *a == addr)
+        .and_then(|(_, fs)| fs.upgrade())
+}
+
+/// Returns the inode number and the number of hard links of `node`, which are
+/// not in [`VfsNodeAttr`](axfs_vfs::VfsNodeAttr).
+///
+/// For the filesystems without inode numbers, the address of the node is
+/// used, which is unique while it's alive, and there is one link.
+pub(crate) fn node_ids(node: &VfsNodeRef) -> (u64, u64) {
+    #[cfg(feature = "ext2")]
+    if let Some(ids) = ext2::node_ids(node.as_any()) {
+        return ids;
+    }
+    #[cfg(any(feature = "ramfs", feature = "procfs", feature = "sysfs"))]
+    if let Some(dir) = node.as_any().downcast_ref::<ramfs::DirNode>() {
+        return (dir.ino(), dir.nlink());
+    } else if let Some(file) = node.as_any().downcast_ref::<ramfs::FileNode>() {
+        return (file.ino(), file.nlink());
+    }
+    (Arc::as_ptr(node) as *const () as u64, 1)
+}
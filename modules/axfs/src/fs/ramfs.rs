@@ -0,0 +1,397 @@
+//! An in-memory filesystem, with symbolic links and hard links.
+//!
+//! A symbolic link is a node of [`VfsNodeType::SymLink`] whose content is the
+//! target path, and a hard link is another directory entry of the same file.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsOps, VfsResult};
+use axsync::Mutex;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::LinkOps;
+
+const FILE_PERM: u16 = 0o666;
+const DIR_PERM: u16 = 0o755;
+const LINK_PERM: u16 = 0o777;
+
+/// The inode number of the next node, unique in all filesystems.
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+fn alloc_ino() -> u64 {
+    NEXT_INO.fetch_add(1, Ordering::Relaxed)
+}
+
+/// An in-memory filesystem.
+pub struct RamFileSystem {
+    root: Arc<DirNode>,
+}
+
+/// A directory in [`RamFileSystem`].
+pub struct DirNode {
+    this: Weak<DirNode>,
+    ino: u64,
+    /// The parent directory in this filesystem, empty for the root.
+    parent: Mutex<Weak<DirNode>>,
+    /// The parent of the mount point, only for the root.
+    mount_parent: Mutex<Option<VfsNodeRef>>,
+    children: Mutex<BTreeMap<String, Node>>,
+}
+
+/// A regular file or a symbolic link in [`RamFileSystem`].
+pub struct FileNode {
+    ino: u64,
+    /// The number of directory entries of the file.
+    nlink: AtomicU64,
+    ty: VfsNodeType,
+    content: Mutex<Vec<u8>>,
+}
+
+#[derive(Clone)]
+enum Node {
+    Dir(Arc<DirNode>),
+    File(Arc<FileNode>),
+}
+
+impl Node {
+    fn to_vfs(&self) -> VfsNodeRef {
+        match self {
+            Node::Dir(dir) => dir.clone(),
+            Node::File(file) => file.clone(),
+        }
+    }
+}
+
+impl RamFileSystem {
+    /// Creates an empty filesystem.
+    pub fn new() -> Self {
+        Self {
+            root: DirNode::new(Weak::new()),
+        }
+    }
+
+    /// Returns the root directory.
+    pub fn root_dir_node(&self) -> Arc<DirNode> {
+        self.root.clone()
+    }
+}
+
+impl LinkOps for RamFileSystem {
+    fn link(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("link at ramfs: {} -> {}", dst_path, src_path);
+        let Node::File(file) = self.root.walk(src_path)? else {
+            return Err(VfsError::PermissionDenied); // no hard links to directories
+        };
+        let (dir, name) = self.root.walk_parent(dst_path)?;
+        let mut children = dir.children.lock();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        file.nlink.fetch_add(1, Ordering::Relaxed);
+        children.insert(name.into(), Node::File(file));
+        Ok(())
+    }
+}
+
+impl Default for RamFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VfsOps for RamFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        *self.root.mount_parent.lock() = mount_point.parent();
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+impl DirNode {
+    fn new(parent: Weak<DirNode>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            ino: alloc_ino(),
+            parent: Mutex::new(parent),
+            mount_parent: Mutex::new(None),
+            children: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    fn this(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    /// Returns the inode number, which is unique in all filesystems.
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// Returns the number of hard links, which are the entry in the parent,
+    /// `.`, and `..` of the subdirectories.
+    pub fn nlink(&self) -> u64 {
+        let children = self.children.lock();
+        2 + children
+            .values()
+            .filter(|n| matches!(n, Node::Dir(_)))
+            .count() as u64
+    }
+
+    /// Returns the parent directory, or itself for the root.
+    fn parent_dir(&self) -> Arc<Self> {
+        self.parent.lock().upgrade().unwrap_or_else(|| self.this())
+    }
+
+    /// Returns the node named `name` in this directory.
+    fn step(self: Arc<Self>, name: &str) -> VfsResult<Node> {
+        match name {
+            "" | "." => Ok(Node::Dir(self)),
+            ".." => Ok(Node::Dir(self.parent_dir())),
+            _ => self
+                .children
+                .lock()
+                .get(name)
+                .cloned()
+                .ok_or(VfsError::NotFound),
+        }
+    }
+
+    /// Finds the node at `path` relative to this directory, without leaving
+    /// this filesystem.
+    fn walk(&self, path: &str) -> VfsResult<Node> {
+        let mut node = Node::Dir(self.this());
+        for name in path.split('/') {
+            let Node::Dir(dir) = node else {
+                return Err(VfsError::NotADirectory);
+            };
+            node = dir.step(name)?;
+        }
+        Ok(node)
+    }
+
+    /// Finds the parent directory of `path` relative to this directory,
+    /// returns it and the last component of `path`.
+    fn walk_parent<'a>(&self, path: &'a str) -> VfsResult<(Arc<Self>, &'a str)> {
+        let path = path.trim_matches('/');
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        match self.walk(parent)? {
+            Node::Dir(dir) => Ok((dir, name)),
+            Node::File(_) => Err(VfsError::NotADirectory),
+        }
+    }
+
+    /// Whether `dir` is this directory or one of its ancestors.
+    fn is_descendant_of(&self, dir: &Arc<Self>) -> bool {
+        let mut cur = self.this();
+        loop {
+            if Arc::ptr_eq(&cur, dir) {
+                return true;
+            }
+            let parent = cur.parent.lock().upgrade();
+            match parent {
+                Some(parent) => cur = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(DIR_PERM),
+            VfsNodeType::Dir,
+            4096,
+            0,
+        ))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        match self.parent.lock().upgrade() {
+            Some(parent) => Some(parent),
+            None => self.mount_parent.lock().clone(),
+        }
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let mut node = Node::Dir(self);
+        let mut rest = path;
+        while !rest.is_empty() {
+            let (name, next) = rest.split_once('/').unwrap_or((rest, ""));
+            rest = next;
+            let Node::Dir(dir) = node else {
+                return Err(VfsError::NotADirectory);
+            };
+            // `..` of the root is in the filesystem of the mount point
+            if name == ".." && dir.parent.lock().upgrade().is_none() {
+                if let Some(parent) = dir.mount_parent.lock().clone() {
+                    return parent.lookup(rest);
+                }
+            }
+            node = dir.step(name)?;
+        }
+        Ok(node.to_vfs())
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at ramfs: {}", ty, path);
+        let (dir, name) = self.walk_parent(path)?;
+        let node = match ty {
+            VfsNodeType::File | VfsNodeType::SymLink => Node::File(Arc::new(FileNode {
+                ino: alloc_ino(),
+                nlink: AtomicU64::new(0),
+                ty,
+                content: Mutex::new(Vec::new()),
+            })),
+            VfsNodeType::Dir => Node::Dir(DirNode::new(dir.this.clone())),
+            _ => return Err(VfsError::Unsupported),
+        };
+        let mut children = dir.children.lock();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        if let Node::File(file) = &node {
+            file.nlink.fetch_add(1, Ordering::Relaxed);
+        }
+        children.insert(name.into(), node);
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at ramfs: {}", path);
+        let (dir, name) = self.walk_parent(path)?;
+        let mut children = dir.children.lock();
+        match children.get(name) {
+            None => return Err(VfsError::NotFound),
+            Some(Node::Dir(sub)) if !sub.children.lock().is_empty() => {
+                return Err(VfsError::DirectoryNotEmpty);
+            }
+            _ => {}
+        }
+        if let Some(Node::File(file)) = children.remove(name) {
+            file.nlink.fetch_sub(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let children = self.children.lock();
+        let mut entries = [(".", VfsNodeType::Dir), ("..", VfsNodeType::Dir)]
+            .into_iter()
+            .chain(children.iter().map(|(name, node)| {
+                let ty = match node {
+                    Node::Dir(_) => VfsNodeType::Dir,
+                    Node::File(file) => file.ty,
+                };
+                (name.as_str(), ty)
+            }))
+            .skip(start_idx);
+        let mut n = 0;
+        for ent in dirents.iter_mut() {
+            let Some((name, ty)) = entries.next() else {
+                break;
+            };
+            *ent = VfsDirEntry::new(name, ty);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at ramfs: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.walk_parent(src_path)?;
+        let (dst_dir, dst_name) = self.walk_parent(dst_path)?;
+        let node = src_dir
+            .children
+            .lock()
+            .get(src_name)
+            .cloned()
+            .ok_or(VfsError::NotFound)?;
+        if let Node::Dir(dir) = &node {
+            // A directory cannot be moved into itself.
+            if dst_dir.is_descendant_of(dir) {
+                return Err(VfsError::InvalidInput);
+            }
+        }
+        if dst_dir.children.lock().contains_key(dst_name) {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        src_dir.children.lock().remove(src_name);
+        if let Node::Dir(dir) = &node {
+            *dir.parent.lock() = dst_dir.this.clone();
+        }
+        dst_dir.children.lock().insert(dst_name.into(), node);
+        Ok(())
+    }
+}
+
+impl FileNode {
+    /// Returns the inode number, which is unique in all filesystems.
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// Returns the number of hard links.
+    pub fn nlink(&self) -> u64 {
+        self.nlink.load(Ordering::Relaxed)
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = if self.ty == VfsNodeType::SymLink {
+            LINK_PERM
+        } else {
+            FILE_PERM
+        };
+        let size = self.content.lock().len() as u64;
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(perm),
+            self.ty,
+            size,
+            size.div_ceil(512),
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = self.content.lock();
+        let start = content.len().min(offset as usize);
+        let end = content.len().min(start + buf.len());
+        let src = &content[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        Ok(src.len())
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let offset = offset as usize;
+        let mut content = self.content.lock();
+        if offset + buf.len() > content.len() {
+            content.resize(offset + buf.len(), 0);
+        }
+        content[offset..offset + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.content.lock().resize(size as usize, 0);
+        Ok(())
+    }
+
+    fn fsync(&self) -> VfsResult {
+        Ok(())
+    }
+}
@@ -6,17 +6,28 @@
 //!
 //! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
 //!    is **enabled** by default.
+//! - `ext2`: Use [ext2] as the main filesystem instead of FAT, the `fatfs`
+//!    feature is ignored if both are enabled.
+//! - `ext4`: Like `ext2`, and also read [ext4] filesystems (mounted read-only).
+//!    This feature is **disabled** by default.
 //! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
 //!    **enabled** by default.
-//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
+//! - `ramfs`: Mount an in-memory filesystem on `/tmp`. This feature is
 //!    **enabled** by default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
 //!    by default, but it will override other filesystem selection features if
 //!    both are enabled.
+//! - `write-back`: Keep the written blocks in the block cache until they are
+//!    evicted or [`sync`] is called, instead of writing them to the device at
+//!    once. The filesystems are only synced when the application exits
+//!    normally, so the data written is lost on a crash or a power-off. This
+//!    feature is **disabled** by default for that reason.
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
+//! [ext2]: https://en.wikipedia.org/wiki/Ext2
+//! [ext4]: https://en.wikipedia.org/wiki/Ext4
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
 
 #![cfg_attr(all(not(test), not(doc)), no_std)]
@@ -26,21 +37,121 @@
 extern crate log;
 extern crate alloc;
 
+mod cache;
 mod dev;
 mod fs;
 mod mounts;
+mod partition;
 mod root;
 
 pub mod api;
 pub mod fops;
 
+use alloc::{sync::Arc, vec::Vec};
 use axdriver::{AxDeviceContainer, prelude::*};
+use axerrno::{AxError, AxResult};
+use axfs_vfs::VfsOps;
+use lazyinit::LazyInit;
+
+pub use self::cache::CacheStats;
+
+/// All block devices, to sync their caches.
+static DISKS: LazyInit<Vec<self::dev::Disk>> = LazyInit::new();
 
 /// Initializes filesystems by block devices.
+///
+/// Each partition in MBR or GPT, or each disk without partition tables, is a
+/// volume named like `disk1p2` or `disk0`. The root volume is selected by the
+/// `AX_ROOT` environment variable at build time, in one of the formats:
+///
+/// - `LABEL=<label>`: the partition name in GPT.
+/// - `PARTUUID=<uuid>`: the unique partition GUID in GPT, or
+///   `<disk signature>-<number>` in MBR, the same as Linux.
+/// - A number: the partition number on the first disk.
+/// - The volume name.
+///
+/// It is the first volume of the first disk by default. Other volumes with
+/// known filesystems are mounted at `/mnt/<name>`.
 pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("Initialize filesystems...");
 
-    let dev = blk_devs.take_one().expect("No block device found!");
-    info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let mut disks = Vec::new();
+    let mut volumes = Vec::new();
+    while let Some(dev) = blk_devs.take_one() {
+        info!(
+            "  use block device {}: {:?}",
+            disks.len(),
+            dev.device_name()
+        );
+        let disk = self::dev::Disk::new(dev);
+        volumes.extend(self::partition::scan_volumes(disks.len(), disk.clone()));
+        disks.push(disk);
+    }
+    assert!(!disks.is_empty(), "No block device found!");
+    DISKS.init_once(disks);
+
+    let root_spec = option_env!("AX_ROOT").unwrap_or("");
+    let root_idx = if root_spec.is_empty() {
+        0
+    } else {
+        volumes
+            .iter()
+            .position(|v| v.matches(root_spec))
+            .unwrap_or_else(|| panic!("root volume {:?} not found", root_spec))
+    };
+    let root_vol = volumes.remove(root_idx);
+    info!("  use {} as the root", root_vol.name);
+    self::root::init_rootfs(root_vol.disk)
+        .unwrap_or_else(|e| panic!("failed to initialize the root filesystem: {:?}", e));
+
+    for vol in volumes {
+        self::root::mount_volume(&vol.name, vol.disk);
+    }
+}
+
+/// Writes all cached changes of the block devices back to them.
+pub fn sync() -> AxResult {
+    for disk in DISKS.get().into_iter().flatten() {
+        disk.sync().map_err(|e| {
+            warn!("failed to sync the block device: {:?}", e);
+            AxError::Io
+        })?;
+    }
+    Ok(())
+}
+
+/// Returns the statistics of the block caches of all devices, or [`None`] if
+/// filesystems are not initialized.
+pub fn cache_stats() -> Option<CacheStats> {
+    let disks = DISKS.get()?;
+    Some(disks.iter().fold(CacheStats::default(), |mut total, disk| {
+        total += disk.cache_stats();
+        total
+    }))
+}
+
+/// Mounts the filesystem `fs` at `path`.
+///
+/// The mount point is created if it does not exist. It can be in the main
+/// filesystem or in another mounted filesystem.
+pub fn mount(path: &str, fs: Arc<dyn VfsOps>) -> AxResult {
+    self::root::mount(path, fs)
+}
+
+/// Unmounts the filesystem mounted at `path`.
+///
+/// It fails with [`ResourceBusy`](axerrno::AxError::ResourceBusy) if any file
+/// or directory in the filesystem is still opened or is the current
+/// directory, or other filesystems are mounted in it.
+pub fn umount(path: &str) -> AxResult {
+    self::root::umount(path)
+}
+
+/// Creates a new filesystem of the given type, to be [mounted](mount).
+///
+/// Only the filesystems living in memory are supported: `ramfs` (or `tmpfs`),
+/// `devfs` (or `devtmpfs`), `proc` and `sysfs`, if the corresponding features
+/// are enabled.
+pub fn new_fs(fstype: &str) -> AxResult<Arc<dyn VfsOps>> {
+    self::mounts::new_fs(fstype)
 }
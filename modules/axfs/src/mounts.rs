@@ -1,6 +1,8 @@
 use alloc::sync::Arc;
+use axerrno::{AxResult, ax_err};
 use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
 
+use crate::dev::Disk;
 use crate::fs;
 
 #[cfg(feature = "devfs")]
@@ -18,7 +20,9 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
 
 #[cfg(feature = "ramfs")]
 pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
-    Arc::new(fs::ramfs::RamFileSystem::new())
+    let ramfs = Arc::new(fs::ramfs::RamFileSystem::new());
+    fs::register_link_ops(&ramfs);
+    ramfs
 }
 
 #[cfg(feature = "procfs")]
@@ -78,3 +82,61 @@ pub(crate) fn sysfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
 
     Ok(Arc::new(sysfs))
 }
+
+pub(crate) fn new_fs(fstype: &str) -> AxResult<Arc<dyn VfsOps>> {
+    let fs: Arc<dyn VfsOps> = match fstype {
+        #[cfg(feature = "ramfs")]
+        "ramfs" | "tmpfs" => ramfs(),
+        #[cfg(feature = "devfs")]
+        "devfs" | "devtmpfs" => devfs(),
+        #[cfg(feature = "procfs")]
+        "proc" => procfs()?,
+        #[cfg(feature = "sysfs")]
+        "sysfs" => sysfs()?,
+        _ => return ax_err!(Unsupported, "unknown filesystem type"),
+    };
+    Ok(fs)
+}
+
+/// Creates the filesystem on the disk by its signature, returns [`None`] if
+/// it is not known.
+pub(crate) fn probe_fs(mut disk: Disk) -> Option<Arc<dyn VfsOps>> {
+    // The signatures of FAT are in the first block, and the superblock of
+    // ext2 is at byte 1024.
+    let mut boot = [0; 512];
+    let mut ext2_sb = [0; 512];
+    disk.set_position(0);
+    disk.read_one(&mut boot).ok()?;
+    disk.set_position(1024);
+    disk.read_one(&mut ext2_sb).ok()?;
+
+    #[cfg(feature = "ext2")]
+    if ext2_sb[56..58] == [0x53, 0xef] {
+        return match fs::ext2::Ext2FileSystem::new(disk) {
+            Ok(ext2) => {
+                let ext2 = Arc::new(ext2);
+                fs::register_link_ops(&ext2);
+                Some(ext2)
+            }
+            Err(e) => {
+                warn!("failed to open the ext2 filesystem: {:?}", e);
+                None
+            }
+        };
+    }
+
+    #[cfg(feature = "fatfs")]
+    if boot[510..512] == [0x55, 0xaa]
+        && (&boot[0x36..0x39] == b"FAT" || &boot[0x52..0x55] == b"FAT")
+    {
+        return match fs::fatfs::FatFileSystem::open(disk) {
+            Ok(fs) => Some(Arc::new(fs)),
+            Err(e) => {
+                warn!("failed to open the FAT filesystem: {:?}", e);
+                None
+            }
+        };
+    }
+
+    None
+}
@@ -0,0 +1,396 @@
+//! Parsing of MBR and GPT partition tables.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use crate::dev::{BLOCK_SIZE, Disk};
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const PART_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+/// Maximum number of logical partitions in an extended partition.
+const MAX_LOGICAL_PARTS: usize = 128;
+
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+const GPT_ENTRY_MIN_SIZE: usize = 128;
+/// Maximum number of GPT entries to read, 128 is the usual number.
+const GPT_MAX_ENTRIES: usize = 1024;
+
+/// A partition on a disk.
+pub struct Partition {
+    /// The partition number, starting from 1. The logical partitions of MBR
+    /// are numbered from 5, the same as Linux.
+    pub number: usize,
+    /// The first block of the partition.
+    pub start: u64,
+    pub num_blocks: u64,
+    /// The partition name, only in GPT.
+    pub label: Option<String>,
+    /// The unique partition GUID in GPT, or `<disk signature>-<number>` in
+    /// MBR, like the `PARTUUID` of Linux.
+    pub uuid: String,
+}
+
+/// Parses the partition table on the disk.
+///
+/// Returns an empty list if there is no partition table, in which case the
+/// whole disk is a volume.
+pub fn parse_partitions(disk: &mut Disk) -> Vec<Partition> {
+    let num_blocks = disk.size() / BLOCK_SIZE as u64;
+    let mut mbr = [0; BLOCK_SIZE];
+    if read_block(disk, 0, &mut mbr).is_none() || !is_mbr(&mbr, num_blocks) {
+        return Vec::new();
+    }
+    let entries = mbr_entries(&mbr);
+    if entries.iter().any(|e| e.ty == PART_TYPE_GPT_PROTECTIVE) {
+        return parse_gpt(disk, num_blocks).unwrap_or_else(|| {
+            warn!("invalid GPT on a protective MBR");
+            Vec::new()
+        });
+    }
+
+    let disk_sig = u32::from_le_bytes(mbr[440..444].try_into().unwrap());
+    let new_part = |number, start: u64, num_blocks: u32| Partition {
+        number,
+        start,
+        num_blocks: num_blocks as u64,
+        label: None,
+        uuid: format!("{:08x}-{:02x}", disk_sig, number),
+    };
+    let mut parts = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.is_extended() {
+            parse_logical(
+                disk,
+                num_blocks,
+                entry.start as u64,
+                |number, start, count| parts.push(new_part(number, start, count)),
+            );
+        } else if !entry.is_empty() {
+            parts.push(new_part(i + 1, entry.start as u64, entry.num_blocks));
+        }
+    }
+    parts.sort_by_key(|p| p.number);
+    parts
+}
+
+fn read_block(disk: &mut Disk, block_id: u64, buf: &mut [u8; BLOCK_SIZE]) -> Option<()> {
+    disk.set_position(block_id * BLOCK_SIZE as u64);
+    match disk.read_one(buf) {
+        Ok(BLOCK_SIZE) => Some(()),
+        _ => None,
+    }
+}
+
+struct MbrEntry {
+    boot: u8,
+    ty: u8,
+    start: u32,
+    num_blocks: u32,
+}
+
+impl MbrEntry {
+    fn is_empty(&self) -> bool {
+        self.ty == 0 || self.num_blocks == 0
+    }
+
+    fn is_extended(&self) -> bool {
+        matches!(self.ty, 0x05 | 0x0f | 0x85)
+    }
+}
+
+fn mbr_entries(block: &[u8]) -> [MbrEntry; 4] {
+    core::array::from_fn(|i| {
+        let raw = &block[MBR_TABLE_OFFSET + i * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+        MbrEntry {
+            boot: raw[0],
+            ty: raw[4],
+            start: u32::from_le_bytes(raw[8..12].try_into().unwrap()),
+            num_blocks: u32::from_le_bytes(raw[12..16].try_into().unwrap()),
+        }
+    })
+}
+
+/// Whether the first block of a disk is an MBR.
+///
+/// A FAT volume without partition tables also has the MBR signature, so the
+/// entries are checked like Linux does.
+fn is_mbr(block: &[u8], disk_blocks: u64) -> bool {
+    if block[510..512] != MBR_SIGNATURE {
+        return false;
+    }
+    if &block[0x36..0x39] == b"FAT" || &block[0x52..0x55] == b"FAT" {
+        return false;
+    }
+    let entries = mbr_entries(block);
+    entries.iter().all(|e| {
+        (e.boot == 0 || e.boot == 0x80)
+            && (e.is_empty()
+                || (e.start != 0 && e.start as u64 + e.num_blocks as u64 <= disk_blocks))
+    }) && entries.iter().any(|e| !e.is_empty())
+}
+
+/// Walks the chain of extended boot records in the extended partition at
+/// `ext_start`, and calls `f` with the number, the start and the size of each
+/// logical partition.
+fn parse_logical(
+    disk: &mut Disk,
+    disk_blocks: u64,
+    ext_start: u64,
+    mut f: impl FnMut(usize, u64, u32),
+) {
+    let mut ebr_start = ext_start;
+    let mut visited = Vec::new();
+    let mut block = [0; BLOCK_SIZE];
+    for number in 5..5 + MAX_LOGICAL_PARTS {
+        if visited.contains(&ebr_start) {
+            warn!("loop in the extended boot records at block {}", ebr_start);
+            return;
+        }
+        visited.push(ebr_start);
+        if read_block(disk, ebr_start, &mut block).is_none() || block[510..512] != MBR_SIGNATURE {
+            warn!("invalid extended boot record at block {}", ebr_start);
+            return;
+        }
+        let [logical, next, ..] = mbr_entries(&block);
+        let start = ebr_start + logical.start as u64;
+        if !logical.is_empty() && start + logical.num_blocks as u64 <= disk_blocks {
+            f(number, start, logical.num_blocks);
+        }
+        // the next EBR is relative to the extended partition
+        if next.is_empty() || !next.is_extended() {
+            return;
+        }
+        ebr_start = ext_start + next.start as u64;
+    }
+}
+
+fn parse_gpt(disk: &mut Disk, disk_blocks: u64) -> Option<Vec<Partition>> {
+    let mut block = [0; BLOCK_SIZE];
+    read_block(disk, 1, &mut block)?;
+    if &block[..8] != GPT_SIGNATURE {
+        return None;
+    }
+    let read_u32 = |b: &[u8], off: usize| u32::from_le_bytes(b[off..off + 4].try_into().unwrap());
+    let read_u64 = |b: &[u8], off: usize| u64::from_le_bytes(b[off..off + 8].try_into().unwrap());
+    let entries_lba = read_u64(&block, 72);
+    let num_entries = read_u32(&block, 80) as usize;
+    let entry_size = read_u32(&block, 84) as usize;
+    if entry_size < GPT_ENTRY_MIN_SIZE
+        || entry_size % 8 != 0
+        || num_entries > GPT_MAX_ENTRIES
+        || entries_lba >= disk_blocks
+    {
+        return None;
+    }
+
+    let mut entries = vec![0; num_entries * entry_size];
+    disk.set_position(entries_lba * BLOCK_SIZE as u64);
+    let mut pos = 0;
+    while pos < entries.len() {
+        match disk.read_one(&mut entries[pos..]) {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => pos += n,
+        }
+    }
+
+    let mut parts = Vec::new();
+    for (i, raw) in entries.chunks(entry_size).enumerate() {
+        if raw[..16].iter().all(|&b| b == 0) {
+            continue; // unused entry
+        }
+        let first = read_u64(raw, 32);
+        let last = read_u64(raw, 40);
+        if first > last || last >= disk_blocks {
+            warn!("GPT entry {} is out of the disk", i);
+            continue;
+        }
+        let name = raw[56..128]
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&c| c != 0);
+        let label = char::decode_utf16(name)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect::<String>();
+        parts.push(Partition {
+            number: i + 1,
+            start: first,
+            num_blocks: last - first + 1,
+            label: Some(label).filter(|l| !l.is_empty()),
+            uuid: guid_to_string(&raw[16..32]),
+        });
+    }
+    Some(parts)
+}
+
+/// Formats a GUID, of which the first three fields are little-endian.
+fn guid_to_string(guid: &[u8]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        u32::from_le_bytes(guid[0..4].try_into().unwrap()),
+        u16::from_le_bytes(guid[4..6].try_into().unwrap()),
+        u16::from_le_bytes(guid[6..8].try_into().unwrap()),
+        guid[8],
+        guid[9],
+        guid[10],
+        guid[11],
+        guid[12],
+        guid[13],
+        guid[14],
+        guid[15],
+    )
+}
+
+/// A volume that can hold a filesystem, which is a partition or a whole disk
+/// without partition tables.
+pub struct Volume {
+    /// The name like `disk0` or `disk1p2`.
+    pub name: String,
+    pub disk: Disk,
+    pub part: Option<Partition>,
+}
+
+impl Volume {
+    /// Whether the volume is selected by `spec`, see
+    /// [`init_filesystems`](crate::init_filesystems) for the formats.
+    pub fn matches(&self, spec: &str) -> bool {
+        if let Some(label) = spec.strip_prefix("LABEL=") {
+            self.part.as_ref().and_then(|p| p.label.as_deref()) == Some(label)
+        } else if let Some(uuid) = spec.strip_prefix("PARTUUID=") {
+            self.part
+                .as_ref()
+                .is_some_and(|p| p.uuid.eq_ignore_ascii_case(uuid))
+        } else if let Ok(number) = spec.parse::<usize>() {
+            self.name == format!("disk0p{}", number)
+        } else {
+            self.name == spec
+        }
+    }
+}
+
+/// Lists the volumes on the `disk_idx`-th disk.
+pub fn scan_volumes(disk_idx: usize, mut disk: Disk) -> Vec<Volume> {
+    let name = format!("disk{}", disk_idx);
+    let parts = parse_partitions(&mut disk);
+    if parts.is_empty() {
+        return vec![Volume {
+            name,
+            disk,
+            part: None,
+        }];
+    }
+    parts
+        .into_iter()
+        .map(|part| {
+            let name = format!("{}p{}", name, part.number);
+            info!(
+                "  {}: blocks [{:#x}, {:#x}), label {:?}, partuuid {}",
+                name,
+                part.start,
+                part.start + part.num_blocks,
+                part.label.as_deref().unwrap_or(""),
+                part.uuid,
+            );
+            Volume {
+                name,
+                disk: disk.partition(part.start, part.num_blocks),
+                part: Some(part),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use axdriver_block::ramdisk::RamDisk;
+
+    use super::*;
+
+    const DISK_BLOCKS: usize = 64;
+
+    fn set_mbr_entry(img: &mut [u8], block: usize, i: usize, ty: u8, start: u32, count: u32) {
+        let off = block * BLOCK_SIZE + MBR_TABLE_OFFSET + i * MBR_ENTRY_SIZE;
+        img[off + 4] = ty;
+        img[off + 8..off + 12].copy_from_slice(&start.to_le_bytes());
+        img[off + 12..off + 16].copy_from_slice(&count.to_le_bytes());
+        img[block * BLOCK_SIZE + 510..][..2].copy_from_slice(&MBR_SIGNATURE);
+    }
+
+    fn parse(img: &[u8]) -> Vec<Partition> {
+        parse_partitions(&mut Disk::new(RamDisk::from(img)))
+    }
+
+    /// A protective MBR, with a GPT header of one partition at blocks
+    /// [34, 40).
+    fn gpt_image() -> Vec<u8> {
+        let mut img = vec![0; DISK_BLOCKS * BLOCK_SIZE];
+        set_mbr_entry(
+            &mut img,
+            0,
+            0,
+            PART_TYPE_GPT_PROTECTIVE,
+            1,
+            DISK_BLOCKS as u32 - 1,
+        );
+        let header = &mut img[BLOCK_SIZE..2 * BLOCK_SIZE];
+        header[..8].copy_from_slice(GPT_SIGNATURE);
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&4u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        let entry = &mut img[2 * BLOCK_SIZE..];
+        entry[..16].fill(0xaa); // type GUID
+        entry[32..40].copy_from_slice(&34u64.to_le_bytes());
+        entry[40..48].copy_from_slice(&39u64.to_le_bytes());
+        img
+    }
+
+    #[test]
+    fn test_gpt() {
+        let parts = parse(&gpt_image());
+        assert_eq!(parts.len(), 1);
+        assert_eq!(
+            (parts[0].number, parts[0].start, parts[0].num_blocks),
+            (1, 34, 6)
+        );
+    }
+
+    #[test]
+    fn test_corrupt_gpt_header() {
+        let corrupt = |off: usize, bytes: &[u8]| {
+            let mut img = gpt_image();
+            img[BLOCK_SIZE + off..][..bytes.len()].copy_from_slice(bytes);
+            parse(&img)
+        };
+        // bad signature
+        assert!(corrupt(0, b"EFI FART").is_empty());
+        // too many entries, or the entries are too small
+        assert!(corrupt(80, &(GPT_MAX_ENTRIES as u32 + 1).to_le_bytes()).is_empty());
+        assert!(corrupt(84, &64u32.to_le_bytes()).is_empty());
+        assert!(corrupt(84, &130u32.to_le_bytes()).is_empty());
+        // the entries are out of the disk
+        assert!(corrupt(72, &(DISK_BLOCKS as u64).to_le_bytes()).is_empty());
+        assert!(corrupt(72, &u64::MAX.to_le_bytes()).is_empty());
+    }
+
+    #[test]
+    fn test_ebr_loop() {
+        // The extended partition is at blocks [8, 32). Its first EBR links
+        // to the second one at block 16, which links back to the first.
+        let mut img = vec![0; DISK_BLOCKS * BLOCK_SIZE];
+        set_mbr_entry(&mut img, 0, 0, 0x83, 1, 7);
+        set_mbr_entry(&mut img, 0, 1, 0x05, 8, 24);
+        set_mbr_entry(&mut img, 8, 0, 0x83, 1, 4);
+        set_mbr_entry(&mut img, 8, 1, 0x05, 8, 8);
+        set_mbr_entry(&mut img, 16, 0, 0x83, 1, 4);
+        set_mbr_entry(&mut img, 16, 1, 0x05, 0, 8);
+        let parts = parse(&img)
+            .iter()
+            .map(|p| (p.number, p.start, p.num_blocks))
+            .collect::<Vec<_>>();
+        assert_eq!(parts, [(1, 1, 7), (5, 9, 4), (6, 17, 4)]);
+
+        // an EBR linking to itself
+        set_mbr_entry(&mut img, 8, 1, 0x05, 0, 8);
+        assert_eq!(parse(&img).len(), 2);
+    }
+}
@@ -1,34 +1,62 @@
 //! Root directory of the filesystem
 //!
-//! TODO: it doesn't work very well if the mount points have containment relationships.
+//! Other filesystems can be mounted on directories of the main filesystem or
+//! of other mounted filesystems. A path is resolved in the filesystem mounted
+//! at its longest prefix, matched on whole path components.
+//!
+//! Symbolic links are resolved here rather than in the filesystems, so that
+//! they can point to other filesystems. A symbolic link is a node of
+//! [`VfsNodeType::SymLink`], whose content is the target path.
 
-use alloc::{string::String, sync::Arc, vec::Vec};
-use axerrno::{AxError, AxResult, ax_err};
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axsync::Mutex;
 use lazyinit::LazyInit;
 
+use crate::fops::{PathError, PathResult};
 use crate::{api::FileType, fs, mounts};
 
+/// Maximum number of symbolic links followed in a path, the same as Linux.
+const MAX_SYMLINKS: usize = 40;
+
 static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
 static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
-
-struct MountPoint {
-    path: &'static str,
+/// The mount point of the current directory, which keeps it busy.
+static CURRENT_DIR_MOUNT: Mutex<Option<Arc<MountPoint>>> = Mutex::new(None);
+
+/// A filesystem mounted on a directory.
+///
+/// Opened files and directories hold a reference to the mount point they
+/// reside in, so that it cannot be unmounted while they are in use.
+pub(crate) struct MountPoint {
+    path: String,
     fs: Arc<dyn VfsOps>,
 }
 
 struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
-    mounts: Vec<MountPoint>,
+    mounts: Mutex<Vec<Arc<MountPoint>>>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
+    pub fn new(path: String, fs: Arc<dyn VfsOps>) -> Self {
         Self { path, fs }
     }
+
+    /// Returns the rest of `path` (without the leading `/`) in this mount
+    /// point, or `None` if `path` is not in it.
+    fn strip_path<'a>(&self, path: &'a str) -> Option<&'a str> {
+        // skip the first '/'
+        let rest = path.strip_prefix(&self.path[1..])?;
+        if rest.is_empty() || rest.starts_with('/') {
+            Some(rest)
+        } else {
+            None // e.g., "/tmpx" is not in "/tmp"
+        }
+    }
 }
 
 impl Drop for MountPoint {
@@ -41,33 +69,107 @@ impl RootDirectory {
     pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
         Self {
             main_fs,
-            mounts: Vec::new(),
+            mounts: Mutex::new(Vec::new()),
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    /// Mounts `fs` at the absolute and canonical `path`.
+    pub fn mount(&self, path: String, fs: Arc<dyn VfsOps>) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
         if !path.starts_with('/') {
             return ax_err!(InvalidInput, "mount path must start with '/'");
         }
-        if self.mounts.iter().any(|mp| mp.path == path) {
+        let mut mounts = self.mounts.lock();
+        if mounts.iter().any(|mp| mp.path == path) {
             return ax_err!(InvalidInput, "mount point already exists");
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
+        // create the mount point in its parent filesystem if it does not exist
+        let (parent_fs, rest_path) = Self::find_mounted_fs(&self.main_fs, &mounts, &path);
+        let parent_root = parent_fs.root_dir();
+        match parent_root.create(rest_path, FileType::Dir) {
+            Ok(()) | Err(AxError::AlreadyExists) => {}
+            Err(e) => return Err(e),
+        }
+        fs.mount(&path, parent_root.lookup(rest_path)?)?;
+        mounts.push(Arc::new(MountPoint::new(path, fs)));
         Ok(())
     }
 
-    pub fn _umount(&mut self, path: &str) {
-        self.mounts.retain(|mp| mp.path != path);
+    /// Unmounts the filesystem at the absolute and canonical `path`.
+    ///
+    /// Returns [`ResourceBusy`](AxError::ResourceBusy) if there are files or
+    /// directories in use, or other filesystems mounted in it.
+    pub fn umount(&self, path: &str) -> AxResult {
+        let mut mounts = self.mounts.lock();
+        let Some(idx) = mounts.iter().position(|mp| mp.path == path) else {
+            return ax_err!(InvalidInput, "not a mount point");
+        };
+        if Arc::strong_count(&mounts[idx]) > 1
+            || mounts.iter().any(|mp| {
+                mp.path.len() > path.len() && mounts[idx].strip_path(&mp.path[1..]).is_some()
+            })
+        {
+            return ax_err!(ResourceBusy);
+        }
+        mounts.swap_remove(idx);
+        Ok(())
+    }
+
+    /// Creates a hard link at the absolute `dst_path` to the file at
+    /// `src_path`, which must be in the same filesystem.
+    pub fn link(&self, src_path: &str, dst_path: &str) -> AxResult {
+        let mounts = self.mounts.lock();
+        let (src_fs, src_rest) = Self::find_mounted_fs(&self.main_fs, &mounts, src_path);
+        let (dst_fs, dst_rest) = Self::find_mounted_fs(&self.main_fs, &mounts, dst_path);
+        drop(mounts);
+        if src_rest.is_empty() || dst_rest.is_empty() {
+            return ax_err!(PermissionDenied); // mount points
+        }
+        if Arc::as_ptr(&src_fs) as *const () != Arc::as_ptr(&dst_fs) as *const () {
+            return ax_err!(Unsupported, "cannot link across filesystems");
+        }
+        match fs::link_ops(&src_fs) {
+            Some(ops) => ops.link(src_rest, dst_rest),
+            None => ax_err!(Unsupported),
+        }
     }
 
     pub fn contains(&self, path: &str) -> bool {
-        self.mounts.iter().any(|mp| mp.path == path)
+        self.mounts.lock().iter().any(|mp| mp.path == path)
+    }
+
+    /// Returns the mount point that the absolute `path` resides in, or `None`
+    /// if it is in the main filesystem.
+    pub fn mount_point_of(&self, path: &str) -> Option<Arc<MountPoint>> {
+        let path = path.trim_matches('/');
+        Self::find_mount_point(&self.mounts.lock(), path).map(|(mp, _)| mp.clone())
+    }
+
+    /// Finds the mount point that has the longest match with `path` (without
+    /// the leading `/`), and the rest of `path` in it.
+    fn find_mount_point<'a, 'b>(
+        mounts: &'a [Arc<MountPoint>],
+        path: &'b str,
+    ) -> Option<(&'a Arc<MountPoint>, &'b str)> {
+        // TODO: more efficient, e.g. trie
+        mounts
+            .iter()
+            .filter_map(|mp| mp.strip_path(path).map(|rest| (mp, rest)))
+            .max_by_key(|(mp, _)| mp.path.len())
+    }
+
+    fn find_mounted_fs<'a>(
+        main_fs: &Arc<dyn VfsOps>,
+        mounts: &[Arc<MountPoint>],
+        path: &'a str,
+    ) -> (Arc<dyn VfsOps>, &'a str) {
+        let path = path.trim_matches('/');
+        match Self::find_mount_point(mounts, path) {
+            Some((mp, rest)) => (mp.fs.clone(), rest), // matched a mount point
+            None => (main_fs.clone(), path),           // not matched any mount point
+        }
     }
 
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
@@ -80,24 +182,9 @@ impl RootDirectory {
             return self.lookup_mounted_fs(rest, f);
         }
 
-        let mut idx = 0;
-        let mut max_len = 0;
-
-        // Find the filesystem that has the longest mounted path match
-        // TODO: more efficient, e.g. trie
-        for (i, mp) in self.mounts.iter().enumerate() {
-            // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
-            }
-        }
-
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
-        }
+        // Do not hold the lock of mount points during the operation.
+        let (fs, rest_path) = Self::find_mounted_fs(&self.main_fs, &self.mounts.lock(), path);
+        f(fs, rest_path)
     }
 }
 
@@ -143,45 +230,46 @@ impl VfsNodeOps for RootDirectory {
     }
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
+pub(crate) fn init_rootfs(disk: crate::dev::Disk) -> AxResult {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
             let main_fs = fs::myfs::new_myfs(disk);
+        } else if #[cfg(feature = "ext2")] {
+            let main_fs = Arc::new(fs::ext2::Ext2FileSystem::new(disk)?);
+            fs::register_link_ops(&main_fs);
         } else if #[cfg(feature = "fatfs")] {
-            static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
-            FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
-            FAT_FS.init();
-            let main_fs = FAT_FS.clone();
+            let main_fs = Arc::new(fs::fatfs::FatFileSystem::new(disk));
         }
     }
 
-    let mut root_dir = RootDirectory::new(main_fs);
+    let root_dir = RootDirectory::new(main_fs);
 
     #[cfg(feature = "devfs")]
     root_dir
-        .mount("/dev", mounts::devfs())
+        .mount("/dev".into(), mounts::devfs())
         .expect("failed to mount devfs at /dev");
 
     #[cfg(feature = "ramfs")]
     root_dir
-        .mount("/tmp", mounts::ramfs())
+        .mount("/tmp".into(), mounts::ramfs())
         .expect("failed to mount ramfs at /tmp");
 
     // Mount another ramfs as procfs
     #[cfg(feature = "procfs")]
     root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
+        .mount("/proc".into(), mounts::procfs().unwrap())
         .expect("fail to mount procfs at /proc");
 
     // Mount another ramfs as sysfs
     #[cfg(feature = "sysfs")]
     root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
+        .mount("/sys".into(), mounts::sysfs().unwrap())
         .expect("fail to mount sysfs at /sys");
 
     ROOT_DIR.init_once(Arc::new(root_dir));
     CURRENT_DIR.init_once(Mutex::new(ROOT_DIR.clone()));
     *CURRENT_DIR_PATH.lock() = "/".into();
+    Ok(())
 }
 
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
@@ -201,75 +289,294 @@ pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
     }
 }
 
-pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+/// Returns the absolute path of a mount point, without the trailing `/`.
+fn mount_path(path: &str) -> AxResult<String> {
+    let mut path = absolute_path(path)?;
+    while path.len() > 1 && path.ends_with('/') {
+        path.pop();
+    }
+    Ok(path)
+}
+
+pub(crate) fn mount(path: &str, fs: Arc<dyn VfsOps>) -> AxResult {
+    ROOT_DIR.mount(mount_path(path)?, fs)
+}
+
+/// Mounts the filesystem on the volume named `name` at `/mnt/<name>`, if the
+/// filesystem is known.
+pub(crate) fn mount_volume(name: &str, disk: crate::dev::Disk) {
+    let Some(fs) = mounts::probe_fs(disk) else {
+        info!("  no known filesystem on {}", name);
+        return;
+    };
+    let path = format!("/mnt/{}", name);
+    let res = match create_dir(None, "/mnt") {
+        Ok(()) | Err(PathError::Ax(AxError::AlreadyExists)) => mount(&path, fs),
+        Err(e) => Err(e.into()),
+    };
+    match res {
+        Ok(()) => info!("  mount {} at {}", name, path),
+        Err(e) => warn!("failed to mount {} at {}: {:?}", name, path, e),
+    }
+}
+
+pub(crate) fn umount(path: &str) -> AxResult {
+    ROOT_DIR.umount(&mount_path(path)?)
+}
+
+/// Reads the target of the symbolic link `node`.
+fn read_link_node(node: &VfsNodeRef) -> AxResult<String> {
+    let mut buf = vec![0; node.get_attr()?.size() as usize];
+    let mut pos = 0;
+    while pos < buf.len() {
+        match node.read_at(pos as u64, &mut buf[pos..])? {
+            0 => return ax_err!(UnexpectedEof),
+            n => pos += n,
+        }
+    }
+    String::from_utf8(buf).map_err(|_| AxError::InvalidData)
+}
+
+/// Replaces the symbolic links in the directories of `path`, and also the
+/// last component if `follow` is true.
+///
+/// The result can be looked up from `parent_node_of(dir, ..)` as `path` is,
+/// since a relative path only becomes absolute by an absolute link target.
+///
+/// It fails with [`PathError::SymlinkLoop`] if there are too many levels of
+/// symbolic links.
+fn resolve_path(dir: Option<&VfsNodeRef>, path: &str, follow: bool) -> PathResult<String> {
+    let mut path = String::from(path);
+    let mut links = 0;
+    // `path[..pos]` has no symbolic links
+    let mut pos = 0;
+    loop {
+        let start = path[pos..]
+            .find(|c| c != '/')
+            .map_or(path.len(), |i| pos + i);
+        if start == path.len() {
+            return Ok(path);
+        }
+        let end = path[start..].find('/').map_or(path.len(), |i| start + i);
+        let is_last = path[end..].trim_matches('/').is_empty();
+        if is_last && !follow {
+            return Ok(path);
+        }
+        pos = end;
+        if matches!(&path[start..end], "." | "..") {
+            continue;
+        }
+
+        let node = parent_node_of(dir, &path).lookup(&path[..end])?;
+        if node.get_attr()?.file_type() != VfsNodeType::SymLink {
+            continue;
+        }
+        links += 1;
+        if links > MAX_SYMLINKS {
+            warn!("too many levels of symbolic links: {}", path);
+            return Err(PathError::SymlinkLoop);
+        }
+        let target = read_link_node(&node)?;
+        if target.is_empty() {
+            return Err(ax_err_type!(NotFound).into());
+        }
+        if target.starts_with('/') {
+            path = target + &path[end..];
+            pos = 0;
+        } else {
+            path = format!("{}{}{}", &path[..start], target, &path[end..]);
+            pos = start;
+        }
+    }
+}
+
+/// Returns the mount point that the node at the resolved `path` resides in.
+///
+/// A relative path is looked up in the filesystem of `dir`, whose mount point
+/// is `dir_mount`, or of the current directory if `dir` is `None`.
+fn mount_point_at(
+    dir: Option<&VfsNodeRef>,
+    dir_mount: Option<Arc<MountPoint>>,
+    path: &str,
+) -> Option<Arc<MountPoint>> {
+    if path.starts_with('/') {
+        ROOT_DIR.mount_point_of(path)
+    } else if dir.is_some() {
+        dir_mount
+    } else if CURRENT_DIR_PATH.lock().as_str() == "/" {
+        // looked up from the root directory, which crosses mount points
+        ROOT_DIR.mount_point_of(path.trim_start_matches("./"))
+    } else {
+        CURRENT_DIR_MOUNT.lock().clone()
+    }
+}
+
+/// Looks up `path`, and returns the node with the path it's resolved to.
+fn lookup_resolved(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+    follow: bool,
+) -> PathResult<(VfsNodeRef, String)> {
     if path.is_empty() {
-        return ax_err!(NotFound);
+        return Err(ax_err_type!(NotFound).into());
     }
-    let node = parent_node_of(dir, path).lookup(path)?;
+    let path = resolve_path(dir, path, follow)?;
+    let node = parent_node_of(dir, &path).lookup(&path)?;
     if path.ends_with('/') && !node.get_attr()?.is_dir() {
-        ax_err!(NotADirectory)
+        Err(ax_err_type!(NotADirectory).into())
     } else {
-        Ok(node)
+        Ok((node, path))
     }
 }
 
-pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+fn lookup_at(dir: Option<&VfsNodeRef>, path: &str, follow: bool) -> PathResult<VfsNodeRef> {
+    lookup_resolved(dir, path, follow).map(|(node, _)| node)
+}
+
+/// Looks up `path`, following symbolic links.
+pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> PathResult<VfsNodeRef> {
+    lookup_at(dir, path, true)
+}
+
+/// Looks up `path`, without following the symbolic link at the last
+/// component.
+pub(crate) fn lookup_no_follow(dir: Option<&VfsNodeRef>, path: &str) -> PathResult<VfsNodeRef> {
+    lookup_at(dir, path, false)
+}
+
+/// Looks up `path` like [`lookup`], and also returns the mount point that the
+/// node resides in, after the symbolic links are resolved.
+///
+/// `dir_mount` is the mount point of `dir`.
+pub(crate) fn lookup_mounted(
+    dir: Option<&VfsNodeRef>,
+    dir_mount: Option<Arc<MountPoint>>,
+    path: &str,
+) -> PathResult<(VfsNodeRef, Option<Arc<MountPoint>>)> {
+    let (node, path) = lookup_resolved(dir, path, true)?;
+    Ok((node, mount_point_at(dir, dir_mount, &path)))
+}
+
+fn create_file_resolved(dir: Option<&VfsNodeRef>, path: &str) -> PathResult<(VfsNodeRef, String)> {
     if path.is_empty() {
-        return ax_err!(NotFound);
+        return Err(ax_err_type!(NotFound).into());
     } else if path.ends_with('/') {
-        return ax_err!(NotADirectory);
+        return Err(ax_err_type!(NotADirectory).into());
     }
-    let parent = parent_node_of(dir, path);
-    parent.create(path, VfsNodeType::File)?;
-    parent.lookup(path)
+    let path = resolve_path(dir, path, false)?;
+    let parent = parent_node_of(dir, &path);
+    parent.create(&path, VfsNodeType::File)?;
+    Ok((parent.lookup(&path)?, path))
+}
+
+pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> PathResult<VfsNodeRef> {
+    create_file_resolved(dir, path).map(|(node, _)| node)
+}
+
+/// Creates a file like [`create_file`], and also returns the mount point that
+/// it resides in. `dir_mount` is the mount point of `dir`.
+pub(crate) fn create_file_mounted(
+    dir: Option<&VfsNodeRef>,
+    dir_mount: Option<Arc<MountPoint>>,
+    path: &str,
+) -> PathResult<(VfsNodeRef, Option<Arc<MountPoint>>)> {
+    let (node, path) = create_file_resolved(dir, path)?;
+    Ok((node, mount_point_at(dir, dir_mount, &path)))
 }
 
-pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    match lookup(dir, path) {
-        Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> PathResult {
+    match lookup_no_follow(dir, path) {
+        Ok(_) => Err(ax_err_type!(AlreadyExists).into()),
+        Err(PathError::Ax(AxError::NotFound)) => {
+            let path = resolve_path(dir, path, false)?;
+            Ok(parent_node_of(dir, &path).create(&path, VfsNodeType::Dir)?)
+        }
         Err(e) => Err(e),
     }
 }
 
-pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    let node = lookup(dir, path)?;
+pub(crate) fn create_symlink(dir: Option<&VfsNodeRef>, target: &str, path: &str) -> PathResult {
+    if target.is_empty() {
+        return Err(ax_err_type!(NotFound).into());
+    }
+    match lookup_no_follow(dir, path) {
+        Ok(_) => return Err(ax_err_type!(AlreadyExists).into()),
+        Err(PathError::Ax(AxError::NotFound)) => {}
+        Err(e) => return Err(e),
+    }
+    let path = resolve_path(dir, path, false)?;
+    let parent = parent_node_of(dir, &path);
+    parent.create(&path, VfsNodeType::SymLink)?;
+    let res = parent
+        .clone()
+        .lookup(&path)
+        .and_then(|node| node.write_at(0, target.as_bytes()));
+    match res {
+        Ok(n) if n == target.len() => Ok(()),
+        res => {
+            parent.remove(&path).ok();
+            match res {
+                Err(e) => Err(e.into()),
+                Ok(_) => Err(ax_err_type!(StorageFull).into()),
+            }
+        }
+    }
+}
+
+pub(crate) fn read_link(dir: Option<&VfsNodeRef>, path: &str) -> PathResult<String> {
+    let node = lookup_no_follow(dir, path)?;
+    if node.get_attr()?.file_type() != VfsNodeType::SymLink {
+        return Err(ax_err_type!(InvalidInput).into());
+    }
+    Ok(read_link_node(&node)?)
+}
+
+pub(crate) fn link(old: &str, new: &str) -> PathResult {
+    let old = absolute_path(&resolve_path(None, old, false)?)?;
+    let new = absolute_path(&resolve_path(None, new, false)?)?;
+    Ok(ROOT_DIR.link(&old, &new)?)
+}
+
+pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> PathResult {
+    let path = &resolve_path(dir, path, false)?;
+    let node = lookup_no_follow(dir, path)?;
     let attr = node.get_attr()?;
     if attr.is_dir() {
-        ax_err!(IsADirectory)
+        Err(ax_err_type!(IsADirectory).into())
     } else if !attr.perm().owner_writable() {
-        ax_err!(PermissionDenied)
+        Err(ax_err_type!(PermissionDenied).into())
     } else {
-        parent_node_of(dir, path).remove(path)
+        Ok(parent_node_of(dir, path).remove(path)?)
     }
 }
 
-pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
+pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> PathResult {
     if path.is_empty() {
-        return ax_err!(NotFound);
+        return Err(ax_err_type!(NotFound).into());
     }
     let path_check = path.trim_matches('/');
     if path_check.is_empty() {
-        return ax_err!(DirectoryNotEmpty); // rm -d '/'
+        return Err(ax_err_type!(DirectoryNotEmpty).into()); // rm -d '/'
     } else if path_check == "."
         || path_check == ".."
         || path_check.ends_with("/.")
         || path_check.ends_with("/..")
     {
-        return ax_err!(InvalidInput);
+        return Err(ax_err_type!(InvalidInput).into());
     }
+    let path = &resolve_path(dir, path, false)?;
     if ROOT_DIR.contains(&absolute_path(path)?) {
-        return ax_err!(PermissionDenied);
+        return Err(ax_err_type!(PermissionDenied).into());
     }
 
-    let node = lookup(dir, path)?;
+    let node = lookup_no_follow(dir, path)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
-        ax_err!(NotADirectory)
+        Err(ax_err_type!(NotADirectory).into())
     } else if !attr.perm().owner_writable() {
-        ax_err!(PermissionDenied)
+        Err(ax_err_type!(PermissionDenied).into())
     } else {
-        parent_node_of(dir, path).remove(path)
+        Ok(parent_node_of(dir, path).remove(path)?)
     }
 }
 
@@ -285,6 +592,7 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     if abs_path == "/" {
         *CURRENT_DIR.lock() = ROOT_DIR.clone();
         *CURRENT_DIR_PATH.lock() = "/".into();
+        *CURRENT_DIR_MOUNT.lock() = None;
         return Ok(());
     }
 
@@ -295,16 +603,19 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     } else if !attr.perm().owner_executable() {
         ax_err!(PermissionDenied)
     } else {
+        *CURRENT_DIR_MOUNT.lock() = ROOT_DIR.mount_point_of(&abs_path);
         *CURRENT_DIR.lock() = node;
         *CURRENT_DIR_PATH.lock() = abs_path;
         Ok(())
     }
 }
 
-pub(crate) fn rename(old: &str, new: &str) -> AxResult {
+pub(crate) fn rename(old: &str, new: &str) -> PathResult {
+    let old = &resolve_path(None, old, false)?;
+    let new = &resolve_path(None, new, false)?;
     if parent_node_of(None, new).lookup(new).is_ok() {
         warn!("dst file already exist, now remove it");
         remove_file(None, new)?;
     }
-    parent_node_of(None, old).rename(old, new)
+    Ok(parent_node_of(None, old).rename(old, new)?)
 }
@@ -252,6 +252,126 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+fn test_mount() -> Result<()> {
+    // nested mount points
+    axfs::mount("/mnt", axfs::new_fs("ramfs")?)?;
+    axfs::mount("./mnt//inner/", axfs::new_fs("ramfs")?)?;
+    assert_err!(
+        axfs::mount("/mnt/inner", axfs::new_fs("ramfs")?),
+        InvalidInput
+    );
+    assert_eq!(fs::write("/mnt/outer.txt", "outer"), Ok(()));
+    assert_eq!(fs::write("/mnt/inner/inner.txt", "inner"), Ok(()));
+    assert_eq!(fs::read_to_string("mnt/./inner//inner.txt")?, "inner");
+    assert_err!(fs::metadata("/mnt/inner.txt"), NotFound);
+    assert_err!(fs::metadata("/mnt/inner/outer.txt"), NotFound);
+
+    // match on whole path components
+    assert_eq!(fs::create_dir("/mntx"), Ok(()));
+    assert_eq!(fs::write("/mntx/test.txt", "test"), Ok(()));
+    assert_err!(fs::metadata("/mnt/test.txt"), NotFound);
+    assert_eq!(fs::remove_file("/mntx/test.txt"), Ok(()));
+    assert_eq!(fs::remove_dir("/mntx"), Ok(()));
+
+    // busy mount points
+    let file = File::open("/mnt/inner/inner.txt")?;
+    assert_err!(axfs::umount("/mnt/inner"), ResourceBusy);
+    assert_err!(axfs::umount("/mnt"), ResourceBusy);
+    assert_err!(fs::remove_dir("/mnt/inner"), PermissionDenied);
+    drop(file);
+    fs::set_current_dir("/mnt/inner")?;
+    assert_err!(axfs::umount("/mnt/inner"), ResourceBusy);
+    fs::set_current_dir("/")?;
+
+    // opened through a symbolic link, which is counted for its target
+    assert_eq!(fs::symlink("/mnt/inner", "/tmp/inner"), Ok(()));
+    let file = File::open("/tmp/inner/inner.txt")?;
+    assert_err!(axfs::umount("/mnt/inner"), ResourceBusy);
+    drop(file);
+    assert_eq!(fs::remove_file("/tmp/inner"), Ok(()));
+
+    // unmount
+    assert_eq!(axfs::umount("/mnt/inner/"), Ok(()));
+    assert_err!(fs::metadata("/mnt/inner/inner.txt"), NotFound);
+    assert_eq!(fs::read_to_string("/mnt/outer.txt")?, "outer");
+    assert_eq!(axfs::umount("/mnt"), Ok(()));
+    assert_err!(axfs::umount("/mnt"), InvalidInput);
+    assert_err!(fs::metadata("/mnt/outer.txt"), NotFound);
+    assert_eq!(fs::remove_dir("/mnt"), Ok(()));
+
+    println!("test_mount() OK!");
+    Ok(())
+}
+
+fn test_links() -> Result<()> {
+    // symbolic links, the ones in /tmp point into the main filesystem
+    assert_eq!(fs::symlink("/short.txt", "/tmp/abs.txt"), Ok(()));
+    assert_eq!(fs::symlink("../very/long", "/tmp/rel"), Ok(()));
+    assert_eq!(fs::symlink("abs.txt", "/tmp/chain.txt"), Ok(()));
+    assert_eq!(fs::read_link("/tmp/abs.txt")?, "/short.txt");
+    assert_eq!(fs::read_link("tmp//rel")?, "../very/long");
+    let contents = fs::read_to_string("/short.txt")?;
+    assert_eq!(fs::read_to_string("/tmp/abs.txt")?, contents);
+    assert_eq!(fs::read_to_string("/tmp/chain.txt")?, contents);
+    let contents2 = fs::read_to_string("/very/long/path/test.txt")?;
+    assert_eq!(fs::read_to_string("/tmp/rel/path/test.txt")?, contents2);
+    assert!(fs::metadata("/tmp/rel")?.is_dir());
+    assert!(fs::symlink_metadata("/tmp/rel")?.is_symlink());
+    assert!(!fs::symlink_metadata("/tmp/rel/path")?.is_symlink());
+    assert_err!(fs::read_link("/short.txt"), InvalidInput);
+
+    // dangling links and loops
+    assert_eq!(fs::symlink("not-exist", "/tmp/dangling"), Ok(()));
+    assert_err!(fs::metadata("/tmp/dangling"), NotFound);
+    assert!(fs::symlink_metadata("/tmp/dangling")?.is_symlink());
+    assert_eq!(fs::symlink("loop2", "/tmp/loop1"), Ok(()));
+    assert_eq!(fs::symlink("loop1", "/tmp/loop2"), Ok(()));
+    assert_err!(fs::read("/tmp/loop1"), InvalidData);
+    let mut opts = axfs::fops::OpenOptions::new();
+    opts.read(true);
+    let loop_err = Some(axfs::fops::PathError::SymlinkLoop);
+    assert_eq!(axfs::fops::File::open("/tmp/loop1", &opts).err(), loop_err);
+    assert_eq!(axfs::fops::read_link("/tmp/loop1/x").err(), loop_err);
+    assert!(axfs::fops::symlink_attr("/tmp/loop1").is_ok());
+    assert_err!(fs::symlink("short.txt", "/tmp/abs.txt"), AlreadyExists);
+
+    // removing a link keeps the target
+    assert_err!(fs::remove_dir("/tmp/rel"), NotADirectory);
+    for name in ["abs.txt", "rel", "chain.txt", "dangling", "loop1", "loop2"] {
+        assert_eq!(fs::remove_file(&format!("/tmp/{}", name)), Ok(()));
+    }
+    assert_eq!(fs::read_to_string("/short.txt")?, contents);
+
+    // hard links
+    assert_eq!(fs::create_dir("/tmp/dir"), Ok(()));
+    assert_eq!(fs::write("/tmp/file.txt", "hard"), Ok(()));
+    assert_eq!(fs::metadata("/tmp/file.txt")?.nlink(), 1);
+    assert_eq!(fs::hard_link("/tmp/file.txt", "/tmp/dir/link.txt"), Ok(()));
+    let (file, link) = (
+        fs::metadata("/tmp/file.txt")?,
+        fs::metadata("/tmp/dir/link.txt")?,
+    );
+    assert_eq!((file.ino(), file.nlink()), (link.ino(), 2));
+    assert_eq!(fs::metadata("/tmp/dir")?.nlink(), 2);
+    assert_eq!(fs::write("/tmp/dir/link.txt", "hard link"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/file.txt")?, "hard link");
+    assert_eq!(fs::remove_file("/tmp/file.txt"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/dir/link.txt")?, "hard link");
+    assert_eq!(fs::metadata("/tmp/dir/link.txt")?.nlink(), 1);
+    assert_err!(fs::hard_link("/tmp/dir", "/tmp/dir2"), PermissionDenied);
+    assert_err!(fs::hard_link("/short.txt", "/tmp/short.txt"), Unsupported);
+    assert_err!(
+        fs::hard_link("/tmp/dir/link.txt", "/tmp/dir/link.txt"),
+        AlreadyExists
+    );
+    assert_eq!(fs::remove_file("/tmp/dir/link.txt"), Ok(()));
+    assert_eq!(fs::remove_dir("/tmp/dir"), Ok(()));
+    assert_eq!(fs::read_dir("tmp").unwrap().count(), 0);
+
+    println!("test_links() OK!");
+    Ok(())
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
@@ -259,4 +379,6 @@ pub fn test_all() {
     test_create_file_dir().expect("test_create_file_dir() failed");
     test_remove_file_dir().expect("test_remove_file_dir() failed");
     test_devfs_ramfs().expect("test_devfs_ramfs() failed");
+    test_mount().expect("test_mount() failed");
+    test_links().expect("test_links() failed");
 }
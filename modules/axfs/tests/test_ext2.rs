@@ -0,0 +1,59 @@
+#![cfg(all(feature = "ext2", not(feature = "myfs")))]
+
+mod test_common;
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api as fs;
+
+const IMG_PATH: &str = "resources/ext2.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+#[test]
+fn test_ext2() {
+    println!("Testing ext2 with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    test_common::test_all();
+
+    // fast (inline) and slow symbolic links, and hard links on ext2
+    let long_target = "./very/long/../long/./path/../path/././../path/./././test.txt";
+    assert_eq!(fs::symlink("short.txt", "/fast-link"), Ok(()));
+    assert_eq!(fs::symlink(long_target, "/slow-link"), Ok(()));
+    assert_eq!(fs::read_link("/slow-link").as_deref(), Ok(long_target));
+    let test_txt = fs::read_to_string("/very/long/path/test.txt").unwrap();
+    assert_eq!(fs::read_to_string("/slow-link"), Ok(test_txt));
+    let contents = fs::read_to_string("/short.txt").unwrap();
+    assert_eq!(fs::read_to_string("/fast-link").as_ref(), Ok(&contents));
+    assert_eq!(fs::hard_link("/short.txt", "/very/hard-link"), Ok(()));
+    let short = fs::metadata("/short.txt").unwrap();
+    let link = fs::metadata("/very/hard-link").unwrap();
+    assert_eq!((short.ino(), short.nlink()), (link.ino(), 2));
+    assert_eq!(
+        fs::read_to_string("/very/hard-link").as_ref(),
+        Ok(&contents)
+    );
+    assert_eq!(fs::remove_file("/short.txt"), Ok(()));
+    assert_eq!(
+        fs::read_to_string("/very/hard-link").as_ref(),
+        Ok(&contents)
+    );
+    assert!(fs::metadata("/fast-link").is_err());
+
+    // the changes are written through the block cache
+    assert_eq!(axfs::sync(), Ok(()));
+    let stats = axfs::cache_stats().unwrap();
+    println!("{:?}", stats);
+    assert!(stats.hits > 0);
+    assert!(stats.dev_writes > 0);
+}
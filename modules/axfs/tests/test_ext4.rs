@@ -0,0 +1,56 @@
+#![cfg(all(feature = "ext4", not(feature = "myfs")))]
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api as fs;
+use axio::Error;
+
+const IMG_PATH: &str = "resources/ext4.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+#[test]
+fn test_ext4() {
+    println!("Testing ext4 with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    // files are mapped by extents
+    let long = fs::read_to_string("/long.txt").unwrap();
+    assert_eq!(long.len(), 14000);
+    assert!(long.lines().all(|line| line == "Rust is cool!"));
+    let path = "///very/long//.././long//./path/./test.txt";
+    assert_eq!(fs::read_to_string(path).unwrap(), "Rust is cool!\n");
+
+    // ext4 is mounted read-only
+    assert!(
+        !fs::metadata("short.txt")
+            .unwrap()
+            .permissions()
+            .owner_writable()
+    );
+    assert_eq!(
+        fs::write("short.txt", "test").err(),
+        Some(Error::PermissionDenied)
+    );
+    assert_eq!(
+        fs::create_dir("/new-dir").err(),
+        Some(Error::PermissionDenied)
+    );
+    assert_eq!(
+        fs::remove_file("/short.txt").err(),
+        Some(Error::PermissionDenied)
+    );
+
+    // other filesystems can still be written
+    assert_eq!(fs::write("/tmp/test.txt", "test"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/test.txt").unwrap(), "test");
+}
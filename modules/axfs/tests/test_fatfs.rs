@@ -1,4 +1,4 @@
-#![cfg(not(feature = "myfs"))]
+#![cfg(not(any(feature = "myfs", feature = "ext2")))]
 
 mod test_common;
 
@@ -24,4 +24,11 @@ fn test_fatfs() {
     axfs::init_filesystems(AxDeviceContainer::from_one(disk));
 
     test_common::test_all();
+
+    // the changes are written through the block cache
+    assert_eq!(axfs::sync(), Ok(()));
+    let stats = axfs::cache_stats().unwrap();
+    println!("{:?}", stats);
+    assert!(stats.hits > 0);
+    assert!(stats.dev_writes > 0);
 }
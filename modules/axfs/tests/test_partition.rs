@@ -0,0 +1,46 @@
+#![cfg(all(feature = "ext2", not(feature = "myfs")))]
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api as fs;
+
+const IMG_PATH: &str = "resources/gpt.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+#[test]
+fn test_partition() {
+    println!("Testing partitions with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    // the first partition is the root
+    assert_eq!(fs::read_to_string("/root.txt").unwrap(), "root\n");
+    assert!(fs::metadata("/data.txt").is_err());
+
+    // the second one is mounted automatically
+    let dirents = fs::read_dir("/mnt")
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert_eq!(dirents, ["disk0p2"]);
+    assert_eq!(
+        fs::read_to_string("/mnt/disk0p2/data.txt").unwrap(),
+        "data\n"
+    );
+
+    // writes stay in their own partitions
+    assert_eq!(fs::write("/mnt/disk0p2/new.txt", "new"), Ok(()));
+    assert_eq!(fs::write("/new.txt", "root new"), Ok(()));
+    assert_eq!(axfs::sync(), Ok(()));
+    assert_eq!(fs::read_to_string("/mnt/disk0p2/new.txt").unwrap(), "new");
+    assert_eq!(fs::read_to_string("/new.txt").unwrap(), "root new");
+}
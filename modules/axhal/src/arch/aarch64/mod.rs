@@ -14,6 +14,8 @@ use memory_addr::{PhysAddr, VirtAddr};
 use tock_registers::interfaces::{Readable, Writeable};
 
 pub use self::context::{FpState, TaskContext, TrapFrame};
+#[cfg(target_os = "none")]
+pub(crate) use self::trap::init_fault_stack;
 
 /// Allows the current CPU to respond to interrupts.
 #[inline]
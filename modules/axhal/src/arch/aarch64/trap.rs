@@ -6,7 +6,27 @@ use tock_registers::interfaces::Readable;
 
 use super::TrapFrame;
 
-global_asm!(include_str!("trap.S"));
+global_asm!(
+    include_str!("trap.S"),
+    check_stack = const cfg!(feature = "paging") as u8,
+    stack_region_shift = const crate::trap::TASK_STACK_SHIFTS.0,
+    stack_red_zone_mask = const STACK_RED_ZONE_MASK,
+);
+
+/// The bits of `sp` that are all zero iff `sp` is less than a page above the
+/// guard page at the bottom of its task stack slot.
+const STACK_RED_ZONE_MASK: usize = {
+    let slot_size = 1 << crate::trap::TASK_STACK_SHIFTS.1;
+    (slot_size - 1) & !(2 * crate::mem::PAGE_SIZE_4K - 1)
+};
+
+// The trap entry tells whether `sp` is in the task stack region by a single
+// bit, which requires all kernel addresses to have the higher bits set.
+#[cfg(feature = "paging")]
+const _: () = assert!(
+    axconfig::plat::KERNEL_ASPACE_BASE >> (crate::trap::TASK_STACK_SHIFTS.0 + 1)
+        == usize::MAX >> (crate::trap::TASK_STACK_SHIFTS.0 + 1)
+);
 
 #[repr(u8)]
 #[derive(Debug)]
@@ -37,8 +57,23 @@ fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
 }
 
 #[unsafe(no_mangle)]
-fn handle_irq_exception(_tf: &TrapFrame) {
-    handle_trap!(IRQ, 0);
+fn handle_kernel_stack_overflow(tf: &TrapFrame) -> ! {
+    #[cfg(feature = "paging")]
+    crate::trap::handle_stack_overflow(tf, tf.r[0] as usize);
+    #[cfg(not(feature = "paging"))]
+    unreachable!("{:#x?}", tf);
+}
+
+/// Sets the top of the fault stack of the current CPU, which the trap entry
+/// switches to on kernel stack overflows.
+pub(crate) fn init_fault_stack(cpu_id: usize) {
+    let top = crate::trap::fault_stack_top(cpu_id);
+    unsafe { core::arch::asm!("msr tpidrro_el0, {}", in(reg) top) };
+}
+
+#[unsafe(no_mangle)]
+fn handle_irq_exception(tf: &TrapFrame) {
+    crate::trap::handle_irq(tf, 0);
 }
 
 fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
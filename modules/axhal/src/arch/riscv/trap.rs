@@ -8,6 +8,11 @@ core::arch::global_asm!(
     include_asm_marcos!(),
     include_str!("trap.S"),
     trapframe_size = const core::mem::size_of::<TrapFrame>(),
+    check_stack = const cfg!(feature = "paging") as u8,
+    stack_region_shift = const crate::trap::TASK_STACK_SHIFTS.0,
+    stack_slot_shl = const usize::BITS - crate::trap::TASK_STACK_SHIFTS.1,
+    fault_stack = sym crate::trap::FAULT_STACK,
+    fault_stack_size = const crate::trap::FAULT_STACK_SIZE,
 );
 
 fn handle_breakpoint(sepc: &mut usize) {
@@ -32,6 +37,14 @@ fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bo
     }
 }
 
+#[unsafe(no_mangle)]
+fn riscv_stack_overflow_handler(tf: &TrapFrame) -> ! {
+    #[cfg(feature = "paging")]
+    crate::trap::handle_stack_overflow(tf, tf.regs.sp);
+    #[cfg(not(feature = "paging"))]
+    unreachable!("{:#x?}", tf);
+}
+
 #[unsafe(no_mangle)]
 fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
     let scause = scause::read();
@@ -43,7 +56,7 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
         }
         Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
         Trap::Interrupt(_) => {
-            handle_trap!(IRQ, scause.bits());
+            crate::trap::handle_irq(tf, scause.bits());
         }
         _ => {
             panic!(
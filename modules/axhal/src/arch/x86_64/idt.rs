@@ -13,6 +13,14 @@ pub struct IdtStruct {
 }
 
 impl IdtStruct {
+    /// The index of the Interrupt Stack Table (IST) entry in the TSS for the
+    /// double fault handler.
+    ///
+    /// A page fault that cannot push its trap frame, e.g., on an overflowed
+    /// kernel stack, escalates to a double fault, so the double fault handler
+    /// must run on a known good stack.
+    pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+
     /// Constructs a new IDT struct that filled with entries from
     /// `trap_handler_table`.
     #[allow(clippy::new_without_default)]
@@ -33,7 +41,10 @@ impl IdtStruct {
         };
         for i in 0..NUM_INT {
             #[allow(clippy::missing_transmute_annotations)]
-            entries[i].set_handler_fn(unsafe { core::mem::transmute(ENTRIES[i]) });
+            let opts = entries[i].set_handler_fn(unsafe { core::mem::transmute(ENTRIES[i]) });
+            if i == x86::irq::DOUBLE_FAULT_VECTOR as usize {
+                unsafe { opts.set_stack_index(Self::DOUBLE_FAULT_IST_INDEX) };
+            }
         }
         idt
     }
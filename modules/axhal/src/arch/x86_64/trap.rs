@@ -26,10 +26,23 @@ fn handle_page_fault(tf: &TrapFrame) {
     }
 }
 
+fn handle_double_fault(tf: &TrapFrame) -> ! {
+    // Running on the IST stack. A double fault is usually escalated from a
+    // page fault on an overflowed kernel stack, so give the page fault
+    // handlers a chance to report it with the address in CR2.
+    let vaddr = va!(unsafe { cr2() });
+    handle_trap!(PAGE_FAULT, vaddr, MappingFlags::WRITE, false);
+    panic!(
+        "#DF @ {:#x}, last fault_vaddr={:#x}:\n{:#x?}",
+        tf.rip, vaddr, tf
+    );
+}
+
 #[unsafe(no_mangle)]
 fn x86_trap_handler(tf: &TrapFrame) {
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
+        DOUBLE_FAULT_VECTOR => handle_double_fault(tf),
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
@@ -38,7 +51,7 @@ fn x86_trap_handler(tf: &TrapFrame) {
             );
         }
         IRQ_VECTOR_START..=IRQ_VECTOR_END => {
-            handle_trap!(IRQ, tf.vector as _);
+            crate::trap::handle_irq(tf, tf.vector as _);
         }
         _ => {
             panic!(
@@ -81,6 +81,8 @@ pub(crate) fn init_primary(cpu_id: usize) {
         CPU_ID.write_current_raw(cpu_id);
         IS_BSP.write_current_raw(true);
     }
+    #[cfg(all(target_arch = "aarch64", target_os = "none"))]
+    crate::arch::init_fault_stack(cpu_id);
 }
 
 #[allow(dead_code)]
@@ -90,4 +92,6 @@ pub(crate) fn init_secondary(cpu_id: usize) {
         CPU_ID.write_current_raw(cpu_id);
         IS_BSP.write_current_raw(false);
     }
+    #[cfg(all(target_arch = "aarch64", target_os = "none"))]
+    crate::arch::init_fault_stack(cpu_id);
 }
@@ -1,5 +1,7 @@
 //! Page table manipulation.
 
+use core::ops::Range;
+
 use axalloc::global_allocator;
 use page_table_multiarch::PagingHandler;
 
@@ -8,6 +10,28 @@ use crate::mem::{MemRegionFlags, PAGE_SIZE_4K, PhysAddr, VirtAddr, phys_to_virt,
 #[doc(no_inline)]
 pub use page_table_multiarch::{MappingFlags, PageSize, PagingError, PagingResult};
 
+/// The virtual region for the kernel stacks of tasks, which takes the upper
+/// half of the kernel address space, far away from the linear mappings at the
+/// bottom.
+///
+/// The region is divided into slots of [`TASK_STACK_SLOT_SIZE`] bytes. Each
+/// slot holds one stack, with an unmapped guard page at the bottom of the
+/// slot. The trap entry relies on this layout to detect kernel stack
+/// overflows before pushing the trap frame.
+pub const TASK_STACK_REGION: Range<usize> = {
+    let base = axconfig::plat::KERNEL_ASPACE_BASE;
+    let size = axconfig::plat::KERNEL_ASPACE_SIZE;
+    let start = memory_addr::align_up_4k(base + size / 2);
+    // The trap entry tells whether `sp` is in the region by its high bits.
+    assert!(start.wrapping_neg().is_power_of_two());
+    assert!(start % TASK_STACK_SLOT_SIZE == 0);
+    start..base + size
+};
+
+/// The size of a slot in [`TASK_STACK_REGION`], i.e., the maximum size of a
+/// task stack plus its guard page.
+pub const TASK_STACK_SLOT_SIZE: usize = 0x80_0000; // 8M
+
 impl From<MemRegionFlags> for MappingFlags {
     fn from(f: MemRegionFlags) -> Self {
         let mut ret = Self::empty();
@@ -15,6 +15,9 @@ pub const TIMER_IRQ_NUM: usize = translate_irq(14, InterruptType::PPI).unwrap();
 /// Non-secure EL2 Physical Timer irq number.
 pub const TIMER_IRQ_NUM: usize = translate_irq(10, InterruptType::PPI).unwrap();
 
+/// The inter-processor interrupt (IPI) number, using SGI 1.
+pub const IPI_IRQ_NUM: usize = translate_irq(1, InterruptType::SGI).unwrap();
+
 /// The UART IRQ number.
 pub const UART_IRQ_NUM: usize = translate_irq(UART_IRQ, InterruptType::SPI).unwrap();
 
@@ -42,6 +45,11 @@ pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
     crate::irq::register_handler_common(irq_num, handler)
 }
 
+/// Sends an inter-processor interrupt ([`IPI_IRQ_NUM`]) to the given CPU.
+pub fn send_ipi(cpu_id: usize) {
+    GICD.lock().send_sgi(cpu_id, IPI_IRQ_NUM);
+}
+
 /// Fetches the IRQ number.
 pub fn fetch_irq() -> usize {
     GICC.iar() as usize
@@ -2,13 +2,12 @@
 
 use crate::irq::IrqHandler;
 use lazyinit::LazyInit;
-use riscv::register::sie;
+use riscv::register::{sie, sip};
 
 /// `Interrupt` bit in `scause`
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
 
 /// Supervisor software interrupt in `scause`
-#[allow(unused)]
 pub(super) const S_SOFT: usize = INTC_IRQ_BASE + 1;
 
 /// Supervisor timer interrupt in `scause`
@@ -19,16 +18,23 @@ pub(super) const S_EXT: usize = INTC_IRQ_BASE + 9;
 
 static TIMER_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
 
+static IPI_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
+
 /// The maximum number of IRQs.
 pub const MAX_IRQ_COUNT: usize = 1024;
 
 /// The timer IRQ number (supervisor timer interrupt in `scause`).
 pub const TIMER_IRQ_NUM: usize = S_TIMER;
 
+/// The inter-processor interrupt (IPI) number (supervisor software interrupt
+/// in `scause`).
+pub const IPI_IRQ_NUM: usize = S_SOFT;
+
 macro_rules! with_cause {
-    ($cause: expr, @TIMER => $timer_op: expr, @EXT => $ext_op: expr $(,)?) => {
+    ($cause: expr, @TIMER => $timer_op: expr, @IPI => $ipi_op: expr, @EXT => $ext_op: expr $(,)?) => {
         match $cause {
             S_TIMER => $timer_op,
+            S_SOFT => $ipi_op,
             S_EXT => $ext_op,
             _ => panic!("invalid trap cause: {:#x}", $cause),
         }
@@ -55,6 +61,12 @@ pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
         } else {
             false
         },
+        @IPI => if !IPI_HANDLER.is_inited() {
+            IPI_HANDLER.init_once(handler);
+            true
+        } else {
+            false
+        },
         @EXT => crate::irq::register_handler_common(scause & !INTC_IRQ_BASE, handler),
     )
 }
@@ -71,10 +83,22 @@ pub fn dispatch_irq(scause: usize) {
             trace!("IRQ: timer");
             TIMER_HANDLER();
         },
+        @IPI => {
+            trace!("IRQ: IPI");
+            unsafe { sip::clear_ssoft() };
+            if let Some(handler) = IPI_HANDLER.get() {
+                handler();
+            }
+        },
         @EXT => crate::irq::dispatch_irq_common(0), // TODO: get IRQ number from PLIC
     );
 }
 
+/// Sends an inter-processor interrupt ([`IPI_IRQ_NUM`]) to the given CPU.
+pub fn send_ipi(cpu_id: usize) {
+    sbi_rt::send_ipi(sbi_rt::HartMask::from_mask_base(1, cpu_id));
+}
+
 pub(super) fn init_percpu() {
     // enable soft interrupts, timer interrupts, and external interrupts
     unsafe {
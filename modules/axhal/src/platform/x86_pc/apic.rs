@@ -16,6 +16,7 @@ pub(super) mod vectors {
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
+    pub const APIC_IPI_VECTOR: u8 = 0xf3;
 }
 
 /// The maximum number of IRQs.
@@ -24,6 +25,9 @@ pub const MAX_IRQ_COUNT: usize = 256;
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = APIC_TIMER_VECTOR as usize;
 
+/// The inter-processor interrupt (IPI) number.
+pub const IPI_IRQ_NUM: usize = APIC_IPI_VECTOR as usize;
+
 const IO_APIC_BASE: PhysAddr = pa!(0xFEC0_0000);
 
 static LOCAL_APIC: SyncUnsafeCell<MaybeUninit<LocalApic>> =
@@ -55,6 +59,12 @@ pub fn register_handler(vector: usize, handler: crate::irq::IrqHandler) -> bool
     crate::irq::register_handler_common(vector, handler)
 }
 
+/// Sends an inter-processor interrupt ([`IPI_IRQ_NUM`]) to the given CPU.
+#[cfg(feature = "irq")]
+pub fn send_ipi(cpu_id: usize) {
+    unsafe { local_apic().send_ipi(APIC_IPI_VECTOR, raw_apic_id(cpu_id as u8)) };
+}
+
 /// Dispatches the IRQ.
 ///
 /// This function is called by the common interrupt handler. It looks
@@ -2,6 +2,7 @@
 
 use crate::arch::{GdtStruct, IdtStruct, TaskStateSegment};
 use lazyinit::LazyInit;
+use x86_64::VirtAddr;
 
 static IDT: LazyInit<IdtStruct> = LazyInit::new();
 
@@ -16,7 +17,13 @@ fn init_percpu() {
         IDT.load();
         let tss = TSS.current_ref_mut_raw();
         let gdt = GDT.current_ref_mut_raw();
-        tss.init_once(TaskStateSegment::new());
+        tss.init_once({
+            let mut tss = TaskStateSegment::new();
+            let fault_stack = crate::trap::fault_stack_top(crate::cpu::this_cpu_id());
+            tss.interrupt_stack_table[IdtStruct::DOUBLE_FAULT_IST_INDEX as usize] =
+                VirtAddr::new(fault_stack as u64);
+            tss
+        });
         gdt.init_once(GdtStruct::new(tss));
         gdt.load();
         gdt.load_tss();
@@ -1,31 +1,117 @@
 //! Trap handling.
 
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
 use linkme::distributed_slice as def_trap_handler;
 use memory_addr::VirtAddr;
 use page_table_entry::MappingFlags;
 
+use crate::arch::TrapFrame;
+use crate::cpu::this_cpu_id;
+
 pub use linkme::distributed_slice as register_trap_handler;
 
 /// A slice of IRQ handler functions.
+///
+/// The handlers are called in turn until one of them returns `true`.
 #[def_trap_handler]
 pub static IRQ: [fn(usize) -> bool];
 
 /// A slice of page fault handler functions.
+///
+/// The handlers are called in turn until one of them returns `true`.
 #[def_trap_handler]
 pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 
+/// The size of the per-CPU fault stack.
+pub(crate) const FAULT_STACK_SIZE: usize = 0x4000; // 16K
+
+#[repr(C, align(16))]
+pub(crate) struct FaultStack([u8; FAULT_STACK_SIZE]);
+
+/// The stack to handle traps on when the kernel stack has overflowed, as the
+/// trap frame cannot be pushed to the overflowed stack.
+///
+/// It's placed in the per-CPU data area, so each CPU has its own copy.
+#[unsafe(link_section = ".percpu")]
+pub(crate) static mut FAULT_STACK: FaultStack = FaultStack([0; FAULT_STACK_SIZE]);
+
+/// The layout of task stacks for the trap entry to detect kernel stack
+/// overflows, as `(region_shift, slot_shift)`, where the task stack region
+/// starts at `-(1 << region_shift)` and consists of `1 << slot_shift` sized
+/// slots. See [`crate::paging::TASK_STACK_REGION`].
+#[allow(dead_code)]
+pub(crate) const TASK_STACK_SHIFTS: (u32, u32) = {
+    #[cfg(feature = "paging")]
+    {
+        (
+            crate::paging::TASK_STACK_REGION.start.trailing_zeros(),
+            crate::paging::TASK_STACK_SLOT_SIZE.trailing_zeros(),
+        )
+    }
+    #[cfg(not(feature = "paging"))]
+    {
+        (0, 0)
+    }
+};
+
+/// The trap frames of the IRQs being handled, indexed by CPU ID.
+static IRQ_TRAP_FRAMES: [AtomicPtr<TrapFrame>; axconfig::SMP] =
+    [const { AtomicPtr::new(null_mut()) }; axconfig::SMP];
+
 #[allow(unused_macros)]
 macro_rules! handle_trap {
     ($trap:ident, $($args:tt)*) => {{
-        let mut iter = $crate::trap::$trap.iter();
-        if let Some(func) = iter.next() {
-            if iter.next().is_some() {
-                warn!("Multiple handlers for trap {} are not currently supported", stringify!($trap));
-            }
-            func($($args)*)
-        } else {
+        let handlers = &$crate::trap::$trap;
+        if handlers.is_empty() {
             warn!("No registered handler for trap {}", stringify!($trap));
             false
+        } else {
+            // Try the handlers in turn until one of them handles the trap.
+            handlers.iter().any(|func| func($($args)*))
         }
     }}
 }
+
+/// Returns the top of the fault stack of the given CPU.
+#[allow(dead_code)]
+pub(crate) fn fault_stack_top(cpu_id: usize) -> usize {
+    // The `.percpu` section is linked at address 0, so the address of
+    // `FAULT_STACK` is its offset in the per-CPU data area.
+    percpu::percpu_area_base(cpu_id) + &raw const FAULT_STACK as usize + FAULT_STACK_SIZE
+}
+
+/// Reports a kernel stack overflow detected at the trap entry, where `sp` is
+/// the stack pointer of the interrupted code. It runs on the fault stack.
+///
+/// The page fault handlers are called with the guard page of the overflowed
+/// stack, so the owner of the stack can report whose stack it is.
+#[cfg(feature = "paging")]
+#[allow(dead_code)]
+pub(crate) fn handle_stack_overflow(tf: &TrapFrame, sp: usize) -> ! {
+    let guard = memory_addr::align_down(sp, crate::paging::TASK_STACK_SLOT_SIZE);
+    handle_trap!(PAGE_FAULT, va!(guard), MappingFlags::WRITE, false);
+    panic!("Kernel stack overflow, sp={:#x}:\n{:#x?}", sp, tf);
+}
+
+/// Calls the IRQ handler, and records `tf` as the trap frame of the IRQ being
+/// handled on the current CPU during the call.
+#[allow(dead_code)]
+pub(crate) fn handle_irq(tf: &TrapFrame, irq_num: usize) -> bool {
+    let slot = &IRQ_TRAP_FRAMES[this_cpu_id()];
+    let prev = slot.swap(tf as *const _ as *mut _, Ordering::Relaxed);
+    let ret = handle_trap!(IRQ, irq_num);
+    slot.store(prev, Ordering::Relaxed);
+    ret
+}
+
+/// Calls `f` with the trap frame of the IRQ being handled on the current CPU,
+/// i.e., the registers of the interrupted code.
+///
+/// Returns [`None`] if it's not called in an IRQ handler.
+pub fn with_irq_trap_frame<R>(f: impl FnOnce(&TrapFrame) -> R) -> Option<R> {
+    let tf = IRQ_TRAP_FRAMES[this_cpu_id()].load(Ordering::Relaxed);
+    // SAFETY: the trap frame is valid until the IRQ handler returns.
+    unsafe { tf.as_ref() }.map(f)
+}
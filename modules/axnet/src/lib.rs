@@ -14,6 +14,15 @@
 //!
 //! - `smoltcp`: Use [smoltcp] as the underlying network stack. This is enabled
 //!   by default.
+//! - `multitask` and `irq`: With both enabled, the tasks blocked in
+//!   [`TcpSocket::accept`] sleep in a wait queue of the listening port, and
+//!   each established connection only wakes up one of them. Otherwise, they
+//!   keep polling the network stack and yielding.
+//! - `async`: Enable the asynchronous socket operations (e.g.,
+//!   [`TcpSocket::recv_async`]) to be run by the executor in
+//!   `axtask::future`. It also enables the `multitask` and `irq` features.
+//!   The interrupt handler of the NIC should call [`on_nic_irq`] to receive
+//!   the packets the futures wait for.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
@@ -32,6 +41,8 @@ cfg_if::cfg_if! {
 
 pub use self::net_impl::TcpSocket;
 pub use self::net_impl::UdpSocket;
+#[cfg(feature = "async")]
+pub use self::net_impl::on_nic_irq;
 pub use self::net_impl::{bench_receive, bench_transmit};
 pub use self::net_impl::{dns_query, poll_interfaces};
 
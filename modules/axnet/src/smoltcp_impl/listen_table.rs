@@ -1,8 +1,16 @@
 use alloc::{boxed::Box, collections::VecDeque};
+#[cfg(all(feature = "multitask", feature = "irq"))]
+use alloc::{sync::Arc, vec::Vec};
 use core::ops::{Deref, DerefMut};
+#[cfg(all(feature = "multitask", feature = "irq"))]
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "async")]
+use core::task::Waker;
 
 use axerrno::{AxError, AxResult, ax_err};
 use axsync::Mutex;
+#[cfg(all(feature = "multitask", feature = "irq"))]
+use axtask::WaitQueue;
 use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{self, State};
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
@@ -11,9 +19,81 @@ use super::{LISTEN_QUEUE_SIZE, SOCKET_SET, SocketSetWrapper};
 
 const PORT_NUM: usize = 65536;
 
+/// The tasks blocked in `accept()` on a listening port.
+#[cfg(all(feature = "multitask", feature = "irq"))]
+pub struct Acceptors {
+    wq: WaitQueue,
+    /// Bumped on each notification, so that a notification right before
+    /// waiting is not lost.
+    seq: AtomicUsize,
+    /// Whether one of the tasks is waiting with a timeout to poll the
+    /// interfaces, see [`Acceptors::wait`].
+    polling: AtomicBool,
+}
+
+#[cfg(all(feature = "multitask", feature = "irq"))]
+impl Acceptors {
+    fn new() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            seq: AtomicUsize::new(0),
+            polling: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the sequence number to wait with, it must be read before
+    /// checking for connections.
+    pub fn seq(&self) -> usize {
+        self.seq.load(Ordering::Acquire)
+    }
+
+    /// Waits for a notification after `seq`.
+    ///
+    /// The tasks wait as exclusive waiters, so that a new connection only
+    /// wakes up one of them. As nobody else may poll the interfaces while
+    /// all of them sleep, one of them also wakes up after `poll_interval` to
+    /// poll the interfaces, in which case `true` is returned. It must call
+    /// [`Acceptors::hand_over`] if it stops waiting.
+    pub fn wait(&self, seq: usize, poll_interval: core::time::Duration) -> bool {
+        if self.polling.swap(true, Ordering::AcqRel) {
+            self.wq.wait_until_exclusive(|| self.seq() != seq);
+            false
+        } else {
+            self.wq
+                .wait_timeout_until_exclusive(poll_interval, || self.seq() != seq);
+            self.polling.store(false, Ordering::Release);
+            true
+        }
+    }
+
+    /// Wakes up another task to poll the interfaces in place of the one
+    /// stopping waiting, unless some task has taken over already.
+    pub fn hand_over(&self) {
+        if !self.polling.load(Ordering::Acquire) {
+            self.notify(false);
+        }
+    }
+
+    /// Wakes up one of the tasks for a new connection, or all of them if the
+    /// port is no longer listening.
+    fn notify(&self, all: bool) {
+        self.seq.fetch_add(1, Ordering::AcqRel);
+        if all {
+            self.wq.notify_all(true);
+        } else {
+            self.wq.notify_exclusive(true);
+        }
+    }
+}
+
 struct ListenTableEntry {
     listen_endpoint: IpListenEndpoint,
     syn_queue: VecDeque<SocketHandle>,
+    /// Woken up when a connection in the SYN queue is established.
+    #[cfg(feature = "async")]
+    accept_waker: Option<Waker>,
+    #[cfg(all(feature = "multitask", feature = "irq"))]
+    acceptors: Arc<Acceptors>,
 }
 
 impl ListenTableEntry {
@@ -21,6 +101,10 @@ impl ListenTableEntry {
         Self {
             listen_endpoint,
             syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            #[cfg(feature = "async")]
+            accept_waker: None,
+            #[cfg(all(feature = "multitask", feature = "irq"))]
+            acceptors: Arc::new(Acceptors::new()),
         }
     }
 
@@ -38,11 +122,17 @@ impl Drop for ListenTableEntry {
         for &handle in &self.syn_queue {
             SOCKET_SET.remove(handle);
         }
+        #[cfg(all(feature = "multitask", feature = "irq"))]
+        self.acceptors.notify(true);
     }
 }
 
 pub struct ListenTable {
     tcp: Box<[Mutex<Option<Box<ListenTableEntry>>>]>,
+    /// The listening ports that received packets for their SYN queues since
+    /// the last check, see [`ListenTable::notify_acceptors`].
+    #[cfg(all(feature = "multitask", feature = "irq"))]
+    active_ports: Mutex<Vec<u16>>,
 }
 
 impl ListenTable {
@@ -54,7 +144,11 @@ impl ListenTable {
             }
             buf.assume_init()
         };
-        Self { tcp }
+        Self {
+            tcp,
+            #[cfg(all(feature = "multitask", feature = "irq"))]
+            active_ports: Mutex::new(Vec::new()),
+        }
     }
 
     pub fn can_listen(&self, port: u16) -> bool {
@@ -110,6 +204,64 @@ impl ListenTable {
         }
     }
 
+    /// Registers a waker to be woken up when a connection on the port may
+    /// be ready to accept.
+    #[cfg(feature = "async")]
+    pub fn register_accept_waker(&self, port: u16, waker: &Waker) {
+        if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
+            // The pending connections wake it up when established, and the
+            // new ones get it in `incoming_tcp_packet`.
+            for &handle in &entry.syn_queue {
+                SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    socket.register_recv_waker(waker)
+                });
+            }
+            entry.accept_waker = Some(waker.clone());
+        }
+    }
+
+    /// Returns the tasks blocked in `accept()` on the port.
+    #[cfg(all(feature = "multitask", feature = "irq"))]
+    pub fn acceptors(&self, port: u16) -> AxResult<Arc<Acceptors>> {
+        if let Some(entry) = self.tcp[port as usize].lock().deref() {
+            Ok(entry.acceptors.clone())
+        } else {
+            ax_err!(InvalidInput, "socket accept() failed: not listen")
+        }
+    }
+
+    /// Records a TCP packet other than the first SYN to `dst`, which may
+    /// establish a connection in the SYN queue.
+    #[cfg(all(feature = "multitask", feature = "irq"))]
+    pub fn incoming_tcp_ack(&self, dst: IpEndpoint) {
+        let has_pending = self.tcp[dst.port as usize]
+            .lock()
+            .as_ref()
+            .is_some_and(|entry| !entry.syn_queue.is_empty());
+        if has_pending {
+            let mut ports = self.active_ports.lock();
+            if !ports.contains(&dst.port) {
+                ports.push(dst.port);
+            }
+        }
+    }
+
+    /// Wakes up one of the tasks blocked in `accept()` on each port that
+    /// has new connections established, after the interfaces are polled.
+    #[cfg(all(feature = "multitask", feature = "irq"))]
+    pub fn notify_acceptors(&self) {
+        let ports = core::mem::take(&mut *self.active_ports.lock());
+        for port in ports {
+            let entry = self.tcp[port as usize].lock();
+            let ready = entry
+                .as_ref()
+                .filter(|entry| entry.syn_queue.iter().any(|&handle| is_connected(handle)));
+            if let Some(entry) = ready {
+                entry.acceptors.notify(false);
+            }
+        }
+    }
+
     pub fn incoming_tcp_packet(
         &self,
         src: IpEndpoint,
@@ -128,6 +280,10 @@ impl ListenTable {
             }
             let mut socket = SocketSetWrapper::new_tcp_socket();
             if socket.listen(entry.listen_endpoint).is_ok() {
+                #[cfg(feature = "async")]
+                if let Some(waker) = &entry.accept_waker {
+                    socket.register_recv_waker(waker);
+                }
                 let handle = sockets.add(socket);
                 debug!(
                     "TCP socket {}: prepare for connection {} -> {}",
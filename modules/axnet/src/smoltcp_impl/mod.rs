@@ -2,6 +2,8 @@ mod addr;
 mod bench;
 mod dns;
 mod listen_table;
+#[cfg(feature = "async")]
+mod poller;
 mod tcp;
 mod udp;
 
@@ -23,6 +25,8 @@ use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
 use self::listen_table::ListenTable;
 
 pub use self::dns::dns_query;
+#[cfg(feature = "async")]
+pub use self::poller::on_nic_irq;
 pub use self::tcp::TcpSocket;
 pub use self::udp::UdpSocket;
 
@@ -121,6 +125,16 @@ impl<'a> SocketSetWrapper<'a> {
 
     pub fn poll_interfaces(&self) {
         ETH0.poll(&self.0);
+        #[cfg(all(feature = "multitask", feature = "irq"))]
+        LISTEN_TABLE.notify_acceptors();
+    }
+
+    /// Polls the interfaces, and returns how long to wait before the next
+    /// poll, or `None` if nothing is due until new packets arrive.
+    #[cfg(feature = "async")]
+    pub fn poll_interfaces_delay(&self) -> Option<core::time::Duration> {
+        self.poll_interfaces();
+        ETH0.poll_delay(&self.0)
     }
 
     pub fn remove(&self, handle: SocketHandle) {
@@ -177,6 +191,16 @@ impl InterfaceWrapper {
         let timestamp = Self::current_time();
         iface.poll(timestamp, dev.deref_mut(), &mut sockets);
     }
+
+    #[cfg(feature = "async")]
+    pub fn poll_delay(&self, sockets: &Mutex<SocketSet>) -> Option<core::time::Duration> {
+        let mut iface = self.iface.lock();
+        let sockets = sockets.lock();
+        let timestamp = Self::current_time();
+        iface
+            .poll_delay(timestamp, &sockets)
+            .map(|delay| core::time::Duration::from_micros(delay.total_micros()))
+    }
 }
 
 impl DeviceWrapper {
@@ -293,6 +317,9 @@ fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) -> Result<(), smolt
         if is_first {
             // create a socket for the first incoming TCP packet, as the later accept() returns.
             LISTEN_TABLE.incoming_tcp_packet(src_addr, dst_addr, sockets);
+        } else {
+            #[cfg(all(feature = "multitask", feature = "irq"))]
+            LISTEN_TABLE.incoming_tcp_ack(dst_addr);
         }
     }
     Ok(())
@@ -0,0 +1,100 @@
+//! Drives the network stack for the asynchronous socket operations.
+//!
+//! A future waiting for a socket registers its waker on the smoltcp sockets
+//! it waits for, which wake it only when their states change. A poller task
+//! polls the interfaces while any future is waiting, and sleeps in between
+//! until the deadline reported by smoltcp (e.g., for a retransmission), or
+//! until new packets arrive, which is signaled by the NIC interrupt handler
+//! calling [`on_nic_irq`].
+
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::{Poll, Waker};
+
+use axerrno::{AxError, AxResult};
+use axtask::WaitQueue;
+
+use super::SOCKET_SET;
+
+static NUM_WAITING: AtomicUsize = AtomicUsize::new(0);
+static POLLER_WQ: WaitQueue = WaitQueue::new();
+static POLLER_STARTED: AtomicBool = AtomicBool::new(false);
+/// Whether the interfaces should be polled before the deadline reported by
+/// smoltcp, e.g., for new futures or incoming packets.
+static POLL_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Counts a waiting future until it's dropped.
+struct Waiting;
+
+impl Waiting {
+    fn new() -> Self {
+        NUM_WAITING.fetch_add(1, Ordering::AcqRel);
+        Self
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        NUM_WAITING.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Makes the poller poll the interfaces immediately, e.g., to transmit the
+/// packets just queued.
+fn kick() {
+    if !POLLER_STARTED.swap(true, Ordering::AcqRel) {
+        axtask::spawn(poller);
+    }
+    on_nic_irq();
+}
+
+/// Makes the poller receive the packets just arrived, called by the interrupt
+/// handler of the NIC.
+pub fn on_nic_irq() {
+    POLL_PENDING.store(true, Ordering::Release);
+    POLLER_WQ.notify_one(false);
+}
+
+fn poller() {
+    let pending = || POLL_PENDING.load(Ordering::Acquire);
+    loop {
+        POLLER_WQ.wait_until(|| NUM_WAITING.load(Ordering::Acquire) > 0);
+        // Cleared before polling, so that the requests during the poll are
+        // not missed.
+        POLL_PENDING.store(false, Ordering::Release);
+        match SOCKET_SET.poll_interfaces_delay() {
+            Some(delay) if delay.is_zero() => axtask::yield_now(),
+            Some(delay) => {
+                POLLER_WQ.wait_timeout_until(delay, pending);
+            }
+            // Nothing is due until new packets arrive.
+            None => POLLER_WQ.wait_until(pending),
+        }
+    }
+}
+
+/// Calls `f` until it does not return [`Err(WouldBlock)`](AxError::WouldBlock),
+/// and suspends the calling future between the calls.
+///
+/// `register` registers the waker of the future on the sockets that `f`
+/// waits for. It's called before each call of `f`, so that a state change in
+/// between is not missed.
+pub(crate) async fn poll_io<F, R, T>(mut f: F, register: R) -> AxResult<T>
+where
+    F: FnMut() -> AxResult<T>,
+    R: Fn(&Waker),
+{
+    let mut waiting = None;
+    poll_fn(|cx| {
+        register(cx.waker());
+        match f() {
+            Err(AxError::WouldBlock) => {
+                waiting.get_or_insert_with(Waiting::new);
+                kick();
+                Poll::Pending
+            }
+            res => Poll::Ready(res),
+        }
+    })
+    .await
+}
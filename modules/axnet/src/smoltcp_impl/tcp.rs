@@ -1,6 +1,8 @@
 use core::cell::UnsafeCell;
 use core::net::SocketAddr;
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+#[cfg(feature = "async")]
+use core::task::Waker;
 
 use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axio::PollState;
@@ -11,6 +13,8 @@ use smoltcp::socket::tcp::{self, ConnectError, State};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{UNSPECIFIED_ENDPOINT, from_core_sockaddr, into_core_sockaddr, is_unspecified};
+#[cfg(feature = "async")]
+use super::poller::poll_io;
 use super::{ETH0, LISTEN_TABLE, SOCKET_SET, SocketSetWrapper};
 
 // State transitions:
@@ -25,6 +29,11 @@ const STATE_CONNECTING: u8 = 2;
 const STATE_CONNECTED: u8 = 3;
 const STATE_LISTENING: u8 = 4;
 
+/// How long the task blocked in `accept()` that polls the interfaces for the
+/// others sleeps between two polls.
+#[cfg(all(feature = "multitask", feature = "irq"))]
+const ACCEPT_POLL_INTERVAL: core::time::Duration = core::time::Duration::from_millis(1);
+
 /// A TCP socket that provides POSIX-like APIs.
 ///
 /// - [`connect`] is for TCP clients.
@@ -119,60 +128,32 @@ impl TcpSocket {
     ///
     /// The local port is generated automatically.
     pub fn connect(&self, remote_addr: SocketAddr) -> AxResult {
-        self.update_state(STATE_CLOSED, STATE_CONNECTING, || {
-            // SAFETY: no other threads can read or write these fields.
-            let handle = unsafe { self.handle.get().read() }
-                .unwrap_or_else(|| SOCKET_SET.add(SocketSetWrapper::new_tcp_socket()));
-
-            // TODO: check remote addr unreachable
-            let remote_endpoint = from_core_sockaddr(remote_addr);
-            let bound_endpoint = self.bound_endpoint()?;
-            let iface = &ETH0.iface;
-            let (local_endpoint, remote_endpoint) = SOCKET_SET
-                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                    socket
-                        .connect(iface.lock().context(), remote_endpoint, bound_endpoint)
-                        .or_else(|e| match e {
-                            ConnectError::InvalidState => {
-                                ax_err!(BadState, "socket connect() failed")
-                            }
-                            ConnectError::Unaddressable => {
-                                ax_err!(ConnectionRefused, "socket connect() failed")
-                            }
-                        })?;
-                    Ok((
-                        socket.local_endpoint().unwrap(),
-                        socket.remote_endpoint().unwrap(),
-                    ))
-                })?;
-            unsafe {
-                // SAFETY: no other threads can read or write these fields as we
-                // have changed the state to `BUSY`.
-                self.local_addr.get().write(local_endpoint);
-                self.peer_addr.get().write(remote_endpoint);
-                self.handle.get().write(Some(handle));
-            }
-            Ok(())
-        })
-        .unwrap_or_else(|_| ax_err!(AlreadyExists, "socket connect() failed: already connected"))?; // EISCONN
-
+        self.start_connect(remote_addr)?;
         // Here our state must be `CONNECTING`, and only one thread can run here.
         if self.is_nonblocking() {
             Err(AxError::WouldBlock)
         } else {
-            self.block_on(|| {
-                let PollState { writable, .. } = self.poll_connect()?;
-                if !writable {
-                    Err(AxError::WouldBlock)
-                } else if self.get_state() == STATE_CONNECTED {
-                    Ok(())
-                } else {
-                    ax_err!(ConnectionRefused, "socket connect() failed")
-                }
-            })
+            self.block_on(|| self.try_finish_connect())
         }
     }
 
+    /// Connects to the given address and port asynchronously, see
+    /// [`connect`](Self::connect).
+    ///
+    /// It ignores the nonblocking mode, and suspends the calling future
+    /// instead of blocking the task.
+    #[cfg(feature = "async")]
+    pub async fn connect_async(&self, remote_addr: SocketAddr) -> AxResult {
+        self.start_connect(remote_addr)?;
+        // SAFETY: `self.handle` is initialized by `start_connect`.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        poll_io(
+            || self.try_finish_connect(),
+            |waker| register_send_waker(handle, waker),
+        )
+        .await
+    }
+
     /// Binds an unbound socket to the given address and port.
     ///
     /// If the given port is 0, it generates one automatically.
@@ -223,17 +204,26 @@ impl TcpSocket {
     ///
     /// It's must be called after [`bind`](Self::bind) and [`listen`](Self::listen).
     pub fn accept(&self) -> AxResult<TcpSocket> {
-        if !self.is_listening() {
-            return ax_err!(InvalidInput, "socket accept() failed: not listen");
+        let local_port = self.listening_port()?;
+        #[cfg(all(feature = "multitask", feature = "irq"))]
+        if !self.is_nonblocking() {
+            return Self::block_on_accept(local_port);
         }
+        self.block_on(|| Self::try_accept(local_port))
+    }
 
-        // SAFETY: `self.local_addr` should be initialized after `bind()`.
-        let local_port = unsafe { self.local_addr.get().read().port };
-        self.block_on(|| {
-            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
-            debug!("TCP socket accepted a new connection {}", peer_addr);
-            Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
-        })
+    /// Accepts a new connection asynchronously, see [`accept`](Self::accept).
+    ///
+    /// It ignores the nonblocking mode, and suspends the calling future
+    /// instead of blocking the task.
+    #[cfg(feature = "async")]
+    pub async fn accept_async(&self) -> AxResult<TcpSocket> {
+        let local_port = self.listening_port()?;
+        poll_io(
+            || Self::try_accept(local_port),
+            |waker| LISTEN_TABLE.register_accept_waker(local_port, waker),
+        )
+        .await
     }
 
     /// Close the connection.
@@ -271,65 +261,43 @@ impl TcpSocket {
 
     /// Receives data from the socket, stores it in the given buffer.
     pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
-        if self.is_connecting() {
-            return Err(AxError::WouldBlock);
-        } else if !self.is_connected() {
-            return ax_err!(NotConnected, "socket recv() failed");
-        }
+        let handle = self.connected_handle("socket recv() failed")?;
+        self.block_on(|| Self::try_recv(handle, buf))
+    }
 
-        // SAFETY: `self.handle` should be initialized in a connected socket.
-        let handle = unsafe { self.handle.get().read().unwrap() };
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() {
-                    // not open
-                    ax_err!(ConnectionRefused, "socket recv() failed")
-                } else if !socket.may_recv() {
-                    // connection closed
-                    Ok(0)
-                } else if socket.recv_queue() > 0 {
-                    // data available
-                    // TODO: use socket.recv(|buf| {...})
-                    let len = socket
-                        .recv_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
-                    Ok(len)
-                } else {
-                    // no more data
-                    Err(AxError::WouldBlock)
-                }
-            })
-        })
+    /// Receives data from the socket asynchronously, see [`recv`](Self::recv).
+    ///
+    /// It ignores the nonblocking mode, and suspends the calling future
+    /// instead of blocking the task.
+    #[cfg(feature = "async")]
+    pub async fn recv_async(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let handle = self.connected_handle("socket recv() failed")?;
+        poll_io(
+            || Self::try_recv(handle, buf),
+            |waker| register_recv_waker(handle, waker),
+        )
+        .await
     }
 
     /// Transmits data in the given buffer.
     pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
-        if self.is_connecting() {
-            return Err(AxError::WouldBlock);
-        } else if !self.is_connected() {
-            return ax_err!(NotConnected, "socket send() failed");
-        }
+        let handle = self.connected_handle("socket send() failed")?;
+        self.block_on(|| Self::try_send(handle, buf))
+    }
 
-        // SAFETY: `self.handle` should be initialized in a connected socket.
-        let handle = unsafe { self.handle.get().read().unwrap() };
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() || !socket.may_send() {
-                    // closed by remote
-                    ax_err!(ConnectionReset, "socket send() failed")
-                } else if socket.can_send() {
-                    // connected, and the tx buffer is not full
-                    // TODO: use socket.send(|buf| {...})
-                    let len = socket
-                        .send_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
-                    Ok(len)
-                } else {
-                    // tx buffer is full
-                    Err(AxError::WouldBlock)
-                }
-            })
-        })
+    /// Transmits data in the given buffer asynchronously, see
+    /// [`send`](Self::send).
+    ///
+    /// It ignores the nonblocking mode, and suspends the calling future
+    /// instead of blocking the task.
+    #[cfg(feature = "async")]
+    pub async fn send_async(&self, buf: &[u8]) -> AxResult<usize> {
+        let handle = self.connected_handle("socket send() failed")?;
+        poll_io(
+            || Self::try_send(handle, buf),
+            |waker| register_send_waker(handle, waker),
+        )
+        .await
     }
 
     /// Whether the socket is readable or writable.
@@ -419,6 +387,128 @@ impl TcpSocket {
         Ok(IpListenEndpoint { addr, port })
     }
 
+    /// Starts connecting to the given address, and changes the state to
+    /// `CONNECTING`.
+    fn start_connect(&self, remote_addr: SocketAddr) -> AxResult {
+        self.update_state(STATE_CLOSED, STATE_CONNECTING, || {
+            // SAFETY: no other threads can read or write these fields.
+            let handle = unsafe { self.handle.get().read() }
+                .unwrap_or_else(|| SOCKET_SET.add(SocketSetWrapper::new_tcp_socket()));
+
+            // TODO: check remote addr unreachable
+            let remote_endpoint = from_core_sockaddr(remote_addr);
+            let bound_endpoint = self.bound_endpoint()?;
+            let iface = &ETH0.iface;
+            let (local_endpoint, remote_endpoint) = SOCKET_SET
+                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    socket
+                        .connect(iface.lock().context(), remote_endpoint, bound_endpoint)
+                        .or_else(|e| match e {
+                            ConnectError::InvalidState => {
+                                ax_err!(BadState, "socket connect() failed")
+                            }
+                            ConnectError::Unaddressable => {
+                                ax_err!(ConnectionRefused, "socket connect() failed")
+                            }
+                        })?;
+                    Ok((
+                        socket.local_endpoint().unwrap(),
+                        socket.remote_endpoint().unwrap(),
+                    ))
+                })?;
+            unsafe {
+                // SAFETY: no other threads can read or write these fields as we
+                // have changed the state to `BUSY`.
+                self.local_addr.get().write(local_endpoint);
+                self.peer_addr.get().write(remote_endpoint);
+                self.handle.get().write(Some(handle));
+            }
+            Ok(())
+        })
+        .unwrap_or_else(|_| ax_err!(AlreadyExists, "socket connect() failed: already connected")) // EISCONN
+    }
+
+    /// Checks whether the connection started by `start_connect` has been
+    /// established.
+    fn try_finish_connect(&self) -> AxResult {
+        let PollState { writable, .. } = self.poll_connect()?;
+        if !writable {
+            Err(AxError::WouldBlock)
+        } else if self.get_state() == STATE_CONNECTED {
+            Ok(())
+        } else {
+            ax_err!(ConnectionRefused, "socket connect() failed")
+        }
+    }
+
+    /// Returns the local port of a listening socket.
+    fn listening_port(&self) -> AxResult<u16> {
+        if !self.is_listening() {
+            return ax_err!(InvalidInput, "socket accept() failed: not listen");
+        }
+        // SAFETY: `self.local_addr` should be initialized after `bind()`.
+        Ok(unsafe { self.local_addr.get().read().port })
+    }
+
+    fn try_accept(local_port: u16) -> AxResult<TcpSocket> {
+        let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
+        debug!("TCP socket accepted a new connection {}", peer_addr);
+        Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
+    }
+
+    /// Returns the socket handle of a connected socket, or an error with
+    /// `err_msg` if not connected.
+    fn connected_handle(&self, err_msg: &str) -> AxResult<SocketHandle> {
+        if self.is_connecting() {
+            return Err(AxError::WouldBlock);
+        } else if !self.is_connected() {
+            return ax_err!(NotConnected, err_msg);
+        }
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        Ok(unsafe { self.handle.get().read().unwrap() })
+    }
+
+    fn try_recv(handle: SocketHandle, buf: &mut [u8]) -> AxResult<usize> {
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            if !socket.is_active() {
+                // not open
+                ax_err!(ConnectionRefused, "socket recv() failed")
+            } else if !socket.may_recv() {
+                // connection closed
+                Ok(0)
+            } else if socket.recv_queue() > 0 {
+                // data available
+                // TODO: use socket.recv(|buf| {...})
+                let len = socket
+                    .recv_slice(buf)
+                    .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
+                Ok(len)
+            } else {
+                // no more data
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
+    fn try_send(handle: SocketHandle, buf: &[u8]) -> AxResult<usize> {
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            if !socket.is_active() || !socket.may_send() {
+                // closed by remote
+                ax_err!(ConnectionReset, "socket send() failed")
+            } else if socket.can_send() {
+                // connected, and the tx buffer is not full
+                // TODO: use socket.send(|buf| {...})
+                let len = socket
+                    .send_slice(buf)
+                    .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
+                Ok(len)
+            } else {
+                // tx buffer is full
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
     fn poll_connect(&self) -> AxResult<PollState> {
         // SAFETY: `self.handle` should be initialized above.
         let handle = unsafe { self.handle.get().read().unwrap() };
@@ -469,6 +559,32 @@ impl TcpSocket {
         })
     }
 
+    /// Blocks the current task until a connection on the port is accepted.
+    ///
+    /// The task sleeps as an exclusive waiter of the port, and is woken up by
+    /// whoever polls the interfaces and finds a new connection. As no task may
+    /// poll the interfaces while all of them sleep, one of the waiters polls
+    /// them every [`ACCEPT_POLL_INTERVAL`], and hands the polling over to
+    /// another one when it leaves.
+    #[cfg(all(feature = "multitask", feature = "irq"))]
+    fn block_on_accept(local_port: u16) -> AxResult<TcpSocket> {
+        let acceptors = LISTEN_TABLE.acceptors(local_port)?;
+        let mut polling = false;
+        loop {
+            let seq = acceptors.seq();
+            SOCKET_SET.poll_interfaces();
+            match Self::try_accept(local_port) {
+                Err(AxError::WouldBlock) => polling = acceptors.wait(seq, ACCEPT_POLL_INTERVAL),
+                res => {
+                    if polling {
+                        acceptors.hand_over();
+                    }
+                    return res;
+                }
+            }
+        }
+    }
+
     /// Block the current thread until the given function completes or fails.
     ///
     /// If the socket is non-blocking, it calls the function once and returns
@@ -525,3 +641,15 @@ fn get_ephemeral_port() -> AxResult<u16> {
     }
     ax_err!(AddrInUse, "no avaliable ports!")
 }
+
+#[cfg(feature = "async")]
+fn register_recv_waker(handle: SocketHandle, waker: &Waker) {
+    SOCKET_SET
+        .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| socket.register_recv_waker(waker));
+}
+
+#[cfg(feature = "async")]
+fn register_send_waker(handle: SocketHandle, waker: &Waker) {
+    SOCKET_SET
+        .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| socket.register_send_waker(waker));
+}
@@ -1,5 +1,7 @@
 use core::net::SocketAddr;
 use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "async")]
+use core::task::Waker;
 
 use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axio::PollState;
@@ -11,6 +13,8 @@ use smoltcp::socket::udp::{self, BindError, SendError};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{UNSPECIFIED_ENDPOINT, from_core_sockaddr, into_core_sockaddr, is_unspecified};
+#[cfg(feature = "async")]
+use super::poller::poll_io;
 use super::{SOCKET_SET, SocketSetWrapper};
 
 /// A UDP socket that provides POSIX-like APIs.
@@ -109,6 +113,25 @@ impl UdpSocket {
         self.send_impl(buf, from_core_sockaddr(remote_addr))
     }
 
+    /// Sends data on the socket to the given address asynchronously, see
+    /// [`send_to`](Self::send_to).
+    ///
+    /// It ignores the nonblocking mode, and suspends the calling future
+    /// instead of blocking the task.
+    #[cfg(feature = "async")]
+    pub async fn send_to_async(&self, buf: &[u8], remote_addr: SocketAddr) -> AxResult<usize> {
+        if remote_addr.port() == 0 || remote_addr.ip().is_unspecified() {
+            return ax_err!(InvalidInput, "socket send_to() failed: invalid address");
+        }
+        self.check_bound("socket send() failed")?;
+        let remote_endpoint = from_core_sockaddr(remote_addr);
+        poll_io(
+            || self.try_send(buf, remote_endpoint),
+            |waker| self.register_send_waker(waker),
+        )
+        .await
+    }
+
     /// Receives a single datagram message on the socket. On success, returns
     /// the number of bytes read and the origin.
     pub fn recv_from(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
@@ -118,6 +141,26 @@ impl UdpSocket {
         })
     }
 
+    /// Receives a single datagram message on the socket asynchronously, see
+    /// [`recv_from`](Self::recv_from).
+    ///
+    /// It ignores the nonblocking mode, and suspends the calling future
+    /// instead of blocking the task.
+    #[cfg(feature = "async")]
+    pub async fn recv_from_async(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
+        self.check_bound("socket send() failed")?;
+        poll_io(
+            || {
+                self.try_recv(|socket| match socket.recv_slice(buf) {
+                    Ok((len, meta)) => Ok((len, into_core_sockaddr(meta.endpoint))),
+                    Err(_) => ax_err!(BadState, "socket recv_from() failed"),
+                })
+            },
+            |waker| self.register_recv_waker(waker),
+        )
+        .await
+    }
+
     /// Receives a single datagram message on the socket, without removing it from
     /// the queue. On success, returns the number of bytes read and the origin.
     pub fn peek_from(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
@@ -206,52 +249,75 @@ impl UdpSocket {
         }
     }
 
-    fn send_impl(&self, buf: &[u8], remote_endpoint: IpEndpoint) -> AxResult<usize> {
+    /// Returns an error with `err_msg` if the socket is not bound.
+    fn check_bound(&self, err_msg: &str) -> AxResult {
         if self.local_addr.read().is_none() {
-            return ax_err!(NotConnected, "socket send() failed");
+            return ax_err!(NotConnected, err_msg);
         }
+        Ok(())
+    }
 
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-                if socket.can_send() {
-                    socket
-                        .send_slice(buf, remote_endpoint)
-                        .map_err(|e| match e {
-                            SendError::BufferFull => AxError::WouldBlock,
-                            SendError::Unaddressable => {
-                                ax_err_type!(ConnectionRefused, "socket send() failed")
-                            }
-                        })?;
-                    Ok(buf.len())
-                } else {
-                    // tx buffer is full
-                    Err(AxError::WouldBlock)
-                }
-            })
-        })
+    fn send_impl(&self, buf: &[u8], remote_endpoint: IpEndpoint) -> AxResult<usize> {
+        self.check_bound("socket send() failed")?;
+        self.block_on(|| self.try_send(buf, remote_endpoint))
     }
 
     fn recv_impl<F, T>(&self, mut op: F) -> AxResult<T>
     where
         F: FnMut(&mut udp::Socket) -> AxResult<T>,
     {
-        if self.local_addr.read().is_none() {
-            return ax_err!(NotConnected, "socket send() failed");
-        }
+        self.check_bound("socket send() failed")?;
+        self.block_on(|| self.try_recv(&mut op))
+    }
 
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-                if socket.can_recv() {
-                    // data available
-                    op(socket)
-                } else {
-                    // no more data
-                    Err(AxError::WouldBlock)
-                }
-            })
+    fn try_send(&self, buf: &[u8], remote_endpoint: IpEndpoint) -> AxResult<usize> {
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+            if socket.can_send() {
+                socket
+                    .send_slice(buf, remote_endpoint)
+                    .map_err(|e| match e {
+                        SendError::BufferFull => AxError::WouldBlock,
+                        SendError::Unaddressable => {
+                            ax_err_type!(ConnectionRefused, "socket send() failed")
+                        }
+                    })?;
+                Ok(buf.len())
+            } else {
+                // tx buffer is full
+                Err(AxError::WouldBlock)
+            }
         })
     }
 
+    fn try_recv<F, T>(&self, op: F) -> AxResult<T>
+    where
+        F: FnOnce(&mut udp::Socket) -> AxResult<T>,
+    {
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+            if socket.can_recv() {
+                // data available
+                op(socket)
+            } else {
+                // no more data
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
+    #[cfg(feature = "async")]
+    fn register_recv_waker(&self, waker: &Waker) {
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+            socket.register_recv_waker(waker)
+        });
+    }
+
+    #[cfg(feature = "async")]
+    fn register_send_waker(&self, waker: &Waker) {
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+            socket.register_send_waker(waker)
+        });
+    }
+
     fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
     where
         F: FnMut() -> AxResult<T>,
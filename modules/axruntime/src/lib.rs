@@ -10,6 +10,7 @@
 //! - `irq`: Enable interrupt handling support.
 //! - `multitask`: Enable multi-threading support.
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
+//! - `watchdog`: Report hung tasks and stuck CPUs on timer ticks.
 //! - `fs`: Enable filesystem support.
 //! - `net`: Enable networking support.
 //! - `display`: Enable graphics support.
@@ -192,6 +193,11 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
 
     unsafe { main() };
 
+    #[cfg(feature = "fs")]
+    if let Err(e) = axfs::sync() {
+        warn!("failed to sync filesystems: {:?}", e);
+    }
+
     #[cfg(feature = "multitask")]
     axtask::exit(0);
     #[cfg(not(feature = "multitask"))]
@@ -258,6 +264,10 @@ fn init_interrupt() {
         axtask::on_timer_tick();
     });
 
+    // Setup IPI handler, e.g., for suspending tasks on other CPUs
+    #[cfg(all(feature = "smp", feature = "multitask"))]
+    axhal::irq::register_handler(axhal::irq::IPI_IRQ_NUM, axtask::on_ipi);
+
     // Enable IRQs before starting app
     axhal::arch::enable_irqs();
 }
@@ -0,0 +1,128 @@
+//! A barrier to synchronize a group of tasks.
+
+use axtask::WaitQueue;
+use kspin::SpinNoIrq;
+
+/// A barrier enables multiple tasks to synchronize the beginning of some
+/// computation, similar to
+/// [`std::sync::Barrier`](https://doc.rust-lang.org/std/sync/struct.Barrier.html).
+///
+/// The barrier is reusable: once all tasks have met, it is reset for the
+/// next round.
+pub struct Barrier {
+    wq: WaitQueue,
+    state: SpinNoIrq<BarrierState>,
+    num_tasks: usize,
+}
+
+struct BarrierState {
+    count: usize,
+    generation: usize,
+}
+
+/// A `BarrierWaitResult` is returned by [`Barrier::wait`] when all tasks in
+/// the [`Barrier`] have rendezvoused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// Returns `true` if this task is the "leader task" for the call to
+    /// [`Barrier::wait`].
+    ///
+    /// Only one task will have `true` returned from their result, all other
+    /// tasks will have `false` returned.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// Creates a new barrier that can block a given number of tasks.
+    ///
+    /// A barrier will block `n - 1` tasks which call [`Barrier::wait`] and
+    /// then wake up all tasks at once when the `n`th task calls
+    /// [`Barrier::wait`].
+    pub const fn new(n: usize) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            state: SpinNoIrq::new(BarrierState {
+                count: 0,
+                generation: 0,
+            }),
+            num_tasks: n,
+        }
+    }
+
+    /// Blocks the current task until all tasks have rendezvoused here.
+    ///
+    /// A single (arbitrary) task will receive a [`BarrierWaitResult`] that
+    /// returns `true` from [`BarrierWaitResult::is_leader`], the last one
+    /// arriving at the barrier in fact.
+    pub fn wait(&self) -> BarrierWaitResult {
+        let mut state = self.state.lock();
+        let generation = state.generation;
+        state.count += 1;
+        if state.count < self.num_tasks {
+            drop(state);
+            self.wq
+                .wait_until(|| self.state.lock().generation != generation);
+            BarrierWaitResult(false)
+        } else {
+            state.count = 0;
+            state.generation = state.generation.wrapping_add(1);
+            drop(state);
+            self.wq.notify_all(true);
+            BarrierWaitResult(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::Barrier;
+    use axtask as thread;
+
+    #[test]
+    fn rendezvous() {
+        let _lock = crate::tests::setup();
+
+        const NUM_TASKS: usize = 10;
+        const NUM_ROUNDS: usize = 5;
+        static BARRIER: Barrier = Barrier::new(NUM_TASKS);
+        static ARRIVED: AtomicUsize = AtomicUsize::new(0);
+        static LEADERS: AtomicUsize = AtomicUsize::new(0);
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        for i in 0..NUM_TASKS - 1 {
+            thread::spawn(move || {
+                for round in 0..NUM_ROUNDS {
+                    ARRIVED.fetch_add(1, Ordering::Relaxed);
+                    if BARRIER.wait().is_leader() {
+                        LEADERS.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // Everyone has arrived before anyone leaves.
+                    assert!(ARRIVED.load(Ordering::Relaxed) >= (round + 1) * NUM_TASKS);
+                    if i % 2 == 0 {
+                        thread::yield_now();
+                    }
+                }
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+
+        for round in 0..NUM_ROUNDS {
+            ARRIVED.fetch_add(1, Ordering::Relaxed);
+            if BARRIER.wait().is_leader() {
+                LEADERS.fetch_add(1, Ordering::Relaxed);
+            }
+            assert!(ARRIVED.load(Ordering::Relaxed) >= (round + 1) * NUM_TASKS);
+        }
+        while FINISHED.load(Ordering::Relaxed) < NUM_TASKS - 1 {
+            thread::yield_now();
+        }
+        assert_eq!(LEADERS.load(Ordering::Relaxed), NUM_ROUNDS);
+        println!("Barrier test OK");
+    }
+}
@@ -0,0 +1,369 @@
+//! A multi-producer, multi-consumer channel where each message is delivered
+//! to all receivers.
+//!
+//! The channel keeps the most recent `capacity` messages. Sending never
+//! blocks: when the buffer is full, the oldest message is dropped, and the
+//! receivers that have not seen it yet get a [`RecvError::Lagged`] error
+//! telling how many messages they missed, then continue from the oldest
+//! message still in the buffer.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::fmt;
+use core::task::Poll;
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use axtask::WaitQueue;
+use axtask::future::WakerQueue;
+use kspin::SpinNoIrq;
+
+pub use crate::mpsc::SendError;
+
+/// An error returned from [`Receiver::recv`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RecvError {
+    /// All senders have been dropped, and no messages are left for this
+    /// receiver.
+    Closed,
+    /// The receiver lagged too far behind, the number of skipped messages
+    /// is returned.
+    Lagged(u64),
+}
+
+/// An error returned from [`Receiver::try_recv`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryRecvError {
+    /// No new messages for this receiver, but the senders are still alive.
+    Empty,
+    /// All senders have been dropped, and no messages are left for this
+    /// receiver.
+    Closed,
+    /// The receiver lagged too far behind, the number of skipped messages
+    /// is returned.
+    Lagged(u64),
+}
+
+/// An error returned from [`Receiver::recv_timeout`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RecvTimeoutError {
+    /// No new messages arrived before the timeout, but the senders are still
+    /// alive.
+    Timeout,
+    /// All senders have been dropped, and no messages are left for this
+    /// receiver.
+    Closed,
+    /// The receiver lagged too far behind, the number of skipped messages
+    /// is returned.
+    Lagged(u64),
+}
+
+struct State<T> {
+    buf: VecDeque<T>,
+    /// The sequence number of the oldest message in `buf`.
+    head: u64,
+    senders: usize,
+    receivers: usize,
+}
+
+struct Channel<T> {
+    state: SpinNoIrq<State<T>>,
+    capacity: usize,
+    wq: WaitQueue,
+    wakers: WakerQueue,
+}
+
+/// The sending half of a broadcast channel created by [`channel`].
+///
+/// The sender can be cloned to send from multiple tasks.
+pub struct Sender<T> {
+    chan: Arc<Channel<T>>,
+}
+
+/// The receiving half of a broadcast channel created by [`channel`] or
+/// [`Sender::subscribe`].
+pub struct Receiver<T> {
+    chan: Arc<Channel<T>>,
+    /// The sequence number of the next message to receive.
+    next: u64,
+}
+
+/// Creates a new broadcast channel that keeps at most `capacity` messages,
+/// returning the sender/receiver halves.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "broadcast channel requires a capacity > 0");
+    let chan = Arc::new(Channel {
+        state: SpinNoIrq::new(State {
+            buf: VecDeque::with_capacity(capacity),
+            head: 0,
+            senders: 1,
+            receivers: 1,
+        }),
+        capacity,
+        wq: WaitQueue::new(),
+        wakers: WakerQueue::new(),
+    });
+    (Sender { chan: chan.clone() }, Receiver { chan, next: 0 })
+}
+
+impl<T> State<T> {
+    /// The sequence number of the next message to send.
+    fn tail(&self) -> u64 {
+        self.head + self.buf.len() as u64
+    }
+}
+
+impl<T: Clone> Sender<T> {
+    /// Sends a message to all receivers, it never blocks.
+    ///
+    /// Returns the number of receivers the message is sent to, or the message
+    /// back if there are no receivers.
+    pub fn send(&self, msg: T) -> Result<usize, SendError<T>> {
+        let mut state = self.chan.state.lock();
+        if state.receivers == 0 {
+            return Err(SendError(msg));
+        }
+        state.buf.push_back(msg);
+        let dropped = if state.buf.len() > self.chan.capacity {
+            state.head += 1;
+            state.buf.pop_front()
+        } else {
+            None
+        };
+        let receivers = state.receivers;
+        drop(state);
+        drop(dropped);
+        self.chan.wq.notify_all(true);
+        self.chan.wakers.notify_all();
+        Ok(receivers)
+    }
+
+    /// Creates a new receiver that receives the messages sent after this
+    /// call.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.chan.state.lock();
+        state.receivers += 1;
+        Receiver {
+            chan: self.chan.clone(),
+            next: state.tail(),
+        }
+    }
+
+    /// Returns the number of live receivers.
+    pub fn receiver_count(&self) -> usize {
+        self.chan.state.lock().receivers
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.state.lock().senders += 1;
+        Self {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.chan.state.lock();
+        state.senders -= 1;
+        let closed = state.senders == 0;
+        drop(state);
+        if closed {
+            self.chan.wq.notify_all(true);
+            self.chan.wakers.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T: Clone> Receiver<T> {
+    /// Attempts to receive the next message without blocking.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let state = self.chan.state.lock();
+        if self.next < state.head {
+            let lagged = state.head - self.next;
+            self.next = state.head;
+            return Err(TryRecvError::Lagged(lagged));
+        }
+        if self.next < state.tail() {
+            let msg = state.buf[(self.next - state.head) as usize].clone();
+            self.next += 1;
+            Ok(msg)
+        } else if state.senders == 0 {
+            Err(TryRecvError::Closed)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+
+    fn can_recv(&self) -> bool {
+        let state = self.chan.state.lock();
+        self.next < state.tail() || state.senders == 0
+    }
+
+    /// Receives the next message, blocking the current task until one is
+    /// available.
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        loop {
+            match self.try_recv() {
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Empty) => self.chan.wq.wait_until(|| self.can_recv()),
+                Err(TryRecvError::Closed) => return Err(RecvError::Closed),
+                Err(TryRecvError::Lagged(n)) => return Err(RecvError::Lagged(n)),
+            }
+        }
+    }
+
+    /// Receives the next message asynchronously.
+    ///
+    /// It's the same as [`Receiver::recv`], but suspends the calling future
+    /// instead of blocking the task.
+    pub async fn recv_async(&mut self) -> Result<T, RecvError> {
+        core::future::poll_fn(|cx| {
+            if !self.can_recv() {
+                self.chan.wakers.register(cx.waker());
+                // A message may have arrived before the registration.
+                if !self.can_recv() {
+                    return Poll::Pending;
+                }
+            }
+            Poll::Ready(self.try_recv().map_err(|err| match err {
+                TryRecvError::Lagged(n) => RecvError::Lagged(n),
+                _ => RecvError::Closed,
+            }))
+        })
+        .await
+    }
+
+    /// Receives the next message, blocking the current task until one is
+    /// available or the given duration has elapsed.
+    #[cfg(feature = "irq")]
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = axhal::time::wall_time() + timeout;
+        loop {
+            match self.try_recv() {
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Closed) => return Err(RecvTimeoutError::Closed),
+                Err(TryRecvError::Lagged(n)) => return Err(RecvTimeoutError::Lagged(n)),
+            }
+            let now = axhal::time::wall_time();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            self.chan
+                .wq
+                .wait_timeout_until(deadline - now, || self.can_recv());
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.chan.state.lock().receivers -= 1;
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RecvError::Closed => "channel closed".fmt(f),
+            RecvError::Lagged(n) => write!(f, "channel lagged by {n}"),
+        }
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TryRecvError::Empty => "channel empty".fmt(f),
+            TryRecvError::Closed => "channel closed".fmt(f),
+            TryRecvError::Lagged(n) => write!(f, "channel lagged by {n}"),
+        }
+    }
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RecvTimeoutError::Timeout => "timed out waiting on channel".fmt(f),
+            RecvTimeoutError::Closed => "channel closed".fmt(f),
+            RecvTimeoutError::Lagged(n) => write!(f, "channel lagged by {n}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::time::Duration;
+
+    use super::*;
+    use axtask as thread;
+
+    #[test]
+    fn fan_out() {
+        let _lock = crate::tests::setup();
+
+        const NUM_TASKS: usize = 5;
+        const NUM_MSGS: usize = 50;
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        let (tx, rx) = channel(NUM_MSGS);
+        for _ in 0..NUM_TASKS {
+            let mut rx = tx.subscribe();
+            thread::spawn(move || {
+                for i in 0..NUM_MSGS {
+                    assert_eq!(rx.recv(), Ok(i));
+                }
+                assert_eq!(rx.recv(), Err(RecvError::Closed));
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        drop(rx);
+        assert_eq!(tx.receiver_count(), NUM_TASKS);
+
+        for i in 0..NUM_MSGS {
+            assert_eq!(tx.send(i).unwrap(), NUM_TASKS);
+            if i % 10 == 0 {
+                thread::yield_now();
+            }
+        }
+        drop(tx);
+        while FINISHED.load(Ordering::Relaxed) < NUM_TASKS {
+            thread::yield_now();
+        }
+
+        // A slow receiver misses the oldest messages.
+        let (tx, mut rx) = channel(2);
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Lagged(3)));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Ok(4));
+        assert_eq!(
+            rx.recv_timeout(Duration::ZERO),
+            Err(RecvTimeoutError::Timeout)
+        );
+        drop(rx);
+        assert_eq!(tx.send(5), Err(SendError(5)));
+        println!("broadcast test OK");
+    }
+}
@@ -0,0 +1,222 @@
+//! A condition variable working with the sleeping [`Mutex`].
+
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use axtask::WaitQueue;
+
+use crate::{Mutex, MutexGuard};
+
+/// A type indicating whether a timed wait on a condition variable returned
+/// due to a time out or not.
+#[cfg(feature = "irq")]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct WaitTimeoutResult(bool);
+
+#[cfg(feature = "irq")]
+impl WaitTimeoutResult {
+    /// Returns `true` if the wait was known to have timed out.
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+/// A condition variable, similar to
+/// [`std::sync::Condvar`](https://doc.rust-lang.org/std/sync/struct.Condvar.html).
+///
+/// It blocks the waiting tasks in a wait queue, and is used with a [`Mutex`]
+/// that protects the shared state. As with other condition variables, a
+/// waiting task may wake up spuriously, so the condition should always be
+/// checked in a loop, or use [`Condvar::wait_while`] instead.
+pub struct Condvar {
+    wq: WaitQueue,
+    /// Bumped on every notification, so that a notification sent between
+    /// releasing the mutex and blocking is not lost.
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    /// Creates a new condition variable.
+    pub const fn new() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Blocks the current task until this condition variable receives a
+    /// notification.
+    ///
+    /// The mutex of `guard` is released while blocking, and re-acquired
+    /// before returning.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        // Take the sequence number with the mutex still held, so that a
+        // notification right after unlocking is seen.
+        let seq = self.seq.load(Ordering::Acquire);
+        let mutex = self.unlock(guard);
+        self.wq
+            .wait_until(|| self.seq.load(Ordering::Acquire) != seq);
+        mutex.lock()
+    }
+
+    /// Blocks the current task as long as `condition` returns `true`.
+    ///
+    /// The `condition` is checked with the mutex held, and the mutex is
+    /// released while blocking.
+    pub fn wait_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Waits on this condition variable for a notification, timing out after
+    /// the specified duration.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        dur: Duration,
+    ) -> (MutexGuard<'a, T>, WaitTimeoutResult) {
+        let seq = self.seq.load(Ordering::Acquire);
+        let mutex = self.unlock(guard);
+        let timeout = self
+            .wq
+            .wait_timeout_until(dur, || self.seq.load(Ordering::Acquire) != seq);
+        (mutex.lock(), WaitTimeoutResult(timeout))
+    }
+
+    /// Waits on this condition variable as long as `condition` returns
+    /// `true`, timing out after the specified duration.
+    ///
+    /// The returned [`WaitTimeoutResult`] tells whether the duration elapsed
+    /// while the condition still held.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        dur: Duration,
+        mut condition: F,
+    ) -> (MutexGuard<'a, T>, WaitTimeoutResult)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let deadline = axhal::time::wall_time() + dur;
+        while condition(&mut *guard) {
+            let now = axhal::time::wall_time();
+            if now >= deadline {
+                return (guard, WaitTimeoutResult(true));
+            }
+            guard = self.wait_timeout(guard, deadline - now).0;
+        }
+        (guard, WaitTimeoutResult(false))
+    }
+
+    /// Wakes up one blocked task on this condition variable.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Wakes up all blocked tasks on this condition variable.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_all(true);
+    }
+
+    fn unlock<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> &'a Mutex<T> {
+        let mutex = guard.lock;
+        drop(guard);
+        // Let tests notify between releasing the mutex and blocking.
+        #[cfg(test)]
+        if tests::NOTIFY_AFTER_UNLOCK.swap(false, Ordering::Relaxed) {
+            self.notify_one();
+        }
+        mutex
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use core::time::Duration;
+
+    use crate::{Condvar, Mutex};
+    use axtask as thread;
+
+    /// Makes the next wait notify the condition variable right after
+    /// releasing the mutex.
+    pub(super) static NOTIFY_AFTER_UNLOCK: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn notify_and_wait() {
+        let _lock = crate::tests::setup();
+
+        const NUM_TASKS: usize = 10;
+        static READY: Mutex<usize> = Mutex::new(0);
+        static CV: Condvar = Condvar::new();
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        for _ in 0..NUM_TASKS {
+            thread::spawn(|| {
+                let mut ready = CV.wait_while(READY.lock(), |ready| *ready == 0);
+                *ready += 1;
+                drop(ready);
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        thread::yield_now();
+
+        *READY.lock() = 1;
+        CV.notify_all();
+        while FINISHED.load(Ordering::Relaxed) < NUM_TASKS {
+            thread::yield_now();
+        }
+        assert_eq!(*READY.lock(), NUM_TASKS + 1);
+
+        // No one notifies, so it must time out.
+        let (guard, res) = CV.wait_timeout(READY.lock(), Duration::ZERO);
+        assert!(res.timed_out());
+        let (_guard, res) = CV.wait_timeout_while(guard, Duration::ZERO, |_| false);
+        assert!(!res.timed_out());
+        println!("Condvar test OK");
+    }
+
+    #[test]
+    fn notify_right_after_unlock() {
+        let _lock = crate::tests::setup();
+
+        static M: Mutex<()> = Mutex::new(());
+        static CV: Condvar = Condvar::new();
+        static RESCUED: AtomicBool = AtomicBool::new(false);
+
+        // The notification must not be lost, so it returns without timing out.
+        NOTIFY_AFTER_UNLOCK.store(true, Ordering::Relaxed);
+        let (guard, res) = CV.wait_timeout(M.lock(), Duration::ZERO);
+        assert!(!res.timed_out());
+
+        // Otherwise it would block until the rescue below.
+        thread::spawn(|| {
+            RESCUED.store(true, Ordering::Relaxed);
+            CV.notify_all();
+        });
+        NOTIFY_AFTER_UNLOCK.store(true, Ordering::Relaxed);
+        drop(CV.wait(guard));
+        assert!(!RESCUED.load(Ordering::Relaxed));
+        println!("Condvar notify after unlock test OK");
+    }
+}
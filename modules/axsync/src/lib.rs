@@ -3,26 +3,106 @@
 //! Currently supported primitives:
 //!
 //! - [`Mutex`]: A mutual exclusion primitive.
-//! - mod [`spin`]: spinlocks imported from the [`kspin`] crate.
+//! - [`PiMutex`]: A mutual exclusion primitive with priority inheritance.
+//! - [`Condvar`]: A condition variable working with [`Mutex`].
+//! - [`RwLock`]: A writer-preferring readers-writer lock, with upgradeable
+//!   reads.
+//! - [`Semaphore`]: A counting semaphore.
+//! - [`Barrier`]: A reusable barrier for a fixed number of tasks.
+//! - mod [`mpsc`]: multi-producer, single-consumer channels, both unbounded
+//!   and bounded.
+//! - mod [`oneshot`]: a channel for sending a single message.
+//! - mod [`broadcast`]: a channel that delivers each message to all
+//!   receivers.
+//! - mod [`spin`]: spinlocks imported from the [`kspin`] crate. With the
+//!   `lockdep` feature, [`spin::SpinNoIrq`] is replaced by the tracked one.
 //!
 //! # Cargo Features
 //!
 //! - `multitask`: For use in the multi-threaded environments. If the feature is
 //!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`]. This
 //!   feature is enabled by default.
+//! - `irq`: Enables the timeout variants of the blocking operations, e.g.,
+//!   `Condvar::wait_timeout` and `RwLock::write_timeout`.
+//! - `lockdep`: Tracks [`Mutex`] and [`spin::SpinNoIrq`] in the lock
+//!   dependency checker of `axtask`, to report potential deadlocks. It also
+//!   enables the `multitask` feature.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
-pub use kspin as spin;
+#[cfg(feature = "multitask")]
+extern crate alloc;
+
+/// Spinlocks imported from the [`kspin`] crate.
+pub mod spin {
+    pub use kspin::*;
 
+    #[cfg(feature = "lockdep")]
+    pub use axtask::lockdep::{SpinNoIrq, SpinNoIrqGuard};
+}
+
+#[cfg(feature = "multitask")]
+mod barrier;
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub mod broadcast;
+#[cfg(feature = "multitask")]
+mod condvar;
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub mod mpsc;
 #[cfg(feature = "multitask")]
 mod mutex;
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub mod oneshot;
+#[cfg(feature = "multitask")]
+mod pi_mutex;
+#[cfg(feature = "multitask")]
+mod rwlock;
+#[cfg(feature = "multitask")]
+mod semaphore;
 
 #[cfg(feature = "multitask")]
 #[doc(cfg(feature = "multitask"))]
 pub use self::mutex::{Mutex, MutexGuard};
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::pi_mutex::{PiMutex, PiMutexGuard};
+
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::barrier::{Barrier, BarrierWaitResult};
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::condvar::Condvar;
+#[cfg(all(feature = "multitask", feature = "irq"))]
+#[doc(cfg(all(feature = "multitask", feature = "irq")))]
+pub use self::condvar::WaitTimeoutResult;
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockUpgradableGuard, RwLockWriteGuard};
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::semaphore::{Semaphore, SemaphoreGuard};
 
 #[cfg(not(feature = "multitask"))]
 #[doc(cfg(not(feature = "multitask")))]
 pub use kspin::{SpinNoIrq as Mutex, SpinNoIrqGuard as MutexGuard};
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard, Once};
+
+    static INIT: Once = Once::new();
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    /// Initializes the scheduler once, and serializes the tests that spawn
+    /// tasks since they share the same run queue.
+    pub fn setup() -> MutexGuard<'static, ()> {
+        let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        INIT.call_once(axtask::init_scheduler);
+        guard
+    }
+}
@@ -0,0 +1,658 @@
+//! Multi-producer, single-consumer FIFO queue communication primitives,
+//! similar to [`std::sync::mpsc`](https://doc.rust-lang.org/std/sync/mpsc/index.html).
+//!
+//! - [`channel`] creates an unbounded channel, sending never blocks.
+//! - [`sync_channel`] creates a bounded channel, sending blocks while the
+//!   buffer is full. A channel with a bound of 0 is a "rendezvous" channel,
+//!   where each [`SyncSender::send`] blocks until the message is received,
+//!   and [`SyncSender::try_send`] only succeeds if the receiver is waiting.
+//!
+//! The channel is disconnected when all senders or the receiver are dropped.
+//! Receiving from a disconnected channel still returns the buffered messages
+//! first, while sending to it fails and returns the message back.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::fmt;
+use core::task::{Context, Poll};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use axtask::WaitQueue;
+use axtask::future::WakerQueue;
+use kspin::SpinNoIrq;
+
+/// An error returned from [`Sender::send`] or [`SyncSender::send`] when the
+/// receiver has been dropped. It contains the message that failed to send.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+/// An error returned from [`Receiver::recv`] when the channel is empty and
+/// all senders have been dropped.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct RecvError;
+
+/// The reasons why [`Receiver::try_recv`] could not return a message.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryRecvError {
+    /// The channel is currently empty, but the senders are still alive.
+    Empty,
+    /// The channel is empty and all senders have been dropped.
+    Disconnected,
+}
+
+/// The reasons why [`Receiver::recv_timeout`] could not return a message.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RecvTimeoutError {
+    /// No message arrived before the timeout, but the senders are still
+    /// alive.
+    Timeout,
+    /// The channel is empty and all senders have been dropped.
+    Disconnected,
+}
+
+/// The reasons why [`SyncSender::try_send`] could not send a message. It
+/// contains the message that failed to send.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    /// The buffer of the channel is full.
+    Full(T),
+    /// The receiver has been dropped.
+    Disconnected(T),
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    senders: usize,
+    receiver_alive: bool,
+    /// The number of messages received so far, which tells the senders of a
+    /// rendezvous channel that their messages have been taken.
+    received: u64,
+    /// Whether the receiver is blocked waiting for a message.
+    receiver_waiting: bool,
+}
+
+struct Channel<T> {
+    state: SpinNoIrq<State<T>>,
+    /// The bound of the channel, or [`None`] if unbounded.
+    bound: Option<usize>,
+    /// The receiver waits here for messages.
+    recv_wq: WaitQueue,
+    /// The receiver waits here for messages in [`Receiver::recv_async`].
+    recv_wakers: WakerQueue,
+    /// The senders of a bounded channel wait here for free space.
+    send_wq: WaitQueue,
+}
+
+/// The sending half of an unbounded channel created by [`channel`].
+///
+/// Messages can be sent with [`Sender::send`], which never blocks. The sender
+/// can be cloned to send from multiple tasks.
+pub struct Sender<T> {
+    chan: Arc<Channel<T>>,
+}
+
+/// The sending half of a bounded channel created by [`sync_channel`].
+///
+/// Messages can be sent with [`SyncSender::send`], which blocks while the
+/// buffer is full. The sender can be cloned to send from multiple tasks.
+pub struct SyncSender<T> {
+    chan: Arc<Channel<T>>,
+}
+
+/// The receiving half of a channel created by [`channel`] or
+/// [`sync_channel`].
+pub struct Receiver<T> {
+    chan: Arc<Channel<T>>,
+}
+
+/// Creates a new unbounded channel, returning the sender/receiver halves.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let chan = Arc::new(Channel::new(None));
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+/// Creates a new bounded channel that buffers at most `bound` messages,
+/// returning the sender/receiver halves.
+///
+/// If `bound` is 0, each message is handed over directly from the sender to
+/// the receiver.
+pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, Receiver<T>) {
+    let chan = Arc::new(Channel::new(Some(bound)));
+    (SyncSender { chan: chan.clone() }, Receiver { chan })
+}
+
+impl<T> Channel<T> {
+    fn new(bound: Option<usize>) -> Self {
+        Self {
+            state: SpinNoIrq::new(State {
+                queue: VecDeque::new(),
+                senders: 1,
+                receiver_alive: true,
+                received: 0,
+                receiver_waiting: false,
+            }),
+            bound,
+            recv_wq: WaitQueue::new(),
+            recv_wakers: WakerQueue::new(),
+            send_wq: WaitQueue::new(),
+        }
+    }
+
+    fn is_full(&self, state: &State<T>) -> bool {
+        // A rendezvous channel still holds one message in flight.
+        self.bound
+            .is_some_and(|bound| state.queue.len() >= bound.max(1))
+    }
+
+    /// Pushes the message if there is free space, returns the sequence
+    /// number of the message.
+    ///
+    /// If `handover` is `true`, a rendezvous channel only takes the message
+    /// when the receiver is waiting for it.
+    fn try_send(&self, msg: T, handover: bool) -> Result<u64, TrySendError<T>> {
+        let mut state = self.state.lock();
+        if !state.receiver_alive {
+            return Err(TrySendError::Disconnected(msg));
+        }
+        if self.is_full(&state) || (handover && self.bound == Some(0) && !state.receiver_waiting) {
+            return Err(TrySendError::Full(msg));
+        }
+        state.queue.push_back(msg);
+        let seq = state.received + state.queue.len() as u64;
+        drop(state);
+        self.recv_wq.notify_one(true);
+        self.recv_wakers.notify_all();
+        Ok(seq)
+    }
+
+    fn send(&self, mut msg: T) -> Result<(), SendError<T>> {
+        loop {
+            match self.try_send(msg, false) {
+                Ok(seq) => {
+                    if self.bound == Some(0) {
+                        self.send_wq.wait_until(|| {
+                            let state = self.state.lock();
+                            state.received >= seq || !state.receiver_alive
+                        });
+                    }
+                    return Ok(());
+                }
+                Err(TrySendError::Full(m)) => {
+                    msg = m;
+                    self.send_wq.wait_until(|| {
+                        let state = self.state.lock();
+                        !self.is_full(&state) || !state.receiver_alive
+                    });
+                }
+                Err(TrySendError::Disconnected(m)) => return Err(SendError(m)),
+            }
+        }
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.state.lock();
+        match state.queue.pop_front() {
+            Some(msg) => {
+                state.received += 1;
+                drop(state);
+                if self.bound.is_some() {
+                    // Senders of a rendezvous channel wait for their own
+                    // messages to be taken, so wake them all.
+                    self.send_wq.notify_all(true);
+                }
+                Ok(msg)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    fn can_recv(&self) -> bool {
+        let state = self.state.lock();
+        !state.queue.is_empty() || state.senders == 0
+    }
+
+    fn recv(&self) -> Result<T, RecvError> {
+        loop {
+            match self.try_recv() {
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Empty) => {
+                    self.set_receiver_waiting(true);
+                    self.recv_wq.wait_until(|| self.can_recv());
+                    self.set_receiver_waiting(false);
+                }
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+            }
+        }
+    }
+
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
+        match self.try_recv() {
+            Err(TryRecvError::Empty) => {}
+            res => return Poll::Ready(res.map_err(|_| RecvError)),
+        }
+        self.recv_wakers.register(cx.waker());
+        // A message may have arrived before the registration.
+        match self.try_recv() {
+            Err(TryRecvError::Empty) => Poll::Pending,
+            res => Poll::Ready(res.map_err(|_| RecvError)),
+        }
+    }
+
+    #[cfg(feature = "irq")]
+    fn recv_timeout(&self, dur: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = axhal::time::wall_time() + dur;
+        loop {
+            match self.try_recv() {
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+            }
+            let now = axhal::time::wall_time();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            self.set_receiver_waiting(true);
+            self.recv_wq
+                .wait_timeout_until(deadline - now, || self.can_recv());
+            self.set_receiver_waiting(false);
+        }
+    }
+
+    fn set_receiver_waiting(&self, waiting: bool) {
+        self.state.lock().receiver_waiting = waiting;
+    }
+
+    fn add_sender(&self) {
+        self.state.lock().senders += 1;
+    }
+
+    fn drop_sender(&self) {
+        let mut state = self.state.lock();
+        state.senders -= 1;
+        let disconnected = state.senders == 0;
+        drop(state);
+        if disconnected {
+            self.recv_wq.notify_all(true);
+            self.recv_wakers.notify_all();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Sends a message on the channel, it never blocks.
+    ///
+    /// Returns the message back if the receiver has been dropped.
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        self.chan.send(msg)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.add_sender();
+        Self {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> SyncSender<T> {
+    /// Sends a message on the channel, blocking the current task while the
+    /// buffer is full.
+    ///
+    /// Returns the message back if the receiver has been dropped.
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        self.chan.send(msg)
+    }
+
+    /// Attempts to send a message on the channel without blocking.
+    ///
+    /// On a rendezvous channel, it fails with [`TrySendError::Full`] unless
+    /// the receiver is blocked in [`Receiver::recv`] or
+    /// [`Receiver::recv_timeout`] waiting for a message.
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        self.chan.try_send(msg, true).map(|_| ())
+    }
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> Self {
+        self.chan.add_sender();
+        Self {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for SyncSender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+impl<T> fmt::Debug for SyncSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncSender").finish_non_exhaustive()
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives a message from the channel, blocking the current task until
+    /// one is available.
+    ///
+    /// Returns [`RecvError`] if the channel is empty and all senders have
+    /// been dropped.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.chan.recv()
+    }
+
+    /// Attempts to receive a message from the channel without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.chan.try_recv()
+    }
+
+    /// Receives a message from the channel asynchronously.
+    ///
+    /// It's the same as [`Receiver::recv`], but suspends the calling future
+    /// instead of blocking the task.
+    pub async fn recv_async(&self) -> Result<T, RecvError> {
+        core::future::poll_fn(|cx| self.chan.poll_recv(cx)).await
+    }
+
+    /// Receives a message from the channel, blocking the current task until
+    /// one is available or the given duration has elapsed.
+    #[cfg(feature = "irq")]
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.chan.recv_timeout(timeout)
+    }
+
+    /// Returns an iterator that blocks waiting for messages, until all senders
+    /// have been dropped.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { rx: self }
+    }
+
+    /// Returns an iterator that yields the messages currently in the channel
+    /// without blocking.
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { rx: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.chan.state.lock();
+        state.receiver_alive = false;
+        let msgs = core::mem::take(&mut state.queue);
+        drop(state);
+        // Drop the pending messages out of the lock.
+        drop(msgs);
+        self.chan.send_wq.notify_all(true);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+/// An iterator over messages on a [`Receiver`], created by
+/// [`Receiver::iter`].
+#[derive(Debug)]
+pub struct Iter<'a, T: 'a> {
+    rx: &'a Receiver<T>,
+}
+
+/// An iterator that attempts to yield all pending messages on a
+/// [`Receiver`], created by [`Receiver::try_iter`].
+#[derive(Debug)]
+pub struct TryIter<'a, T: 'a> {
+    rx: &'a Receiver<T>,
+}
+
+/// An owning iterator over messages on a [`Receiver`], created by
+/// [`Receiver::into_iter`].
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    rx: Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { rx: self }
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "sending on a closed channel".fmt(f)
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TrySendError::Full(..) => "Full(..)".fmt(f),
+            TrySendError::Disconnected(..) => "Disconnected(..)".fmt(f),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TrySendError::Full(..) => "sending on a full channel".fmt(f),
+            TrySendError::Disconnected(..) => "sending on a closed channel".fmt(f),
+        }
+    }
+}
+
+impl<T> From<SendError<T>> for TrySendError<T> {
+    fn from(err: SendError<T>) -> TrySendError<T> {
+        TrySendError::Disconnected(err.0)
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "receiving on a closed channel".fmt(f)
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TryRecvError::Empty => "receiving on an empty channel".fmt(f),
+            TryRecvError::Disconnected => "receiving on a closed channel".fmt(f),
+        }
+    }
+}
+
+impl From<RecvError> for TryRecvError {
+    fn from(err: RecvError) -> TryRecvError {
+        match err {
+            RecvError => TryRecvError::Disconnected,
+        }
+    }
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RecvTimeoutError::Timeout => "timed out waiting on channel".fmt(f),
+            RecvTimeoutError::Disconnected => "channel is empty and sending half is closed".fmt(f),
+        }
+    }
+}
+
+impl From<RecvError> for RecvTimeoutError {
+    fn from(err: RecvError) -> RecvTimeoutError {
+        match err {
+            RecvError => RecvTimeoutError::Disconnected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+    use axtask as thread;
+
+    #[test]
+    fn unbounded() {
+        let _lock = crate::tests::setup();
+
+        const NUM_TASKS: usize = 10;
+        const NUM_MSGS: usize = 100;
+
+        let (tx, rx) = channel();
+        for i in 0..NUM_TASKS {
+            let tx = tx.clone();
+            thread::spawn(move || {
+                for j in 0..NUM_MSGS {
+                    tx.send(i * NUM_MSGS + j).unwrap();
+                    if j % 10 == 0 {
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        let mut received: Vec<usize> = rx.iter().collect();
+        received.sort_unstable();
+        assert_eq!(received, (0..NUM_TASKS * NUM_MSGS).collect::<Vec<_>>());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        println!("mpsc unbounded test OK");
+    }
+
+    #[test]
+    fn bounded() {
+        let _lock = crate::tests::setup();
+
+        const NUM_MSGS: usize = 100;
+
+        let (tx, rx) = sync_channel(2);
+        tx.send(0).unwrap();
+        tx.send(1).unwrap();
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+
+        thread::spawn(move || {
+            for i in 2..NUM_MSGS {
+                tx.send(i).unwrap();
+            }
+        });
+        for i in 0..NUM_MSGS {
+            assert_eq!(rx.recv(), Ok(i));
+        }
+        assert_eq!(rx.recv(), Err(RecvError));
+
+        // A rendezvous channel.
+        let (tx, rx) = sync_channel(0);
+        let task = thread::spawn(move || tx.send(42).unwrap());
+        assert_eq!(rx.recv(), Ok(42));
+        task.join();
+
+        // `try_send` on a rendezvous channel needs a waiting receiver.
+        let (tx, rx) = sync_channel(0);
+        assert_eq!(tx.try_send(0), Err(TrySendError::Full(0)));
+        let task = thread::spawn(move || assert_eq!(rx.recv(), Ok(1)));
+        thread::yield_now();
+        assert_eq!(tx.try_send(1), Ok(()));
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+        task.join();
+
+        let (tx, rx) = sync_channel::<usize>(1);
+        assert_eq!(
+            rx.recv_timeout(Duration::ZERO),
+            Err(RecvTimeoutError::Timeout)
+        );
+        drop(rx);
+        assert_eq!(tx.send(0), Err(SendError(0)));
+        println!("mpsc bounded test OK");
+    }
+
+    #[test]
+    fn recv_async() {
+        let _lock = crate::tests::setup();
+
+        const NUM_MSGS: usize = 100;
+
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for i in 0..NUM_MSGS {
+                tx.send(i).unwrap();
+                if i % 10 == 0 {
+                    thread::yield_now();
+                }
+            }
+        });
+        let sum = axtask::future::block_on(async {
+            let mut sum = 0;
+            while let Ok(i) = rx.recv_async().await {
+                sum += i;
+            }
+            sum
+        });
+        assert_eq!(sum, (0..NUM_MSGS).sum());
+        println!("mpsc recv_async test OK");
+    }
+}
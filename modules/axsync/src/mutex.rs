@@ -3,6 +3,8 @@
 use core::cell::UnsafeCell;
 use core::fmt;
 use core::ops::{Deref, DerefMut};
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
 use core::sync::atomic::{AtomicU64, Ordering};
 
 use axtask::{WaitQueue, current};
@@ -11,11 +13,14 @@ use axtask::{WaitQueue, current};
 /// [`std::sync::Mutex`](https://doc.rust-lang.org/std/sync/struct.Mutex.html).
 ///
 /// When the mutex is locked, the current task will block and be put into the
-/// wait queue. When the mutex is unlocked, all tasks waiting on the queue
-/// will be woken up.
+/// wait queue. When the mutex is unlocked, the waiting task with the highest
+/// priority will be woken up.
 pub struct Mutex<T: ?Sized> {
     wq: WaitQueue,
     owner_id: AtomicU64,
+    /// The lock class for the dependency checker, i.e., where it's created.
+    #[cfg(feature = "lockdep")]
+    class: Option<&'static Location<'static>>,
     data: UnsafeCell<T>,
 }
 
@@ -34,10 +39,13 @@ unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
 impl<T> Mutex<T> {
     /// Creates a new [`Mutex`] wrapping the supplied data.
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub const fn new(data: T) -> Self {
         Self {
-            wq: WaitQueue::new(),
+            wq: WaitQueue::new_prio_ordered(),
             owner_id: AtomicU64::new(0),
+            #[cfg(feature = "lockdep")]
+            class: Some(Location::caller()),
             data: UnsafeCell::new(data),
         }
     }
@@ -64,12 +72,26 @@ impl<T: ?Sized> Mutex<T> {
         self.owner_id.load(Ordering::Relaxed) != 0
     }
 
+    #[cfg(feature = "lockdep")]
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
     /// Locks the [`Mutex`] and returns a guard that permits access to the inner data.
     ///
     /// The returned value may be dereferenced for data access
     /// and the lock will be dropped when the guard falls out of scope.
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn lock(&self) -> MutexGuard<T> {
         let current_id = current().id().as_u64();
+        #[cfg(feature = "lockdep")]
+        axtask::lockdep::acquire(
+            self.addr(),
+            self.class,
+            axtask::lockdep::LockKind::Sleep,
+            Location::caller(),
+            false,
+        );
         loop {
             // Can fail to lock even if the spinlock is not locked. May be more efficient than `try_lock`
             // when called in a loop.
@@ -100,6 +122,7 @@ impl<T: ?Sized> Mutex<T> {
 
     /// Try to lock this [`Mutex`], returning a lock guard if successful.
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
         let current_id = current().id().as_u64();
         // The reason for using a strong compare_exchange is explained here:
@@ -109,6 +132,14 @@ impl<T: ?Sized> Mutex<T> {
             .compare_exchange(0, current_id, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
         {
+            #[cfg(feature = "lockdep")]
+            axtask::lockdep::acquire(
+                self.addr(),
+                self.class,
+                axtask::lockdep::LockKind::Sleep,
+                Location::caller(),
+                true,
+            );
             Some(MutexGuard {
                 lock: self,
                 data: unsafe { &mut *self.data.get() },
@@ -133,6 +164,8 @@ impl<T: ?Sized> Mutex<T> {
             "{} tried to release mutex it doesn't own",
             current().id_name()
         );
+        #[cfg(feature = "lockdep")]
+        axtask::lockdep::release(self.addr());
         self.wq.notify_one(true);
     }
 
@@ -201,9 +234,6 @@ impl<T: ?Sized> Drop for MutexGuard<'_, T> {
 mod tests {
     use crate::Mutex;
     use axtask as thread;
-    use std::sync::Once;
-
-    static INIT: Once = Once::new();
 
     fn may_interrupt() {
         // simulate interrupts
@@ -214,7 +244,7 @@ mod tests {
 
     #[test]
     fn lots_and_lots() {
-        INIT.call_once(thread::init_scheduler);
+        let _lock = crate::tests::setup();
 
         const NUM_TASKS: u32 = 10;
         const NUM_ITERS: u32 = 10_000;
@@ -0,0 +1,198 @@
+//! A channel for sending a single message between two tasks.
+//!
+//! It is usually used to hand the result of a request back to the requester.
+
+use alloc::sync::Arc;
+use core::fmt;
+use core::task::Poll;
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use axtask::WaitQueue;
+use axtask::future::WakerQueue;
+use kspin::SpinNoIrq;
+
+#[cfg(feature = "irq")]
+pub use crate::mpsc::RecvTimeoutError;
+pub use crate::mpsc::{RecvError, TryRecvError};
+
+struct State<T> {
+    msg: Option<T>,
+    sender_alive: bool,
+    receiver_alive: bool,
+}
+
+struct Channel<T> {
+    state: SpinNoIrq<State<T>>,
+    wq: WaitQueue,
+    wakers: WakerQueue,
+}
+
+/// The sending half of a oneshot channel created by [`channel`].
+pub struct Sender<T> {
+    chan: Arc<Channel<T>>,
+}
+
+/// The receiving half of a oneshot channel created by [`channel`].
+pub struct Receiver<T> {
+    chan: Arc<Channel<T>>,
+}
+
+/// Creates a new oneshot channel, returning the sender/receiver halves.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let chan = Arc::new(Channel {
+        state: SpinNoIrq::new(State {
+            msg: None,
+            sender_alive: true,
+            receiver_alive: true,
+        }),
+        wq: WaitQueue::new(),
+        wakers: WakerQueue::new(),
+    });
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+impl<T> Channel<T> {
+    fn can_recv(&self) -> bool {
+        let state = self.state.lock();
+        state.msg.is_some() || !state.sender_alive
+    }
+}
+
+impl<T> Sender<T> {
+    /// Sends the message, consuming the sender. It never blocks.
+    ///
+    /// Returns the message back if the receiver has been dropped.
+    pub fn send(self, msg: T) -> Result<(), T> {
+        let mut state = self.chan.state.lock();
+        if !state.receiver_alive {
+            return Err(msg);
+        }
+        state.msg = Some(msg);
+        // The receiver is woken up when `self` is dropped.
+        Ok(())
+    }
+
+    /// Returns `true` if the receiver has been dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.chan.state.lock().receiver_alive
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.chan.state.lock().sender_alive = false;
+        self.chan.wq.notify_one(true);
+        self.chan.wakers.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives the message, blocking the current task until it is sent.
+    ///
+    /// Returns [`RecvError`] if the sender has been dropped without sending.
+    pub fn recv(self) -> Result<T, RecvError> {
+        self.chan.wq.wait_until(|| self.chan.can_recv());
+        self.chan.state.lock().msg.take().ok_or(RecvError)
+    }
+
+    /// Attempts to receive the message without blocking.
+    ///
+    /// The message can only be received once, the following calls return
+    /// [`TryRecvError::Disconnected`].
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.chan.state.lock();
+        match state.msg.take() {
+            Some(msg) => Ok(msg),
+            None if state.sender_alive => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    /// Receives the message asynchronously.
+    ///
+    /// It's the same as [`Receiver::recv`], but suspends the calling future
+    /// instead of blocking the task.
+    pub async fn recv_async(self) -> Result<T, RecvError> {
+        core::future::poll_fn(|cx| {
+            if !self.chan.can_recv() {
+                self.chan.wakers.register(cx.waker());
+                if !self.chan.can_recv() {
+                    return Poll::Pending;
+                }
+            }
+            Poll::Ready(self.chan.state.lock().msg.take().ok_or(RecvError))
+        })
+        .await
+    }
+
+    /// Receives the message, blocking the current task until it is sent or
+    /// the given duration has elapsed.
+    #[cfg(feature = "irq")]
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.chan
+            .wq
+            .wait_timeout_until(timeout, || self.chan.can_recv());
+        self.try_recv().map_err(|err| match err {
+            TryRecvError::Empty => RecvTimeoutError::Timeout,
+            TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+        })
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.chan.state.lock();
+        state.receiver_alive = false;
+        let msg = state.msg.take();
+        drop(state);
+        drop(msg);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+    use axtask as thread;
+
+    #[test]
+    fn send_and_recv() {
+        let _lock = crate::tests::setup();
+
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            thread::yield_now();
+            tx.send(42).unwrap();
+        });
+        assert_eq!(rx.recv(), Ok(42));
+
+        let (tx, rx) = channel::<usize>();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(
+            rx.recv_timeout(Duration::ZERO),
+            Err(RecvTimeoutError::Timeout)
+        );
+        drop(tx);
+        assert_eq!(rx.recv(), Err(RecvError));
+
+        let (tx, rx) = channel();
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(0), Err(0));
+        println!("oneshot test OK");
+    }
+}
@@ -0,0 +1,359 @@
+//! A sleeping mutex with priority inheritance.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use axtask::{AxTaskRef, WaitQueue, current};
+use kspin::SpinNoIrq;
+
+/// A mutual exclusion primitive with priority inheritance, which prevents
+/// priority inversion.
+///
+/// It behaves like [`Mutex`](crate::Mutex), except that while tasks are
+/// blocked on it, the owner runs with the highest priority among itself and
+/// all the waiters. The owner's priority is restored when it releases the
+/// mutex. When unlocked, the waiter with the highest priority is woken up.
+///
+/// Note that the inheritance is not transitive: if the owner is blocked on
+/// another [`PiMutex`], the owner of that one only inherits the priority of
+/// the blocked owner itself.
+///
+/// Priorities only take effect with a scheduler that supports them (e.g.,
+/// `sched_cfs`), where a smaller value means a higher priority.
+pub struct PiMutex<T: ?Sized> {
+    wq: WaitQueue,
+    state: SpinNoIrq<PiMutexState>,
+    data: UnsafeCell<T>,
+}
+
+struct PiMutexState {
+    owner: Option<AxTaskRef>,
+    waiters: Vec<AxTaskRef>,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct PiMutexGuard<'a, T: ?Sized + 'a> {
+    lock: &'a PiMutex<T>,
+    data: *mut T,
+}
+
+// Same unsafe impls as `std::sync::Mutex`
+unsafe impl<T: ?Sized + Send> Sync for PiMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for PiMutex<T> {}
+
+impl PiMutexState {
+    /// Returns the waiter with the highest priority.
+    fn top_waiter(&self) -> Option<&AxTaskRef> {
+        self.waiters.iter().min_by_key(|t| t.priority())
+    }
+}
+
+impl<T> PiMutex<T> {
+    /// Creates a new [`PiMutex`] wrapping the supplied data.
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            state: SpinNoIrq::new(PiMutexState {
+                owner: None,
+                waiters: Vec::new(),
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this [`PiMutex`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        let PiMutex { data, .. } = self;
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> PiMutex<T> {
+    /// The key that identifies this mutex when lending priorities.
+    #[inline(always)]
+    fn key(&self) -> usize {
+        self as *const Self as *const u8 as usize
+    }
+
+    /// Returns `true` if the lock is currently held.
+    ///
+    /// # Safety
+    ///
+    /// This function provides no synchronization guarantees and so its result should be considered 'out of date'
+    /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.state.lock().owner.is_some()
+    }
+
+    /// Takes the ownership for the current task, which must have checked
+    /// that the mutex is not owned. The priorities of the remaining waiters
+    /// are lent to the new owner.
+    fn acquire(&self, state: &mut PiMutexState, curr: &AxTaskRef) {
+        state.waiters.retain(|t| !Arc::ptr_eq(t, curr));
+        state.owner = Some(curr.clone());
+        if let Some(top) = state.top_waiter() {
+            axtask::inherit_priority(curr, self.key(), top.priority());
+        }
+    }
+
+    /// Locks the [`PiMutex`] and returns a guard that permits access to the inner data.
+    ///
+    /// If the mutex is held by another task, the current task lends its
+    /// priority to the owner and blocks until the mutex is released.
+    pub fn lock(&self) -> PiMutexGuard<T> {
+        let curr = current();
+        let curr_ref = curr.as_task_ref();
+        loop {
+            let mut state = self.state.lock();
+            match &state.owner {
+                None => {
+                    self.acquire(&mut state, curr_ref);
+                    break;
+                }
+                Some(owner) => {
+                    assert!(
+                        !Arc::ptr_eq(owner, curr_ref),
+                        "{} tried to acquire mutex it already owns.",
+                        curr.id_name()
+                    );
+                    let owner = owner.clone();
+                    if !state.waiters.iter().any(|t| Arc::ptr_eq(t, curr_ref)) {
+                        state.waiters.push(curr_ref.clone());
+                    }
+                    let prio = state.top_waiter().map_or(curr.priority(), |t| t.priority());
+                    axtask::inherit_priority(&owner, self.key(), prio);
+                }
+            }
+            drop(state);
+            // Wait until the lock looks unlocked before retrying
+            self.wq.wait_until(|| !self.is_locked());
+        }
+        PiMutexGuard {
+            lock: self,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    /// Try to lock this [`PiMutex`], returning a lock guard if successful.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<PiMutexGuard<T>> {
+        let mut state = self.state.lock();
+        if state.owner.is_some() {
+            return None;
+        }
+        self.acquire(&mut state, current().as_task_ref());
+        Some(PiMutexGuard {
+            lock: self,
+            data: unsafe { &mut *self.data.get() },
+        })
+    }
+
+    /// Force unlock the [`PiMutex`].
+    ///
+    /// The priority of the current task is restored, and the waiter with the
+    /// highest priority is woken up.
+    ///
+    /// # Safety
+    ///
+    /// This is *extremely* unsafe if the lock is not held by the current
+    /// thread. However, this can be useful in some instances for exposing
+    /// the lock to FFI that doesn’t know how to deal with RAII.
+    pub unsafe fn force_unlock(&self) {
+        let curr = current();
+        let next = {
+            let mut state = self.state.lock();
+            let owner = state.owner.take();
+            assert!(
+                owner.is_some_and(|owner| Arc::ptr_eq(&owner, curr.as_task_ref())),
+                "{} tried to release mutex it doesn't own",
+                curr.id_name()
+            );
+            state.top_waiter().cloned()
+        };
+        axtask::disinherit_priority(curr.as_task_ref(), self.key());
+        if let Some(task) = next {
+            // The top waiter may not be in the wait queue yet, it will find
+            // the mutex unlocked before blocking.
+            if !self.wq.notify_task(true, &task) {
+                self.wq.notify_one(true);
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`PiMutex`] mutably, and a mutable reference is guaranteed to be exclusive in
+    /// Rust, no actual locking needs to take place -- the mutable borrow statically guarantees no locks exist. As
+    /// such, this is a 'zero-cost' operation.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner mutex.
+        unsafe { &mut *self.data.get() }
+    }
+}
+
+impl<T: Default> Default for PiMutex<T> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PiMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "PiMutex {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "PiMutex {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for PiMutexGuard<'_, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        // We know statically that only we are referencing data
+        unsafe { &*self.data }
+    }
+}
+
+impl<T: ?Sized> DerefMut for PiMutexGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        // We know statically that only we are referencing data
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PiMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> Drop for PiMutexGuard<'_, T> {
+    /// The dropping of the [`PiMutexGuard`] will release the lock it was created from.
+    fn drop(&mut self) {
+        unsafe { self.lock.force_unlock() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PiMutex;
+    use axtask as thread;
+
+    fn may_interrupt() {
+        // simulate interrupts
+        if rand::random::<u32>() % 3 == 0 {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn lots_and_lots() {
+        let _lock = crate::tests::setup();
+
+        const NUM_TASKS: u32 = 10;
+        const NUM_ITERS: u32 = 10_000;
+        static M: PiMutex<u32> = PiMutex::new(0);
+
+        fn inc(delta: u32) {
+            for _ in 0..NUM_ITERS {
+                let mut val = M.lock();
+                *val += delta;
+                may_interrupt();
+                drop(val);
+                may_interrupt();
+            }
+        }
+
+        for _ in 0..NUM_TASKS {
+            thread::spawn(|| inc(1));
+            thread::spawn(|| inc(2));
+        }
+
+        loop {
+            let val = M.lock();
+            if *val == NUM_ITERS * NUM_TASKS * 3 {
+                break;
+            }
+            may_interrupt();
+            drop(val);
+            may_interrupt();
+        }
+
+        assert_eq!(*M.lock(), NUM_ITERS * NUM_TASKS * 3);
+        // No priority is left inherited after all tasks release the mutex.
+        let curr = thread::current();
+        assert_eq!(curr.priority(), curr.base_priority());
+        println!("PiMutex test OK");
+    }
+
+    #[test]
+    fn priority_inheritance() {
+        use std::sync::Mutex;
+        use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+
+        let _lock = crate::tests::setup();
+
+        static M: PiMutex<()> = PiMutex::new(());
+        static WQ: axtask::WaitQueue = axtask::WaitQueue::new();
+        static RELEASE: AtomicBool = AtomicBool::new(false);
+        static PRIO_AFTER_UNLOCK: AtomicIsize = AtomicIsize::new(0);
+        static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+        // Priorities only take effect with a scheduler that supports them.
+        if !thread::set_priority(19) {
+            return;
+        }
+
+        // A smaller value means a higher priority.
+        let owner = thread::spawn(|| {
+            assert!(thread::set_priority(10));
+            let guard = M.lock();
+            WQ.wait_until(|| RELEASE.load(Ordering::Acquire));
+            drop(guard);
+            PRIO_AFTER_UNLOCK.store(thread::current().priority(), Ordering::Release);
+        });
+        thread::yield_now(); // let the owner lock the mutex
+
+        let waiters = [("normal", 0), ("high", -10)].map(|(name, prio)| {
+            thread::spawn(move || {
+                assert!(thread::set_priority(prio));
+                let _guard = M.lock();
+                ORDER.lock().unwrap().push(name);
+            })
+        });
+        thread::yield_now(); // let the waiters block on the mutex
+
+        // The owner is boosted by the highest-priority waiter.
+        assert_eq!(owner.priority(), -10);
+        assert_eq!(owner.base_priority(), 10);
+
+        RELEASE.store(true, Ordering::Release);
+        WQ.notify_one(true);
+        owner.join();
+        for waiter in waiters {
+            waiter.join();
+        }
+
+        // The owner drops back to its base priority on unlock, and the
+        // highest-priority waiter gets the mutex next.
+        assert_eq!(PRIO_AFTER_UNLOCK.load(Ordering::Acquire), 10);
+        assert_eq!(*ORDER.lock().unwrap(), ["high", "normal"]);
+        assert!(thread::set_priority(0));
+    }
+}
@@ -0,0 +1,470 @@
+//! A sleeping readers-writer lock.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use axtask::WaitQueue;
+use kspin::SpinNoIrq;
+
+/// A readers-writer lock, similar to
+/// [`std::sync::RwLock`](https://doc.rust-lang.org/std/sync/struct.RwLock.html).
+///
+/// It allows a number of readers or at most one writer at any point in time.
+/// The tasks that cannot acquire the lock block in a wait queue.
+///
+/// The lock is writer-preferring: once a writer is waiting, new readers block
+/// until the writer has acquired and released the lock, so that writers are
+/// not starved by a continuous stream of readers.
+///
+/// Besides the read and write guards, an upgradeable read guard can be
+/// acquired by [`RwLock::upgradeable_read`]. It coexists with readers but not
+/// with writers or other upgradeable readers, and can be atomically upgraded
+/// to a write guard. A write guard can also be downgraded to a read guard or
+/// an upgradeable read guard without releasing the lock.
+pub struct RwLock<T: ?Sized> {
+    wq: WaitQueue,
+    state: SpinNoIrq<RwLockState>,
+    data: UnsafeCell<T>,
+}
+
+struct RwLockState {
+    /// The number of readers, not including the upgradeable reader.
+    readers: usize,
+    writer: bool,
+    upgradeable: bool,
+    /// The number of writers (including an upgrading reader) waiting for the
+    /// lock.
+    waiting_writers: usize,
+}
+
+/// A guard that provides immutable data access.
+///
+/// When the guard falls out of scope it will release the shared access.
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the exclusive access.
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+
+/// A guard that provides immutable data access, and can be upgraded to a
+/// [`RwLockWriteGuard`].
+///
+/// When the guard falls out of scope it will release the shared access.
+pub struct RwLockUpgradableGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl RwLockState {
+    fn try_read(&mut self) -> bool {
+        if self.writer || self.waiting_writers > 0 {
+            return false;
+        }
+        self.readers += 1;
+        true
+    }
+
+    fn try_upgradeable_read(&mut self) -> bool {
+        if self.writer || self.upgradeable || self.waiting_writers > 0 {
+            return false;
+        }
+        self.upgradeable = true;
+        true
+    }
+
+    fn try_write(&mut self) -> bool {
+        if self.writer || self.upgradeable || self.readers > 0 {
+            return false;
+        }
+        self.writer = true;
+        true
+    }
+
+    fn try_upgrade(&mut self) -> bool {
+        if self.readers > 0 {
+            return false;
+        }
+        self.upgradeable = false;
+        self.writer = true;
+        true
+    }
+}
+
+impl<T> RwLock<T> {
+    /// Creates a new [`RwLock`] wrapping the supplied data.
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            state: SpinNoIrq::new(RwLockState {
+                readers: 0,
+                writer: false,
+                upgradeable: false,
+                waiting_writers: 0,
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this [`RwLock`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        let RwLock { data, .. } = self;
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Locks this [`RwLock`] with shared read access, blocking the current
+    /// task until it can be acquired.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        self.wq.wait_until(|| self.state.lock().try_read());
+        RwLockReadGuard { lock: self }
+    }
+
+    /// Attempts to acquire this [`RwLock`] with shared read access without
+    /// blocking.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        if self.state.lock().try_read() {
+            Some(RwLockReadGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    /// Locks this [`RwLock`] with shared read access, blocking the current
+    /// task until it can be acquired or the given duration has elapsed.
+    ///
+    /// Returns [`None`] if timed out.
+    #[cfg(feature = "irq")]
+    pub fn read_timeout(&self, dur: Duration) -> Option<RwLockReadGuard<T>> {
+        let timeout = self
+            .wq
+            .wait_timeout_until(dur, || self.state.lock().try_read());
+        (!timeout).then(|| RwLockReadGuard { lock: self })
+    }
+
+    /// Locks this [`RwLock`] with upgradeable read access, blocking the
+    /// current task until it can be acquired.
+    pub fn upgradeable_read(&self) -> RwLockUpgradableGuard<T> {
+        self.wq
+            .wait_until(|| self.state.lock().try_upgradeable_read());
+        RwLockUpgradableGuard { lock: self }
+    }
+
+    /// Attempts to acquire this [`RwLock`] with upgradeable read access
+    /// without blocking.
+    pub fn try_upgradeable_read(&self) -> Option<RwLockUpgradableGuard<T>> {
+        if self.state.lock().try_upgradeable_read() {
+            Some(RwLockUpgradableGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    /// Locks this [`RwLock`] with exclusive write access, blocking the current
+    /// task until it can be acquired.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        if !self.state.lock().try_write() {
+            self.wait_as_writer(|| self.state.lock().try_write());
+        }
+        RwLockWriteGuard { lock: self }
+    }
+
+    /// Attempts to acquire this [`RwLock`] with exclusive write access without
+    /// blocking.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        if self.state.lock().try_write() {
+            Some(RwLockWriteGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    /// Locks this [`RwLock`] with exclusive write access, blocking the current
+    /// task until it can be acquired or the given duration has elapsed.
+    ///
+    /// Returns [`None`] if timed out.
+    #[cfg(feature = "irq")]
+    pub fn write_timeout(&self, dur: Duration) -> Option<RwLockWriteGuard<T>> {
+        if self.state.lock().try_write() {
+            return Some(RwLockWriteGuard { lock: self });
+        }
+        self.state.lock().waiting_writers += 1;
+        let timeout = self
+            .wq
+            .wait_timeout_until(dur, || self.state.lock().try_write());
+        self.state.lock().waiting_writers -= 1;
+        if timeout {
+            // Readers may be blocked only because of this writer.
+            self.wq.notify_all(true);
+            None
+        } else {
+            Some(RwLockWriteGuard { lock: self })
+        }
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`RwLock`] mutably, no actual locking needs
+    /// to take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner lock.
+        unsafe { &mut *self.data.get() }
+    }
+
+    /// Blocks as a waiting writer until `acquire` succeeds, new readers are
+    /// held off in the meantime.
+    fn wait_as_writer<F>(&self, acquire: F)
+    where
+        F: Fn() -> bool,
+    {
+        self.state.lock().waiting_writers += 1;
+        self.wq.wait_until(acquire);
+        self.state.lock().waiting_writers -= 1;
+    }
+
+    fn release<F>(&self, f: F)
+    where
+        F: FnOnce(&mut RwLockState),
+    {
+        f(&mut self.state.lock());
+        self.wq.notify_all(true);
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "RwLock {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "RwLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> RwLockWriteGuard<'a, T> {
+    /// Downgrades the write guard to a read guard without releasing the lock,
+    /// so no writer can get in between.
+    pub fn downgrade(self) -> RwLockReadGuard<'a, T> {
+        let lock = ManuallyDrop::new(self).lock;
+        lock.release(|state| {
+            state.writer = false;
+            state.readers += 1;
+        });
+        RwLockReadGuard { lock }
+    }
+
+    /// Downgrades the write guard to an upgradeable read guard without
+    /// releasing the lock.
+    pub fn downgrade_to_upgradeable(self) -> RwLockUpgradableGuard<'a, T> {
+        let lock = ManuallyDrop::new(self).lock;
+        lock.release(|state| {
+            state.writer = false;
+            state.upgradeable = true;
+        });
+        RwLockUpgradableGuard { lock }
+    }
+}
+
+impl<'a, T: ?Sized> RwLockUpgradableGuard<'a, T> {
+    /// Upgrades the guard to a write guard, blocking the current task until
+    /// all readers have released the lock.
+    pub fn upgrade(self) -> RwLockWriteGuard<'a, T> {
+        let lock = ManuallyDrop::new(self).lock;
+        if !lock.state.lock().try_upgrade() {
+            lock.wait_as_writer(|| lock.state.lock().try_upgrade());
+        }
+        RwLockWriteGuard { lock }
+    }
+
+    /// Attempts to upgrade the guard to a write guard without blocking.
+    ///
+    /// Returns the guard itself if there are still readers.
+    pub fn try_upgrade(self) -> Result<RwLockWriteGuard<'a, T>, Self> {
+        if self.lock.state.lock().try_upgrade() {
+            let lock = ManuallyDrop::new(self).lock;
+            Ok(RwLockWriteGuard { lock })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Downgrades the guard to a read guard, allowing another upgradeable
+    /// reader or writer to acquire the lock.
+    pub fn downgrade(self) -> RwLockReadGuard<'a, T> {
+        let lock = ManuallyDrop::new(self).lock;
+        lock.release(|state| {
+            state.upgradeable = false;
+            state.readers += 1;
+        });
+        RwLockReadGuard { lock }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        // We know statically that only readers are referencing data
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockUpgradableGuard<'_, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        // We know statically that only readers are referencing data
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        // We know statically that only we are referencing data
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        // We know statically that only we are referencing data
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockUpgradableGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.release(|state| state.readers -= 1);
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockUpgradableGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.release(|state| state.upgradeable = false);
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.release(|state| state.writer = false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::time::Duration;
+
+    use crate::RwLock;
+    use axtask as thread;
+
+    fn may_interrupt() {
+        // simulate interrupts
+        if rand::random::<u32>() % 3 == 0 {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn readers_and_writers() {
+        let _lock = crate::tests::setup();
+
+        const NUM_TASKS: usize = 10;
+        const NUM_ITERS: usize = 1_000;
+        static LOCK: RwLock<(usize, usize)> = RwLock::new((0, 0));
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        for i in 0..NUM_TASKS {
+            thread::spawn(move || {
+                for _ in 0..NUM_ITERS {
+                    if i % 2 == 0 {
+                        let mut val = LOCK.write();
+                        val.0 += 1;
+                        may_interrupt();
+                        val.1 += 1;
+                    } else {
+                        let val = LOCK.read();
+                        let first = val.0;
+                        may_interrupt();
+                        assert_eq!(first, val.1);
+                    }
+                    may_interrupt();
+                }
+                let upgradeable = LOCK.upgradeable_read();
+                may_interrupt();
+                let mut val = upgradeable.upgrade();
+                val.0 += 1;
+                val.1 += 1;
+                let val = val.downgrade();
+                assert_eq!(val.0, val.1);
+                drop(val);
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+
+        while FINISHED.load(Ordering::Relaxed) < NUM_TASKS {
+            thread::yield_now();
+        }
+        let expected = NUM_TASKS / 2 * NUM_ITERS + NUM_TASKS;
+        assert_eq!(*LOCK.read(), (expected, expected));
+
+        let reader = LOCK.read();
+        assert!(LOCK.try_write().is_none());
+        assert!(LOCK.write_timeout(Duration::ZERO).is_none());
+        // The timed-out writer no longer holds off readers.
+        assert!(LOCK.try_read().is_some());
+        drop(reader);
+
+        let writer = LOCK.write();
+        assert!(LOCK.read_timeout(Duration::ZERO).is_none());
+        drop(writer);
+        println!("RwLock test OK");
+    }
+}
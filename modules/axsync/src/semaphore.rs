@@ -0,0 +1,124 @@
+//! A counting semaphore.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use axtask::WaitQueue;
+
+/// A counting semaphore.
+///
+/// It maintains a number of permits. [`Semaphore::acquire`] takes a permit,
+/// blocking the current task while there are none left, and
+/// [`Semaphore::release`] returns a permit and wakes up a waiting task, the
+/// one with the highest priority first.
+pub struct Semaphore {
+    wq: WaitQueue,
+    permits: AtomicUsize,
+}
+
+/// A guard that holds a permit of a [`Semaphore`].
+///
+/// When the guard falls out of scope it will release the permit.
+pub struct SemaphoreGuard<'a> {
+    sem: &'a Semaphore,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            wq: WaitQueue::new_prio_ordered(),
+            permits: AtomicUsize::new(permits),
+        }
+    }
+
+    /// Returns the number of permits currently available.
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Acquire)
+    }
+
+    /// Acquires a permit, blocking the current task until one is available.
+    pub fn acquire(&self) {
+        self.wq.wait_until(|| self.try_acquire());
+    }
+
+    /// Attempts to acquire a permit without blocking.
+    ///
+    /// Returns `true` if a permit is acquired.
+    pub fn try_acquire(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Acquires a permit, blocking the current task until one is available or
+    /// the given duration has elapsed.
+    ///
+    /// Returns `true` if a permit is acquired, or `false` if timed out.
+    #[cfg(feature = "irq")]
+    pub fn acquire_timeout(&self, dur: Duration) -> bool {
+        !self.wq.wait_timeout_until(dur, || self.try_acquire())
+    }
+
+    /// Releases a permit, waking up a task waiting for it.
+    ///
+    /// It does not have to be called by the task that acquired the permit,
+    /// so a semaphore created with no permits can be used for signaling.
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Acquires a permit and returns a guard that releases it when dropped.
+    pub fn access(&self) -> SemaphoreGuard<'_> {
+        self.acquire();
+        SemaphoreGuard { sem: self }
+    }
+}
+
+impl Drop for SemaphoreGuard<'_> {
+    fn drop(&mut self) {
+        self.sem.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::time::Duration;
+
+    use crate::Semaphore;
+    use axtask as thread;
+
+    #[test]
+    fn bounded_access() {
+        let _lock = crate::tests::setup();
+
+        const NUM_TASKS: usize = 10;
+        const NUM_PERMITS: usize = 3;
+        static SEM: Semaphore = Semaphore::new(NUM_PERMITS);
+        static DONE: Semaphore = Semaphore::new(0);
+        static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+        for _ in 0..NUM_TASKS {
+            thread::spawn(|| {
+                let guard = SEM.access();
+                let active = ACTIVE.fetch_add(1, Ordering::Relaxed) + 1;
+                assert!(active <= NUM_PERMITS);
+                thread::yield_now();
+                ACTIVE.fetch_sub(1, Ordering::Relaxed);
+                drop(guard);
+                DONE.release();
+            });
+        }
+
+        for _ in 0..NUM_TASKS {
+            DONE.acquire();
+        }
+        assert_eq!(SEM.available_permits(), NUM_PERMITS);
+        assert!(!DONE.try_acquire());
+        assert!(!DONE.acquire_timeout(Duration::ZERO));
+        println!("Semaphore test OK");
+    }
+}
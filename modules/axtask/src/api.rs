@@ -4,15 +4,34 @@ use alloc::{string::String, sync::Arc};
 
 use kernel_guard::NoPreemptIrqSave;
 
-pub(crate) use crate::run_queue::{current_run_queue, select_run_queue};
+pub(crate) use crate::run_queue::{current_run_queue, select_run_queue, task_run_queue};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::join_set::JoinSet;
+#[doc(cfg(feature = "multitask"))]
+pub use crate::registry::{CpuUsage, TaskInfo, cpu_usage, lookup, tasks};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::stats::TaskStats;
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task::{Cancelled, CurrentTask, TaskId, TaskInner, TaskState};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task_ext::{TaskExtMut, TaskExtRef};
 #[doc(cfg(feature = "multitask"))]
+pub use crate::task_local::{AccessError, TaskLocal};
+#[doc(cfg(feature = "multitask"))]
 pub use crate::wait_queue::WaitQueue;
 
+#[cfg(feature = "select_rq_if")]
+#[doc(cfg(feature = "select_rq_if"))]
+pub use crate::run_queue::SelectRunQueueIf;
+#[cfg(feature = "sched_rt")]
+#[doc(cfg(feature = "sched_rt"))]
+pub use crate::sched_rt::DeadlineParams;
+
+/// The exit code of a task that exits at a cancellation point, see
+/// [`test_cancel`].
+pub const CANCELLED_EXIT_CODE: i32 = -1;
+
 /// The reference type of a task.
 pub type AxTaskRef = Arc<AxTask>;
 
@@ -20,7 +39,10 @@ pub type AxTaskRef = Arc<AxTask>;
 pub type AxCpuMask = cpumask::CpuMask<{ axconfig::SMP }>;
 
 cfg_if::cfg_if! {
-    if #[cfg(feature = "sched_rr")] {
+    if #[cfg(feature = "sched_rt")] {
+        pub(crate) type AxTask = crate::sched_rt::RTTask;
+        pub(crate) type Scheduler = crate::sched_rt::RTScheduler;
+    } else if #[cfg(feature = "sched_rr")] {
         const MAX_TIME_SLICE: usize = 5;
         pub(crate) type AxTask = scheduler::RRTask<TaskInner, MAX_TIME_SLICE>;
         pub(crate) type Scheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
@@ -94,11 +116,21 @@ pub fn init_scheduler_secondary() {
 pub fn on_timer_tick() {
     use kernel_guard::NoOp;
     crate::timers::check_events();
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::on_timer_tick();
     // Since irq and preemption are both disabled here,
     // we can get current run queue with the default `kernel_guard::NoOp`.
     current_run_queue::<NoOp>().scheduler_timer_tick();
 }
 
+/// Handles the inter-processor interrupts (IPIs) sent by the task manager,
+/// e.g., to suspend a task running on this CPU, or to stop this CPU.
+#[cfg(feature = "irq")]
+#[doc(cfg(feature = "irq"))]
+pub fn on_ipi() {
+    crate::ipi::handle_ipi();
+}
+
 /// Adds the given task to the run queue, returns the task reference.
 pub fn spawn_task(task: TaskInner) -> AxTaskRef {
     let task_ref = task.into_arc();
@@ -142,6 +174,103 @@ pub fn set_priority(prio: isize) -> bool {
     current_run_queue::<NoPreemptIrqSave>().set_current_priority(prio)
 }
 
+/// Lends the priority `prio` to `task` on behalf of the resource identified
+/// by `key`, usually a lock that the current task is waiting for `task` to
+/// release.
+///
+/// The effective priority of a task is the highest one of its own priority
+/// and all priorities lent to it, where a smaller value means a higher
+/// priority. Lending again through the same `key` replaces the previous one.
+///
+/// Returns `true` if the effective priority is applied to the scheduler
+/// successfully.
+pub fn inherit_priority(task: &AxTaskRef, key: usize, prio: isize) -> bool {
+    task.add_inherited_priority(key, prio);
+    apply_effective_priority(task)
+}
+
+/// Takes back the priority lent to `task` through `key` by
+/// [`inherit_priority`], and restores its effective priority.
+///
+/// Returns `true` if the effective priority is applied to the scheduler
+/// successfully.
+pub fn disinherit_priority(task: &AxTaskRef, key: usize) -> bool {
+    task.remove_inherited_priority(key);
+    apply_effective_priority(task)
+}
+
+/// Applies the effective priority of `task` to the scheduler of the run
+/// queue that owns it.
+fn apply_effective_priority(task: &AxTaskRef) -> bool {
+    loop {
+        if let Some(ok) = task_run_queue::<NoPreemptIrqSave>(task).update_task_priority(task) {
+            return ok;
+        }
+    }
+}
+
+/// Suspends the given task, it will not run until [`resume`] is called.
+///
+/// The task is only marked to be suspended here. A task that is ready stays in
+/// its run queue, and is suspended instead of being run when it's picked next.
+/// A blocked task is suspended once it's woken up. If the task is running on
+/// another CPU, it's interrupted by an IPI if both the `smp` and `preempt`
+/// features are enabled, otherwise it stops at its next reschedule point. This
+/// function waits for the task to stop running before returning, and
+/// suspending the current task returns after it's resumed.
+///
+/// Returns `false` if the task is an idle task or has exited.
+pub fn suspend(task: &AxTaskRef) -> bool {
+    if task.is_idle() || task.state() == TaskState::Exited {
+        return false;
+    }
+    task.set_suspend_requested(true);
+    if current().ptr_eq(task) {
+        yield_now();
+        return true;
+    }
+    #[cfg(feature = "smp")]
+    {
+        if task.is_running() {
+            #[cfg(feature = "irq")]
+            axhal::irq::send_ipi(task.sched_stats().last_cpu());
+        }
+        while task.is_running() || task.on_cpu() {
+            core::hint::spin_loop();
+        }
+    }
+    true
+}
+
+/// Resumes the task suspended by [`suspend`], and puts it into a run queue.
+///
+/// If the task has not been suspended yet (e.g., it's blocked), the pending
+/// suspension is cancelled. Returns `false` if the task is not suspended.
+pub fn resume(task: &AxTaskRef) -> bool {
+    select_run_queue::<NoPreemptIrqSave>(task).resume_task(task.clone())
+}
+
+/// Stops all other CPUs, e.g., before dumping the states of a crashed system.
+///
+/// The stopped CPUs disable IRQs and halt forever, so it's only used when the
+/// system is not going to continue. It waits for other CPUs to stop for at
+/// most one second, and returns `false` if some of them did not respond to the
+/// IPI in time. If it's called by several CPUs at the same time, only one of
+/// them returns, and the others are stopped.
+///
+/// It always returns `true` for single-core systems, and returns `false` if
+/// the feature `irq` is not enabled on multi-core systems, as IPIs can not be
+/// handled.
+pub fn stop_other_cpus() -> bool {
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "smp", feature = "irq"))] {
+            crate::ipi::stop_other_cpus(core::time::Duration::from_secs(1))
+        } else {
+            !cfg!(feature = "smp")
+        }
+    }
+}
+
 /// Set the affinity for the current task.
 /// [`AxCpuMask`] is used to specify the CPU affinity.
 /// Returns `true` if the affinity is set successfully.
@@ -178,29 +307,80 @@ pub fn set_current_affinity(cpumask: AxCpuMask) -> bool {
 
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
+#[cfg_attr(feature = "lockdep", track_caller)]
 pub fn yield_now() {
+    #[cfg(feature = "lockdep")]
+    crate::lockdep::check_blocking(core::panic::Location::caller());
     current_run_queue::<NoPreemptIrqSave>().yield_current()
 }
 
 /// Current task is going to sleep for the given duration.
 ///
-/// If the feature `irq` is not enabled, it uses busy-wait instead.
+/// If the feature `irq` is not enabled, it uses busy-wait instead. It is not a
+/// cancellation point, see [`sleep_cancellable`] for that.
+#[cfg_attr(feature = "lockdep", track_caller)]
 pub fn sleep(dur: core::time::Duration) {
     sleep_until(axhal::time::wall_time() + dur);
 }
 
 /// Current task is going to sleep, it will be woken up at the given deadline.
 ///
-/// If the feature `irq` is not enabled, it uses busy-wait instead.
+/// If the feature `irq` is not enabled, it uses busy-wait instead. It is not a
+/// cancellation point, see [`sleep_until_cancellable`] for that.
+#[cfg_attr(feature = "lockdep", track_caller)]
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
+    #[cfg(feature = "lockdep")]
+    crate::lockdep::check_blocking(core::panic::Location::caller());
+    #[cfg(feature = "irq")]
+    current_run_queue::<NoPreemptIrqSave>().sleep_until(deadline, false);
+    #[cfg(not(feature = "irq"))]
+    axhal::time::busy_wait_until(deadline);
+}
+
+/// Current task is going to sleep for the given duration, or until it is
+/// cancelled.
+///
+/// Returns [`Cancelled`] if the current task has been cancelled, see
+/// [`TaskInner::cancel`]. If the feature `irq` is not enabled, it uses
+/// busy-wait instead, and only checks the cancellation afterwards.
+pub fn sleep_cancellable(dur: core::time::Duration) -> Result<(), Cancelled> {
+    sleep_until_cancellable(axhal::time::wall_time() + dur)
+}
+
+/// Current task is going to sleep, it will be woken up at the given deadline,
+/// or when it is cancelled.
+///
+/// Returns [`Cancelled`] if the current task has been cancelled, see
+/// [`TaskInner::cancel`]. If the feature `irq` is not enabled, it uses
+/// busy-wait instead, and only checks the cancellation afterwards.
+pub fn sleep_until_cancellable(deadline: axhal::time::TimeValue) -> Result<(), Cancelled> {
+    if current().is_cancelled() {
+        return Err(Cancelled);
+    }
     #[cfg(feature = "irq")]
-    current_run_queue::<NoPreemptIrqSave>().sleep_until(deadline);
+    current_run_queue::<NoPreemptIrqSave>().sleep_until(deadline, true);
     #[cfg(not(feature = "irq"))]
     axhal::time::busy_wait_until(deadline);
+    if current().is_cancelled() {
+        Err(Cancelled)
+    } else {
+        Ok(())
+    }
+}
+
+/// A cancellation point, exits the current task with [`CANCELLED_EXIT_CODE`]
+/// if it has been cancelled.
+pub fn test_cancel() {
+    if current().is_cancelled() {
+        exit(CANCELLED_EXIT_CODE);
+    }
 }
 
 /// Exits the current task.
+///
+/// The task-local values of the task are dropped before it exits.
 pub fn exit(exit_code: i32) -> ! {
+    crate::task_local::destroy_current();
     current_run_queue::<NoPreemptIrqSave>().exit_current(exit_code)
 }
 
@@ -211,6 +391,8 @@ pub fn run_idle() -> ! {
     loop {
         yield_now();
         debug!("idle task: waiting for IRQs...");
+        #[cfg(feature = "tickless")]
+        crate::timers::stop_tick();
         #[cfg(feature = "irq")]
         axhal::arch::wait_for_irqs();
     }
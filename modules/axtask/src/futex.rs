@@ -0,0 +1,229 @@
+//! Futex-style wait and wake on addresses.
+//!
+//! A task can block on the address of an [`AtomicU32`] (the futex word) if it
+//! holds an expected value, and other tasks wake it up by the same address.
+//! No object needs to be allocated for each futex: the waiters are kept in a
+//! fixed table of wait queues hashed by the address, and each waiter records
+//! the address it waits on, so that only the waiters of the given address
+//! are woken up.
+//!
+//! The addresses are kernel virtual addresses, so a futex word must not be
+//! accessed through different mappings.
+
+use alloc::collections::VecDeque;
+#[cfg(feature = "irq")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use kernel_guard::NoPreemptIrqSave;
+
+use crate::wait_queue::unblock_one_task;
+use crate::{AxTaskRef, WaitQueue, current_run_queue};
+
+/// The number of buckets, must be a power of two.
+const NUM_BUCKETS: usize = 64;
+
+static BUCKETS: [WaitQueue; NUM_BUCKETS] = [const { WaitQueue::new() }; NUM_BUCKETS];
+
+/// The result of [`wait`] and [`wait_timeout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitResult {
+    /// The task was blocked and then woken up by [`wake`] or [`requeue`].
+    Woken,
+    /// The futex word did not hold the expected value, so the task was not
+    /// blocked.
+    Mismatched,
+    /// The task was blocked, but not woken up before the timeout.
+    TimedOut,
+}
+
+fn key_of(futex: &AtomicU32) -> usize {
+    futex as *const AtomicU32 as usize
+}
+
+fn bucket_index(key: usize) -> usize {
+    // Fibonacci hashing takes the high bits of the product, as the low bits
+    // depend only on the low bits of the key. The low 2 bits of the key are
+    // always zero.
+    (key >> 2).wrapping_mul(0x9e37_79b9_7f4a_7c15_u64 as usize)
+        >> (usize::BITS - NUM_BUCKETS.trailing_zeros())
+}
+
+fn bucket_of(key: usize) -> &'static WaitQueue {
+    &BUCKETS[bucket_index(key)]
+}
+
+/// Removes at most `max` tasks waiting on `key` from `queue`, in FIFO order.
+fn take_waiters(queue: &mut VecDeque<AxTaskRef>, key: usize, max: usize) -> Vec<AxTaskRef> {
+    let mut taken = Vec::new();
+    let mut i = 0;
+    while taken.len() < max && i < queue.len() {
+        if queue[i].futex_key() == key {
+            taken.push(queue.remove(i).unwrap());
+        } else {
+            i += 1;
+        }
+    }
+    taken
+}
+
+/// Blocks the current task if the futex word still holds `expected`, until
+/// it is woken up by [`wake`] or [`requeue`].
+///
+/// The check and the blocking are atomic with respect to [`wake`], so a wake
+/// up after changing the futex word is never lost. Spurious wakeups are not
+/// possible, but the caller should still recheck the futex word, as it may
+/// have changed again after the wake up.
+pub fn wait(futex: &AtomicU32, expected: u32) -> WaitResult {
+    let key = key_of(futex);
+    let curr = crate::current();
+    let mut rq = current_run_queue::<NoPreemptIrqSave>();
+    let wq = bucket_of(key).lock();
+    if futex.load(Ordering::Acquire) != expected {
+        return WaitResult::Mismatched;
+    }
+    curr.set_futex_key(key);
+    rq.blocked_resched(wq, false);
+    WaitResult::Woken
+}
+
+/// Blocks the current task if the futex word still holds `expected`, until
+/// it is woken up by [`wake`] or [`requeue`], or the given duration has
+/// elapsed.
+#[cfg(feature = "irq")]
+pub fn wait_timeout(futex: &AtomicU32, expected: u32, dur: Duration) -> WaitResult {
+    let key = key_of(futex);
+    let curr = crate::current();
+    let deadline = axhal::time::wall_time() + dur;
+    let mut rq = current_run_queue::<NoPreemptIrqSave>();
+    let wq = bucket_of(key).lock();
+    if futex.load(Ordering::Acquire) != expected {
+        return WaitResult::Mismatched;
+    }
+    curr.set_futex_key(key);
+    crate::timers::set_alarm_wakeup(deadline, curr.clone());
+    rq.blocked_resched(wq, false);
+
+    curr.timer_ticket_expired();
+    // Still in the wait queue, must have timed out.
+    if curr.in_wait_queue() && remove_waiter(curr.as_task_ref()) {
+        return WaitResult::TimedOut;
+    }
+    if axhal::time::wall_time() >= deadline {
+        // Timed out, but a concurrent wake up took this task before it was
+        // removed. Pass the wake up on to another waiter so it's not lost.
+        wake_key(curr.futex_key(), 1);
+        return WaitResult::TimedOut;
+    }
+    WaitResult::Woken
+}
+
+/// Removes a timed out task from the bucket of the futex it waits on, which
+/// may have been changed by [`requeue`].
+///
+/// Returns `false` if the task has already been woken up.
+#[cfg(feature = "irq")]
+fn remove_waiter(task: &AxTaskRef) -> bool {
+    loop {
+        let key = task.futex_key();
+        let mut wq = bucket_of(key).lock();
+        if task.futex_key() != key {
+            // Requeued before we got the lock, try again.
+            continue;
+        }
+        return match wq.iter().position(|t| Arc::ptr_eq(t, task)) {
+            Some(i) => {
+                wq.remove(i);
+                task.set_in_wait_queue(false);
+                true
+            }
+            None => false,
+        };
+    }
+}
+
+/// Wakes up at most `count` tasks waiting on the futex.
+///
+/// Returns the number of tasks woken up.
+pub fn wake(futex: &AtomicU32, count: usize) -> usize {
+    wake_key(key_of(futex), count)
+}
+
+fn wake_key(key: usize, count: usize) -> usize {
+    let mut wq = bucket_of(key).lock();
+    let woken = take_waiters(&mut wq, key, count);
+    let num = woken.len();
+    for task in woken {
+        unblock_one_task(task, true);
+    }
+    num
+}
+
+/// Wakes up at most `wake_count` tasks waiting on the futex `from`, and
+/// moves at most `requeue_count` of the remaining waiters to wait on the
+/// futex `to`, without waking them up.
+///
+/// It is useful for a condition variable to wake up only one waiter at a
+/// broadcast, and let the others wait on the mutex instead, which avoids the
+/// thundering herd.
+///
+/// Returns the number of tasks woken up or requeued.
+pub fn requeue(from: &AtomicU32, wake_count: usize, to: &AtomicU32, requeue_count: usize) -> usize {
+    requeue_inner(from, wake_count, to, requeue_count, None).unwrap()
+}
+
+/// Same as [`requeue`], but only if the futex word `from` still holds
+/// `expected`.
+///
+/// Returns [`None`] without waking up or requeuing any task if the futex
+/// word does not hold `expected`.
+pub fn cmp_requeue(
+    from: &AtomicU32,
+    wake_count: usize,
+    to: &AtomicU32,
+    requeue_count: usize,
+    expected: u32,
+) -> Option<usize> {
+    requeue_inner(from, wake_count, to, requeue_count, Some(expected))
+}
+
+fn requeue_inner(
+    from: &AtomicU32,
+    wake_count: usize,
+    to: &AtomicU32,
+    requeue_count: usize,
+    expected: Option<u32>,
+) -> Option<usize> {
+    let (from_key, to_key) = (key_of(from), key_of(to));
+    let (from_idx, to_idx) = (bucket_index(from_key), bucket_index(to_key));
+
+    // Lock the buckets in the order of indices to avoid deadlocks.
+    let mut from_wq = BUCKETS[from_idx.min(to_idx)].lock();
+    let mut to_wq = if from_idx == to_idx {
+        None
+    } else {
+        Some(BUCKETS[from_idx.max(to_idx)].lock())
+    };
+    if from_idx > to_idx {
+        core::mem::swap(&mut from_wq, to_wq.as_mut().unwrap());
+    }
+
+    if expected.is_some_and(|val| from.load(Ordering::Acquire) != val) {
+        return None;
+    }
+    let woken = take_waiters(&mut from_wq, from_key, wake_count);
+    let moved = take_waiters(&mut from_wq, from_key, requeue_count);
+    let num = woken.len() + moved.len();
+    let target = to_wq.as_mut().unwrap_or(&mut from_wq);
+    for task in moved {
+        task.set_futex_key(to_key);
+        target.push_back(task);
+    }
+    for task in woken {
+        unblock_one_task(task, true);
+    }
+    Some(num)
+}
@@ -0,0 +1,535 @@
+//! Running `async` code on top of the tasks.
+//!
+//! An [`Executor`] runs many futures on a few worker tasks, so that a future
+//! waiting for I/O costs a small heap allocation rather than a whole task
+//! with its own stack. The workers sleep in a [`WaitQueue`] when no future is
+//! ready, and a [`Waker`] puts its future into the ready queue and notifies
+//! the wait queue. [`block_on`] runs a single future on the current task in
+//! the same way.
+//!
+//! [`WakerQueue`] helps to implement futures that wait for events, and
+//! [`sleep`] and [`sleep_until`] wait for the timer (with the `irq`
+//! feature).
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::sync::{Arc, Weak};
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::pin::{Pin, pin};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+#[cfg(feature = "irq")]
+use axhal::time::{TimeValue, wall_time};
+#[cfg(not(feature = "lockdep"))]
+use kspin::SpinNoIrq;
+
+#[cfg(feature = "lockdep")]
+use crate::lockdep::SpinNoIrq;
+use crate::{AxCpuMask, WaitQueue};
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// A queue of the [`Waker`]s of the futures waiting for an event, the
+/// counterpart of [`WaitQueue`] for futures.
+///
+/// A future registers the waker from its [`Context`] before returning
+/// [`Poll::Pending`], and checks the event again after the registration, so
+/// that a notification in between is not lost.
+pub struct WakerQueue {
+    wakers: SpinNoIrq<VecDeque<Waker>>,
+}
+
+impl WakerQueue {
+    /// Creates an empty waker queue.
+    pub const fn new() -> Self {
+        Self {
+            wakers: SpinNoIrq::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns `true` if no wakers are registered.
+    pub fn is_empty(&self) -> bool {
+        self.wakers.lock().is_empty()
+    }
+
+    /// Registers a waker to be woken up by the next notification.
+    ///
+    /// A waker that wakes the same task as a registered one is ignored.
+    pub fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push_back(waker.clone());
+        }
+    }
+
+    /// Wakes up the first registered waker.
+    ///
+    /// Returns `false` if there are no registered wakers. Note that a waker
+    /// is never removed if its future is dropped before being notified, so
+    /// prefer [`WakerQueue::notify_all`] if the futures can be cancelled.
+    pub fn notify_one(&self) -> bool {
+        let waker = self.wakers.lock().pop_front();
+        match waker {
+            Some(waker) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wakes up all registered wakers.
+    pub fn notify_all(&self) {
+        let wakers = core::mem::take(&mut *self.wakers.lock());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for WakerQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wakes up the task in [`block_on`].
+struct BlockOnSignal {
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl Wake for BlockOnSignal {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+}
+
+/// Runs a future to completion on the current task, blocking the task while
+/// the future is pending.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let signal = Arc::new(BlockOnSignal {
+        woken: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    let waker = Waker::from(signal.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = pin!(fut);
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+        signal
+            .wq
+            .wait_until(|| signal.woken.swap(false, Ordering::AcqRel));
+    }
+}
+
+// The states of a spawned future.
+const STATE_IDLE: u8 = 0;
+const STATE_SCHEDULED: u8 = 1;
+const STATE_RUNNING: u8 = 2;
+/// Woken up while it is being polled, so it must be polled again.
+const STATE_NOTIFIED: u8 = 3;
+const STATE_COMPLETE: u8 = 4;
+
+struct Shared {
+    ready: SpinNoIrq<VecDeque<Arc<Job>>>,
+    /// All the live jobs by their addresses, including the ones only held by
+    /// wakers, so that their futures can be dropped with the executor.
+    jobs: SpinNoIrq<BTreeMap<usize, Weak<Job>>>,
+    /// The workers wait here for ready jobs.
+    wq: WaitQueue,
+    shutdown: AtomicBool,
+}
+
+/// A future spawned on an [`Executor`].
+struct Job {
+    /// Only accessed by the worker that changed the state to
+    /// `STATE_RUNNING`.
+    future: UnsafeCell<Option<BoxFuture>>,
+    state: AtomicU8,
+    shared: Arc<Shared>,
+}
+
+unsafe impl Sync for Job {}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.shared
+            .jobs
+            .lock()
+            .remove(&(self as *const Self as usize));
+    }
+}
+
+impl Shared {
+    fn schedule(&self, job: Arc<Job>) {
+        let mut ready = self.ready.lock();
+        if self.shutdown.load(Ordering::Acquire) {
+            return;
+        }
+        ready.push_back(job);
+        drop(ready);
+        self.wq.notify_one(true);
+    }
+
+    fn next_job(&self) -> Option<Arc<Job>> {
+        loop {
+            self.wq.wait_until(|| {
+                !self.ready.lock().is_empty() || self.shutdown.load(Ordering::Acquire)
+            });
+            if self.shutdown.load(Ordering::Acquire) {
+                return None;
+            }
+            // May be taken by other workers.
+            if let Some(job) = self.ready.lock().pop_front() {
+                return Some(job);
+            }
+        }
+    }
+
+    fn run_worker(&self) {
+        while let Some(job) = self.next_job() {
+            job.run();
+        }
+    }
+}
+
+impl Job {
+    fn run(self: Arc<Self>) {
+        if self
+            .state
+            .compare_exchange(
+                STATE_SCHEDULED,
+                STATE_RUNNING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            return; // dropped with the executor
+        }
+        let waker = Waker::from(self.clone());
+        let mut cx = Context::from_waker(&waker);
+        // SAFETY: no other workers can poll this job while it's running.
+        let future = unsafe { &mut *self.future.get() };
+        let Some(fut) = future.as_mut() else {
+            return;
+        };
+        if fut.as_mut().poll(&mut cx).is_ready() {
+            *future = None;
+            self.state.store(STATE_COMPLETE, Ordering::Release);
+            return;
+        }
+        if self
+            .state
+            .compare_exchange(
+                STATE_RUNNING,
+                STATE_IDLE,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            // Woken up during polling.
+            self.state.store(STATE_SCHEDULED, Ordering::Release);
+            self.shared.schedule(self.clone());
+        }
+        // The executor may have been dropped during polling, and skipped
+        // this job as it was running.
+        if self.shared.shutdown.load(Ordering::SeqCst) {
+            self.cancel();
+        }
+    }
+
+    /// Drops the future if it's not being polled.
+    fn cancel(&self) {
+        for state in [STATE_IDLE, STATE_SCHEDULED] {
+            if self
+                .state
+                .compare_exchange(state, STATE_COMPLETE, Ordering::SeqCst, Ordering::Acquire)
+                .is_ok()
+            {
+                // SAFETY: no workers can poll a complete job.
+                drop(unsafe { (*self.future.get()).take() });
+                return;
+            }
+        }
+    }
+}
+
+impl Wake for Job {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let new_state = match state {
+                STATE_IDLE => STATE_SCHEDULED,
+                STATE_RUNNING => STATE_NOTIFIED,
+                _ => return, // already scheduled, or completed
+            };
+            match self.state.compare_exchange_weak(
+                state,
+                new_state,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(s) => state = s,
+            }
+        }
+        if state == STATE_IDLE {
+            self.shared.schedule(self.clone());
+        }
+    }
+}
+
+/// The result of a spawned future, shared with its [`JoinHandle`].
+struct Packet<T> {
+    output: SpinNoIrq<Option<T>>,
+    finished: AtomicBool,
+    wq: WaitQueue,
+    wakers: WakerQueue,
+}
+
+/// Marks the packet finished when the spawned future completes or is
+/// dropped.
+struct Finisher<T>(Arc<Packet<T>>);
+
+impl<T> Drop for Finisher<T> {
+    fn drop(&mut self) {
+        self.0.finished.store(true, Ordering::Release);
+        self.0.wq.notify_all(true);
+        self.0.wakers.notify_all();
+    }
+}
+
+/// A handle to wait for a future spawned by [`Executor::spawn`].
+///
+/// It can be waited for by the blocking [`JoinHandle::join`], or be awaited
+/// in another future. Both return [`None`] if the future has been dropped
+/// without completion because the executor was dropped. Dropping the handle
+/// detaches the future.
+pub struct JoinHandle<T> {
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns `true` if the future has completed or been dropped.
+    pub fn is_finished(&self) -> bool {
+        self.packet.finished.load(Ordering::Acquire)
+    }
+
+    /// Blocks the current task until the future completes, and returns its
+    /// output.
+    pub fn join(self) -> Option<T> {
+        self.packet.wq.wait_until(|| self.is_finished());
+        self.packet.output.lock().take()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if !self.is_finished() {
+            self.packet.wakers.register(cx.waker());
+            if !self.is_finished() {
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(self.packet.output.lock().take())
+    }
+}
+
+/// An executor that runs futures on a pool of worker tasks.
+///
+/// The futures in the pool can be polled by any worker. Dropping the
+/// executor stops the workers after their current polls, and drops the
+/// futures that have not completed.
+pub struct Executor {
+    shared: Arc<Shared>,
+}
+
+impl Executor {
+    /// Creates an executor with `num_workers` worker tasks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_workers` is zero.
+    pub fn new(num_workers: usize) -> Self {
+        assert!(num_workers > 0, "an executor requires at least one worker");
+        let executor = Self::empty();
+        for i in 0..num_workers {
+            executor.spawn_worker(i, None);
+        }
+        executor
+    }
+
+    /// Creates an executor with one worker task pinned to each CPU.
+    pub fn per_cpu() -> Self {
+        let executor = Self::empty();
+        for cpu_id in 0..axconfig::SMP {
+            executor.spawn_worker(cpu_id, Some(AxCpuMask::one_shot(cpu_id)));
+        }
+        executor
+    }
+
+    fn empty() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                ready: SpinNoIrq::new(VecDeque::new()),
+                jobs: SpinNoIrq::new(BTreeMap::new()),
+                wq: WaitQueue::new(),
+                shutdown: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    fn spawn_worker(&self, id: usize, cpumask: Option<AxCpuMask>) {
+        let shared = self.shared.clone();
+        let task = crate::TaskInner::new(
+            move || shared.run_worker(),
+            format!("executor-{id}"),
+            axconfig::TASK_STACK_SIZE,
+        );
+        if let Some(cpumask) = cpumask {
+            task.set_cpumask(cpumask);
+        }
+        crate::spawn_task(task);
+    }
+
+    /// Spawns a future to run on the executor.
+    pub fn spawn<F>(&self, fut: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let packet = Arc::new(Packet {
+            output: SpinNoIrq::new(None),
+            finished: AtomicBool::new(false),
+            wq: WaitQueue::new(),
+            wakers: WakerQueue::new(),
+        });
+        let finisher = Finisher(packet.clone());
+        let job = Arc::new(Job {
+            future: UnsafeCell::new(Some(Box::pin(async move {
+                let output = fut.await;
+                *finisher.0.output.lock() = Some(output);
+                drop(finisher);
+            }))),
+            state: AtomicU8::new(STATE_SCHEDULED),
+            shared: self.shared.clone(),
+        });
+        self.shared
+            .jobs
+            .lock()
+            .insert(Arc::as_ptr(&job) as usize, Arc::downgrade(&job));
+        self.shared.schedule(job);
+        JoinHandle { packet }
+    }
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        let mut ready = self.shared.ready.lock();
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        let scheduled = core::mem::take(&mut *ready);
+        drop(ready);
+        self.shared.wq.notify_all(true);
+        // The futures may be woken up after the executor is dropped, but they
+        // will not be scheduled, so drop them now, including the ones only
+        // held by wakers that may never be woken up. The running ones are
+        // dropped by the workers after polling.
+        let jobs: Vec<_> = self
+            .shared
+            .jobs
+            .lock()
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for job in jobs {
+            job.cancel();
+        }
+        drop(scheduled);
+    }
+}
+
+/// A future that completes at a deadline, returned by [`sleep`] and
+/// [`sleep_until`].
+#[cfg(feature = "irq")]
+pub struct Sleep {
+    deadline: TimeValue,
+    timer: Option<(crate::timer::TimerHandle, Arc<SpinNoIrq<Option<Waker>>>)>,
+}
+
+#[cfg(feature = "irq")]
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if wall_time() >= self.deadline {
+            return Poll::Ready(());
+        }
+        // Check the time again after updating the waker, as the timer may
+        // fire and take the old waker at any time.
+        match &self.timer {
+            Some((_, waker)) => *waker.lock() = Some(cx.waker().clone()),
+            None => {
+                let waker = Arc::new(SpinNoIrq::new(Some(cx.waker().clone())));
+                let timer_waker = waker.clone();
+                let handle = crate::timer::add(self.deadline, move |_| {
+                    if let Some(waker) = timer_waker.lock().take() {
+                        waker.wake();
+                    }
+                });
+                self.timer = Some((handle, waker));
+            }
+        }
+        if wall_time() >= self.deadline {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(feature = "irq")]
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some((handle, _)) = &self.timer {
+            handle.cancel();
+        }
+    }
+}
+
+/// Returns a future that completes after the given duration.
+#[cfg(feature = "irq")]
+pub fn sleep(dur: Duration) -> Sleep {
+    sleep_until(wall_time() + dur)
+}
+
+/// Returns a future that completes at the given deadline.
+#[cfg(feature = "irq")]
+pub fn sleep_until(deadline: TimeValue) -> Sleep {
+    Sleep {
+        deadline,
+        timer: None,
+    }
+}
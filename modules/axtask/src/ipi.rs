@@ -0,0 +1,89 @@
+//! Inter-processor interrupt (IPI) handling.
+//!
+//! IPIs are used to interrupt the tasks running on other CPUs when they are
+//! requested to be suspended, and to stop other CPUs by
+//! [`stop_other_cpus`](crate::stop_other_cpus).
+
+#[cfg(feature = "smp")]
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(feature = "smp")]
+use axhal::cpu::this_cpu_id;
+
+/// Whether the CPU is online, i.e., its run queue is initialized.
+#[cfg(feature = "smp")]
+static CPU_ONLINE: [AtomicBool; axconfig::SMP] = [const { AtomicBool::new(false) }; axconfig::SMP];
+
+/// Whether other CPUs are requested to stop.
+#[cfg(feature = "smp")]
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The number of CPUs that have stopped.
+#[cfg(feature = "smp")]
+static STOPPED_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// Marks the CPU as online, so that it will be stopped by
+/// [`stop_other_cpus`].
+#[cfg(feature = "smp")]
+pub(crate) fn cpu_online(cpu_id: usize) {
+    CPU_ONLINE[cpu_id].store(true, Ordering::Release);
+}
+
+/// Handles the IPI on the current CPU.
+pub(crate) fn handle_ipi() {
+    #[cfg(feature = "smp")]
+    if STOP_REQUESTED.load(Ordering::Acquire) {
+        stop_this_cpu();
+    }
+    // Reschedule at once if the current task is requested to be suspended.
+    // Since IRQs and preemption are both disabled here, the current task can
+    // be accessed safely.
+    #[cfg(feature = "preempt")]
+    if let Some(curr) = crate::current_may_uninit() {
+        if curr.is_suspend_requested() {
+            curr.set_preempt_pending(true);
+        }
+    }
+}
+
+#[cfg(feature = "smp")]
+fn stop_this_cpu() -> ! {
+    axhal::arch::disable_irqs();
+    STOPPED_CPUS.fetch_add(1, Ordering::AcqRel);
+    loop {
+        axhal::arch::halt();
+    }
+}
+
+/// Stops all other online CPUs by IPIs, and waits for them to stop for at most
+/// `timeout`.
+///
+/// If it's called by multiple CPUs concurrently, only the first one returns,
+/// and the others stop as well.
+#[cfg(feature = "smp")]
+pub(crate) fn stop_other_cpus(timeout: core::time::Duration) -> bool {
+    if STOP_REQUESTED.swap(true, Ordering::AcqRel) {
+        stop_this_cpu();
+    }
+    let this_cpu = this_cpu_id();
+    let mut others = 0;
+    for cpu_id in 0..axconfig::SMP {
+        if cpu_id != this_cpu && CPU_ONLINE[cpu_id].load(Ordering::Acquire) {
+            axhal::irq::send_ipi(cpu_id);
+            others += 1;
+        }
+    }
+    let deadline = axhal::time::wall_time() + timeout;
+    while STOPPED_CPUS.load(Ordering::Acquire) < others {
+        if axhal::time::wall_time() >= deadline {
+            warn!(
+                "stop other CPUs: only {} of {} stopped",
+                STOPPED_CPUS.load(Ordering::Acquire),
+                others
+            );
+            return false;
+        }
+        core::hint::spin_loop();
+    }
+    true
+}
@@ -0,0 +1,116 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use crate::{AxTaskRef, TaskInner, TaskState, WaitQueue};
+
+/// A collection of tasks spawned together, which can be joined in the order
+/// they exit.
+///
+/// Unlike [`AxTaskRef::join`], which waits for a specific task,
+/// [`JoinSet::join_next`] waits for *any* task in the set to exit.
+///
+/// Dropping a `JoinSet` does not wait for or cancel the remaining tasks, they
+/// keep running detached. Use [`JoinSet::join_all`] to wait for all of them,
+/// or [`JoinSet::cancel_all`] to request their cancellation first.
+pub struct JoinSet {
+    tasks: Vec<AxTaskRef>,
+    exited: Arc<WaitQueue>,
+}
+
+impl JoinSet {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            exited: Arc::new(WaitQueue::new()),
+        }
+    }
+
+    /// Returns the number of tasks in the set that have not been joined.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Whether all tasks in the set have been joined.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Spawns a new task into the set with the given parameters.
+    ///
+    /// Returns the task reference.
+    pub fn spawn_raw<F>(&mut self, f: F, name: String, stack_size: usize) -> AxTaskRef
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut task = TaskInner::new(f, name, stack_size);
+        task.set_exit_notifier(self.exited.clone());
+        let task = crate::spawn_task(task);
+        self.tasks.push(task.clone());
+        task
+    }
+
+    /// Spawns a new task into the set with the default parameters.
+    ///
+    /// See [`spawn`](crate::spawn) for the default parameters.
+    pub fn spawn<F>(&mut self, f: F) -> AxTaskRef
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
+    }
+
+    /// Removes an exited task from the set without blocking, and returns it
+    /// with its exit code.
+    ///
+    /// Returns [`None`] if no task in the set has exited yet.
+    pub fn try_join_next(&mut self) -> Option<(AxTaskRef, i32)> {
+        let idx = self
+            .tasks
+            .iter()
+            .position(|t| t.state() == TaskState::Exited)?;
+        let task = self.tasks.swap_remove(idx);
+        let exit_code = task.join().unwrap_or_default();
+        Some((task, exit_code))
+    }
+
+    /// Waits for any task in the set to exit, removes it from the set, and
+    /// returns it with its exit code.
+    ///
+    /// Tasks that exited earlier are returned first, without blocking.
+    /// Returns [`None`] if the set is empty.
+    pub fn join_next(&mut self) -> Option<(AxTaskRef, i32)> {
+        if self.tasks.is_empty() {
+            return None;
+        }
+        let tasks = &self.tasks;
+        self.exited
+            .wait_until(|| tasks.iter().any(|t| t.state() == TaskState::Exited));
+        self.try_join_next()
+    }
+
+    /// Waits for all tasks in the set to exit, and returns them with their
+    /// exit codes, in the order they are joined.
+    pub fn join_all(&mut self) -> Vec<(AxTaskRef, i32)> {
+        let mut res = Vec::with_capacity(self.tasks.len());
+        while let Some(joined) = self.join_next() {
+            res.push(joined);
+        }
+        res
+    }
+
+    /// Requests the cancellation of all tasks remaining in the set.
+    ///
+    /// See [`TaskInner::cancel`] for details. The tasks still need to be
+    /// joined to be removed from the set.
+    pub fn cancel_all(&self) {
+        for task in &self.tasks {
+            task.cancel();
+        }
+    }
+}
+
+impl Default for JoinSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
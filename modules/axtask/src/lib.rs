@@ -11,7 +11,8 @@
 //!   Otherwise, only a few APIs with naive implementation is available.
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
 //!    APIs can be used, such as [`sleep`], [`sleep_until`], and
-//!    [`WaitQueue::wait_timeout`].
+//!    [`WaitQueue::wait_timeout`]. With `smp`, tasks running on other CPUs
+//!    are interrupted by IPIs when they are [suspended](suspend).
 //! - `preempt`: Enable preemptive scheduling.
 //! - `sched_fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
 //!   `multitask` feature if it is enabled. This feature is enabled by default,
@@ -20,10 +21,34 @@
 //!   the `multitask` and `preempt` features if it is enabled.
 //! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
 //!   the `multitask` and `preempt` features if it is enabled.
+//! - `sched_rt`: Use the [real-time scheduler][4] with an EDF deadline class
+//!   and a fixed-priority class. It also enables the `multitask` and
+//!   `preempt` features if it is enabled, and overrides other scheduler
+//!   features.
+//! - `paging`: Allocate task stacks in a dedicated virtual region with a guard
+//!   page below each of them, to detect stack overflows by page faults.
+//!   Without it, the overflows are detected by checking a canary at the
+//!   bottom of the stacks when switching tasks.
+//! - `tickless`: Stop the periodic timer tick while the CPU is idle, and wake
+//!   it up at the next timer event instead. It also enables the `preempt`
+//!   feature, so that a task woken up by IRQs preempts the idle task at once.
+//!   It has no effect on SMP systems for now, as an idle CPU is not notified
+//!   when a task is put into its run queue by other CPUs.
+//! - `select_rq_if`: Allow users to define a custom policy to select run
+//!   queues for tasks on SMP systems, by implementing [`SelectRunQueueIf`].
+//! - `lockdep`: Enable the [lock dependency checker](lockdep), which reports
+//!   inconsistent lock orders and spinlocks held across blocking. It also
+//!   enables the `multitask` feature.
+//! - `watchdog`: Check the progress of tasks and CPUs on timer ticks, and
+//!   report tasks running too long with preemption disabled (with the
+//!   `preempt` feature) and CPUs without timer ticks. The timeouts are
+//!   configured in [`axconfig`]. It also enables the `multitask` and `irq`
+//!   features.
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
 //! [3]: scheduler::CFScheduler
+//! [4]: crate::sched_rt::RTScheduler
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -42,12 +67,35 @@ cfg_if::cfg_if! {
         #[macro_use]
         mod run_queue;
         mod task;
+        mod join_set;
         mod task_ext;
+        mod task_local;
         mod api;
+        mod registry;
+        mod stack;
+        mod stats;
         mod wait_queue;
 
+        #[cfg(feature = "sched_rt")]
+        mod sched_rt;
+
+        #[doc(cfg(feature = "multitask"))]
+        pub mod futex;
+        #[doc(cfg(feature = "multitask"))]
+        pub mod future;
+
+        #[cfg(feature = "irq")]
+        mod ipi;
         #[cfg(feature = "irq")]
         mod timers;
+        #[cfg(feature = "irq")]
+        #[doc(cfg(all(feature = "multitask", feature = "irq")))]
+        pub mod timer;
+        #[cfg(feature = "watchdog")]
+        mod watchdog;
+        #[cfg(feature = "lockdep")]
+        #[doc(cfg(feature = "lockdep"))]
+        pub mod lockdep;
 
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
@@ -0,0 +1,377 @@
+//! Lock dependency checker (lockdep).
+//!
+//! Each tracked lock belongs to a lock class, which is the source location
+//! where the lock is created (e.g., a `static` item, or the `new()` call in a
+//! constructor), so that all locks created at the same place share the
+//! dependencies. When a task acquires a lock while holding others, the
+//! dependencies "held class -> acquired class" are recorded in a global
+//! graph, and a potential deadlock is reported once a cycle appears, even if
+//! the deadlock never actually happened. The checks are done before the lock
+//! is acquired, so a real deadlock is still reported before the task hangs.
+//!
+//! The following problems are reported as errors in the log, with the call
+//! sites of the acquisitions involved:
+//!
+//! - Acquiring locks in inconsistent orders, e.g., ABBA deadlocks.
+//! - Acquiring a lock that is already held by the current task.
+//! - Holding a spinlock across [`yield_now`](crate::yield_now), sleeping, or
+//!   waiting on a [`WaitQueue`](crate::WaitQueue), even if the wait returns
+//!   without blocking.
+//!
+//! [`axsync::Mutex`] and [`SpinNoIrq`] in this module are tracked. The
+//! internal locks of the scheduler (run queues, wait queues, timer lists) are
+//! not, as the checker runs under them. Locks acquired in IRQ context are not
+//! tracked either, as they are not held by the interrupted task.
+//!
+//! [`axsync::Mutex`]: https://arceos-org.github.io/arceos/axsync/struct.Mutex.html
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The source location of a lock creation or acquisition.
+pub type Site = &'static Location<'static>;
+
+/// The kind of a tracked lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// A spinlock, which must not be held when the task blocks or yields.
+    Spin,
+    /// A sleeping lock, e.g., [`axsync::Mutex`].
+    ///
+    /// [`axsync::Mutex`]: https://arceos-org.github.io/arceos/axsync/struct.Mutex.html
+    Sleep,
+}
+
+/// A lock held by a task.
+pub(crate) struct HeldLock {
+    addr: usize,
+    class: usize,
+    kind: LockKind,
+    site: Site,
+}
+
+/// The first-seen acquisitions that made a dependency `from -> to`.
+#[derive(Clone, Copy)]
+struct Dependency {
+    /// Where the lock of class `from` was acquired.
+    from_site: Site,
+    /// Where the lock of class `to` was acquired while holding the former.
+    to_site: Site,
+}
+
+struct Graph {
+    edges: BTreeMap<usize, BTreeMap<usize, Dependency>>,
+    /// The reported problems, keyed by the pairs of classes or sites, so that
+    /// each of them is only reported once.
+    reported: BTreeSet<(usize, usize)>,
+}
+
+/// The number of problems reported so far.
+static NUM_REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+static GRAPH: kspin::SpinNoIrq<Graph> = kspin::SpinNoIrq::new(Graph {
+    edges: BTreeMap::new(),
+    reported: BTreeSet::new(),
+});
+
+impl Graph {
+    /// Adds a dependency `from -> to`, returns `false` if it already exists.
+    fn add(&mut self, from: usize, to: usize, dep: Dependency) -> bool {
+        let edges = self.edges.entry(from).or_default();
+        if edges.contains_key(&to) {
+            return false;
+        }
+        edges.insert(to, dep);
+        true
+    }
+
+    /// Searches for a path `from -> ... -> to`, and returns the first
+    /// dependency on it.
+    fn find_path(&self, from: usize, to: usize) -> Option<Dependency> {
+        let mut visited = BTreeSet::new();
+        let mut stack: Vec<(usize, Dependency)> = Vec::new();
+        for (&next, &dep) in self.edges.get(&from)? {
+            stack.push((next, dep));
+        }
+        while let Some((class, first)) = stack.pop() {
+            if class == to {
+                return Some(first);
+            }
+            if !visited.insert(class) {
+                continue;
+            }
+            if let Some(edges) = self.edges.get(&class) {
+                stack.extend(edges.keys().map(|&next| (next, first)));
+            }
+        }
+        None
+    }
+
+    fn first_report(&mut self, key: (usize, usize)) -> bool {
+        self.reported.insert(key)
+    }
+}
+
+fn site_key(site: Site) -> usize {
+    site as *const Location as usize
+}
+
+/// Returns the class of a lock created at `created`, or identifies the lock
+/// by its address if the creation site is unknown (e.g., created by C code).
+fn class_of(addr: usize, created: Option<Site>) -> usize {
+    created.map_or(addr, site_key)
+}
+
+/// Returns the current task if the locks it acquires should be tracked, i.e.,
+/// it's in a task context but not in IRQ context.
+fn tracked_task() -> Option<crate::CurrentTask> {
+    if axhal::trap::with_irq_trap_frame(|_| ()).is_some() {
+        return None;
+    }
+    crate::current_may_uninit()
+}
+
+/// Returns the number of problems reported so far.
+pub fn num_reports() -> usize {
+    NUM_REPORTS.load(Ordering::Relaxed)
+}
+
+enum Report {
+    Recursive { held: Site },
+    Inversion { held: Site, reverse: Dependency },
+    HeldAcrossBlocking { held: Site },
+}
+
+/// Records that the current task is going to acquire the lock at `addr`,
+/// and checks the dependencies against the locks it holds.
+///
+/// It must be called before the lock is acquired, and paired with
+/// [`release`] when the lock is released. For a lock acquired by a trylock,
+/// set `trylock` to `true`, as it can not cause deadlocks by itself.
+pub fn acquire(addr: usize, created: Option<Site>, kind: LockKind, site: Site, trylock: bool) {
+    let Some(curr) = tracked_task() else {
+        return; // not in a task context
+    };
+    let class = class_of(addr, created);
+    let mut reports = Vec::new();
+
+    let mut held = curr.held_locks().lock();
+    if !trylock {
+        let mut graph = GRAPH.lock();
+        for h in held.iter() {
+            if h.addr == addr {
+                if graph.first_report((site_key(h.site), site_key(site))) {
+                    reports.push(Report::Recursive { held: h.site });
+                }
+                continue;
+            }
+            if h.class == class {
+                continue; // nested locks of the same class are not checked
+            }
+            let dep = Dependency {
+                from_site: h.site,
+                to_site: site,
+            };
+            if !graph.add(h.class, class, dep) {
+                continue;
+            }
+            if let Some(reverse) = graph.find_path(class, h.class) {
+                if graph.first_report((h.class.min(class), h.class.max(class))) {
+                    reports.push(Report::Inversion {
+                        held: h.site,
+                        reverse,
+                    });
+                }
+            }
+        }
+    }
+    held.push(HeldLock {
+        addr,
+        class,
+        kind,
+        site,
+    });
+    drop(held);
+
+    for report in reports {
+        print_report(&curr.id_name(), site, report);
+    }
+}
+
+/// Records that the current task has released the lock at `addr`.
+pub fn release(addr: usize) {
+    let Some(curr) = tracked_task() else {
+        return;
+    };
+    let mut held = curr.held_locks().lock();
+    if let Some(i) = held.iter().rposition(|h| h.addr == addr) {
+        held.remove(i);
+    }
+}
+
+/// Checks that the current task holds no spinlocks before it blocks or
+/// yields the CPU at `site`.
+pub(crate) fn check_blocking(site: Site) {
+    let Some(curr) = tracked_task() else {
+        return;
+    };
+    let mut reports = Vec::new();
+    let held = curr.held_locks().lock();
+    let mut graph = GRAPH.lock();
+    for h in held.iter().filter(|h| h.kind == LockKind::Spin) {
+        if graph.first_report((site_key(h.site), site_key(site))) {
+            reports.push(Report::HeldAcrossBlocking { held: h.site });
+        }
+    }
+    drop(graph);
+    drop(held);
+
+    for report in reports {
+        print_report(&curr.id_name(), site, report);
+    }
+}
+
+fn print_report(task: &str, site: Site, report: Report) {
+    NUM_REPORTS.fetch_add(1, Ordering::Relaxed);
+    match report {
+        Report::Recursive { held } => {
+            error!("lockdep: recursive locking detected in task {}:", task);
+            error!("  acquiring the lock at {}", site);
+            error!("  which is already held since {}", held);
+        }
+        Report::Inversion { held, reverse } => {
+            error!(
+                "lockdep: possible circular locking dependency in task {}:",
+                task
+            );
+            error!("  acquiring a lock at {}", site);
+            error!("  while holding a lock acquired at {}", held);
+            error!("  but the reverse order has been seen:");
+            error!("  acquiring a lock at {}", reverse.to_site);
+            error!("  while holding a lock acquired at {}", reverse.from_site);
+        }
+        Report::HeldAcrossBlocking { held } => {
+            error!("lockdep: spinlock held across blocking in task {}:", task);
+            error!("  blocking or yielding at {}", site);
+            error!("  while holding a spinlock acquired at {}", held);
+        }
+    }
+}
+
+/// A [`kspin::SpinNoIrq`] tracked by the lock dependency checker.
+///
+/// It has the same interface as [`kspin::SpinNoIrq`], and its lock class is
+/// the location of [`SpinNoIrq::new`].
+pub struct SpinNoIrq<T: ?Sized> {
+    created: Site,
+    inner: kspin::SpinNoIrq<T>,
+}
+
+/// A guard of [`SpinNoIrq`].
+pub struct SpinNoIrqGuard<'a, T: ?Sized + 'a> {
+    addr: usize,
+    inner: kspin::SpinNoIrqGuard<'a, T>,
+}
+
+impl<T> SpinNoIrq<T> {
+    /// Creates a new [`SpinNoIrq`] wrapping the supplied data.
+    #[track_caller]
+    pub const fn new(data: T) -> Self {
+        Self {
+            created: Location::caller(),
+            inner: kspin::SpinNoIrq::new(data),
+        }
+    }
+
+    /// Consumes this [`SpinNoIrq`] and unwraps the underlying data.
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> SpinNoIrq<T> {
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
+    /// Locks the [`SpinNoIrq`] and returns a guard that permits access to the
+    /// inner data.
+    #[track_caller]
+    pub fn lock(&self) -> SpinNoIrqGuard<T> {
+        let addr = self.addr();
+        acquire(
+            addr,
+            Some(self.created),
+            LockKind::Spin,
+            Location::caller(),
+            false,
+        );
+        SpinNoIrqGuard {
+            addr,
+            inner: self.inner.lock(),
+        }
+    }
+
+    /// Tries to lock the [`SpinNoIrq`], returning a guard if successful.
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<SpinNoIrqGuard<T>> {
+        let inner = self.inner.try_lock()?;
+        let addr = self.addr();
+        acquire(
+            addr,
+            Some(self.created),
+            LockKind::Spin,
+            Location::caller(),
+            true,
+        );
+        Some(SpinNoIrqGuard { addr, inner })
+    }
+
+    /// Returns `true` if the lock is currently held.
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+impl<T: Default> Default for SpinNoIrq<T> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinNoIrqGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> Deref for SpinNoIrqGuard<'_, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> DerefMut for SpinNoIrqGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: ?Sized> Drop for SpinNoIrqGuard<'_, T> {
+    fn drop(&mut self) {
+        release(self.addr);
+    }
+}
@@ -0,0 +1,149 @@
+//! The registry of all live tasks.
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::time::Duration;
+
+#[cfg(not(feature = "lockdep"))]
+use kspin::SpinNoIrq;
+
+#[cfg(feature = "lockdep")]
+use crate::lockdep::SpinNoIrq;
+use crate::stats::SchedStats;
+use crate::task::TaskState;
+use crate::{AxCpuMask, AxTask, AxTaskRef, TaskId, TaskStats};
+
+/// A snapshot of the information of a task, see
+/// [`TaskInner::info`](crate::TaskInner::info).
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    /// The task ID.
+    pub id: TaskId,
+    /// The task name.
+    pub name: String,
+    /// The state of the task.
+    pub state: TaskState,
+    /// Whether the task is the idle task of a CPU.
+    pub is_idle: bool,
+    /// The effective priority of the task.
+    pub priority: isize,
+    /// The CPU affinity mask of the task.
+    pub cpumask: AxCpuMask,
+    /// The exit code, or [`None`] if the task has not exited.
+    pub exit_code: Option<i32>,
+    /// The CPU time and scheduling statistics of the task.
+    pub stats: TaskStats,
+}
+
+/// The CPU time and the context switches of all tasks, see [`cpu_usage`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuUsage {
+    /// The CPU time consumed.
+    pub runtime: Duration,
+    /// The number of context switches caused by blocking or exiting.
+    pub voluntary_switches: u64,
+    /// The number of context switches caused by preemption or yielding.
+    pub involuntary_switches: u64,
+}
+
+impl CpuUsage {
+    const ZERO: Self = Self {
+        runtime: Duration::ZERO,
+        voluntary_switches: 0,
+        involuntary_switches: 0,
+    };
+
+    fn add(&mut self, stats: &TaskStats) {
+        self.runtime += stats.runtime;
+        self.voluntary_switches += stats.voluntary_switches;
+        self.involuntary_switches += stats.involuntary_switches;
+    }
+}
+
+struct Registry {
+    /// All live tasks, keyed by task IDs.
+    ///
+    /// It holds weak references, so that it does not extend the lifetime of
+    /// the tasks. A task is removed when it is dropped, its statistics are
+    /// kept until then, as the task can not be upgraded while being dropped.
+    tasks: BTreeMap<u64, (Weak<AxTask>, Arc<SchedStats>)>,
+    /// The CPU usage of the dropped tasks.
+    dropped: CpuUsage,
+}
+
+static REGISTRY: SpinNoIrq<Registry> = SpinNoIrq::new(Registry {
+    tasks: BTreeMap::new(),
+    dropped: CpuUsage::ZERO,
+});
+
+pub(crate) fn register(task: &AxTaskRef) {
+    REGISTRY.lock().tasks.insert(
+        task.id().as_u64(),
+        (Arc::downgrade(task), task.sched_stats().clone()),
+    );
+}
+
+pub(crate) fn unregister(id: TaskId) {
+    let mut registry = REGISTRY.lock();
+    if let Some((_, stats)) = registry.tasks.remove(&id.as_u64()) {
+        registry.dropped.add(&stats.snapshot(TaskState::Exited));
+    }
+}
+
+/// Returns an iterator over all live tasks in the order of task IDs.
+///
+/// It iterates over a snapshot taken when called, the tasks created after
+/// that are not included.
+pub fn tasks() -> impl Iterator<Item = AxTaskRef> {
+    let tasks: Vec<AxTaskRef> = REGISTRY
+        .lock()
+        .tasks
+        .values()
+        .filter_map(|(task, _)| task.upgrade())
+        .collect();
+    tasks.into_iter()
+}
+
+/// Looks up a live task by its ID.
+///
+/// An exited task can still be found until it is dropped, i.e., all
+/// references to it (including the one held by the joiner) are released.
+pub fn lookup(id: TaskId) -> Option<AxTaskRef> {
+    REGISTRY
+        .lock()
+        .tasks
+        .get(&id.as_u64())
+        .and_then(|(task, _)| task.upgrade())
+}
+
+/// Returns the CPU time and the context switches of all tasks except the idle
+/// ones, including the tasks that have been dropped.
+///
+/// Unlike summing up the statistics of [`tasks`], it never decreases when a
+/// task is dropped.
+pub fn cpu_usage() -> CpuUsage {
+    let (mut usage, tasks) = {
+        let registry = REGISTRY.lock();
+        let tasks: Vec<_> = registry
+            .tasks
+            .values()
+            .map(|(task, stats)| (task.upgrade(), stats.clone()))
+            .collect();
+        (registry.dropped, tasks)
+    };
+    // The upgraded tasks are released after the lock, as dropping the last
+    // reference unregisters the task.
+    for (task, stats) in &tasks {
+        let state = match task {
+            Some(task) if task.is_idle() => continue,
+            Some(task) => task.state(),
+            None => TaskState::Exited, // being dropped
+        };
+        usage.add(&stats.snapshot(state));
+    }
+    usage
+}
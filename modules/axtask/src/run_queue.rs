@@ -1,6 +1,7 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 #[cfg(feature = "smp")]
 use alloc::sync::Weak;
@@ -53,6 +54,36 @@ static mut RUN_QUEUES: [MaybeUninit<&'static mut AxRunQueue>; axconfig::SMP] =
 #[allow(clippy::declare_interior_mutable_const)] // It's ok because it's used only for initialization `RUN_QUEUES`.
 const ARRAY_REPEAT_VALUE: MaybeUninit<&'static mut AxRunQueue> = MaybeUninit::uninit();
 
+/// The number of ready tasks in each run queue, indexed by cpu_id.
+///
+/// It is kept outside of [`AxRunQueue`] so that it can be read without
+/// touching the run queues of CPUs that have not been initialized.
+static RUN_QUEUE_LOADS: [AtomicUsize; axconfig::SMP] =
+    [const { AtomicUsize::new(0) }; axconfig::SMP];
+
+/// Returns the number of ready tasks in the run queue of the given CPU.
+#[cfg(feature = "smp")]
+#[inline]
+fn run_queue_load(cpu_id: usize) -> usize {
+    RUN_QUEUE_LOADS[cpu_id].load(Ordering::Acquire)
+}
+
+/// The interface to define custom policies for selecting run queues in user apps.
+///
+/// It is consulted by [`select_run_queue`] when a task is spawned, woken up or
+/// migrated with the `smp` feature enabled.
+#[cfg(feature = "select_rq_if")]
+#[crate_interface::def_interface]
+pub trait SelectRunQueueIf {
+    /// Selects the run queue (indexed by cpu_id) for the given task.
+    ///
+    /// `loads` is the number of ready tasks in each run queue, indexed by
+    /// cpu_id. The selected index must be allowed by the task's CPU affinity
+    /// ([`TaskInner::cpumask`]). Returns [`None`] to fall back to the default
+    /// policy, which selects the least loaded run queue.
+    fn select_run_queue(task: &TaskInner, loads: &[usize]) -> Option<usize>;
+}
+
 /// Returns a reference to the current run queue in [`CurrentRunQueueRef`].
 ///
 /// ## Safety
@@ -78,12 +109,14 @@ pub(crate) fn current_run_queue<G: BaseGuard>() -> CurrentRunQueueRef<'static, G
 
 /// Selects the run queue index based on a CPU set bitmap and load balancing.
 ///
-/// This function filters the available run queues based on the provided `cpumask` and
-/// selects the run queue index for the next task. The selection is based on a round-robin algorithm.
+/// This function filters the available run queues based on the `cpumask` of the task and
+/// selects the run queue index for it. If the feature `select_rq_if` is enabled, the policy
+/// defined by [`SelectRunQueueIf`] is consulted first. By default, the run queue with the
+/// fewest ready tasks is selected, and ties are broken in a round-robin manner.
 ///
 /// ## Arguments
 ///
-/// * `cpumask` - A bitmap representing the CPUs that are eligible for task execution.
+/// * `task` - The task to be put into the selected run queue.
 ///
 /// ## Returns
 ///
@@ -97,19 +130,44 @@ pub(crate) fn current_run_queue<G: BaseGuard>() -> CurrentRunQueueRef<'static, G
 // The modulo operation is safe here because `axconfig::SMP` is always greater than 1 with "smp" enabled.
 #[allow(clippy::modulo_one)]
 #[inline]
-fn select_run_queue_index(cpumask: AxCpuMask) -> usize {
-    use core::sync::atomic::{AtomicUsize, Ordering};
+fn select_run_queue_index(task: &TaskInner) -> usize {
     static RUN_QUEUE_INDEX: AtomicUsize = AtomicUsize::new(0);
 
+    let cpumask = task.cpumask();
     assert!(!cpumask.is_empty(), "No available CPU for task execution");
 
-    // Round-robin selection of the run queue index.
-    loop {
-        let index = RUN_QUEUE_INDEX.fetch_add(1, Ordering::SeqCst) % axconfig::SMP;
-        if cpumask.get(index) {
-            return index;
+    #[cfg(feature = "select_rq_if")]
+    {
+        let loads: [usize; axconfig::SMP] = core::array::from_fn(run_queue_load);
+        if let Some(index) =
+            crate_interface::call_interface!(SelectRunQueueIf::select_run_queue(task, &loads))
+        {
+            if index < axconfig::SMP && cpumask.get(index) {
+                return index;
+            }
+            warn!(
+                "invalid run queue {} selected for {}, use the default policy",
+                index,
+                task.id_name()
+            );
         }
     }
+
+    // Scan from a round-robin start, so that tasks are spread among the run queues
+    // with the same load.
+    let start = RUN_QUEUE_INDEX.fetch_add(1, Ordering::SeqCst) % axconfig::SMP;
+    let mut selected: Option<(usize, usize)> = None;
+    for i in 0..axconfig::SMP {
+        let index = (start + i) % axconfig::SMP;
+        if !cpumask.get(index) {
+            continue;
+        }
+        let load = run_queue_load(index);
+        if selected.is_none_or(|(_, min_load)| load < min_load) {
+            selected = Some((index, load));
+        }
+    }
+    selected.unwrap().0
 }
 
 /// Retrieves a `'static` reference to the run queue corresponding to the given index.
@@ -138,7 +196,8 @@ fn get_run_queue(index: usize) -> &'static mut AxRunQueue {
 /// Selects the appropriate run queue for the provided task.
 ///
 /// * In a single-core system, this function always returns a reference to the global run queue.
-/// * In a multi-core system, this function selects the run queue based on the task's CPU affinity and load balance.
+/// * In a multi-core system, this function selects the run queue by [`select_run_queue_index`], which
+///   respects the task's CPU affinity and can be customized by [`SelectRunQueueIf`].
 ///
 /// ## Arguments
 ///
@@ -148,11 +207,6 @@ fn get_run_queue(index: usize) -> &'static mut AxRunQueue {
 ///
 /// * [`AxRunQueueRef`] - a static reference to the selected [`AxRunQueue`] (current or remote).
 ///
-/// ## TODO
-///
-/// 1. Implement better load balancing across CPUs for more efficient task distribution.
-/// 2. Use a more generic load balancing algorithm that can be customized or replaced.
-///
 #[inline]
 pub(crate) fn select_run_queue<G: BaseGuard>(task: &AxTaskRef) -> AxRunQueueRef<'static, G> {
     let irq_state = G::acquire();
@@ -169,7 +223,7 @@ pub(crate) fn select_run_queue<G: BaseGuard>(task: &AxTaskRef) -> AxRunQueueRef<
     #[cfg(feature = "smp")]
     {
         // When SMP is enabled, select the run queue based on the task's CPU affinity and load balance.
-        let index = select_run_queue_index(task.cpumask());
+        let index = select_run_queue_index(task);
         AxRunQueueRef {
             inner: get_run_queue(index),
             state: irq_state,
@@ -178,6 +232,29 @@ pub(crate) fn select_run_queue<G: BaseGuard>(task: &AxTaskRef) -> AxRunQueueRef<
     }
 }
 
+/// Returns a reference to the run queue that the task is put into most
+/// recently, where it may still be queued.
+///
+/// * In a single-core system, this function always returns a reference to the global run queue.
+/// * In a multi-core system, the task may be moved to another run queue at any time,
+///   the caller should check the CPU ID of the task again with the scheduler locked.
+#[inline]
+pub(crate) fn task_run_queue<G: BaseGuard>(task: &AxTaskRef) -> AxRunQueueRef<'static, G> {
+    let irq_state = G::acquire();
+    #[cfg(not(feature = "smp"))]
+    let inner = {
+        let _ = task;
+        unsafe { RUN_QUEUE.current_ref_mut_raw() }
+    };
+    #[cfg(feature = "smp")]
+    let inner = get_run_queue(task.cpu_id());
+    AxRunQueueRef {
+        inner,
+        state: irq_state,
+        _phantom: core::marker::PhantomData,
+    }
+}
+
 /// [`AxRunQueue`] represents a run queue for global system or a specific CPU.
 pub(crate) struct AxRunQueue {
     /// The ID of the CPU this run queue is associated with.
@@ -237,7 +314,11 @@ impl<G: BaseGuard> AxRunQueueRef<'_, G> {
             self.inner.cpu_id
         );
         assert!(task.is_ready());
-        self.inner.scheduler.lock().add_task(task);
+        let mut scheduler = self.inner.scheduler.lock();
+        #[cfg(feature = "smp")]
+        task.set_cpu_id(self.inner.cpu_id);
+        scheduler.add_task(task);
+        self.inner.inc_load();
     }
 
     /// Unblock one task by inserting it into the run queue.
@@ -266,6 +347,61 @@ impl<G: BaseGuard> AxRunQueueRef<'_, G> {
             }
         }
     }
+
+    /// Resumes a suspended task by inserting it into the run queue.
+    ///
+    /// Returns `false` if the task is not in [`TaskState::Suspended`], which
+    /// means it has not been suspended yet, or it's already resumed.
+    pub fn resume_task(&mut self, task: AxTaskRef) -> bool {
+        task.set_suspend_requested(false);
+        if !task.transition_state(TaskState::Suspended, TaskState::Ready) {
+            return false;
+        }
+        debug!(
+            "task resume: {} on run_queue {}",
+            task.id_name(),
+            self.inner.cpu_id
+        );
+        task.sched_stats()
+            .on_resume(axhal::time::monotonic_time_nanos());
+        // The task may be suspended just now in the middle of `resched()` on
+        // its CPU, wait for it to be switched out.
+        #[cfg(feature = "smp")]
+        while task.on_cpu() {
+            core::hint::spin_loop();
+        }
+        let mut scheduler = self.inner.scheduler.lock();
+        #[cfg(feature = "smp")]
+        task.set_cpu_id(self.inner.cpu_id);
+        scheduler.put_prev_task(task, false);
+        self.inner.inc_load();
+        true
+    }
+
+    /// Applies the effective priority of the given task to the scheduler of
+    /// this run queue, which should be got by [`task_run_queue`].
+    ///
+    /// The task may be in any state. If it is queued, it is re-queued in the
+    /// place of the new priority.
+    ///
+    /// Returns [`None`] if the task has been put into another run queue
+    /// meanwhile, the caller should retry with that one.
+    pub fn update_task_priority(&mut self, task: &AxTaskRef) -> Option<bool> {
+        let prio = task.effective_priority();
+        let mut scheduler = self.inner.scheduler.lock();
+        // The CPU ID is only changed with the lock of the target scheduler
+        // held, so it can not leave this run queue until the lock is released.
+        #[cfg(feature = "smp")]
+        if task.cpu_id() != self.inner.cpu_id {
+            return None;
+        }
+        if scheduler.set_priority(task, prio) {
+            task.set_effective_priority(prio);
+            Some(true)
+        } else {
+            Some(false)
+        }
+    }
 }
 
 /// Core functions of run queue.
@@ -392,7 +528,10 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
     ///     2. The caller must ensure that the current task is in the running state.
     ///     3. The caller must ensure that the current task is not the idle task.
     ///     4. The lock of the wait queue will be released explicitly after current task is pushed into it.
-    pub fn blocked_resched(&mut self, mut wq_guard: WaitQueueGuard) {
+    ///
+    /// If `cancellable` is true, the current task can be woken up by [`TaskInner::cancel`], and it
+    /// returns immediately if the task has been cancelled.
+    pub fn blocked_resched(&mut self, mut wq_guard: WaitQueueGuard, cancellable: bool) {
         let curr = &self.current_task;
         assert!(curr.is_running());
         assert!(!curr.is_idle());
@@ -404,10 +543,15 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
 
         // Mark the task as blocked, this has to be done before adding it to the wait queue
         // while holding the lock of the wait queue.
+        if cancellable {
+            curr.set_in_cancellable_wait(true);
+        }
         curr.set_state(TaskState::Blocked);
-        curr.set_in_wait_queue(true);
-
-        wq_guard.push_back(curr.clone());
+        // The task may be still in the wait queue if it was woken up by `cancel()`.
+        if !curr.in_wait_queue() {
+            curr.set_in_wait_queue(true);
+            wq_guard.push_back(curr.clone());
+        }
         // Drop the lock of wait queue explictly.
         drop(wq_guard);
 
@@ -416,11 +560,31 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
         // see `unblock_task()` for details.
 
         debug!("task block: {}", curr.id_name());
-        self.inner.resched();
+        self.resched_blocked(cancellable);
+    }
+
+    /// Reschedules after the current task is marked as `Blocked`.
+    ///
+    /// If `cancellable` is true and the current task has been cancelled, it
+    /// returns without rescheduling.
+    fn resched_blocked(&mut self, cancellable: bool) {
+        let curr = &self.current_task;
+        if !cancellable {
+            self.inner.resched();
+            return;
+        }
+        if !curr.abort_block_if_cancelled() {
+            self.inner.resched();
+        }
+        curr.set_in_cancellable_wait(false);
     }
 
+    /// Block the current task until the deadline.
+    ///
+    /// If `cancellable` is true, the current task can be woken up by [`TaskInner::cancel`], and it
+    /// returns immediately if the task has been cancelled.
     #[cfg(feature = "irq")]
-    pub fn sleep_until(&mut self, deadline: axhal::time::TimeValue) {
+    pub fn sleep_until(&mut self, deadline: axhal::time::TimeValue, cancellable: bool) {
         let curr = &self.current_task;
         debug!("task sleep: {}, deadline={:?}", curr.id_name(), deadline);
         assert!(curr.is_running());
@@ -429,16 +593,34 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
         let now = axhal::time::wall_time();
         if now < deadline {
             crate::timers::set_alarm_wakeup(deadline, curr.clone());
+            if cancellable {
+                curr.set_in_cancellable_wait(true);
+            }
             curr.set_state(TaskState::Blocked);
-            self.inner.resched();
+            self.resched_blocked(cancellable);
+            // Expire the timer event in case of being woken up by `cancel()`.
+            self.current_task.timer_ticket_expired();
         }
     }
 
+    /// Sets the base priority of the current task.
+    ///
+    /// If the current task has inherited a higher priority from other tasks,
+    /// the inherited one keeps taking effect until it is taken back.
     pub fn set_current_priority(&mut self, prio: isize) -> bool {
-        self.inner
-            .scheduler
-            .lock()
-            .set_priority(self.current_task.as_task_ref(), prio)
+        let curr = self.current_task.as_task_ref();
+        let mut scheduler = self.inner.scheduler.lock();
+        // Let the scheduler validate the new priority first.
+        if !scheduler.set_priority(curr, prio) {
+            return false;
+        }
+        curr.set_base_priority(prio);
+        let effective = curr.effective_priority();
+        if effective != prio {
+            scheduler.set_priority(curr, effective);
+        }
+        curr.set_effective_priority(effective);
+        true
     }
 }
 
@@ -458,6 +640,16 @@ impl AxRunQueue {
         }
     }
 
+    #[inline]
+    fn inc_load(&self) {
+        RUN_QUEUE_LOADS[self.cpu_id].fetch_add(1, Ordering::AcqRel);
+    }
+
+    #[inline]
+    fn dec_load(&self) {
+        RUN_QUEUE_LOADS[self.cpu_id].fetch_sub(1, Ordering::AcqRel);
+    }
+
     /// Puts target task into current run queue with `Ready` state
     /// if its state matches `current_state` (except idle task).
     ///
@@ -471,12 +663,19 @@ impl AxRunQueue {
         current_state: TaskState,
         preempt: bool,
     ) -> bool {
+        // Leave the task out of the run queue if it's requested to be suspended.
+        if task.suspend_if_requested(current_state) {
+            debug!("task suspend: {}", task.id_name());
+            return false;
+        }
         // If the task's state matches `current_state`, set its state to `Ready` and
         // put it back to the run queue (except idle task).
         if task.transition_state(current_state, TaskState::Ready) && !task.is_idle() {
             // If the task is blocked, wait for the task to finish its scheduling process.
             // See `unblock_task()` for details.
             if current_state == TaskState::Blocked {
+                task.sched_stats()
+                    .on_wakeup(axhal::time::monotonic_time_nanos());
                 // Wait for next task's scheduling process to complete.
                 // If the owning (remote) CPU is still in the middle of schedule() with
                 // this task (next task) as prev, wait until it's done referencing the task.
@@ -494,7 +693,11 @@ impl AxRunQueue {
                 }
             }
             // TODO: priority
-            self.scheduler.lock().put_prev_task(task, preempt);
+            let mut scheduler = self.scheduler.lock();
+            #[cfg(feature = "smp")]
+            task.set_cpu_id(self.cpu_id);
+            scheduler.put_prev_task(task, preempt);
+            self.inc_load();
             true
         } else {
             false
@@ -504,14 +707,28 @@ impl AxRunQueue {
     /// Core reschedule subroutine.
     /// Pick the next task to run and switch to it.
     fn resched(&mut self) {
-        let next = self
-            .scheduler
-            .lock()
-            .pick_next_task()
-            .unwrap_or_else(|| unsafe {
-                // Safety: IRQs must be disabled at this time.
-                IDLE_TASK.current_ref_raw().get_unchecked().clone()
-            });
+        let next = loop {
+            let next = self.scheduler.lock().pick_next_task();
+            if next.is_some() {
+                self.dec_load();
+            }
+            // Steal a task from other CPUs before going idle.
+            #[cfg(feature = "smp")]
+            let next = next.or_else(|| self.steal_task());
+            match next {
+                // Requested to be suspended while waiting in the run queue.
+                Some(task) if task.suspend_if_requested(TaskState::Ready) => {
+                    debug!("task suspend: {}", task.id_name());
+                }
+                next => break next,
+            }
+        };
+        let next = next.unwrap_or_else(|| unsafe {
+            // Safety: IRQs must be disabled at this time.
+            IDLE_TASK.current_ref_raw().get_unchecked().clone()
+        });
+        #[cfg(feature = "watchdog")]
+        crate::watchdog::on_resched(self.cpu_id, &next);
         assert!(
             next.is_ready(),
             "next {} is not ready: {:?}",
@@ -521,6 +738,46 @@ impl AxRunQueue {
         self.switch_to(crate::current(), next);
     }
 
+    /// Steals a ready task that is allowed to run on this CPU from the busiest
+    /// run queue of other CPUs.
+    ///
+    /// Returns [`None`] if no task can be stolen.
+    #[cfg(feature = "smp")]
+    fn steal_task(&mut self) -> Option<AxTaskRef> {
+        let busiest = (0..axconfig::SMP)
+            .filter(|&i| i != self.cpu_id)
+            .max_by_key(|&i| run_queue_load(i))?;
+        let load = run_queue_load(busiest);
+        if load == 0 {
+            return None;
+        }
+
+        // A non-zero load means the run queue has been initialized.
+        let src = get_run_queue(busiest);
+        let stolen = {
+            let mut scheduler = src.scheduler.lock();
+            let stolen = steal_from(&mut scheduler, self.cpu_id, load)?;
+            // Changed with the source scheduler locked, so that the ones
+            // locking it by `task_run_queue` see the move and retry.
+            stolen.set_cpu_id(self.cpu_id);
+            stolen
+        };
+        src.dec_load();
+        debug!(
+            "task steal: {} from run_queue {} to {}",
+            stolen.id_name(),
+            busiest,
+            self.cpu_id
+        );
+
+        // The task may have just been put back by the source CPU, wait for
+        // it to finish its scheduling process before running it here.
+        while stolen.on_cpu() {
+            core::hint::spin_loop();
+        }
+        Some(stolen)
+    }
+
     fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
         // Make sure that IRQs are disabled by kernel guard or other means.
         #[cfg(all(not(test), feature = "irq"))] // Note: irq is faked under unit tests.
@@ -536,15 +793,33 @@ impl AxRunQueue {
         #[cfg(feature = "preempt")]
         next_task.set_preempt_pending(false);
         next_task.set_state(TaskState::Running);
+        // Start charging the budget only when the task really runs, not when it
+        // is picked, e.g., by stealing.
+        #[cfg(feature = "sched_rt")]
+        next_task.on_switch_in(axhal::time::monotonic_time_nanos());
         if prev_task.ptr_eq(&next_task) {
             return;
         }
 
+        #[cfg(not(feature = "paging"))]
+        prev_task.check_stack_overflow();
+        #[cfg(feature = "tickless")]
+        if prev_task.is_idle() {
+            crate::timers::restart_tick();
+        }
+
         // Claim the task as running, we do this before switching to it
         // such that any running task will have this set.
         #[cfg(feature = "smp")]
         next_task.set_on_cpu(true);
 
+        let now = axhal::time::monotonic_time_nanos();
+        let voluntary = matches!(prev_task.state(), TaskState::Blocked | TaskState::Exited);
+        prev_task.sched_stats().on_switch_out(now, voluntary);
+        #[cfg(feature = "sched_rt")]
+        prev_task.as_task_ref().on_switch_out(now);
+        next_task.sched_stats().on_switch_in(now, self.cpu_id);
+
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
             let next_ctx_ptr = next_task.ctx_mut_ptr();
@@ -597,17 +872,53 @@ fn gc_entry() {
     }
 }
 
+/// Takes the first task that is allowed to run on the CPU `cpu_id` out of
+/// `scheduler`, scanning at most `max_scan` tasks.
+///
+/// The skipped tasks are put back as if they were never taken out: not
+/// through the preemption path, which may charge them or put them to the
+/// head of the queue. The rest of the queue is also taken out and put back
+/// after them, so that all tasks keep their order for the schedulers that
+/// append a put back task to the tail.
+#[cfg(any(feature = "smp", test))]
+pub(crate) fn steal_from<S>(scheduler: &mut S, cpu_id: usize, max_scan: usize) -> Option<AxTaskRef>
+where
+    S: BaseScheduler<SchedItem = AxTaskRef>,
+{
+    let mut skipped = alloc::vec::Vec::new();
+    let mut stolen = None;
+    for _ in 0..max_scan {
+        match scheduler.pick_next_task() {
+            Some(task) if task.cpumask().get(cpu_id) => {
+                stolen = Some(task);
+                break;
+            }
+            Some(task) => skipped.push(task),
+            None => break,
+        }
+    }
+    if !skipped.is_empty() {
+        while let Some(task) = scheduler.pick_next_task() {
+            skipped.push(task);
+        }
+        for task in skipped {
+            scheduler.put_prev_task(task, false);
+        }
+    }
+    stolen
+}
+
 /// The task routine for migrating the current task to the correct CPU.
 ///
 /// It calls `select_run_queue` to get the correct run queue for the task, and
 /// then puts the task to the scheduler of target run queue.
 #[cfg(feature = "smp")]
 pub(crate) fn migrate_entry(migrated_task: AxTaskRef) {
-    select_run_queue::<kernel_guard::NoPreemptIrqSave>(&migrated_task)
-        .inner
-        .scheduler
-        .lock()
-        .put_prev_task(migrated_task, false)
+    let rq = select_run_queue::<kernel_guard::NoPreemptIrqSave>(&migrated_task);
+    let mut scheduler = rq.inner.scheduler.lock();
+    migrated_task.set_cpu_id(rq.inner.cpu_id);
+    scheduler.put_prev_task(migrated_task, false);
+    rq.inner.inc_load();
 }
 
 /// Clear the `on_cpu` field of previous task running on this CPU.
@@ -635,6 +946,8 @@ pub(crate) fn init() {
     // Put the subsequent execution into the `main` task.
     let main_task = TaskInner::new_init("main".into()).into_arc();
     main_task.set_state(TaskState::Running);
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::on_resched(cpu_id, &main_task);
     unsafe { CurrentTask::init_current(main_task) }
 
     RUN_QUEUE.with_current(|rq| {
@@ -643,6 +956,11 @@ pub(crate) fn init() {
     unsafe {
         RUN_QUEUES[cpu_id].write(RUN_QUEUE.current_ref_mut_raw());
     }
+    // Count the gc task after the run queue is published, since other CPUs
+    // only steal from run queues with non-zero load.
+    RUN_QUEUE_LOADS[cpu_id].store(1, Ordering::Release);
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    crate::ipi::cpu_online(cpu_id);
 }
 
 pub(crate) fn init_secondary() {
@@ -662,4 +980,9 @@ pub(crate) fn init_secondary() {
     unsafe {
         RUN_QUEUES[cpu_id].write(RUN_QUEUE.current_ref_mut_raw());
     }
+    // Count the gc task after the run queue is published, since other CPUs
+    // only steal from run queues with non-zero load.
+    RUN_QUEUE_LOADS[cpu_id].store(1, Ordering::Release);
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    crate::ipi::cpu_online(cpu_id);
 }
@@ -0,0 +1,523 @@
+//! A real-time scheduler with a deadline class and a fixed-priority class.
+//!
+//! - Tasks with [`DeadlineParams`] are in the deadline class, scheduled by the
+//!   Earliest Deadline First (EDF) algorithm. Each of them is given a CPU
+//!   time budget in every period. When the budget is exhausted, the overrun
+//!   is recorded, the deadline is postponed by as many periods as the overrun
+//!   takes from their budgets, and the task is throttled, i.e., kept out of
+//!   the ready queue until the period it can run again starts, as done by the
+//!   Constant Bandwidth Server (CBS).
+//! - Other tasks are in the fixed-priority class, which is strictly lower
+//!   than the deadline class. Tasks with the same priority are scheduled in
+//!   FIFO order.
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::ops::Deref;
+use core::sync::atomic::{AtomicI64, AtomicIsize, AtomicU64, Ordering};
+use core::time::Duration;
+
+use kspin::SpinRaw;
+use scheduler::BaseScheduler;
+
+use crate::TaskInner;
+
+/// The highest priority of the fixed-priority class.
+const MIN_PRIO: isize = -20;
+/// The lowest priority of the fixed-priority class.
+const MAX_PRIO: isize = 19;
+
+const CLASS_DEADLINE: u8 = 0;
+const CLASS_FIXED_PRIO: u8 = 1;
+/// Not a scheduling class, the key class of tasks in the throttled queue.
+const CLASS_THROTTLED: u8 = 2;
+
+/// The position of a task in the ready queue: the scheduling class, the
+/// absolute deadline (deadline class) or priority (fixed-priority class),
+/// and a sequence number to keep FIFO order among equals.
+///
+/// In the throttled queue, it is [`CLASS_THROTTLED`], the time the task is
+/// throttled until, and the sequence number.
+type SortKey = (u8, i64, u64);
+
+/// Parameters of a task in the deadline scheduling class.
+///
+/// The task is allowed to run for `budget` in every `period`, and each run
+/// should be finished within `deadline` since the period starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineParams {
+    /// The interval between two activations of the task.
+    pub period: Duration,
+    /// The CPU time the task can consume in each period.
+    pub budget: Duration,
+    /// The relative deadline since the period starts.
+    pub deadline: Duration,
+}
+
+impl DeadlineParams {
+    /// Returns `true` if `0 < budget <= deadline <= period`.
+    pub fn is_valid(&self) -> bool {
+        !self.budget.is_zero() && self.budget <= self.deadline && self.deadline <= self.period
+    }
+}
+
+/// The value of [`RTTask::exec_start`] when the task is not running.
+const NOT_RUNNING: u64 = u64::MAX;
+
+fn next_seq() -> u64 {
+    static SEQ: AtomicU64 = AtomicU64::new(1);
+    SEQ.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A task wrapper for the [`RTScheduler`].
+pub struct RTTask {
+    inner: TaskInner,
+    /// The deadline parameters taken from the inner task when it is queued,
+    /// so that the rank does not change while the task is in the queue.
+    params: SpinRaw<Option<DeadlineParams>>,
+    prio: AtomicIsize,
+    seq: AtomicU64,
+    /// The absolute deadline of the current period, in nanoseconds.
+    abs_deadline: AtomicU64,
+    /// The remaining budget of the current period, in nanoseconds.
+    budget_left: AtomicI64,
+    /// The last time the budget is charged, in nanoseconds, or
+    /// [`NOT_RUNNING`] if the task is not running.
+    exec_start: AtomicU64,
+    /// The start of the period the task can run again after overrunning its
+    /// budget, in nanoseconds, or 0 if the task is not throttled.
+    throttled_until: AtomicU64,
+    /// The key in the ready queue or the throttled queue, `None` if the task
+    /// is not queued.
+    key: SpinRaw<Option<SortKey>>,
+}
+
+impl RTTask {
+    /// Creates a new [`RTTask`] from the inner task struct.
+    pub const fn new(inner: TaskInner) -> Self {
+        Self {
+            inner,
+            params: SpinRaw::new(None),
+            prio: AtomicIsize::new(0),
+            seq: AtomicU64::new(0),
+            abs_deadline: AtomicU64::new(0),
+            budget_left: AtomicI64::new(0),
+            exec_start: AtomicU64::new(NOT_RUNNING),
+            throttled_until: AtomicU64::new(0),
+            key: SpinRaw::new(None),
+        }
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &TaskInner {
+        &self.inner
+    }
+
+    /// Returns the deadline parameters in effect, see [`Self::params`].
+    #[inline]
+    fn params(&self) -> Option<DeadlineParams> {
+        *self.params.lock()
+    }
+
+    /// Applies the deadline parameters set by
+    /// [`TaskInner::set_deadline_params`] since the task was queued last time.
+    fn update_params(&self) {
+        let params = self.inner.deadline_params();
+        if params.is_none() {
+            self.throttled_until.store(0, Ordering::Release);
+        }
+        *self.params.lock() = params;
+    }
+
+    /// Returns the scheduling class and the value to be sorted in it.
+    fn rank(&self) -> (u8, i64) {
+        match self.params() {
+            Some(_) => (
+                CLASS_DEADLINE,
+                self.abs_deadline.load(Ordering::Acquire) as i64,
+            ),
+            None => (CLASS_FIXED_PRIO, self.prio.load(Ordering::Acquire) as i64),
+        }
+    }
+
+    /// Starts a new period if the task is woken up after the deadline of the
+    /// current one, so that a task sleeping for a long time does not starve
+    /// others with an outdated deadline.
+    fn replenish_if_expired(&self, now: u64) {
+        if let Some(params) = self.params() {
+            if now >= self.abs_deadline.load(Ordering::Acquire) {
+                self.abs_deadline
+                    .store(now + params.deadline.as_nanos() as u64, Ordering::Release);
+                self.budget_left
+                    .store(params.budget.as_nanos() as i64, Ordering::Release);
+                self.throttled_until.store(0, Ordering::Release);
+            }
+        }
+    }
+
+    /// Charges the CPU time consumed since the last charge to the budget.
+    ///
+    /// Returns `true` if the budget is overrun, in which case the task is
+    /// throttled, see [`Self::charge_since`].
+    fn charge(&self, now: u64) -> bool {
+        let start = self.exec_start.swap(now, Ordering::AcqRel);
+        self.charge_since(start, now)
+    }
+
+    /// Charges the CPU time consumed since the last charge, and stops the
+    /// accounting until the task is picked to run again.
+    ///
+    /// It does nothing if the task is not running.
+    fn stop_charge(&self, now: u64) -> bool {
+        let start = self.exec_start.swap(NOT_RUNNING, Ordering::AcqRel);
+        self.charge_since(start, now)
+    }
+
+    /// Called when the task is switched in, starts charging the budget.
+    pub(crate) fn on_switch_in(&self, now: u64) {
+        self.exec_start.store(now, Ordering::Release);
+    }
+
+    /// Called when the task is switched out, no matter whether it is put back
+    /// into the ready queue, blocked or exited.
+    pub(crate) fn on_switch_out(&self, now: u64) {
+        self.stop_charge(now);
+    }
+
+    /// Charges the CPU time consumed from `start` to `now` to the budget.
+    ///
+    /// If the budget is overrun, the overrun is paid by the budgets of the
+    /// following periods. The task is throttled until the first of them with
+    /// some budget left starts, and its deadline is postponed to the end of
+    /// that period.
+    fn charge_since(&self, start: u64, now: u64) -> bool {
+        if start == NOT_RUNNING {
+            return false;
+        }
+        let Some(params) = self.params() else {
+            return false;
+        };
+        let delta = now.saturating_sub(start) as i64;
+        let left = self.budget_left.fetch_sub(delta, Ordering::AcqRel) - delta;
+        if left > 0 {
+            return false;
+        }
+        let overruns = self.inner.inc_budget_overruns();
+        warn!(
+            "{} overran its budget {:?} ({} times)",
+            self.inner.id_name(),
+            params.budget,
+            overruns
+        );
+        let budget = params.budget.as_nanos() as i64;
+        let periods = -left / budget + 1;
+        let postponed = params.period.as_nanos() as u64 * periods as u64;
+        let abs_deadline = self.abs_deadline.fetch_add(postponed, Ordering::AcqRel) + postponed;
+        self.budget_left
+            .store(budget * periods + left, Ordering::Release);
+        self.throttled_until.store(
+            abs_deadline - params.deadline.as_nanos() as u64,
+            Ordering::Release,
+        );
+        true
+    }
+}
+
+impl Deref for RTTask {
+    type Target = TaskInner;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// A real-time scheduler with a deadline (EDF) class and a fixed-priority
+/// class.
+///
+/// The priority of the fixed-priority class ranges from -20 to 19, where a
+/// smaller value means a higher priority. The default priority is 0.
+///
+/// The CPU time is charged to the budget of a deadline task on timer ticks
+/// and every time it is switched out, e.g., by preemption, yielding or
+/// blocking. An overrun is detected at the next of them. The throttled tasks
+/// are moved back to the ready queue on timer ticks and when the next task is
+/// picked.
+pub struct RTScheduler {
+    ready_queue: BTreeMap<SortKey, Arc<RTTask>>,
+    throttled: BTreeMap<SortKey, Arc<RTTask>>,
+}
+
+impl RTScheduler {
+    /// Creates a new empty [`RTScheduler`].
+    pub const fn new() -> Self {
+        Self {
+            ready_queue: BTreeMap::new(),
+            throttled: BTreeMap::new(),
+        }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Real-time (EDF + fixed-priority)"
+    }
+
+    /// Inserts the task into the ready queue, or the throttled queue if it
+    /// is throttled. If `keep_order` is `true`, the task is placed before the
+    /// tasks queued after its last insertion.
+    fn enqueue(&mut self, task: Arc<RTTask>, keep_order: bool) {
+        let seq = if keep_order {
+            task.seq.load(Ordering::Acquire)
+        } else {
+            let seq = next_seq();
+            task.seq.store(seq, Ordering::Release);
+            seq
+        };
+        let until = task.throttled_until.load(Ordering::Acquire);
+        if until != 0 {
+            let key = (CLASS_THROTTLED, until as i64, seq);
+            *task.key.lock() = Some(key);
+            self.throttled.insert(key, task);
+            // Nobody else wakes up the CPU for the task if it goes idle.
+            #[cfg(feature = "tickless")]
+            crate::timers::set_cpu_wakeup(axhal::time::TimeValue::from_nanos(
+                until + axhal::time::epochoffset_nanos(),
+            ));
+            return;
+        }
+        let (class, value) = task.rank();
+        let key = (class, value, seq);
+        *task.key.lock() = Some(key);
+        self.ready_queue.insert(key, task);
+    }
+
+    /// Moves the tasks throttled until no later than `now` back to the ready
+    /// queue, in the order they were queued.
+    fn release_throttled(&mut self, now: u64) {
+        while let Some(entry) = self.throttled.first_entry() {
+            if entry.key().1 as u64 > now {
+                break;
+            }
+            let task = entry.remove();
+            task.throttled_until.store(0, Ordering::Release);
+            self.enqueue(task, true);
+        }
+    }
+}
+
+impl BaseScheduler for RTScheduler {
+    type SchedItem = Arc<RTTask>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        task.update_params();
+        task.replenish_if_expired(axhal::time::monotonic_time_nanos());
+        self.enqueue(task, false);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        let mut key = task.key.lock();
+        let k = (*key)?;
+        let queue = if k.0 == CLASS_THROTTLED {
+            &mut self.throttled
+        } else {
+            &mut self.ready_queue
+        };
+        match queue.get(&k) {
+            Some(t) if Arc::ptr_eq(t, task) => {
+                *key = None;
+                queue.remove(&k)
+            }
+            // Queued in another scheduler.
+            _ => None,
+        }
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        if !self.throttled.is_empty() {
+            self.release_throttled(axhal::time::monotonic_time_nanos());
+        }
+        while let Some((key, task)) = self.ready_queue.pop_first() {
+            *task.key.lock() = None;
+            // The task may have been moved to a lower rank while queued in
+            // this scheduler by another CPU, re-queue it in the right place.
+            if task.rank() > (key.0, key.1) {
+                self.enqueue(task, true);
+                continue;
+            }
+            return Some(task);
+        }
+        None
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        let now = axhal::time::monotonic_time_nanos();
+        // Charge the time if it is put back by preemption or yielding, it
+        // does nothing for a woken up task.
+        prev.stop_charge(now);
+        prev.update_params();
+        prev.replenish_if_expired(now);
+        // A preempted task keeps its place among the tasks with the same rank.
+        self.enqueue(prev, preempt);
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        let now = axhal::time::monotonic_time_nanos();
+        if current.charge(now) {
+            return true;
+        }
+        self.release_throttled(now);
+        // Preempt the current task if a task with higher rank is ready.
+        self.ready_queue
+            .first_key_value()
+            .is_some_and(|(key, _)| (key.0, key.1) < current.rank())
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        if !(MIN_PRIO..=MAX_PRIO).contains(&prio) {
+            return false;
+        }
+        let queued = self.remove_task(task);
+        task.prio.store(prio, Ordering::Release);
+        if let Some(task) = queued {
+            self.enqueue(task, true);
+        }
+        true
+    }
+}
+
+impl Default for RTScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    fn new_task() -> Arc<RTTask> {
+        Arc::new(RTTask::new(TaskInner::new(|| {}, "rt".into(), 0x1000)))
+    }
+
+    fn deadline_params(budget_ms: u64, period_ms: u64) -> Option<DeadlineParams> {
+        Some(DeadlineParams {
+            period: Duration::from_millis(period_ms),
+            budget: Duration::from_millis(budget_ms),
+            deadline: Duration::from_millis(period_ms),
+        })
+    }
+
+    #[test]
+    fn test_fixed_priority_order() {
+        let mut sched = RTScheduler::new();
+        let (low, normal1, normal2, high) = (new_task(), new_task(), new_task(), new_task());
+        assert!(sched.set_priority(&low, 10));
+        assert!(sched.set_priority(&high, -10));
+        assert!(!sched.set_priority(&high, MAX_PRIO + 1));
+        for t in [&low, &normal1, &normal2, &high] {
+            sched.add_task(t.clone());
+        }
+        for t in [&high, &normal1, &normal2, &low] {
+            assert!(Arc::ptr_eq(&sched.pick_next_task().unwrap(), t));
+        }
+        assert!(sched.pick_next_task().is_none());
+    }
+
+    #[test]
+    fn test_deadline_class_first() {
+        let mut sched = RTScheduler::new();
+        let (fixed, late, early) = (new_task(), new_task(), new_task());
+        assert!(sched.set_priority(&fixed, MIN_PRIO));
+        assert!(late.set_deadline_params(deadline_params(1, 10)));
+        assert!(early.set_deadline_params(deadline_params(1, 5)));
+        for t in [&fixed, &late, &early] {
+            sched.add_task(t.clone());
+        }
+        // EDF among the deadline tasks, which all go before fixed-priority ones.
+        for t in [&early, &late, &fixed] {
+            assert!(Arc::ptr_eq(&sched.pick_next_task().unwrap(), t));
+        }
+    }
+
+    #[test]
+    fn test_charge_on_switch_out() {
+        let mut sched = RTScheduler::new();
+        let task = new_task();
+        assert!(task.set_deadline_params(deadline_params(1, 10)));
+        sched.add_task(task.clone());
+        let task = sched.pick_next_task().unwrap();
+        let start = 0;
+        task.on_switch_in(start);
+        let deadline = task.abs_deadline.load(Ordering::Acquire);
+
+        // Blocks before any timer tick, after running for longer than the budget.
+        task.on_switch_out(start + 3 * MS / 2);
+        assert_eq!(task.budget_overruns(), 1);
+        assert_eq!(
+            task.abs_deadline.load(Ordering::Acquire),
+            deadline + 10 * MS
+        );
+        assert_eq!(task.budget_left.load(Ordering::Acquire), MS as i64 / 2);
+        assert_eq!(task.throttled_until.load(Ordering::Acquire), deadline);
+        // Not charged while it is not running.
+        task.on_switch_out(start + 20 * MS);
+        assert_eq!(task.budget_overruns(), 1);
+        assert!(!task.charge_since(NOT_RUNNING, start + 30 * MS));
+    }
+
+    #[test]
+    fn test_overrun_throttled() {
+        let mut sched = RTScheduler::new();
+        let (looping, fixed) = (new_task(), new_task());
+        assert!(looping.set_deadline_params(deadline_params(10, 100)));
+        sched.add_task(looping.clone());
+        sched.add_task(fixed.clone());
+        let task = sched.pick_next_task().unwrap();
+        assert!(Arc::ptr_eq(&task, &looping));
+        let start = axhal::time::monotonic_time_nanos();
+        task.on_switch_in(start);
+        let deadline = task.abs_deadline.load(Ordering::Acquire);
+
+        // The overrun of 25ms takes the budgets of the next 2 periods and a
+        // half of the third one.
+        assert!(task.charge(start + 35 * MS));
+        assert_eq!(
+            task.abs_deadline.load(Ordering::Acquire),
+            deadline + 300 * MS
+        );
+        assert_eq!(task.budget_left.load(Ordering::Acquire), 5 * MS as i64);
+        let until = deadline + 200 * MS;
+        assert_eq!(task.throttled_until.load(Ordering::Acquire), until);
+
+        // The fixed-priority task runs while the deadline task is throttled.
+        sched.put_prev_task(task, true);
+        assert!(Arc::ptr_eq(&sched.pick_next_task().unwrap(), &fixed));
+        assert!(sched.pick_next_task().is_none());
+
+        sched.release_throttled(until);
+        assert_eq!(looping.throttled_until.load(Ordering::Acquire), 0);
+        assert!(Arc::ptr_eq(&sched.pick_next_task().unwrap(), &looping));
+    }
+
+    #[test]
+    fn test_params_take_effect_when_queued() {
+        let mut sched = RTScheduler::new();
+        let (first, second) = (new_task(), new_task());
+        sched.add_task(first.clone());
+        sched.add_task(second.clone());
+
+        // The rank of a queued task is not changed.
+        assert!(second.set_deadline_params(deadline_params(1, 10)));
+        assert_eq!(second.rank().0, CLASS_FIXED_PRIO);
+        let first = sched.pick_next_task().unwrap();
+        let second = sched.pick_next_task().unwrap();
+
+        sched.put_prev_task(first.clone(), false);
+        sched.put_prev_task(second.clone(), false);
+        assert_eq!(second.rank().0, CLASS_DEADLINE);
+        assert!(Arc::ptr_eq(&sched.pick_next_task().unwrap(), &second));
+        assert!(Arc::ptr_eq(&sched.pick_next_task().unwrap(), &first));
+    }
+}
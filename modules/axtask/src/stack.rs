@@ -0,0 +1,238 @@
+//! Kernel stacks of tasks, with stack overflow detection.
+//!
+//! - With the `paging` feature, each stack is mapped into a slot of a
+//!   dedicated virtual region, with an unmapped guard page below it. Touching
+//!   the guard page, or trapping with less than a page of stack left, is
+//!   reported as a stack overflow of the current task. The report runs on a
+//!   per-CPU fault stack provided by [`axhal`], as the overflowed stack cannot
+//!   hold the trap frame.
+//! - Otherwise, a canary word is placed at the bottom of each stack, and it
+//!   is checked when the task is switched out.
+
+#[cfg(any(feature = "paging", test))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "paging"))]
+use core::{alloc::Layout, ptr::NonNull};
+
+use memory_addr::VirtAddr;
+
+#[cfg(not(feature = "paging"))]
+const STACK_CANARY: u64 = 0xdead_beef_cafe_babe;
+
+/// The kernel stack of a task.
+pub(crate) struct TaskStack {
+    #[cfg(not(feature = "paging"))]
+    ptr: NonNull<u8>,
+    #[cfg(not(feature = "paging"))]
+    layout: Layout,
+    #[cfg(feature = "paging")]
+    bottom: VirtAddr,
+    #[cfg(feature = "paging")]
+    size: usize,
+}
+
+#[cfg(not(feature = "paging"))]
+impl TaskStack {
+    pub fn alloc(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, 16).unwrap();
+        let ptr = NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap();
+        unsafe { ptr.cast::<u64>().write(STACK_CANARY) };
+        Self { ptr, layout }
+    }
+
+    pub const fn top(&self) -> VirtAddr {
+        unsafe { core::mem::transmute(self.ptr.as_ptr().add(self.layout.size())) }
+    }
+
+    /// Returns `true` if the canary at the bottom of the stack is intact.
+    pub fn canary_intact(&self) -> bool {
+        unsafe { self.ptr.cast::<u64>().read() == STACK_CANARY }
+    }
+}
+
+#[cfg(not(feature = "paging"))]
+impl Drop for TaskStack {
+    fn drop(&mut self) {
+        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// Released stacks kept for reuse, each with its size.
+#[cfg(any(feature = "paging", test))]
+pub(crate) struct StackCache<T> {
+    stacks: Vec<(usize, T)>,
+    capacity: usize,
+}
+
+#[cfg(any(feature = "paging", test))]
+impl<T> StackCache<T> {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            stacks: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Takes the smallest cached stack with at least `size` bytes.
+    pub fn take(&mut self, size: usize) -> Option<(usize, T)> {
+        let (i, _) = self
+            .stacks
+            .iter()
+            .enumerate()
+            .filter(|(_, (s, _))| *s >= size)
+            .min_by_key(|(_, (s, _))| *s)?;
+        Some(self.stacks.swap_remove(i))
+    }
+
+    /// Caches a released stack.
+    ///
+    /// Returns the largest stack to be freed if the cache is full.
+    pub fn put(&mut self, size: usize, stack: T) -> Option<(usize, T)> {
+        self.stacks.push((size, stack));
+        if self.stacks.len() <= self.capacity {
+            return None;
+        }
+        let (i, _) = self
+            .stacks
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (s, _))| *s)?;
+        Some(self.stacks.swap_remove(i))
+    }
+}
+
+#[cfg(feature = "paging")]
+mod guarded {
+    use core::alloc::Layout;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use axhal::mem::{phys_to_virt, virt_to_phys};
+    use axhal::paging::{MappingFlags, TASK_STACK_REGION, TASK_STACK_SLOT_SIZE};
+    use kspin::SpinNoIrq;
+    use memory_addr::{PAGE_SIZE_4K, VirtAddr, align_up_4k};
+
+    use super::{StackCache, TaskStack};
+
+    /// The maximum number of released stacks kept mapped for reuse.
+    ///
+    /// Only unmapping flushes the TLB of the current CPU, and there is no TLB
+    /// shootdown, so with SMP other CPUs may still cache the old mappings of
+    /// an unmapped stack. All released stacks are kept mapped in that case,
+    /// so that their slots are reused safely.
+    const MAX_CACHED_STACKS: usize = if cfg!(feature = "smp") {
+        usize::MAX
+    } else {
+        16
+    };
+
+    /// The next unused slot in [`TASK_STACK_REGION`].
+    static NEXT_SLOT: AtomicUsize = AtomicUsize::new(TASK_STACK_REGION.start);
+
+    /// Stacks released by exited tasks, still mapped, as `size => bottom`.
+    static FREE_STACKS: SpinNoIrq<StackCache<VirtAddr>> =
+        SpinNoIrq::new(StackCache::new(MAX_CACHED_STACKS));
+
+    /// Slots whose stacks have been unmapped, never with SMP.
+    static FREE_SLOTS: SpinNoIrq<alloc::vec::Vec<usize>> = SpinNoIrq::new(alloc::vec::Vec::new());
+
+    fn alloc_slot() -> usize {
+        if let Some(slot) = FREE_SLOTS.lock().pop() {
+            return slot;
+        }
+        let slot = NEXT_SLOT.fetch_add(TASK_STACK_SLOT_SIZE, Ordering::Relaxed);
+        assert!(
+            TASK_STACK_REGION.end - slot >= TASK_STACK_SLOT_SIZE,
+            "task stack region exhausted"
+        );
+        slot
+    }
+
+    fn stack_layout(size: usize) -> Layout {
+        Layout::from_size_align(size, PAGE_SIZE_4K).unwrap()
+    }
+
+    /// Unmaps the stack and frees its memory.
+    fn free_stack(bottom: VirtAddr, size: usize) {
+        let mut aspace = axmm::kernel_aspace().lock();
+        let (paddr, ..) = aspace
+            .page_table()
+            .query(bottom)
+            .expect("task stack not mapped");
+        aspace
+            .unmap(bottom, size)
+            .expect("failed to unmap task stack");
+        drop(aspace);
+        unsafe { alloc::alloc::dealloc(phys_to_virt(paddr).as_mut_ptr(), stack_layout(size)) };
+        FREE_SLOTS.lock().push(bottom.as_usize() - PAGE_SIZE_4K);
+    }
+
+    impl TaskStack {
+        pub fn alloc(size: usize) -> Self {
+            let size = align_up_4k(size);
+            assert!(
+                size <= TASK_STACK_SLOT_SIZE - PAGE_SIZE_4K,
+                "task stack too large: {:#x}",
+                size
+            );
+            // A larger cached stack is fine, the task just gets more room.
+            if let Some((size, bottom)) = FREE_STACKS.lock().take(size) {
+                return Self { bottom, size };
+            }
+
+            // The guard page is at the bottom of the slot.
+            let bottom = VirtAddr::from(alloc_slot() + PAGE_SIZE_4K);
+            let backing = unsafe { alloc::alloc::alloc(stack_layout(size)) };
+            assert!(!backing.is_null(), "failed to allocate task stack");
+            axmm::kernel_aspace()
+                .lock()
+                .map_linear(
+                    bottom,
+                    virt_to_phys(VirtAddr::from(backing as usize)),
+                    size,
+                    MappingFlags::READ | MappingFlags::WRITE,
+                )
+                .expect("failed to map task stack");
+            Self { bottom, size }
+        }
+
+        pub const fn top(&self) -> VirtAddr {
+            VirtAddr::from_usize(self.bottom.as_usize() + self.size)
+        }
+
+        /// Returns `true` if `vaddr` is in the guard page of the stack.
+        pub fn guard_page_contains(&self, vaddr: VirtAddr) -> bool {
+            (self.bottom.as_usize() - PAGE_SIZE_4K..self.bottom.as_usize())
+                .contains(&vaddr.as_usize())
+        }
+    }
+
+    impl Drop for TaskStack {
+        fn drop(&mut self) {
+            let evicted = FREE_STACKS.lock().put(self.size, self.bottom);
+            if let Some((size, bottom)) = evicted {
+                free_stack(bottom, size);
+            }
+        }
+    }
+
+    /// Reports page faults in the guard pages of task stacks as stack
+    /// overflows, and leaves other page faults to other handlers.
+    ///
+    /// It's called on the per-CPU fault stack when the trap entry finds the
+    /// stack overflowed, or from the double fault handler on x86_64.
+    #[axhal::trap::register_trap_handler(axhal::trap::PAGE_FAULT)]
+    fn handle_stack_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
+        if is_user || !TASK_STACK_REGION.contains(&vaddr.as_usize()) {
+            return false;
+        }
+        match crate::current_may_uninit() {
+            Some(curr) if curr.stack_guard_page_contains(vaddr) => {
+                panic!("stack overflow in task {}", curr.id_name())
+            }
+            _ => panic!(
+                "invalid {:?} access to task stacks @ {:#x}",
+                access_flags, vaddr
+            ),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Per-task CPU time accounting and scheduling statistics.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use crate::task::TaskState;
+
+/// A snapshot of the scheduling statistics of a task, see
+/// [`TaskInner::stats`](crate::TaskInner::stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaskStats {
+    /// The CPU time consumed by the task.
+    pub runtime: Duration,
+    /// The time spent in run queues waiting to run.
+    pub ready_time: Duration,
+    /// The time spent blocked in wait queues or sleeping.
+    pub blocked_time: Duration,
+    /// The number of context switches caused by blocking or exiting.
+    pub voluntary_switches: u64,
+    /// The number of context switches caused by preemption or yielding.
+    pub involuntary_switches: u64,
+    /// The ID of the CPU the task ran on most recently.
+    pub last_cpu: usize,
+}
+
+/// Scheduling statistics of a task, updated by the run queue when the task
+/// is switched in, switched out or woken up.
+///
+/// All times are in nanoseconds of the monotonic clock.
+pub(crate) struct SchedStats {
+    runtime: AtomicU64,
+    ready_time: AtomicU64,
+    blocked_time: AtomicU64,
+    voluntary_switches: AtomicU64,
+    involuntary_switches: AtomicU64,
+    last_cpu: AtomicUsize,
+    /// The time when the task entered its current state.
+    since: AtomicU64,
+}
+
+impl SchedStats {
+    pub fn new() -> Self {
+        Self {
+            runtime: AtomicU64::new(0),
+            ready_time: AtomicU64::new(0),
+            blocked_time: AtomicU64::new(0),
+            voluntary_switches: AtomicU64::new(0),
+            involuntary_switches: AtomicU64::new(0),
+            last_cpu: AtomicUsize::new(axhal::cpu::this_cpu_id()),
+            since: AtomicU64::new(axhal::time::monotonic_time_nanos()),
+        }
+    }
+
+    /// Returns the time elapsed since the task entered its current state,
+    /// and restarts the measurement from `now`.
+    #[inline]
+    fn restart(&self, now: u64) -> u64 {
+        now.saturating_sub(self.since.swap(now, Ordering::AcqRel))
+    }
+
+    /// Called when the task is switched in on the CPU `cpu_id`.
+    pub fn on_switch_in(&self, now: u64, cpu_id: usize) {
+        let delta = self.restart(now);
+        self.ready_time.fetch_add(delta, Ordering::Relaxed);
+        self.last_cpu.store(cpu_id, Ordering::Relaxed);
+    }
+
+    /// Called when the task is switched out, `voluntary` is `true` if it is
+    /// blocked or exited.
+    pub fn on_switch_out(&self, now: u64, voluntary: bool) {
+        let delta = self.restart(now);
+        self.runtime.fetch_add(delta, Ordering::Relaxed);
+        if voluntary {
+            self.voluntary_switches.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.involuntary_switches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Called when the blocked task is woken up and put into a run queue.
+    pub fn on_wakeup(&self, now: u64) {
+        let delta = self.restart(now);
+        self.blocked_time.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    /// Called when the suspended task is resumed, the time spent suspended is
+    /// not accounted.
+    pub fn on_resume(&self, now: u64) {
+        self.restart(now);
+    }
+
+    /// Returns the ID of the CPU the task ran on most recently.
+    pub fn last_cpu(&self) -> usize {
+        self.last_cpu.load(Ordering::Relaxed)
+    }
+
+    /// Takes a snapshot of the statistics, including the time spent in the
+    /// current `state` so far.
+    pub fn snapshot(&self, state: TaskState) -> TaskStats {
+        let now = axhal::time::monotonic_time_nanos();
+        let elapsed = now.saturating_sub(self.since.load(Ordering::Acquire));
+        let mut runtime = self.runtime.load(Ordering::Relaxed);
+        let mut ready_time = self.ready_time.load(Ordering::Relaxed);
+        let mut blocked_time = self.blocked_time.load(Ordering::Relaxed);
+        match state {
+            TaskState::Running => runtime += elapsed,
+            TaskState::Ready => ready_time += elapsed,
+            TaskState::Blocked => blocked_time += elapsed,
+            TaskState::Exited | TaskState::Suspended => {}
+        }
+        TaskStats {
+            runtime: Duration::from_nanos(runtime),
+            ready_time: Duration::from_nanos(ready_time),
+            blocked_time: Duration::from_nanos(blocked_time),
+            voluntary_switches: self.voluntary_switches.load(Ordering::Relaxed),
+            involuntary_switches: self.involuntary_switches.load(Ordering::Relaxed),
+            last_cpu: self.last_cpu.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -1,10 +1,14 @@
-use alloc::{boxed::Box, string::String, sync::Arc};
+use alloc::{
+    boxed::Box,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU64, Ordering};
-use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
-
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicIsize, AtomicU8, AtomicU64, AtomicUsize, Ordering, fence,
+};
+use core::{cell::UnsafeCell, fmt};
 
 use kspin::SpinNoIrq;
 use memory_addr::{VirtAddr, align_up_4k};
@@ -13,7 +17,15 @@ use axhal::arch::TaskContext;
 #[cfg(feature = "tls")]
 use axhal::tls::TlsArea;
 
+#[cfg(feature = "sched_rt")]
+use crate::DeadlineParams;
+#[cfg(feature = "lockdep")]
+use crate::lockdep::HeldLock;
+use crate::registry::TaskInfo;
+use crate::stack::TaskStack;
+use crate::stats::{SchedStats, TaskStats};
 use crate::task_ext::AxTaskExt;
+use crate::task_local::LocalValues;
 use crate::{AxCpuMask, AxTask, AxTaskRef, WaitQueue};
 
 /// A unique identifier for a thread.
@@ -23,7 +35,7 @@ pub struct TaskId(u64);
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum TaskState {
+pub enum TaskState {
     /// Task is running on some CPU.
     Running = 1,
     /// Task is ready to run on some scheduler's ready queue.
@@ -33,8 +45,16 @@ pub(crate) enum TaskState {
     Blocked = 3,
     /// Task is exited and waiting for being dropped.
     Exited = 4,
+    /// Task is suspended by [`crate::suspend`], and it's not in any run queue
+    /// until [`crate::resume`] is called.
+    Suspended = 5,
 }
 
+/// The error returned by cancellable waits if the current task has been
+/// cancelled, see [`TaskInner::cancel`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Cancelled;
+
 /// The inner task structure.
 pub struct TaskInner {
     id: TaskId,
@@ -45,15 +65,61 @@ pub struct TaskInner {
     entry: Option<*mut dyn FnOnce()>,
     state: AtomicU8,
 
+    /// The weak reference to the task itself, set in `into_arc()`.
+    this: Weak<AxTask>,
+
     /// CPU affinity mask.
     cpumask: SpinNoIrq<AxCpuMask>,
 
+    /// The priority set by the task itself, see [`crate::set_priority`].
+    base_prio: AtomicIsize,
+    /// The effective priority that is applied to the scheduler.
+    prio: AtomicIsize,
+    /// Priorities lent by other tasks, keyed by the resource they wait for.
+    inherited_prios: SpinNoIrq<Vec<(usize, isize)>>,
+
+    /// Parameters of the deadline scheduling class, `None` if the task is in
+    /// the fixed-priority class.
+    #[cfg(feature = "sched_rt")]
+    deadline_params: SpinNoIrq<Option<DeadlineParams>>,
+    /// The number of times the task has overrun its budget.
+    #[cfg(feature = "sched_rt")]
+    budget_overruns: AtomicU64,
+
     /// Mark whether the task is in the wait queue.
     in_wait_queue: AtomicBool,
+    /// The address of the futex the task is waiting on, see [`crate::futex`].
+    futex_key: AtomicUsize,
+
+    /// Mark whether the cancellation of the task has been requested.
+    cancelled: AtomicBool,
+    /// Mark whether the task is blocked in a cancellable wait, so that it
+    /// can be woken up by `cancel()`.
+    in_cancellable_wait: AtomicBool,
+    /// Mark whether the task is an exclusive waiter in the wait queue, see
+    /// [`WaitQueue::notify_exclusive`].
+    wait_exclusive: AtomicBool,
+    /// Mark whether the task is requested to be suspended, it's cleared by
+    /// [`crate::resume`].
+    suspend_requested: AtomicBool,
+
+    /// CPU time and scheduling statistics, shared with the registry to be
+    /// accounted after the task is dropped.
+    stats: Arc<SchedStats>,
+
+    /// Task-local values, `None` after they are destroyed on exit.
+    task_locals: SpinNoIrq<Option<LocalValues>>,
+
+    /// Locks held by the task, tracked by the lock dependency checker.
+    #[cfg(feature = "lockdep")]
+    held_locks: SpinNoIrq<Vec<HeldLock>>,
 
     /// Used to indicate whether the task is running on a CPU.
     #[cfg(feature = "smp")]
     on_cpu: AtomicBool,
+    /// The CPU whose run queue the task is put into most recently.
+    #[cfg(feature = "smp")]
+    cpu_id: AtomicUsize,
 
     /// A ticket ID used to identify the timer event.
     /// Set by `set_timer_ticket()` when creating a timer event in `set_alarm_wakeup()`,
@@ -68,6 +134,9 @@ pub struct TaskInner {
 
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
+    /// Notified besides `wait_for_exit` when the task exits, used by
+    /// [`JoinSet`](crate::JoinSet) to wait for any of its tasks.
+    exit_notifier: Option<Arc<WaitQueue>>,
 
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
@@ -87,6 +156,12 @@ impl TaskId {
     pub const fn as_u64(&self) -> u64 {
         self.0
     }
+
+    /// Creates a task ID from a `u64`, e.g., to look up a task by
+    /// [`crate::lookup`].
+    pub const fn from_u64(id: u64) -> Self {
+        Self(id)
+    }
 }
 
 impl From<u8> for TaskState {
@@ -97,6 +172,7 @@ impl From<u8> for TaskState {
             2 => Self::Ready,
             3 => Self::Blocked,
             4 => Self::Exited,
+            5 => Self::Suspended,
             _ => unreachable!(),
         }
     }
@@ -153,6 +229,29 @@ impl TaskInner {
         Some(self.exit_code.load(Ordering::Acquire))
     }
 
+    /// Returns the exit code of the task, or [`None`] if it has not exited.
+    pub fn exit_code(&self) -> Option<i32> {
+        if self.state() == TaskState::Exited {
+            Some(self.exit_code.load(Ordering::Acquire))
+        } else {
+            None
+        }
+    }
+
+    /// Takes a snapshot of the task's information.
+    pub fn info(&self) -> TaskInfo {
+        TaskInfo {
+            id: self.id,
+            name: self.name.clone(),
+            state: self.state(),
+            is_idle: self.is_idle,
+            priority: self.priority(),
+            cpumask: self.cpumask(),
+            exit_code: self.exit_code(),
+            stats: self.stats(),
+        }
+    }
+
     /// Returns the pointer to the user-defined task extended data.
     ///
     /// # Safety
@@ -193,6 +292,12 @@ impl TaskInner {
         }
     }
 
+    /// Gets the state of the task.
+    #[inline]
+    pub fn state(&self) -> TaskState {
+        self.state.load(Ordering::Acquire).into()
+    }
+
     /// Gets the cpu affinity mask of the task.
     ///
     /// Returns the cpu affinity mask of the task in type [`AxCpuMask`].
@@ -209,6 +314,102 @@ impl TaskInner {
     pub fn set_cpumask(&self, cpumask: AxCpuMask) {
         *self.cpumask.lock() = cpumask
     }
+
+    /// Requests the cancellation of the task.
+    ///
+    /// If the task is blocked in a cancellable wait (e.g.,
+    /// [`WaitQueue::wait_until_cancellable`] or [`crate::sleep_cancellable`]),
+    /// it is woken up and the wait returns [`Cancelled`]. Otherwise, the
+    /// request stays pending until the task reaches a cancellation point (a
+    /// cancellable wait or [`crate::test_cancel`]).
+    ///
+    /// Plain waits such as [`WaitQueue::wait`] and [`crate::sleep`] are not
+    /// cancellation points, as they are also used where the wait must not be
+    /// aborted, e.g., acquiring a lock. A task blocked in them is not woken up
+    /// by the cancellation.
+    pub fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        // Pairs with the fence in `abort_block_if_cancelled()`, so either the
+        // waiter sees the request, or we see it blocked.
+        fence(Ordering::SeqCst);
+        if self.state() == TaskState::Blocked && self.in_cancellable_wait.load(Ordering::Acquire) {
+            if let Some(task) = self.this.upgrade() {
+                use kernel_guard::NoPreemptIrqSave;
+                crate::select_run_queue::<NoPreemptIrqSave>(&task).unblock_task(task, false);
+            }
+        }
+    }
+
+    /// Returns `true` if the cancellation of the task has been requested.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Returns whether the task is the idle task of a CPU.
+    #[inline]
+    pub const fn is_idle(&self) -> bool {
+        self.is_idle
+    }
+
+    /// Gets the CPU time and scheduling statistics of the task.
+    ///
+    /// The time spent in the current state (e.g., running) so far is
+    /// included.
+    pub fn stats(&self) -> TaskStats {
+        self.stats.snapshot(self.state())
+    }
+
+    /// Gets the effective priority of the task.
+    ///
+    /// It is the highest one (the smallest value) of the task's own priority
+    /// and all priorities inherited from other tasks.
+    #[inline]
+    pub fn priority(&self) -> isize {
+        self.prio.load(Ordering::Acquire)
+    }
+
+    /// Gets the priority set by the task itself, regardless of the inherited
+    /// priorities.
+    #[inline]
+    pub fn base_priority(&self) -> isize {
+        self.base_prio.load(Ordering::Acquire)
+    }
+
+    /// Gets the parameters of the deadline scheduling class, or [`None`] if
+    /// the task is in the fixed-priority class.
+    #[cfg(feature = "sched_rt")]
+    #[inline]
+    pub fn deadline_params(&self) -> Option<DeadlineParams> {
+        *self.deadline_params.lock()
+    }
+
+    /// Sets the period, budget and deadline of the task to move it into the
+    /// deadline scheduling class, or moves it back to the fixed-priority
+    /// class if `params` is [`None`].
+    ///
+    /// It takes effect the next time the task is put into a run queue.
+    ///
+    /// Returns `false` if the parameters are invalid, see
+    /// [`DeadlineParams::is_valid`].
+    #[cfg(feature = "sched_rt")]
+    pub fn set_deadline_params(&self, params: Option<DeadlineParams>) -> bool {
+        if params.is_some_and(|p| !p.is_valid()) {
+            return false;
+        }
+        *self.deadline_params.lock() = params;
+        true
+    }
+
+    /// Gets the number of times the task has run out of its budget in the
+    /// deadline scheduling class.
+    #[cfg(feature = "sched_rt")]
+    #[inline]
+    pub fn budget_overruns(&self) -> u64 {
+        self.budget_overruns.load(Ordering::Acquire)
+    }
 }
 
 // private methods
@@ -221,19 +422,39 @@ impl TaskInner {
             is_init: false,
             entry: None,
             state: AtomicU8::new(TaskState::Ready as u8),
+            this: Weak::new(),
             // By default, the task is allowed to run on all CPUs.
             cpumask: SpinNoIrq::new(AxCpuMask::full()),
+            base_prio: AtomicIsize::new(0),
+            prio: AtomicIsize::new(0),
+            inherited_prios: SpinNoIrq::new(Vec::new()),
+            #[cfg(feature = "sched_rt")]
+            deadline_params: SpinNoIrq::new(None),
+            #[cfg(feature = "sched_rt")]
+            budget_overruns: AtomicU64::new(0),
             in_wait_queue: AtomicBool::new(false),
+            futex_key: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+            in_cancellable_wait: AtomicBool::new(false),
+            wait_exclusive: AtomicBool::new(false),
+            suspend_requested: AtomicBool::new(false),
+            stats: Arc::new(SchedStats::new()),
+            task_locals: SpinNoIrq::new(Some(LocalValues::new())),
+            #[cfg(feature = "lockdep")]
+            held_locks: SpinNoIrq::new(Vec::new()),
             #[cfg(feature = "irq")]
             timer_ticket_id: AtomicU64::new(0),
             #[cfg(feature = "smp")]
             on_cpu: AtomicBool::new(false),
+            #[cfg(feature = "smp")]
+            cpu_id: AtomicUsize::new(axhal::cpu::this_cpu_id()),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
             preempt_disable_count: AtomicUsize::new(0),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            exit_notifier: None,
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
@@ -261,13 +482,13 @@ impl TaskInner {
         t
     }
 
-    pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
-    }
-
-    #[inline]
-    pub(crate) fn state(&self) -> TaskState {
-        self.state.load(Ordering::Acquire).into()
+    pub(crate) fn into_arc(mut self) -> AxTaskRef {
+        let task = Arc::new_cyclic(|this| {
+            self.this = this.clone();
+            AxTask::new(self)
+        });
+        crate::registry::register(&task);
+        task
     }
 
     #[inline]
@@ -306,8 +527,60 @@ impl TaskInner {
     }
 
     #[inline]
-    pub(crate) const fn is_idle(&self) -> bool {
-        self.is_idle
+    pub(crate) fn set_base_priority(&self, prio: isize) {
+        self.base_prio.store(prio, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn set_effective_priority(&self, prio: isize) {
+        self.prio.store(prio, Ordering::Release);
+    }
+
+    /// Computes the effective priority from the base priority and the
+    /// inherited priorities. A smaller value means a higher priority.
+    pub(crate) fn effective_priority(&self) -> isize {
+        self.inherited_prios
+            .lock()
+            .iter()
+            .fold(self.base_priority(), |prio, &(_, p)| prio.min(p))
+    }
+
+    /// Records the priority lent through `key`, replacing the previous one
+    /// with the same `key`.
+    pub(crate) fn add_inherited_priority(&self, key: usize, prio: isize) {
+        let mut prios = self.inherited_prios.lock();
+        match prios.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = prio,
+            None => prios.push((key, prio)),
+        }
+    }
+
+    /// Removes the priority lent through `key`.
+    pub(crate) fn remove_inherited_priority(&self, key: usize) {
+        self.inherited_prios.lock().retain(|(k, _)| *k != key);
+    }
+
+    /// Increases the budget overrun counter, returns the new value.
+    #[cfg(feature = "sched_rt")]
+    #[inline]
+    pub(crate) fn inc_budget_overruns(&self) -> u64 {
+        self.budget_overruns.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    #[inline]
+    pub(crate) fn sched_stats(&self) -> &Arc<SchedStats> {
+        &self.stats
+    }
+
+    #[inline]
+    pub(crate) fn task_locals(&self) -> &SpinNoIrq<Option<LocalValues>> {
+        &self.task_locals
+    }
+
+    #[cfg(feature = "lockdep")]
+    #[inline]
+    pub(crate) fn held_locks(&self) -> &SpinNoIrq<Vec<HeldLock>> {
+        &self.held_locks
     }
 
     #[inline]
@@ -320,6 +593,74 @@ impl TaskInner {
         self.in_wait_queue.store(in_wait_queue, Ordering::Release);
     }
 
+    #[inline]
+    pub(crate) fn futex_key(&self) -> usize {
+        self.futex_key.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_futex_key(&self, key: usize) {
+        self.futex_key.store(key, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn set_in_cancellable_wait(&self, in_cancellable_wait: bool) {
+        self.in_cancellable_wait
+            .store(in_cancellable_wait, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn is_wait_exclusive(&self) -> bool {
+        self.wait_exclusive.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_wait_exclusive(&self, exclusive: bool) {
+        self.wait_exclusive.store(exclusive, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn is_suspend_requested(&self) -> bool {
+        self.suspend_requested.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_suspend_requested(&self, requested: bool) {
+        self.suspend_requested.store(requested, Ordering::Release);
+        // Pairs with the fence in `suspend_if_requested()`.
+        fence(Ordering::SeqCst);
+    }
+
+    /// Moves the task from `current_state` to `Suspended` if it has been
+    /// requested to be suspended, instead of putting it into a run queue.
+    ///
+    /// Returns `true` if the task is suspended.
+    pub(crate) fn suspend_if_requested(&self, current_state: TaskState) -> bool {
+        if !self.is_suspend_requested()
+            || !self.transition_state(current_state, TaskState::Suspended)
+        {
+            return false;
+        }
+        // The request may have been withdrawn by `resume()` before the state
+        // is set, in which case `resume()` has found nothing to resume, so
+        // take the task back here.
+        fence(Ordering::SeqCst);
+        !(!self.is_suspend_requested()
+            && self.transition_state(TaskState::Suspended, current_state))
+    }
+
+    /// Restores the state of the current task from `Blocked` to `Running` if
+    /// it has been cancelled, which must be called after the task is marked
+    /// as `Blocked` in a cancellable wait.
+    ///
+    /// Returns `true` if the blocking is aborted. Returns `false` if it is not
+    /// cancelled, or it has already been woken up by others.
+    pub(crate) fn abort_block_if_cancelled(&self) -> bool {
+        // Pairs with the fence in `cancel()`.
+        fence(Ordering::SeqCst);
+        self.is_cancelled() && self.transition_state(TaskState::Blocked, TaskState::Running)
+    }
+
     /// Returns task's current timer ticket ID.
     #[inline]
     #[cfg(feature = "irq")]
@@ -391,6 +732,14 @@ impl TaskInner {
     pub(crate) fn notify_exit(&self, exit_code: i32) {
         self.exit_code.store(exit_code, Ordering::Release);
         self.wait_for_exit.notify_all(false);
+        if let Some(wq) = &self.exit_notifier {
+            wq.notify_all(false);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn set_exit_notifier(&mut self, wq: Arc<WaitQueue>) {
+        self.exit_notifier = Some(wq);
     }
 
     #[inline]
@@ -398,6 +747,23 @@ impl TaskInner {
         self.ctx.get()
     }
 
+    /// Panics if the canary at the bottom of the kernel stack has been
+    /// overwritten, i.e., the stack has overflowed.
+    #[cfg(not(feature = "paging"))]
+    pub(crate) fn check_stack_overflow(&self) {
+        if self.kstack.as_ref().is_some_and(|s| !s.canary_intact()) {
+            panic!("stack overflow in task {}", self.id_name());
+        }
+    }
+
+    /// Returns `true` if `vaddr` is in the guard page of the kernel stack.
+    #[cfg(feature = "paging")]
+    pub(crate) fn stack_guard_page_contains(&self, vaddr: VirtAddr) -> bool {
+        self.kstack
+            .as_ref()
+            .is_some_and(|s| s.guard_page_contains(vaddr))
+    }
+
     /// Returns whether the task is running on a CPU.
     ///
     /// It is used to protect the task from being moved to a different run queue
@@ -416,6 +782,23 @@ impl TaskInner {
     pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
         self.on_cpu.store(on_cpu, Ordering::Release)
     }
+
+    /// Returns the ID of the CPU whose run queue the task is put into most
+    /// recently.
+    ///
+    /// It is updated with the lock of the target run queue's scheduler held.
+    #[cfg(feature = "smp")]
+    #[inline]
+    pub(crate) fn cpu_id(&self) -> usize {
+        self.cpu_id.load(Ordering::Acquire)
+    }
+
+    /// Sets the ID of the CPU whose run queue the task is put into.
+    #[cfg(feature = "smp")]
+    #[inline]
+    pub(crate) fn set_cpu_id(&self, cpu_id: usize) {
+        self.cpu_id.store(cpu_id, Ordering::Release)
+    }
 }
 
 impl fmt::Debug for TaskInner {
@@ -431,31 +814,7 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
-    }
-}
-
-struct TaskStack {
-    ptr: NonNull<u8>,
-    layout: Layout,
-}
-
-impl TaskStack {
-    pub fn alloc(size: usize) -> Self {
-        let layout = Layout::from_size_align(size, 16).unwrap();
-        Self {
-            ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap(),
-            layout,
-        }
-    }
-
-    pub const fn top(&self) -> VirtAddr {
-        unsafe { core::mem::transmute(self.ptr.as_ptr().add(self.layout.size())) }
-    }
-}
-
-impl Drop for TaskStack {
-    fn drop(&mut self) {
-        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+        crate::registry::unregister(self.id);
     }
 }
 
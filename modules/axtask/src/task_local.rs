@@ -0,0 +1,152 @@
+//! Task-local storage.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use core::any::Any;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The task-local values of a task, indexed by the keys.
+pub(crate) struct LocalValues(BTreeMap<usize, Box<dyn Any>>);
+
+// SAFETY: the values are only accessed by the task that owns them, including
+// the destruction on exit.
+unsafe impl Send for LocalValues {}
+
+impl LocalValues {
+    pub(crate) const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+}
+
+/// The next key to allocate, 0 means not allocated.
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(1);
+
+/// A key for task-local storage, each task has its own copy of the value.
+///
+/// The value is lazily initialized by the `init` function the first time
+/// the task accesses it, and dropped when the task exits. It does not depend
+/// on the `tls` feature, and the key can be created at any time, unlike the
+/// task extended data ([`TaskExtRef`](crate::TaskExtRef)).
+///
+/// # Examples
+///
+/// ```
+/// use core::cell::Cell;
+/// use axtask::TaskLocal;
+///
+/// static COUNTER: TaskLocal<Cell<u32>> = TaskLocal::new(|| Cell::new(0));
+///
+/// axtask::init_scheduler();
+/// COUNTER.with(|c| c.set(c.get() + 1));
+/// axtask::spawn(|| COUNTER.with(|c| assert_eq!(c.get(), 0))).join();
+/// COUNTER.with(|c| assert_eq!(c.get(), 1));
+/// ```
+pub struct TaskLocal<T: 'static> {
+    key: AtomicUsize,
+    init: fn() -> T,
+}
+
+/// An error returned by [`TaskLocal::try_with`], if there is no current task
+/// or the values of the task have been destroyed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessError;
+
+impl<T: 'static> TaskLocal<T> {
+    /// Creates a new key, the values are initialized by `init`.
+    pub const fn new(init: fn() -> T) -> Self {
+        Self {
+            key: AtomicUsize::new(0),
+            init,
+        }
+    }
+
+    fn key(&self) -> usize {
+        let key = self.key.load(Ordering::Acquire);
+        if key != 0 {
+            return key;
+        }
+        let new_key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+        match self
+            .key
+            .compare_exchange(0, new_key, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => new_key,
+            Err(key) => key, // allocated by others, waste `new_key`
+        }
+    }
+
+    /// Acquires a reference to the value of the current task, and passes it
+    /// to `f`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no current task, or it's called by a destructor
+    /// of the task-local values when the task exits.
+    pub fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.try_with(f)
+            .expect("cannot access a task-local value outside of a task or during destruction")
+    }
+
+    /// Acquires a reference to the value of the current task, and passes it
+    /// to `f`, or returns [`AccessError`] if the value is not accessible.
+    pub fn try_with<F, R>(&self, f: F) -> Result<R, AccessError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let curr = crate::current_may_uninit().ok_or(AccessError)?;
+        let key = self.key();
+        let found = match curr.task_locals().lock().as_ref() {
+            Some(values) => values.0.get(&key).map(|v| value_ptr::<T>(&**v)),
+            None => return Err(AccessError),
+        };
+        let ptr = match found {
+            Some(ptr) => ptr,
+            None => {
+                // Not holding the lock, as `init` may access other keys.
+                let value: Box<dyn Any> = Box::new((self.init)());
+                // Declared after `value` to be dropped first, so that `value`
+                // is never dropped with the lock held.
+                let mut locals = curr.task_locals().lock();
+                let values = locals.as_mut().ok_or(AccessError)?;
+                match values.0.get(&key) {
+                    // Initialized by a recursive call in `init`, keep it.
+                    Some(v) => value_ptr::<T>(&**v),
+                    None => value_ptr::<T>(&**values.0.entry(key).or_insert(value)),
+                }
+            }
+        };
+        // SAFETY: the value is boxed and only dropped when the current task
+        // exits, so it outlives `f`.
+        Ok(f(unsafe { &*ptr }))
+    }
+}
+
+fn value_ptr<T: 'static>(value: &dyn Any) -> *const T {
+    value.downcast_ref::<T>().unwrap()
+}
+
+/// Drops the task-local values of the current task, it's called before the
+/// task exits.
+pub(crate) fn destroy_current() {
+    if let Some(curr) = crate::current_may_uninit() {
+        let values = curr.task_locals().lock().take();
+        // The values are dropped without holding the lock.
+        drop(values);
+    }
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "task-local value is not accessible".fmt(f)
+    }
+}
+
+impl<T: 'static> fmt::Debug for TaskLocal<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskLocal").finish_non_exhaustive()
+    }
+}
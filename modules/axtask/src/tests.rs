@@ -1,7 +1,10 @@
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use core::task::Poll;
 use std::sync::{Mutex, Once};
 
-use crate::{WaitQueue, api as axtask, current};
+use crate::future::{self, Executor, WakerQueue};
+use crate::{Cancelled, TaskId, TaskLocal, TaskState, WaitQueue, api as axtask, current, futex};
 
 static INIT: Once = Once::new();
 static SERIAL: Mutex<()> = Mutex::new(());
@@ -104,6 +107,57 @@ fn test_wait_queue() {
     assert!(!current().in_wait_queue());
 }
 
+#[test]
+fn test_wait_queue_order() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new_prio_ordered();
+    static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+    // A smaller value means a higher priority.
+    const PRIOS: [isize; 4] = [3, 1, 2, 1];
+    for (i, &prio) in PRIOS.iter().enumerate() {
+        let task = axtask::spawn(move || {
+            WQ.wait();
+            ORDER.lock().unwrap().push(i);
+        });
+        axtask::inherit_priority(&task, 0, prio);
+    }
+    axtask::yield_now(); // let all the tasks wait
+
+    while WQ.notify_one(true) {
+        axtask::yield_now();
+    }
+    assert_eq!(*ORDER.lock().unwrap(), [1, 3, 2, 0]);
+
+    static WQ_EXCL: WaitQueue = WaitQueue::new();
+    static READY: AtomicBool = AtomicBool::new(false);
+    static WOKEN: AtomicUsize = AtomicUsize::new(0);
+
+    for i in 0..5 {
+        axtask::spawn(move || {
+            if i % 2 == 0 {
+                WQ_EXCL.wait_until_exclusive(|| READY.load(Ordering::Acquire));
+            } else {
+                WQ_EXCL.wait_until(|| READY.load(Ordering::Acquire));
+            }
+            WOKEN.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    axtask::yield_now();
+
+    READY.store(true, Ordering::Release);
+    WQ_EXCL.notify_exclusive(true);
+    axtask::yield_now();
+    // 2 non-exclusive waiters and 1 of the 3 exclusive waiters.
+    assert_eq!(WOKEN.load(Ordering::Relaxed), 3);
+
+    WQ_EXCL.notify_all(true);
+    axtask::yield_now();
+    assert_eq!(WOKEN.load(Ordering::Relaxed), 5);
+}
+
 #[test]
 fn test_task_join() {
     let _lock = SERIAL.lock();
@@ -128,3 +182,484 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+#[test]
+fn test_task_local() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    struct Value(usize);
+    impl Drop for Value {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(self.0, Ordering::Relaxed);
+        }
+    }
+    static VALUE: TaskLocal<Value> = TaskLocal::new(|| Value(1));
+
+    const NUM_TASKS: usize = 5;
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|i| {
+            axtask::spawn(move || {
+                VALUE.with(|v| assert_eq!(v.0, 1));
+                axtask::yield_now();
+                VALUE.with(|v| assert_eq!(v.0, 1));
+                if i % 2 == 0 {
+                    axtask::exit(0);
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(DROPPED.load(Ordering::Relaxed), NUM_TASKS);
+
+    // Tasks that never access the value do not initialize it.
+    axtask::spawn(|| {}).join();
+    assert_eq!(DROPPED.load(Ordering::Relaxed), NUM_TASKS);
+}
+
+#[test]
+fn test_task_cancel() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+
+    let task = axtask::spawn(|| {
+        // Never notified, can only be woken up by the cancellation.
+        assert_eq!(WQ.wait_until_cancellable(|| false), Err(Cancelled));
+        assert!(!current().in_wait_queue());
+        axtask::test_cancel();
+        unreachable!("task_cancel: task is not exited at the cancellation point");
+    });
+
+    axtask::yield_now(); // let the task block on `WQ`
+    assert!(!task.is_cancelled());
+    task.cancel();
+    assert_eq!(task.join(), Some(axtask::CANCELLED_EXIT_CODE));
+}
+
+#[test]
+fn test_task_cancel_notified() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+    static READY: AtomicBool = AtomicBool::new(false);
+
+    let cancelled = axtask::spawn(|| {
+        assert_eq!(WQ.wait_until_cancellable(|| false), Err(Cancelled));
+        assert!(!current().in_wait_queue());
+    });
+    let notified = axtask::spawn(|| {
+        WQ.wait_until(|| READY.load(Ordering::Acquire));
+        assert!(!current().in_wait_queue());
+    });
+
+    axtask::yield_now(); // let both tasks block on `WQ`
+    // The cancelled task is still the first one in the queue, so it takes
+    // the notification, and must pass it on to the other.
+    cancelled.cancel();
+    READY.store(true, Ordering::Release);
+    WQ.notify_one(true);
+    assert_eq!(cancelled.join(), Some(0));
+    assert_eq!(notified.join(), Some(0));
+}
+
+#[test]
+fn test_task_suspend() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    let task = axtask::spawn(|| {
+        while !STOP.load(Ordering::Acquire) {
+            COUNTER.fetch_add(1, Ordering::Relaxed);
+            axtask::yield_now();
+        }
+    });
+    axtask::yield_now();
+    assert!(COUNTER.load(Ordering::Relaxed) > 0);
+
+    assert!(axtask::suspend(&task));
+    axtask::yield_now(); // the task is suspended when it's picked
+    assert_eq!(task.state(), TaskState::Suspended);
+    let count = COUNTER.load(Ordering::Relaxed);
+    for _ in 0..10 {
+        axtask::yield_now();
+    }
+    assert_eq!(COUNTER.load(Ordering::Relaxed), count);
+
+    assert!(axtask::resume(&task));
+    assert!(!axtask::resume(&task));
+    axtask::yield_now();
+    assert!(COUNTER.load(Ordering::Relaxed) > count);
+
+    STOP.store(true, Ordering::Release);
+    assert_eq!(task.join(), Some(0));
+    assert!(!axtask::suspend(&task));
+}
+
+#[test]
+fn test_task_stats() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_YIELDS: u64 = 5;
+    static WQ: WaitQueue = WaitQueue::new();
+    static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+    let task = axtask::spawn(|| {
+        for _ in 0..NUM_YIELDS {
+            axtask::yield_now();
+        }
+        STARTED.store(1, Ordering::Release);
+        WQ.wait(); // a voluntary switch
+    });
+    assert!(axtask::tasks().any(|t| t.id() == task.id()));
+
+    while STARTED.load(Ordering::Acquire) == 0 {
+        axtask::yield_now();
+    }
+    WQ.notify_one(true);
+    assert_eq!(task.join(), Some(0));
+
+    let stats = task.stats();
+    assert!(stats.involuntary_switches >= NUM_YIELDS);
+    assert!(stats.voluntary_switches >= 2); // blocked once and exited
+    assert_eq!(stats.last_cpu, 0);
+}
+
+#[test]
+fn test_task_registry() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let task = axtask::spawn_raw(|| axtask::exit(7), "registry".into(), 0x1000);
+    let info = axtask::lookup(task.id()).unwrap().info();
+    assert_eq!(info.name, "registry");
+    assert_eq!(info.state, TaskState::Ready);
+    assert_eq!(info.exit_code, None);
+
+    assert_eq!(task.join(), Some(7));
+    let info = axtask::lookup(task.id()).unwrap().info();
+    assert_eq!(info.state, TaskState::Exited);
+    assert_eq!(info.exit_code, Some(7));
+    assert!(axtask::lookup(TaskId::from_u64(u64::MAX)).is_none());
+
+    // The CPU usage of a dropped task is still accounted.
+    let id = task.id();
+    let usage = axtask::cpu_usage();
+    drop(task);
+    assert!(axtask::lookup(id).is_none());
+    let new_usage = axtask::cpu_usage();
+    assert!(new_usage.runtime >= usage.runtime);
+    assert!(new_usage.voluntary_switches >= usage.voluntary_switches);
+}
+
+#[test]
+fn test_futex() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_TASKS: usize = 4;
+    static FUTEX: AtomicU32 = AtomicU32::new(0);
+    static TARGET: AtomicU32 = AtomicU32::new(0);
+    static WOKEN: AtomicUsize = AtomicUsize::new(0);
+
+    assert_eq!(futex::wait(&FUTEX, 1), futex::WaitResult::Mismatched);
+
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|_| {
+            axtask::spawn(|| {
+                assert_eq!(futex::wait(&FUTEX, 0), futex::WaitResult::Woken);
+                WOKEN.fetch_add(1, Ordering::Relaxed);
+            })
+        })
+        .collect();
+    axtask::yield_now(); // let the tasks block on `FUTEX`
+
+    assert_eq!(futex::wake(&TARGET, usize::MAX), 0);
+    assert_eq!(futex::wake(&FUTEX, 1), 1);
+    assert_eq!(futex::cmp_requeue(&FUTEX, 0, &TARGET, 1, 1), None);
+    // Wake up one, and move the others to `TARGET`.
+    assert_eq!(
+        futex::requeue(&FUTEX, 1, &TARGET, usize::MAX),
+        NUM_TASKS - 1
+    );
+    assert_eq!(futex::wake(&FUTEX, usize::MAX), 0);
+    assert_eq!(futex::wake(&TARGET, usize::MAX), NUM_TASKS - 2);
+
+    for task in tasks {
+        task.join();
+    }
+    assert_eq!(WOKEN.load(Ordering::Relaxed), NUM_TASKS);
+}
+
+#[test]
+fn test_executor() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_FUTURES: usize = 10;
+    static READY: AtomicBool = AtomicBool::new(false);
+    static WAKERS: WakerQueue = WakerQueue::new();
+
+    let executor = Executor::new(2);
+    let handles: Vec<_> = (0..NUM_FUTURES)
+        .map(|i| {
+            executor.spawn(poll_fn(move |cx| {
+                if !READY.load(Ordering::Acquire) {
+                    WAKERS.register(cx.waker());
+                    if !READY.load(Ordering::Acquire) {
+                        return Poll::Pending;
+                    }
+                }
+                Poll::Ready(i)
+            }))
+        })
+        .collect();
+    axtask::yield_now(); // let the workers poll the futures
+    assert!(handles.iter().all(|h| !h.is_finished()));
+
+    READY.store(true, Ordering::Release);
+    WAKERS.notify_all();
+    let sum = future::block_on(async {
+        let mut sum = 0;
+        for handle in handles {
+            sum += handle.await.unwrap();
+        }
+        sum
+    });
+    assert_eq!(sum, (0..NUM_FUTURES).sum());
+
+    // A future only held by its waker is dropped with the executor.
+    static NEVER: WakerQueue = WakerQueue::new();
+    let handle = executor.spawn(poll_fn(|cx| {
+        NEVER.register(cx.waker());
+        Poll::<()>::Pending
+    }));
+    while NEVER.is_empty() {
+        axtask::yield_now();
+    }
+    drop(executor);
+    assert_eq!(handle.join(), None);
+}
+
+#[test]
+fn test_join_set() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_TASKS: i32 = 5;
+
+    let mut set = axtask::JoinSet::new();
+    for i in 0..NUM_TASKS {
+        set.spawn(move || {
+            for _ in 0..i {
+                axtask::yield_now();
+            }
+            axtask::exit(i);
+        });
+    }
+    assert_eq!(set.len(), NUM_TASKS as usize);
+    assert!(set.try_join_next().is_none()); // none of them has run yet
+
+    let (first, code) = set.join_next().unwrap();
+    assert_eq!(code, 0); // the task without yielding exits first
+    assert_eq!(first.state(), TaskState::Exited);
+
+    let mut codes: Vec<_> = set.join_all().into_iter().map(|(_, code)| code).collect();
+    codes.sort();
+    assert_eq!(codes, (1..NUM_TASKS).collect::<Vec<_>>());
+    assert!(set.is_empty());
+    assert!(set.join_next().is_none());
+}
+
+#[test]
+fn test_steal_keeps_order() {
+    use scheduler::BaseScheduler;
+
+    use crate::{AxCpuMask, Scheduler, TaskInner, run_queue::steal_from};
+
+    let new_task = |name: &str, stealable: bool| {
+        let task = TaskInner::new(|| {}, name.into(), 0x1000).into_arc();
+        if !stealable {
+            task.set_cpumask(AxCpuMask::new()); // not allowed on any CPU
+        }
+        task
+    };
+    let names = |scheduler: &mut Scheduler| {
+        core::iter::from_fn(|| scheduler.pick_next_task())
+            .map(|t| t.name().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let mut scheduler = Scheduler::new();
+    for (name, stealable) in [
+        ("a", false),
+        ("b", false),
+        ("c", true),
+        ("d", false),
+        ("e", true),
+    ] {
+        scheduler.add_task(new_task(name, stealable));
+    }
+    let stolen = steal_from(&mut scheduler, 0, 5).unwrap();
+    assert_eq!(stolen.name(), "c");
+    assert_eq!(names(&mut scheduler), ["a", "b", "d", "e"]);
+
+    // Nothing to steal within the scan limit.
+    for name in ["a", "b", "c"] {
+        scheduler.add_task(new_task(name, name == "c"));
+    }
+    assert!(steal_from(&mut scheduler, 0, 2).is_none());
+    assert_eq!(names(&mut scheduler), ["a", "b", "c"]);
+}
+
+#[test]
+fn test_stack_cache() {
+    use crate::stack::StackCache;
+
+    let mut cache = StackCache::new(2);
+    assert!(cache.take(0x1000).is_none());
+    assert!(cache.put(0x4000, "a").is_none());
+    assert!(cache.put(0x2000, "b").is_none());
+
+    // Takes the smallest stack that fits.
+    assert_eq!(cache.take(0x1000), Some((0x2000, "b")));
+    assert!(cache.take(0x8000).is_none());
+
+    // Frees the largest stack when full.
+    assert!(cache.put(0x2000, "c").is_none());
+    assert_eq!(cache.put(0x3000, "d"), Some((0x4000, "a")));
+    assert_eq!(cache.take(0x3000), Some((0x3000, "d")));
+    assert_eq!(cache.take(0x1000), Some((0x2000, "c")));
+    assert!(cache.take(0).is_none());
+}
+
+#[cfg(feature = "irq")]
+#[test]
+fn test_timer_callbacks() {
+    use crate::{timer, timers};
+    use core::time::Duration;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static FIRED: AtomicUsize = AtomicUsize::new(0);
+    let run_expired = |now| {
+        let _guard = kernel_guard::NoPreemptIrqSave::new();
+        timers::run_expired(|| now);
+    };
+    let now = axhal::time::wall_time();
+
+    // A one-shot timer fires once its deadline is reached.
+    timer::add(now + Duration::from_secs(1), |_| {
+        FIRED.fetch_add(1, Ordering::Relaxed);
+    });
+    run_expired(now);
+    assert_eq!(FIRED.load(Ordering::Relaxed), 0);
+    run_expired(now + Duration::from_secs(1));
+    assert_eq!(FIRED.load(Ordering::Relaxed), 1);
+    assert!(timers::next_deadline().is_none());
+
+    // A cancelled timer is removed from the timer list.
+    let handle = timer::add(now, |_| panic!("cancelled timer fired"));
+    assert_eq!(timers::next_deadline(), Some(now));
+    handle.cancel();
+    assert!(handle.is_cancelled());
+    assert!(timers::next_deadline().is_none());
+    run_expired(now);
+
+    // A periodic timer is re-armed after firing, until it is cancelled.
+    let period = Duration::from_millis(10);
+    let handle = timer::add_periodic(period, |_| {
+        FIRED.fetch_add(1, Ordering::Relaxed);
+    });
+    let first = timers::next_deadline().unwrap();
+    run_expired(first);
+    assert_eq!(FIRED.load(Ordering::Relaxed), 2);
+    assert_eq!(timers::next_deadline(), Some(first + period));
+    handle.cancel();
+    assert!(timers::next_deadline().is_none());
+    run_expired(first + period);
+    assert_eq!(FIRED.load(Ordering::Relaxed), 2);
+}
+
+#[cfg(feature = "lockdep")]
+#[test]
+fn test_lockdep() {
+    use crate::lockdep::{self, LockKind, SpinNoIrq};
+    use core::panic::Location;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static A: SpinNoIrq<()> = SpinNoIrq::new(());
+    static B: SpinNoIrq<()> = SpinNoIrq::new(());
+    let reports = lockdep::num_reports();
+
+    // Consistent order.
+    for _ in 0..2 {
+        let _a = A.lock();
+        let _b = B.lock();
+    }
+    assert_eq!(lockdep::num_reports(), reports);
+
+    // Inversion, reported once.
+    for _ in 0..2 {
+        let _b = B.lock();
+        let _a = A.lock();
+    }
+    assert_eq!(lockdep::num_reports(), reports + 1);
+
+    // Recursion, checked without really acquiring the lock twice.
+    let addr = &A as *const _ as usize;
+    let site = Location::caller();
+    lockdep::acquire(addr, None, LockKind::Sleep, site, false);
+    lockdep::acquire(addr, None, LockKind::Sleep, Location::caller(), false);
+    lockdep::release(addr);
+    lockdep::release(addr);
+    assert_eq!(lockdep::num_reports(), reports + 2);
+
+    // Blocking while holding a spinlock, even if the wait does not block.
+    let guard = A.lock();
+    axtask::yield_now();
+    assert_eq!(lockdep::num_reports(), reports + 3);
+    WaitQueue::new().wait_until(|| true);
+    assert_eq!(lockdep::num_reports(), reports + 4);
+    drop(guard);
+    WaitQueue::new().wait_until(|| true);
+    assert_eq!(lockdep::num_reports(), reports + 4);
+}
+
+#[cfg(all(feature = "watchdog", feature = "preempt"))]
+#[test]
+fn test_watchdog_task_check() {
+    use crate::watchdog::CpuProgress;
+
+    const TIMEOUT: u64 = axconfig::WATCHDOG_TASK_TIMEOUT as u64 * axhal::time::NANOS_PER_MILLIS;
+
+    let progress = CpuProgress::new();
+    progress.on_resched(1, 0);
+    // A compute-bound task that is never preempted, e.g., under FIFO.
+    assert_eq!(progress.check_task(TIMEOUT * 2, true), None);
+    // Preemption disabled after that.
+    assert_eq!(progress.check_task(TIMEOUT * 3, false), None);
+    assert_eq!(progress.check_task(TIMEOUT * 4, false), Some(TIMEOUT * 2));
+    assert_eq!(progress.check_task(TIMEOUT * 5, false), None); // reported once
+
+    // Rescheduled to another task, or to the idle task.
+    progress.on_resched(2, TIMEOUT * 5);
+    assert_eq!(
+        progress.check_task(TIMEOUT * 6 + 1, false),
+        Some(TIMEOUT + 1)
+    );
+    progress.on_resched(0, TIMEOUT * 7);
+    assert_eq!(progress.check_task(TIMEOUT * 9, false), None);
+}
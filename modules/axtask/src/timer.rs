@@ -0,0 +1,139 @@
+//! General-purpose timer callbacks.
+//!
+//! Timers are kept in the timer list of the CPU that adds them, and the
+//! callbacks are called in IRQ context, i.e., in the timer IRQ handler of that
+//! CPU with IRQs and preemption disabled. So the callbacks must be short and
+//! never block or sleep, but they can add or cancel timers, and wake up tasks
+//! (e.g., by [`WaitQueue::notify_one`](crate::WaitQueue::notify_one)).
+//!
+//! The deadlines are in wall time, the same as [`crate::sleep_until`].
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axhal::time::{TimeValue, wall_time};
+use kernel_guard::NoPreemptIrqSave;
+// Not tracked by lockdep, as the timers are fired in IRQ context.
+use kspin::SpinNoIrq;
+
+type TimerCallback = Box<dyn FnMut(TimeValue) + Send>;
+
+pub(crate) struct TimerInner {
+    callback: SpinNoIrq<TimerCallback>,
+    /// The interval of a periodic timer, `None` for a one-shot timer.
+    period: Option<Duration>,
+    /// The deadline of the next expiration.
+    deadline: SpinNoIrq<TimeValue>,
+    cancelled: AtomicBool,
+    /// The CPU whose timer list holds the timer.
+    cpu_id: usize,
+}
+
+impl TimerInner {
+    pub(crate) const fn cpu_id(&self) -> usize {
+        self.cpu_id
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Runs the callback if the timer is not cancelled.
+    ///
+    /// Returns the next deadline if the timer is periodic.
+    pub(crate) fn fire(&self, now: TimeValue) -> Option<TimeValue> {
+        if self.is_cancelled() {
+            return None;
+        }
+        (self.callback.lock())(now);
+
+        let period = self.period?;
+        if self.is_cancelled() {
+            return None;
+        }
+        let mut deadline = self.deadline.lock();
+        // Keep the expirations aligned to the period, but skip the missed
+        // ones if the timer is late for more than one period.
+        *deadline += period;
+        if *deadline <= now {
+            *deadline = now + period;
+        }
+        Some(*deadline)
+    }
+}
+
+/// A handle to a timer added by [`add`] or [`add_periodic`].
+///
+/// Dropping the handle does not cancel the timer, call [`TimerHandle::cancel`]
+/// instead.
+#[derive(Clone)]
+pub struct TimerHandle(Arc<TimerInner>);
+
+impl TimerHandle {
+    /// Cancels the timer and removes it from the timer list, its callback
+    /// will not be called anymore.
+    ///
+    /// A callback that is running on another CPU is not interrupted, and a
+    /// periodic timer is not re-armed after it.
+    pub fn cancel(&self) {
+        if !self.0.cancelled.swap(true, Ordering::AcqRel) {
+            crate::timers::remove_callback(&self.0);
+        }
+    }
+
+    /// Returns `true` if the timer has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.is_cancelled()
+    }
+}
+
+fn add_timer(
+    deadline: TimeValue,
+    period: Option<Duration>,
+    callback: TimerCallback,
+) -> TimerHandle {
+    let _guard = NoPreemptIrqSave::new();
+    let timer = Arc::new(TimerInner {
+        callback: SpinNoIrq::new(callback),
+        period,
+        deadline: SpinNoIrq::new(deadline),
+        cancelled: AtomicBool::new(false),
+        cpu_id: axhal::cpu::this_cpu_id(),
+    });
+    crate::timers::add_callback(deadline, timer.clone());
+    TimerHandle(timer)
+}
+
+/// Adds a one-shot timer, `callback` will be called with the current time
+/// once the `deadline` is reached.
+pub fn add<F>(deadline: TimeValue, callback: F) -> TimerHandle
+where
+    F: FnOnce(TimeValue) + Send + 'static,
+{
+    let mut callback = Some(callback);
+    add_timer(
+        deadline,
+        None,
+        Box::new(move |now| {
+            if let Some(f) = callback.take() {
+                f(now)
+            }
+        }),
+    )
+}
+
+/// Adds a periodic timer, `callback` will be called with the current time
+/// every `period`, starting from `now + period`.
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+pub fn add_periodic<F>(period: Duration, callback: F) -> TimerHandle
+where
+    F: FnMut(TimeValue) + Send + 'static,
+{
+    assert!(!period.is_zero(), "the period of a timer must not be zero");
+    add_timer(wall_time() + period, Some(period), Box::new(callback))
+}
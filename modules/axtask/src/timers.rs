@@ -1,17 +1,54 @@
+use alloc::sync::Arc;
 use core::sync::atomic::{AtomicU64, Ordering};
 
+use axhal::cpu::this_cpu_id;
 use kernel_guard::NoOp;
+use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
 use timer_list::{TimeValue, TimerEvent, TimerList};
 
 use axhal::time::wall_time;
 
+use crate::timer::TimerInner;
 use crate::{AxTaskRef, select_run_queue};
 
 static TIMER_TICKET_ID: AtomicU64 = AtomicU64::new(1);
 
+/// The timer lists of all CPUs, indexed by cpu_id.
+///
+/// They are locked by the untracked spinlock, as they are also used in IRQ
+/// context. Other CPUs lock a timer list only to cancel timers in it.
+static TIMER_LISTS: [LazyInit<SpinNoIrq<TimerList<AxTimerEvent>>>; axconfig::SMP] =
+    [const { LazyInit::new() }; axconfig::SMP];
+
+fn timer_list(cpu_id: usize) -> &'static SpinNoIrq<TimerList<AxTimerEvent>> {
+    &TIMER_LISTS[cpu_id]
+}
+
 percpu_static! {
-    TIMER_LIST: LazyInit<TimerList<TaskWakeupEvent>> = LazyInit::new(),
+    /// Whether the periodic tick is stopped by the idle task.
+    #[cfg(feature = "tickless")]
+    TICK_STOPPED: bool = false,
+}
+
+/// The interval of the periodic tick.
+#[cfg(feature = "tickless")]
+const TICK_INTERVAL_NANOS: u64 = axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+/// The longest time to sleep without ticks, which keeps the timer deadline
+/// within the range of all platform timers.
+#[cfg(feature = "tickless")]
+const MAX_IDLE_NANOS: u64 = axhal::time::NANOS_PER_SEC;
+
+enum AxTimerEvent {
+    /// Wakes up a sleeping task.
+    TaskWakeup(TaskWakeupEvent),
+    /// Runs a callback registered by [`crate::timer::add`] and
+    /// [`crate::timer::add_periodic`].
+    Callback(Arc<TimerInner>),
+    /// Does nothing but wakes up the idle CPU, see [`set_cpu_wakeup`].
+    #[cfg(feature = "tickless")]
+    CpuWakeup,
 }
 
 struct TaskWakeupEvent {
@@ -19,8 +56,24 @@ struct TaskWakeupEvent {
     task: AxTaskRef,
 }
 
-impl TimerEvent for TaskWakeupEvent {
-    fn callback(self, _now: TimeValue) {
+impl TimerEvent for AxTimerEvent {
+    fn callback(self, now: TimeValue) {
+        match self {
+            Self::TaskWakeup(event) => event.wakeup(),
+            Self::Callback(timer) => {
+                if let Some(next) = timer.fire(now) {
+                    // Periodic timers stay on this CPU.
+                    add_callback(next, timer);
+                }
+            }
+            #[cfg(feature = "tickless")]
+            Self::CpuWakeup => {}
+        }
+    }
+}
+
+impl TaskWakeupEvent {
+    fn wakeup(self) {
         // Ignore the timer event if timeout was set but not triggered
         // (wake up by `WaitQueue::notify()`).
         // Judge if this timer event is still valid by checking the ticket ID.
@@ -36,21 +89,69 @@ impl TimerEvent for TaskWakeupEvent {
 }
 
 pub fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
-    TIMER_LIST.with_current(|timer_list| {
-        let ticket_id = TIMER_TICKET_ID.fetch_add(1, Ordering::AcqRel);
-        task.set_timer_ticket(ticket_id);
-        timer_list.set(deadline, TaskWakeupEvent { ticket_id, task });
-    })
+    let ticket_id = TIMER_TICKET_ID.fetch_add(1, Ordering::AcqRel);
+    task.set_timer_ticket(ticket_id);
+    timer_list(this_cpu_id()).lock().set(
+        deadline,
+        AxTimerEvent::TaskWakeup(TaskWakeupEvent { ticket_id, task }),
+    );
+}
+
+/// Makes sure the current CPU is woken up at `deadline` even if the tick is
+/// stopped by then, so that the idle task can pick the tasks that become
+/// ready at that time without being woken up by anyone, e.g., the deadline
+/// tasks throttled by the real-time scheduler.
+#[cfg(feature = "tickless")]
+pub fn set_cpu_wakeup(deadline: TimeValue) {
+    timer_list(this_cpu_id())
+        .lock()
+        .set(deadline, AxTimerEvent::CpuWakeup);
+}
+
+/// Adds a callback timer to the timer list of the CPU it belongs to, unless
+/// it has been cancelled.
+///
+/// IRQs must be disabled.
+pub fn add_callback(deadline: TimeValue, timer: Arc<TimerInner>) {
+    let mut list = timer_list(timer.cpu_id()).lock();
+    // Checked with the list locked, so a concurrent `cancel` either sees the
+    // timer in the list or keeps it from being added.
+    if timer.is_cancelled() {
+        return;
+    }
+    list.set(deadline, AxTimerEvent::Callback(timer));
+    drop(list);
+    // The timer may be added by IRQ handlers while the tick is stopped.
+    #[cfg(feature = "tickless")]
+    restart_tick();
+}
+
+/// Removes a cancelled callback timer from the timer list it is in.
+pub fn remove_callback(timer: &Arc<TimerInner>) {
+    timer_list(timer.cpu_id())
+        .lock()
+        .cancel(|event| matches!(event, AxTimerEvent::Callback(t) if Arc::ptr_eq(t, timer)));
+}
+
+/// Returns the deadline of the next timer event on the current CPU.
+pub fn next_deadline() -> Option<TimeValue> {
+    timer_list(this_cpu_id()).lock().next_deadline()
 }
 
 pub fn check_events() {
+    run_expired(wall_time)
+}
+
+/// Runs the timer events on the current CPU that have expired by the time
+/// returned by `now`.
+///
+/// IRQs must be disabled.
+pub(crate) fn run_expired(now: impl Fn() -> TimeValue) {
     loop {
-        let now = wall_time();
-        let event = unsafe {
-            // Safety: IRQs are disabled at this time.
-            TIMER_LIST.current_ref_mut_raw()
-        }
-        .expire_one(now);
+        let now = now();
+        // Do not hold the lock when running the event, which may add or
+        // cancel timers.
+        let event = timer_list(this_cpu_id()).lock().expire_one(now);
         if let Some((_deadline, event)) = event {
             event.callback(now);
         } else {
@@ -59,8 +160,39 @@ pub fn check_events() {
     }
 }
 
+/// Stops the periodic tick before the idle task waits for IRQs, and programs
+/// the timer to the deadline of the next timer event instead.
+#[cfg(feature = "tickless")]
+pub fn stop_tick() {
+    // An idle CPU must keep ticking to find tasks put into its run queue by
+    // other CPUs.
+    if cfg!(feature = "smp") {
+        return;
+    }
+    let _guard = kernel_guard::IrqSave::new();
+    let now = axhal::time::monotonic_time_nanos();
+    let mut deadline = now + MAX_IDLE_NANOS;
+    if let Some(next) = next_deadline() {
+        // Timer events use the wall time.
+        let next = (next.as_nanos() as u64).saturating_sub(axhal::time::epochoffset_nanos());
+        deadline = deadline.min(next);
+    }
+    unsafe { TICK_STOPPED.write_current_raw(true) };
+    axhal::time::set_oneshot_timer(deadline);
+}
+
+/// Restarts the periodic tick if it was stopped, called when the idle task is
+/// switched out, or a timer is added while idle.
+///
+/// IRQs must be disabled.
+#[cfg(feature = "tickless")]
+pub fn restart_tick() {
+    if unsafe { TICK_STOPPED.read_current_raw() } {
+        unsafe { TICK_STOPPED.write_current_raw(false) };
+        axhal::time::set_oneshot_timer(axhal::time::monotonic_time_nanos() + TICK_INTERVAL_NANOS);
+    }
+}
+
 pub fn init() {
-    TIMER_LIST.with_current(|timer_list| {
-        timer_list.init_once(TimerList::new());
-    });
+    TIMER_LISTS[this_cpu_id()].init_once(SpinNoIrq::new(TimerList::new()));
 }
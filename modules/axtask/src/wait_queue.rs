@@ -4,10 +4,35 @@ use alloc::sync::Arc;
 use kernel_guard::{NoOp, NoPreemptIrqSave};
 use kspin::{SpinNoIrq, SpinNoIrqGuard};
 
-use crate::{AxTaskRef, CurrentTask, current_run_queue, select_run_queue};
+use crate::{AxTaskRef, Cancelled, CurrentTask, current_run_queue, select_run_queue};
+
+/// Checks that the current task holds no spinlocks before it may block, at
+/// the call site of the wait.
+macro_rules! check_blocking {
+    () => {
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::check_blocking(core::panic::Location::caller());
+    };
+}
 
 /// A queue to store sleeping tasks.
 ///
+/// By default, the tasks are woken up in FIFO order. A queue created by
+/// [`WaitQueue::new_prio_ordered`] wakes up the task with the highest
+/// (effective) priority first instead, and the tasks with the same priority
+/// in FIFO order, which is required by real-time tasks waiting on locks.
+///
+/// A task can wait as an exclusive waiter (e.g., by
+/// [`WaitQueue::wait_until_exclusive`]), so that
+/// [`WaitQueue::notify_exclusive`] only wakes up one of the exclusive waiters.
+/// It avoids the thundering herd when the resource can only be taken by one
+/// of them, e.g., accepting a connection. [`WaitQueue::notify_all`] still
+/// wakes up all of them, e.g., when the resource is closed.
+///
+/// Only the `*_cancellable` waits can be interrupted by
+/// [`TaskInner::cancel`](crate::TaskInner::cancel), other waits are not
+/// cancellation points.
+///
 /// # Examples
 ///
 /// ```
@@ -30,6 +55,8 @@ use crate::{AxTaskRef, CurrentTask, current_run_queue, select_run_queue};
 /// ```
 pub struct WaitQueue {
     queue: SpinNoIrq<VecDeque<AxTaskRef>>,
+    /// Whether to wake up the task with the highest priority first.
+    prio_ordered: bool,
 }
 
 pub(crate) type WaitQueueGuard<'a> = SpinNoIrqGuard<'a, VecDeque<AxTaskRef>>;
@@ -39,6 +66,16 @@ impl WaitQueue {
     pub const fn new() -> Self {
         Self {
             queue: SpinNoIrq::new(VecDeque::new()),
+            prio_ordered: false,
+        }
+    }
+
+    /// Creates an empty wait queue that wakes up the task with the highest
+    /// priority first.
+    pub const fn new_prio_ordered() -> Self {
+        Self {
+            queue: SpinNoIrq::new(VecDeque::new()),
+            prio_ordered: true,
         }
     }
 
@@ -46,9 +83,15 @@ impl WaitQueue {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             queue: SpinNoIrq::new(VecDeque::with_capacity(capacity)),
+            prio_ordered: false,
         }
     }
 
+    /// Locks the queue of the waiting tasks.
+    pub(crate) fn lock(&self) -> WaitQueueGuard<'_> {
+        self.queue.lock()
+    }
+
     /// Cancel events by removing the task from the wait queue.
     /// If `from_timer_list` is true, try to remove the task from the timer list.
     fn cancel_events(&self, curr: CurrentTask, _from_timer_list: bool) {
@@ -59,6 +102,7 @@ impl WaitQueue {
             self.queue.lock().retain(|t| !curr.ptr_eq(t));
             curr.set_in_wait_queue(false);
         }
+        curr.set_wait_exclusive(false);
 
         // Try to cancel a timer event from timer lists.
         // Just mark task's current timer ticket ID as expired.
@@ -73,10 +117,30 @@ impl WaitQueue {
         }
     }
 
+    /// Removes the cancelled current task from the wait queue, where it's
+    /// still in if it was woken up by [`TaskInner::cancel`](crate::TaskInner::cancel).
+    ///
+    /// If a notification has taken the task from the queue meanwhile, it's
+    /// passed on to another waiter, so that it is not lost. `blocked` tells
+    /// whether the task has been in the queue.
+    fn abort_cancelled(&self, curr: &CurrentTask, blocked: bool) {
+        let mut wq = self.queue.lock();
+        if curr.in_wait_queue() {
+            wq.retain(|t| !curr.ptr_eq(t));
+            curr.set_in_wait_queue(false);
+        } else if blocked {
+            if let Some(index) = self.next_index(&wq, |_| true) {
+                unblock_one_task(wq.remove(index).unwrap(), true);
+            }
+        }
+    }
+
     /// Blocks the current task and put it into the wait queue, until other task
     /// notifies it.
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn wait(&self) {
-        current_run_queue::<NoPreemptIrqSave>().blocked_resched(self.queue.lock());
+        check_blocking!();
+        current_run_queue::<NoPreemptIrqSave>().blocked_resched(self.queue.lock(), false);
         self.cancel_events(crate::current(), false);
     }
 
@@ -85,18 +149,39 @@ impl WaitQueue {
     ///
     /// Note that even other tasks notify this task, it will not wake up until
     /// the condition becomes true.
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn wait_until<F>(&self, condition: F)
+    where
+        F: Fn() -> bool,
+    {
+        check_blocking!();
+        self.wait_until_inner(condition, false)
+    }
+
+    /// Same as [`WaitQueue::wait_until`], but the current task waits as an
+    /// exclusive waiter, see [`WaitQueue::notify_exclusive`].
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn wait_until_exclusive<F>(&self, condition: F)
+    where
+        F: Fn() -> bool,
+    {
+        check_blocking!();
+        self.wait_until_inner(condition, true)
+    }
+
+    fn wait_until_inner<F>(&self, condition: F, exclusive: bool)
     where
         F: Fn() -> bool,
     {
         let curr = crate::current();
+        curr.set_wait_exclusive(exclusive);
         loop {
             let mut rq = current_run_queue::<NoPreemptIrqSave>();
             let wq = self.queue.lock();
             if condition() {
                 break;
             }
-            rq.blocked_resched(wq);
+            rq.blocked_resched(wq, false);
             // Preemption may occur here.
         }
         self.cancel_events(curr, false);
@@ -105,7 +190,9 @@ impl WaitQueue {
     /// Blocks the current task and put it into the wait queue, until other tasks
     /// notify it, or the given duration has elapsed.
     #[cfg(feature = "irq")]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn wait_timeout(&self, dur: core::time::Duration) -> bool {
+        check_blocking!();
         let mut rq = current_run_queue::<NoPreemptIrqSave>();
         let curr = crate::current();
         let deadline = axhal::time::wall_time() + dur;
@@ -116,7 +203,7 @@ impl WaitQueue {
         );
         crate::timers::set_alarm_wakeup(deadline, curr.clone());
 
-        rq.blocked_resched(self.queue.lock());
+        rq.blocked_resched(self.queue.lock(), false);
 
         let timeout = curr.in_wait_queue(); // still in the wait queue, must have timed out
 
@@ -131,11 +218,39 @@ impl WaitQueue {
     /// Note that even other tasks notify this task, it will not wake up until
     /// the above conditions are met.
     #[cfg(feature = "irq")]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn wait_timeout_until<F>(&self, dur: core::time::Duration, condition: F) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        check_blocking!();
+        self.wait_timeout_until_inner(dur, condition, false)
+    }
+
+    /// Same as [`WaitQueue::wait_timeout_until`], but the current task waits
+    /// as an exclusive waiter, see [`WaitQueue::notify_exclusive`].
+    #[cfg(feature = "irq")]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn wait_timeout_until_exclusive<F>(&self, dur: core::time::Duration, condition: F) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        check_blocking!();
+        self.wait_timeout_until_inner(dur, condition, true)
+    }
+
+    #[cfg(feature = "irq")]
+    fn wait_timeout_until_inner<F>(
+        &self,
+        dur: core::time::Duration,
+        condition: F,
+        exclusive: bool,
+    ) -> bool
     where
         F: Fn() -> bool,
     {
         let curr = crate::current();
+        curr.set_wait_exclusive(exclusive);
         let deadline = axhal::time::wall_time() + dur;
         debug!(
             "task wait_timeout: {}, deadline={:?}",
@@ -156,7 +271,7 @@ impl WaitQueue {
                 break;
             }
 
-            rq.blocked_resched(wq);
+            rq.blocked_resched(wq, false);
             // Preemption may occur here.
         }
         // Always try to remove the task from the timer list.
@@ -164,21 +279,131 @@ impl WaitQueue {
         timeout
     }
 
-    /// Wakes up one task in the wait queue, usually the first one.
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, or the current task is cancelled.
+    ///
+    /// Returns [`Cancelled`] if the current task has been cancelled, see
+    /// [`TaskInner::cancel`](crate::TaskInner::cancel).
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn wait_until_cancellable<F>(&self, condition: F) -> Result<(), Cancelled>
+    where
+        F: Fn() -> bool,
+    {
+        check_blocking!();
+        let curr = crate::current();
+        let mut res = Ok(());
+        let mut blocked = false;
+        loop {
+            let mut rq = current_run_queue::<NoPreemptIrqSave>();
+            let wq = self.queue.lock();
+            if condition() {
+                break;
+            }
+            if curr.is_cancelled() {
+                res = Err(Cancelled);
+                break;
+            }
+            rq.blocked_resched(wq, true);
+            blocked = true;
+            // Preemption may occur here.
+        }
+        if res.is_err() {
+            self.abort_cancelled(&curr, blocked);
+        }
+        self.cancel_events(curr, false);
+        res
+    }
+
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, the given duration has elapsed, or the current
+    /// task is cancelled.
+    ///
+    /// Returns whether the wait has timed out, or [`Cancelled`] if the current
+    /// task has been cancelled, see
+    /// [`TaskInner::cancel`](crate::TaskInner::cancel).
+    #[cfg(feature = "irq")]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn wait_timeout_until_cancellable<F>(
+        &self,
+        dur: core::time::Duration,
+        condition: F,
+    ) -> Result<bool, Cancelled>
+    where
+        F: Fn() -> bool,
+    {
+        check_blocking!();
+        let curr = crate::current();
+        let deadline = axhal::time::wall_time() + dur;
+        debug!(
+            "task wait_timeout: {}, deadline={:?}",
+            curr.id_name(),
+            deadline
+        );
+        crate::timers::set_alarm_wakeup(deadline, curr.clone());
+
+        let mut res = Ok(true);
+        let mut blocked = false;
+        loop {
+            let mut rq = current_run_queue::<NoPreemptIrqSave>();
+            if axhal::time::wall_time() >= deadline {
+                break;
+            }
+            let wq = self.queue.lock();
+            if condition() {
+                res = Ok(false);
+                break;
+            }
+            if curr.is_cancelled() {
+                res = Err(Cancelled);
+                break;
+            }
+
+            rq.blocked_resched(wq, true);
+            blocked = true;
+            // Preemption may occur here.
+        }
+        if res.is_err() {
+            self.abort_cancelled(&curr, blocked);
+        }
+        // Always try to remove the task from the timer list.
+        self.cancel_events(curr, true);
+        res
+    }
+
+    /// Returns the index of the next task to wake up among the tasks that
+    /// satisfy `filter`.
+    fn next_index(
+        &self,
+        wq: &VecDeque<AxTaskRef>,
+        filter: impl Fn(&AxTaskRef) -> bool,
+    ) -> Option<usize> {
+        let mut candidates = wq.iter().enumerate().filter(|(_, t)| filter(t));
+        if self.prio_ordered {
+            // `min_by_key` returns the first one among the equal keys.
+            candidates
+                .min_by_key(|(_, t)| t.effective_priority())
+                .map(|(i, _)| i)
+        } else {
+            candidates.next().map(|(i, _)| i)
+        }
+    }
+
+    /// Wakes up one task in the wait queue, usually the first one, or the one
+    /// with the highest priority if the queue is priority-ordered.
     ///
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_one(&self, resched: bool) -> bool {
         let mut wq = self.queue.lock();
-        if let Some(task) = wq.pop_front() {
-            unblock_one_task(task, resched);
+        if let Some(index) = self.next_index(&wq, |_| true) {
+            unblock_one_task(wq.remove(index).unwrap(), resched);
             true
         } else {
             false
         }
     }
 
-    /// Wakes all tasks in the wait queue.
+    /// Wakes all tasks in the wait queue, including the exclusive ones.
     ///
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
@@ -188,11 +413,26 @@ impl WaitQueue {
         }
     }
 
+    /// Wakes all non-exclusive tasks and one of the exclusive tasks in the
+    /// wait queue.
+    ///
+    /// If `resched` is true, the current task will be preempted when the
+    /// preemption is enabled.
+    pub fn notify_exclusive(&self, resched: bool) {
+        let mut wq = self.queue.lock();
+        if let Some(index) = self.next_index(&wq, |t| t.is_wait_exclusive()) {
+            unblock_one_task(wq.remove(index).unwrap(), resched);
+        }
+        while let Some(index) = self.next_index(&wq, |t| !t.is_wait_exclusive()) {
+            unblock_one_task(wq.remove(index).unwrap(), resched);
+        }
+    }
+
     /// Wake up the given task in the wait queue.
     ///
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
-    pub fn notify_task(&mut self, resched: bool, task: &AxTaskRef) -> bool {
+    pub fn notify_task(&self, resched: bool, task: &AxTaskRef) -> bool {
         let mut wq = self.queue.lock();
         if let Some(index) = wq.iter().position(|t| Arc::ptr_eq(t, task)) {
             unblock_one_task(wq.remove(index).unwrap(), resched);
@@ -203,7 +443,7 @@ impl WaitQueue {
     }
 }
 
-fn unblock_one_task(task: AxTaskRef, resched: bool) {
+pub(crate) fn unblock_one_task(task: AxTaskRef, resched: bool) {
     // Mark task as not in wait queue.
     task.set_in_wait_queue(false);
     // Select run queue by the CPU set of the task.
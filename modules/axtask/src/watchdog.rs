@@ -0,0 +1,148 @@
+//! Software watchdog for hung tasks and stuck CPUs.
+//!
+//! On each timer tick, the watchdog checks that:
+//!
+//! - The current task of this CPU has not been running with preemption
+//!   disabled for more than [`axconfig::WATCHDOG_TASK_TIMEOUT`] milliseconds,
+//!   so that a task spinning while holding a spinlock is found. The registers
+//!   of the interrupted code are logged as well. A compute-bound task with
+//!   preemption enabled is not reported, even if the scheduler (e.g., FIFO)
+//!   never preempts it. It requires the `preempt` feature.
+//! - Other CPUs have received timer ticks within
+//!   [`axconfig::WATCHDOG_CPU_TIMEOUT`] milliseconds, so that a CPU spinning
+//!   with IRQs disabled is found. Idle CPUs are not checked, as their ticks
+//!   may be stopped.
+//!
+//! Each hang is reported once, by logging a warning, or by a panic if
+//! [`axconfig::WATCHDOG_PANIC`] is set. A single-core system spinning with
+//! IRQs disabled can not be found, as there is no other CPU to check it.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use axhal::time::{NANOS_PER_MILLIS, monotonic_time_nanos};
+
+use crate::{AxTaskRef, TaskId};
+
+#[cfg(feature = "preempt")]
+const TASK_TIMEOUT_NANOS: u64 = axconfig::WATCHDOG_TASK_TIMEOUT as u64 * NANOS_PER_MILLIS;
+#[cfg(feature = "smp")]
+const CPU_TIMEOUT_NANOS: u64 = axconfig::WATCHDOG_CPU_TIMEOUT as u64 * NANOS_PER_MILLIS;
+
+/// The progress of a CPU.
+struct CpuProgress {
+    /// The time of the last timer tick.
+    last_tick: AtomicU64,
+    /// The last time the current task was seen preemptible, or the time it
+    /// was scheduled.
+    last_preemptible: AtomicU64,
+    /// The ID of the current task, or 0 if the CPU is idle.
+    task_id: AtomicU64,
+    /// Whether the current task is reported as hung.
+    task_reported: AtomicBool,
+    /// Whether the CPU is reported as stuck.
+    #[cfg(feature = "smp")]
+    cpu_reported: AtomicBool,
+}
+
+impl CpuProgress {
+    pub(crate) const fn new() -> Self {
+        Self {
+            last_tick: AtomicU64::new(0),
+            last_preemptible: AtomicU64::new(0),
+            task_id: AtomicU64::new(0),
+            task_reported: AtomicBool::new(false),
+            #[cfg(feature = "smp")]
+            cpu_reported: AtomicBool::new(false),
+        }
+    }
+
+    /// Records that the task `task_id` (0 for the idle task) is scheduled at
+    /// `now`.
+    pub(crate) fn on_resched(&self, task_id: u64, now: u64) {
+        self.task_id.store(task_id, Ordering::Relaxed);
+        self.last_preemptible.store(now, Ordering::Relaxed);
+        self.task_reported.store(false, Ordering::Relaxed);
+    }
+
+    /// Checks the current task at `now`, and returns how long it has been
+    /// running with preemption disabled if it's newly found hung.
+    #[cfg(feature = "preempt")]
+    pub(crate) fn check_task(&self, now: u64, preemptible: bool) -> Option<u64> {
+        if preemptible {
+            self.on_resched(self.task_id.load(Ordering::Relaxed), now);
+            return None;
+        }
+        let running = now.saturating_sub(self.last_preemptible.load(Ordering::Relaxed));
+        (self.task_id.load(Ordering::Relaxed) != 0
+            && running > TASK_TIMEOUT_NANOS
+            && !self.task_reported.swap(true, Ordering::Relaxed))
+        .then_some(running)
+    }
+}
+
+static PROGRESS: [CpuProgress; axconfig::SMP] = [const { CpuProgress::new() }; axconfig::SMP];
+
+macro_rules! report {
+    ($($arg:tt)+) => {
+        if axconfig::WATCHDOG_PANIC {
+            panic!($($arg)+);
+        } else {
+            warn!($($arg)+);
+        }
+    };
+}
+
+/// Records that the CPU `cpu_id` is going to run `next`.
+pub(crate) fn on_resched(cpu_id: usize, next: &AxTaskRef) {
+    let task_id = if next.is_idle() {
+        0
+    } else {
+        next.id().as_u64()
+    };
+    PROGRESS[cpu_id].on_resched(task_id, monotonic_time_nanos());
+}
+
+/// Checks the progress of the current task and other CPUs, it's called on
+/// each timer tick with IRQs disabled.
+pub(crate) fn on_timer_tick() {
+    let now = monotonic_time_nanos();
+    let cpu_id = axhal::cpu::this_cpu_id();
+    let progress = &PROGRESS[cpu_id];
+    progress.last_tick.store(now, Ordering::Relaxed);
+    #[cfg(feature = "smp")]
+    progress.cpu_reported.store(false, Ordering::Relaxed);
+
+    // The IRQ handler disables preemption once more.
+    #[cfg(feature = "preempt")]
+    if let Some(running) = progress.check_task(now, crate::current().can_preempt(1)) {
+        let curr = crate::current();
+        axhal::trap::with_irq_trap_frame(|tf| {
+            warn!("watchdog: registers of {}:\n{:#x?}", curr.id_name(), tf)
+        });
+        report!(
+            "watchdog: task {} has been running on CPU {} for {} ms with preemption disabled",
+            curr.id_name(),
+            cpu_id,
+            running / NANOS_PER_MILLIS
+        );
+    }
+
+    #[cfg(feature = "smp")]
+    for (other_id, other) in PROGRESS.iter().enumerate() {
+        let last_tick = other.last_tick.load(Ordering::Relaxed);
+        let task_id = other.task_id.load(Ordering::Relaxed);
+        if other_id == cpu_id || last_tick == 0 || task_id == 0 {
+            continue;
+        }
+        let stuck = now.saturating_sub(last_tick);
+        if stuck > CPU_TIMEOUT_NANOS && !other.cpu_reported.swap(true, Ordering::Relaxed) {
+            let task = crate::lookup(TaskId::from_u64(task_id));
+            report!(
+                "watchdog: CPU {} has no timer ticks for {} ms, running task {}",
+                other_id,
+                stuck / NANOS_PER_MILLIS,
+                task.map_or_else(|| alloc::format!("{}", task_id), |t| t.id_name())
+            );
+        }
+    }
+}
@@ -50,5 +50,8 @@ endef
 
 define unit_test
   $(call run_cmd,cargo test,-p axfs $(1) --features "myfs" $(verbose) -- --nocapture)
+  $(call run_cmd,cargo test,-p axfs $(1) --features "ext4" $(verbose) -- --nocapture)
+  $(call run_cmd,cargo test,-p axtask $(1) --features "sched_rt lockdep watchdog" $(verbose) -- --nocapture)
+  $(call run_cmd,cargo test,-p axsync $(1) --features "axtask/sched_rt" $(verbose) -- --nocapture)
   $(call run_cmd,cargo test,--workspace $(1) $(verbose) -- --nocapture)
 endef
@@ -18,20 +18,6 @@ int pthread_setcanceltype(int new, int *old)
     return 0;
 }
 
-// TODO
-void pthread_testcancel(void)
-{
-    unimplemented();
-    return;
-}
-
-// TODO
-int pthread_cancel(pthread_t t)
-{
-    unimplemented();
-    return 0;
-}
-
 // TODO
 int pthread_mutex_trylock(pthread_mutex_t *m)
 {
@@ -24,6 +24,7 @@ int dup2(int, int);
 int dup3(int, int, int);
 off_t lseek(int, off_t, int);
 int fsync(int);
+void sync(void);
 int fdatasync(int);
 
 ssize_t read(int, void *, size_t);
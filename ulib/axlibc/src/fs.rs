@@ -1,7 +1,8 @@
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_ulong, c_void};
 
 use arceos_posix_api::{
-    sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat,
+    sys_fstat, sys_getcwd, sys_link, sys_lseek, sys_lstat, sys_mount, sys_open, sys_readlink,
+    sys_rename, sys_stat, sys_symlink, sys_sync, sys_umount2,
 };
 
 use crate::{ctypes, utils::e};
@@ -65,3 +66,67 @@ pub unsafe extern "C" fn getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
 pub unsafe extern "C" fn rename(old: *const c_char, new: *const c_char) -> c_int {
     e(sys_rename(old, new))
 }
+
+/// Create a symbolic link `linkpath` which contains the string `target`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn symlink(target: *const c_char, linkpath: *const c_char) -> c_int {
+    e(sys_symlink(target, linkpath))
+}
+
+/// Read the target of the symbolic link `path` into `buf`.
+///
+/// Return the number of bytes placed in `buf`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn readlink(
+    path: *const c_char,
+    buf: *mut c_char,
+    bufsiz: usize,
+) -> ctypes::ssize_t {
+    e(sys_readlink(path, buf, bufsiz) as _) as _
+}
+
+/// Create a new hard link `new` to the existing file `old`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn link(old: *const c_char, new: *const c_char) -> c_int {
+    e(sys_link(old, new))
+}
+
+/// Mount a filesystem of type `fstype` at `target`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mount(
+    source: *const c_char,
+    target: *const c_char,
+    fstype: *const c_char,
+    flags: c_ulong,
+    data: *const c_void,
+) -> c_int {
+    e(sys_mount(source, target, fstype, flags, data))
+}
+
+/// Unmount the filesystem mounted at `target`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umount(target: *const c_char) -> c_int {
+    e(sys_umount2(target, 0))
+}
+
+/// Unmount the filesystem mounted at `target`, with the given `flags`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umount2(target: *const c_char, flags: c_int) -> c_int {
+    e(sys_umount2(target, flags))
+}
+
+/// Write all cached filesystem changes back to the disk.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sync() {
+    sys_sync()
+}
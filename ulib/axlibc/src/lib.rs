@@ -85,7 +85,7 @@ pub use self::io::{read, writev};
 pub use self::errno::strerror;
 pub use self::mktime::mktime;
 pub use self::rand::{rand, random, srand};
-pub use self::resource::{getrlimit, setrlimit};
+pub use self::resource::{getrlimit, getrusage, setrlimit};
 pub use self::setjmp::{longjmp, setjmp};
 pub use self::sys::sysconf;
 pub use self::time::{clock_gettime, nanosleep};
@@ -100,7 +100,10 @@ pub use self::strftime::strftime;
 pub use self::fd_ops::{ax_fcntl, close, dup, dup2, dup3};
 
 #[cfg(feature = "fs")]
-pub use self::fs::{ax_open, fstat, getcwd, lseek, lstat, rename, stat};
+pub use self::fs::{
+    ax_open, fstat, getcwd, link, lseek, lstat, mount, readlink, rename, stat, symlink, sync,
+    umount, umount2,
+};
 
 #[cfg(feature = "net")]
 pub use self::net::{
@@ -109,7 +112,10 @@ pub use self::net::{
 };
 
 #[cfg(feature = "multitask")]
-pub use self::pthread::{pthread_create, pthread_exit, pthread_join, pthread_self};
+pub use self::pthread::{
+    pthread_cancel, pthread_create, pthread_exit, pthread_join, pthread_kill, pthread_self,
+    pthread_testcancel,
+};
 #[cfg(feature = "multitask")]
 pub use self::pthread::{pthread_mutex_init, pthread_mutex_lock, pthread_mutex_unlock};
 
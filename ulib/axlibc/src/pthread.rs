@@ -37,6 +37,28 @@ pub unsafe extern "C" fn pthread_join(
     e(api::sys_pthread_join(thread, retval))
 }
 
+/// Requests the cancellation of the given thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pthread_cancel(thread: ctypes::pthread_t) -> c_int {
+    e(api::sys_pthread_cancel(thread))
+}
+
+/// A cancellation point, exits the current thread if it has been cancelled.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pthread_testcancel() {
+    api::sys_pthread_testcancel()
+}
+
+/// Sends a signal to the given thread, only `sig == 0` is supported to check
+/// whether the thread is alive.
+///
+/// Other signals fail with `ENOSYS` (or `EINVAL` if the number is invalid),
+/// and nothing is sent.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pthread_kill(thread: ctypes::pthread_t, sig: c_int) -> c_int {
+    e(api::sys_pthread_kill(thread, sig))
+}
+
 /// Initialize a mutex.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn pthread_mutex_init(
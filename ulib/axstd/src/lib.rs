@@ -27,10 +27,18 @@
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.
 //!     - `sched_rr`: Use the Round-robin preemptive scheduler.
 //!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `sched_rt`: Use the real-time (EDF + fixed-priority) preemptive scheduler.
+//!     - `select_rq_if`: Allow users to define a custom policy to select run queues on SMP systems.
+//!     - `tickless`: Stop the periodic timer tick while the CPU is idle.
+//!     - `watchdog`: Report tasks running too long without rescheduling and CPUs without timer ticks.
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `ext2`: Use ext2 as the main filesystem instead of FAT.
+//!     - `ext4`: Use ext2 as the main filesystem, with read-only support of ext4.
+//!     - `fs-write-back`: Write the file data to the disk on sync or exit instead of at once.
 //!     - `net`: Enable networking support.
+//!     - `net-async`: Enable the asynchronous socket operations of `axnet`.
 //!     - `dns`: Enable DNS lookup support.
 //!     - `display`: Enable graphics support.
 //! - Device drivers
@@ -21,3 +21,52 @@ macro_rules! println {
         $crate::io::__print_impl(format_args!("{}\n", format_args!($($arg)*)));
     }
 }
+
+/// Declares a new thread local storage key of type
+/// [`LocalKey`](crate::thread::LocalKey).
+///
+/// The macro wraps any number of static declarations and makes them thread
+/// local. Publicity and attributes for each static are allowed, and the
+/// initializer may be wrapped in a `const { ... }` block. Each thread gets
+/// its own copy of the value, see [`LocalKey`](crate::thread::LocalKey).
+///
+/// # Examples
+///
+/// ```
+/// use std::cell::RefCell;
+///
+/// thread_local! {
+///     pub static FOO: RefCell<u32> = RefCell::new(1);
+///     static BAR: RefCell<f32> = const { RefCell::new(1.0) };
+/// }
+///
+/// FOO.with(|foo| assert_eq!(*foo.borrow(), 1));
+/// BAR.with(|bar| assert_eq!(*bar.borrow(), 1.0));
+/// ```
+#[macro_export]
+macro_rules! thread_local {
+    () => {};
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = const { $init:expr }; $($rest:tt)*) => {
+        $crate::thread_local!($(#[$attr])* $vis static $name: $t = $init; $($rest)*);
+    };
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = const { $init:expr }) => {
+        $crate::thread_local!($(#[$attr])* $vis static $name: $t = $init);
+    };
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::thread_local!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::thread_local!($($rest)*);
+    };
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])*
+        $vis static $name: $crate::thread::LocalKey<$t> = $crate::thread::LocalKey::new({
+            fn __init() -> $t {
+                $init
+            }
+            __init
+        });
+    };
+}
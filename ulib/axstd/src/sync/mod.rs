@@ -10,6 +10,10 @@ pub use alloc::sync::{Arc, Weak};
 #[cfg(feature = "multitask")]
 mod mutex;
 
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use arceos_api::modules::axsync::mpsc;
+
 #[cfg(feature = "multitask")]
 #[doc(cfg(feature = "multitask"))]
 pub use self::mutex::{Mutex, MutexGuard};
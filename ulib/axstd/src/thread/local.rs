@@ -0,0 +1,119 @@
+//! Thread-local storage.
+
+use core::fmt;
+
+#[cfg(feature = "multitask")]
+use arceos_api::modules::axtask::TaskLocal;
+
+/// A thread-local storage key which owns its contents.
+///
+/// It's created by the [`thread_local!`](crate::thread_local) macro. Each
+/// thread has its own copy of the value, which is lazily initialized the first
+/// time the thread accesses it, and dropped when the thread exits.
+///
+/// For single-threaded configuration (`multitask` feature is disabled), there
+/// is only one copy of the value, and it is never dropped.
+pub struct LocalKey<T: 'static> {
+    #[cfg(feature = "multitask")]
+    inner: TaskLocal<T>,
+    #[cfg(not(feature = "multitask"))]
+    inner: single::Local<T>,
+}
+
+/// An error returned by [`LocalKey::try_with`].
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct AccessError;
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> Self {
+        Self {
+            #[cfg(feature = "multitask")]
+            inner: TaskLocal::new(init),
+            #[cfg(not(feature = "multitask"))]
+            inner: single::Local::new(init),
+        }
+    }
+
+    /// Acquires a reference to the value in this thread-local storage key.
+    ///
+    /// This will lazily initialize the value if this thread has not
+    /// referenced this key yet.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the key is accessed during or after the
+    /// value is destroyed.
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.try_with(f)
+            .expect("cannot access a Thread Local Storage value during or after destruction")
+    }
+
+    /// Acquires a reference to the value in this thread-local storage key.
+    ///
+    /// This will lazily initialize the value if this thread has not
+    /// referenced this key yet. If the key has been destroyed, this function
+    /// will return an [`AccessError`].
+    pub fn try_with<F, R>(&'static self, f: F) -> Result<R, AccessError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        #[cfg(feature = "multitask")]
+        return self.inner.try_with(f).map_err(|_| AccessError);
+        #[cfg(not(feature = "multitask"))]
+        return Ok(f(self.inner.get()));
+    }
+}
+
+#[cfg(not(feature = "multitask"))]
+mod single {
+    use core::cell::UnsafeCell;
+
+    pub struct Local<T> {
+        value: UnsafeCell<Option<T>>,
+        init: fn() -> T,
+    }
+
+    // SAFETY: there is only one thread in single-threaded configuration.
+    unsafe impl<T> Sync for Local<T> {}
+
+    impl<T> Local<T> {
+        pub const fn new(init: fn() -> T) -> Self {
+            Self {
+                value: UnsafeCell::new(None),
+                init,
+            }
+        }
+
+        pub fn get(&self) -> &T {
+            // SAFETY: the value is never dropped or replaced once initialized.
+            if let Some(value) = unsafe { &*self.value.get() } {
+                return value;
+            }
+            // `init` may access the key recursively, keep the first value.
+            let value = (self.init)();
+            unsafe { (*self.value.get()).get_or_insert(value) }
+        }
+    }
+}
+
+impl<T: 'static> fmt::Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalKey").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessError").finish()
+    }
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt("already destroyed", f)
+    }
+}
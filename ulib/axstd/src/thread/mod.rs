@@ -1,9 +1,16 @@
 //! Native threads.
 
+mod local;
+pub use local::{AccessError, LocalKey};
+
 #[cfg(feature = "multitask")]
 mod multi;
 #[cfg(feature = "multitask")]
+mod scoped;
+#[cfg(feature = "multitask")]
 pub use multi::*;
+#[cfg(feature = "multitask")]
+pub use scoped::{Scope, ScopedJoinHandle, scope};
 
 use arceos_api::task as api;
 
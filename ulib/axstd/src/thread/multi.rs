@@ -3,12 +3,14 @@
 extern crate alloc;
 
 use crate::io;
-use alloc::{string::String, sync::Arc};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
 use core::{cell::UnsafeCell, num::NonZeroU64};
 
-use arceos_api::task::{self as api, AxTaskHandle};
+use arceos_api::task::{self as api, AxJoinSetHandle, AxTaskHandle};
 use axerrno::ax_err_type;
 
+use super::scoped::ScopeData;
+
 /// A unique identifier for a running thread.
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
 pub struct ThreadId(NonZeroU64);
@@ -86,36 +88,43 @@ impl Builder {
         F: Send + 'static,
         T: Send + 'static,
     {
-        unsafe { self.spawn_unchecked(f) }
+        unsafe { self.spawn_unchecked(f, None) }
     }
 
-    unsafe fn spawn_unchecked<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
+    /// Spawns a new thread without checking the lifetimes of `f` and `T`.
+    ///
+    /// If `scope_data` is given, the thread is added to the scope, which
+    /// waits for it to exit.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the spawned thread does not outlive any
+    /// references in `f` and `T`, e.g., by joining it in a [`scope`].
+    ///
+    /// [`scope`]: super::scope
+    pub(super) unsafe fn spawn_unchecked<'a, F, T>(
+        self,
+        f: F,
+        scope_data: Option<Arc<ScopeData>>,
+    ) -> io::Result<JoinHandle<T>>
     where
         F: FnOnce() -> T,
-        F: Send + 'static,
-        T: Send + 'static,
+        F: Send + 'a,
+        T: Send + 'a,
     {
         let name = self.name.unwrap_or_default();
         let stack_size = self
             .stack_size
             .unwrap_or(arceos_api::config::TASK_STACK_SIZE);
 
-        let my_packet = Arc::new(Packet {
-            result: UnsafeCell::new(None),
-        });
-        let their_packet = my_packet.clone();
-
-        let main = move || {
-            let ret = f();
-            // SAFETY: `their_packet` as been built just above and moved by the
-            // closure (it is an Arc<...>) and `my_packet` will be stored in the
-            // same `JoinHandle` as this closure meaning the mutation will be
-            // safe (not modify it and affect a value far away).
-            unsafe { *their_packet.result.get() = Some(ret) };
-            drop(their_packet);
-        };
+        let (my_packet, main) = Packet::with_main(f);
+        // SAFETY: the caller guarantees that the thread does not outlive `'a`.
+        let main: Box<dyn FnOnce() + Send + 'static> = unsafe { core::mem::transmute(main) };
 
         let task = api::ax_spawn(main, name, stack_size);
+        if let Some(scope_data) = scope_data {
+            scope_data.add_thread(task.clone());
+        }
         Ok(JoinHandle {
             thread: Thread::from_id(task.id()),
             native: task,
@@ -147,12 +156,54 @@ where
     Builder::new().spawn(f).expect("failed to spawn thread")
 }
 
+/// A cancellation point, exits the current thread if it has been cancelled by
+/// [`JoinHandle::cancel`].
+pub fn test_cancel() {
+    api::ax_test_cancel();
+}
+
 struct Packet<T> {
     result: UnsafeCell<Option<T>>,
 }
 
 unsafe impl<T> Sync for Packet<T> {}
 
+impl<T> Packet<T> {
+    /// Creates a packet for the result of `f`, and the entry point of the
+    /// thread which runs `f` and stores the result in the packet.
+    fn with_main<'a, F>(f: F) -> (Arc<Self>, Box<dyn FnOnce() + Send + 'a>)
+    where
+        F: FnOnce() -> T,
+        F: Send + 'a,
+        T: Send + 'a,
+    {
+        let my_packet = Arc::new(Packet {
+            result: UnsafeCell::new(None),
+        });
+        let their_packet = my_packet.clone();
+
+        let main = move || {
+            let ret = f();
+            // SAFETY: `their_packet` as been built just above and moved by the
+            // closure (it is an Arc<...>) and `my_packet` will be stored in the
+            // same `JoinHandle` as this closure meaning the mutation will be
+            // safe (not modify it and affect a value far away).
+            unsafe { *their_packet.result.get() = Some(ret) };
+            drop(their_packet);
+        };
+        (my_packet, Box::new(main))
+    }
+
+    /// Takes the result out of the packet, after the thread has finished.
+    fn take_result(mut self: Arc<Self>) -> io::Result<T> {
+        // The thread still holds the packet if it exited without returning
+        // from `f`, e.g., by `exit` or cancellation.
+        Arc::get_mut(&mut self)
+            .and_then(|packet| packet.result.get_mut().take())
+            .ok_or_else(|| ax_err_type!(BadState))
+    }
+}
+
 /// An owned permission to join on a thread (block on its termination).
 ///
 /// A `JoinHandle` *detaches* the associated thread when it is dropped, which
@@ -173,17 +224,107 @@ impl<T> JoinHandle<T> {
         &self.thread
     }
 
+    /// Requests the cancellation of the associated thread.
+    ///
+    /// The thread is not terminated immediately, but at the next cancellation
+    /// point, see [`test_cancel`]. After that, [`join`](Self::join) returns an
+    /// error since the thread does not produce a result.
+    ///
+    /// [`test_cancel`] is the only cancellation point in this module. Blocking
+    /// calls such as [`sleep`](super::sleep) or joining another thread are
+    /// not, the thread keeps blocking in them after being cancelled.
+    pub fn cancel(&self) {
+        api::ax_cancel_task(&self.native);
+    }
+
     /// Waits for the associated thread to finish.
     ///
     /// This function will return immediately if the associated thread has
     /// already finished.
-    pub fn join(mut self) -> io::Result<T> {
+    pub fn join(self) -> io::Result<T> {
         api::ax_wait_for_exit(self.native).ok_or_else(|| ax_err_type!(BadState))?;
-        Arc::get_mut(&mut self.packet)
-            .unwrap()
-            .result
-            .get_mut()
-            .take()
-            .ok_or_else(|| ax_err_type!(BadState))
+        self.packet.take_result()
+    }
+}
+
+/// A collection of threads spawned together, which can be joined in the
+/// order they finish.
+///
+/// Like [`JoinHandle`], dropping a `JoinSet` detaches the threads that have
+/// not been joined.
+pub struct JoinSet<T> {
+    native: AxJoinSetHandle,
+    packets: Vec<(u64, Arc<Packet<T>>)>,
+}
+
+unsafe impl<T: Send> Send for JoinSet<T> {}
+unsafe impl<T: Send> Sync for JoinSet<T> {}
+
+impl<T> JoinSet<T> {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self {
+            native: AxJoinSetHandle::new(),
+            packets: Vec::new(),
+        }
+    }
+
+    /// Returns the number of threads in the set that have not been joined.
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    /// Whether all threads in the set have been joined.
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Spawns a new thread into the set, with the default parameters of
+    /// [`spawn`].
+    ///
+    /// Returns a handle to the spawned thread.
+    pub fn spawn<F>(&mut self, f: F) -> Thread
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (packet, main) = Packet::with_main(f);
+        let task = api::ax_join_set_spawn(
+            &mut self.native,
+            main,
+            String::new(),
+            arceos_api::config::TASK_STACK_SIZE,
+        );
+        self.packets.push((task.id(), packet));
+        Thread::from_id(task.id())
+    }
+
+    /// Waits for any thread in the set to finish, and returns it with its
+    /// result.
+    ///
+    /// The result is an error if the thread exited without returning, e.g.,
+    /// by [`exit`](super::exit) or [cancellation](JoinHandle::cancel).
+    /// Returns [`None`] if the set is empty.
+    pub fn join_next(&mut self) -> Option<(Thread, io::Result<T>)> {
+        let (task, _exit_code) = api::ax_join_set_join_next(&mut self.native)?;
+        let idx = self.packets.iter().position(|(id, _)| *id == task.id())?;
+        let (_, packet) = self.packets.swap_remove(idx);
+        Some((Thread::from_id(task.id()), packet.take_result()))
+    }
+
+    /// Waits for all threads in the set to finish, and returns their results
+    /// in the order they finish.
+    pub fn join_all(&mut self) -> Vec<io::Result<T>> {
+        let mut results = Vec::with_capacity(self.len());
+        while let Some((_, res)) = self.join_next() {
+            results.push(res);
+        }
+        results
+    }
+}
+
+impl<T> Default for JoinSet<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
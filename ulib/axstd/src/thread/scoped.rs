@@ -0,0 +1,146 @@
+//! Scoped threads, which can borrow non-`'static` data from the spawner.
+
+extern crate alloc;
+
+use crate::io;
+use crate::sync::Mutex;
+use alloc::{sync::Arc, vec::Vec};
+use core::marker::PhantomData;
+
+use arceos_api::task::{self as api, AxTaskHandle};
+
+use super::{Builder, JoinHandle, Thread};
+
+/// A scope to spawn scoped threads in.
+///
+/// See [`scope`] for details.
+pub struct Scope<'scope, 'env: 'scope> {
+    data: Arc<ScopeData>,
+    /// Invariance over `'scope`, to make sure `'scope` cannot shrink, which
+    /// is necessary for soundness.
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+pub(super) struct ScopeData {
+    /// The threads spawned in the scope that have not been waited for.
+    threads: Mutex<Vec<AxTaskHandle>>,
+}
+
+impl ScopeData {
+    pub(super) fn add_thread(&self, task: AxTaskHandle) {
+        self.threads.lock().push(task);
+    }
+
+    /// Waits for all the threads in the scope to exit, including the ones
+    /// spawned by them meanwhile.
+    ///
+    /// It waits for the tasks rather than their results, so that a thread
+    /// leaving by [`exit`](super::exit) or cancellation, or a forgotten
+    /// [`ScopedJoinHandle`], does not block the scope forever.
+    fn wait_all(&self) {
+        loop {
+            let Some(task) = self.threads.lock().pop() else {
+                break;
+            };
+            api::ax_wait_for_exit(task);
+        }
+    }
+}
+
+/// An owned permission to join on a scoped thread (block on its termination).
+///
+/// See [`Scope::spawn`] for details.
+pub struct ScopedJoinHandle<'scope, T> {
+    inner: JoinHandle<T>,
+    scope: PhantomData<&'scope ()>,
+}
+
+/// Creates a scope for spawning scoped threads.
+///
+/// The function passed to `scope` will be provided a [`Scope`] object,
+/// through which scoped threads can be [spawned][`Scope::spawn`].
+///
+/// Unlike non-scoped threads, scoped threads can borrow non-`'static` data,
+/// as the scope guarantees all threads will be joined at the end of the scope.
+///
+/// All threads spawned within the scope that haven't been manually joined
+/// will be automatically joined before this function returns, including the
+/// ones that leave by [`exit`](super::exit) instead of returning.
+pub fn scope<'env, F, T>(f: F) -> T
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+{
+    let scope = Scope {
+        data: Arc::new(ScopeData {
+            threads: Mutex::new(Vec::new()),
+        }),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+
+    let result = f(&scope);
+
+    scope.data.wait_all();
+    result
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Spawns a new thread within a scope, returning a [`ScopedJoinHandle`]
+    /// for it.
+    ///
+    /// Unlike non-scoped threads, threads spawned with this function may
+    /// borrow non-`'static` data from the outside the scope. See [`scope`]
+    /// for details.
+    ///
+    /// The default task name is an empty string. The default thread stack
+    /// size is [`arceos_api::config::TASK_STACK_SIZE`].
+    pub fn spawn<F, T>(&'scope self, f: F) -> ScopedJoinHandle<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        Builder::new()
+            .spawn_scoped(self, f)
+            .expect("failed to spawn thread")
+    }
+}
+
+impl Builder {
+    /// Spawns a new scoped thread using the settings set through this
+    /// `Builder`.
+    ///
+    /// See [`Scope::spawn`] for details.
+    pub fn spawn_scoped<'scope, 'env, F, T>(
+        self,
+        scope: &'scope Scope<'scope, 'env>,
+        f: F,
+    ) -> io::Result<ScopedJoinHandle<'scope, T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        // SAFETY: the thread is joined before `scope` returns, so it does not
+        // outlive `'scope`.
+        let inner = unsafe { self.spawn_unchecked(f, Some(scope.data.clone()))? };
+        Ok(ScopedJoinHandle {
+            inner,
+            scope: PhantomData,
+        })
+    }
+}
+
+impl<T> ScopedJoinHandle<'_, T> {
+    /// Extracts a handle to the underlying thread.
+    pub fn thread(&self) -> &Thread {
+        self.inner.thread()
+    }
+
+    /// Waits for the associated thread to finish.
+    ///
+    /// This function will return immediately if the associated thread has
+    /// already finished.
+    pub fn join(self) -> io::Result<T> {
+        self.inner.join()
+    }
+}